@@ -0,0 +1,18 @@
+// Copies some text, then keeps the window (the clipboard owner) open. Try it
+// under Xvfb with a second client reading the selection:
+//
+//   Xvfb :99 &
+//   DISPLAY=:99 cargo run -- examples/clipboard.js &
+//   DISPLAY=:99 xclip -o -selection clipboard
+
+const win = Deno.createWindow({ title: "Clipboard", width: 320, height: 240 });
+
+console.log("Clipboard had:", JSON.stringify(await navigator.clipboard.readText({ window: win })));
+await navigator.clipboard.writeText("Hello from deno_desktop", { window: win });
+console.log("Copied, close the window to give up the clipboard");
+
+for await (const event of Deno.eventLoop()) {
+  if (event.type === "windowEvent" && event.windowID === win.id && event.event.type === "closeRequested") {
+    Deno.exit(0);
+  }
+}
@@ -0,0 +1,118 @@
+// Run with `cargo run -- --headless examples/headless.js`.
+
+function assertEquals(actual, expected) {
+  if (JSON.stringify(actual) !== JSON.stringify(expected)) {
+    throw new Error(`Expected ${JSON.stringify(expected)}, got ${JSON.stringify(actual)}`);
+  }
+}
+
+const win = Deno.createWindow({ title: "Headless", width: 320, height: 240 });
+assertEquals(win.getSize(), { width: 320, height: 240 });
+
+win.setTitle("Renamed");
+win.setSize({ width: 640, height: 480 });
+assertEquals(Deno.headless.getWindowState(win).title, "Renamed");
+
+Deno.headless.setScaleFactor(win, 2);
+assertEquals(win.getSize(), { width: 1280, height: 960 });
+win.setUnits("logical");
+assertEquals(win.getSize(), { width: 640, height: 480 });
+
+const pad = Deno.headless.connectGamepad("Test pad");
+Deno.headless.updateGamepad(pad, { buttons: [1], axes: [0, -1] });
+assertEquals(navigator.getGamepads()[pad].buttons[0].pressed, true);
+assertEquals(navigator.getGamepads()[pad].axes, [0, -1, 0, 0]);
+Deno.headless.disconnectGamepad(pad);
+assertEquals(navigator.getGamepads()[pad], null);
+
+await navigator.clipboard.writeText("Copied");
+assertEquals(await navigator.clipboard.readText(), "Copied");
+await navigator.clipboard.writeImage({ width: 1, height: 1, data: new Uint8Array([255, 0, 0, 255]) });
+assertEquals(await navigator.clipboard.readText(), "");
+const image = await navigator.clipboard.readImage();
+assertEquals([image.width, image.height, [...image.data]], [1, 1, [255, 0, 0, 255]]);
+
+assertEquals(await win.confirm("Discard changes?"), false);
+assertEquals(await prompt("Name?", "Deno"), null);
+assertEquals(await alert("Saved"), undefined);
+
+assertEquals(await Notification.requestPermission(), "granted");
+const notification = new Notification("Export finished", {
+  body: "out.mp4 is ready",
+  actions: [{ action: "open", title: "Open" }],
+});
+await new Promise((resolve) => notification.onshow = resolve);
+const [shown] = Deno.headless.notifications();
+assertEquals([shown.title, shown.body, shown.actions], ["Export finished", "out.mp4 is ready", [{ action: "open", title: "Open" }]]);
+const clicked = new Promise((resolve) => notification.onclick = (event) => resolve(event.action));
+Deno.headless.clickNotification(shown.id, "open");
+assertEquals(Deno.headless.notifications(), []);
+
+const tray = await Deno.desktop.createTrayIcon({
+  icon: { width: 1, height: 1, data: new Uint8Array([0, 128, 255, 255]) },
+  tooltip: "Syncing",
+  menu: [
+    { id: "pause", label: "Pause", type: "checkbox", checked: false },
+    { type: "separator" },
+    { label: "More", submenu: [{ id: "quit", label: "Quit" }] },
+  ],
+});
+Deno.headless.clickTrayIcon(tray);
+Deno.headless.activateTrayMenuItem(tray, "quit");
+tray.setMenu([{ id: "resume", label: "Resume", enabled: false }]);
+let threw = false;
+try {
+  Deno.headless.activateTrayMenuItem(tray, "resume");
+} catch {
+  threw = true;
+}
+assertEquals(threw, true);
+tray.close();
+
+win.setMenu([
+  { label: "View", submenu: [
+    { id: "light", label: "Light", type: "radio", checked: true },
+    { id: "dark", label: "Dark", type: "radio", accelerator: "Ctrl+D" },
+  ] },
+]);
+Deno.headless.activateMenuItem(win, "dark");
+const chosen = Deno.desktop.showContextMenu([{ id: "copy", label: "Copy" }], { window: win });
+Deno.headless.activateMenuItem(win, "copy");
+assertEquals(await chosen, "copy");
+
+const palette = Deno.createWindow({ parent: win, kind: "tool" });
+const paletteState = Deno.headless.getWindowState(palette);
+assertEquals([paletteState.parent, paletteState.kind, paletteState.modal], [win.id, "tool", false]);
+const tooltip = Deno.createWindow({ parent: win, kind: "popup", visible: false });
+assertEquals(Deno.headless.getWindowState(tooltip).decorations, false);
+for (const options of [{ modal: true }, { kind: "popup" }]) {
+  let error;
+  try {
+    Deno.createWindow(options);
+  } catch (err) {
+    error = err;
+  }
+  assertEquals(error instanceof TypeError, true);
+}
+palette.close();
+tooltip.close();
+
+Deno.headless.pushEvent({
+  type: "windowEvent",
+  windowID: win.id,
+  event: { type: "closeRequested" },
+});
+
+const seen = [];
+const activated = [];
+for await (const event of Deno.eventLoop()) {
+  seen.push(event.event?.type ?? event.type);
+  if (event.type === "menuItemActivated" && event.windowID === win.id) {
+    activated.push([event.itemID, event.checked]);
+  }
+}
+assertEquals(activated, [["dark", true], ["copy", false]]);
+assertEquals(await clicked, "open");
+
+assertEquals(seen, ["resized", "scaleFactorChanged", "resized", "gamepadconnected", "gamepaddisconnected", "notificationclick", "notificationclose", "trayIconClicked", "menuItemActivated", "menuItemActivated", "menuItemActivated", "closeRequested"]);
+console.log("ok");
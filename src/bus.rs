@@ -0,0 +1,83 @@
+//! D-Bus connections served on threads of their own, which sleep until either
+//! the bus or the runtime has something for them.
+
+use std::io::ErrorKind;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+
+use deno_runtime::deno_core::error::AnyError;
+use nix::errno::Errno;
+use nix::fcntl::FcntlArg;
+use nix::fcntl::OFlag;
+use nix::fcntl::fcntl;
+use nix::poll::PollFd;
+use nix::poll::PollFlags;
+use nix::poll::poll;
+use nix::sys::eventfd::EfdFlags;
+use nix::sys::eventfd::eventfd;
+use nix::unistd;
+use zbus::Connection;
+
+/// An eventfd for waking up the thread serving a connection, which otherwise
+/// sleeps until a message comes in.
+#[derive(Clone)]
+pub struct Waker(Arc<EventFd>);
+
+struct EventFd(RawFd);
+
+impl Drop for EventFd {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0);
+    }
+}
+
+impl Waker {
+    pub fn new() -> Result<Waker, AnyError> {
+        Ok(Waker(Arc::new(EventFd(eventfd(0, EfdFlags::EFD_CLOEXEC | EfdFlags::EFD_NONBLOCK)?))))
+    }
+
+    /// Gets the serving thread to look at its state again.
+    pub fn wake(&self) -> Result<(), AnyError> {
+        match unistd::write((self.0).0, &1u64.to_ne_bytes()) {
+            // The counter is full, so the thread is due to wake up anyway.
+            Ok(_) | Err(Errno::EAGAIN) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Sleeps until the bus or `wake` has something for us, which only works
+    /// once `conn` doesn't block.
+    pub fn wait(&self, conn: &Connection) -> nix::Result<()> {
+        // Messages that didn't fit into the socket are sent first.
+        let events = match conn.flush() {
+            Err(err) if would_block(&err) => PollFlags::POLLIN | PollFlags::POLLOUT,
+            _ => PollFlags::POLLIN,
+        };
+        let fd = (self.0).0;
+        let mut fds = [PollFd::new(conn.as_raw_fd(), events), PollFd::new(fd, PollFlags::POLLIN)];
+        while let Err(err) = poll(&mut fds, -1) {
+            if err != Errno::EINTR {
+                return Err(err);
+            }
+        }
+        let mut count = [0; 8];
+        match unistd::read(fd, &mut count) {
+            Ok(_) | Err(Errno::EAGAIN) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Has receiving on `conn` return a `WouldBlock` error rather than sleep when
+/// nothing came in, for `Waker::wait` to do the sleeping. Method calls still
+/// wait for their reply.
+pub fn set_nonblocking(conn: &Connection) -> Result<(), AnyError> {
+    fcntl(conn.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+    Ok(())
+}
+
+/// Whether `err` only means that a non-blocking connection has nothing more.
+pub fn would_block(err: &zbus::Error) -> bool {
+    matches!(err, zbus::Error::Io(err) if err.kind() == ErrorKind::WouldBlock)
+}
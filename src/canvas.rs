@@ -0,0 +1,466 @@
+//! Software drawing for the runtime's own windows, which have no renderer.
+//! Text is rasterized with rusttype, in the fonts fontconfig picks for each
+//! character, and the pixels go on the window with `PutImage` on X11 or in a
+//! `wl_shm` buffer on Wayland. On Wayland, where nothing can be placed on the
+//! screen, there are subsurfaces to draw over part of a window instead.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::custom_error;
+use rusttype::Font;
+use rusttype::GlyphId;
+use rusttype::PositionedGlyph;
+use rusttype::Scale;
+use rusttype::point;
+use winit_main::reexports::dpi::PhysicalSize;
+use winit_main::reexports::platform::unix::WindowExtUnix;
+use winit_main::reexports::window::Window;
+
+use crate::x11;
+
+lazy_static! {
+    static ref FONTS: Mutex<Option<Fonts>> = Mutex::new(None);
+}
+
+/// The sans-serif font, and the ones fontconfig falls back to for characters
+/// it doesn't have.
+struct Fonts {
+    fonts: Vec<Font<'static>>,
+    paths: Vec<(PathBuf, u32)>,
+    /// Which font each character missing from the first one is drawn with.
+    fallbacks: HashMap<char, usize>,
+}
+
+/// The font file and face index fontconfig matches `pattern` with.
+fn fc_match(pattern: &str) -> Result<(PathBuf, u32), AnyError> {
+    let output = Command::new("fc-match")
+        .args(&["--format=%{file}\n%{index}", pattern])
+        .output()
+        .map_err(|err| custom_error("NotSupported", format!("Can't run fc-match to find fonts: {}", err)))?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    match (lines.next(), lines.next().and_then(|index| index.parse().ok())) {
+        (Some(file), Some(index)) if !file.is_empty() => Ok((PathBuf::from(file), index)),
+        _ => Err(custom_error("NotSupported", format!("No font matches {:?}", pattern))),
+    }
+}
+
+fn load_font(path: &Path, index: u32) -> Result<Font<'static>, AnyError> {
+    let data = std::fs::read(path)?;
+    Font::try_from_vec_and_index(data, index)
+        .ok_or_else(|| custom_error("NotSupported", format!("Can't read the font in {}", path.display())))
+}
+
+fn has_glyph(font: &Font, ch: char) -> bool {
+    font.glyph(ch).id() != GlyphId(0)
+}
+
+impl Fonts {
+    fn load() -> Result<Fonts, AnyError> {
+        let (path, index) = fc_match("sans-serif")?;
+        let font = load_font(&path, index)?;
+        Ok(Fonts { fonts: vec![font], paths: vec![(path, index)], fallbacks: HashMap::new() })
+    }
+
+    /// The font to draw `ch` with. Characters no font has are drawn as the
+    /// first font's missing glyph box.
+    fn font_for(&mut self, ch: char) -> usize {
+        if has_glyph(&self.fonts[0], ch) {
+            return 0;
+        }
+        if let Some(&font) = self.fallbacks.get(&ch) {
+            return font;
+        }
+        let font = self.find_fallback(ch).unwrap_or(0);
+        self.fallbacks.insert(ch, font);
+        font
+    }
+
+    fn find_fallback(&mut self, ch: char) -> Option<usize> {
+        if let Some(font) = self.fonts.iter().position(|font| has_glyph(font, ch)) {
+            return Some(font);
+        }
+        let (path, index) = fc_match(&format!("sans-serif:charset={:x}", ch as u32)).ok()?;
+        if self.paths.contains(&(path.clone(), index)) {
+            return None;
+        }
+        let font = load_font(&path, index).ok()?;
+        if !has_glyph(&font, ch) {
+            return None;
+        }
+        self.fonts.push(font);
+        self.paths.push((path, index));
+        Some(self.fonts.len() - 1)
+    }
+
+    /// Lays `text` out from `x` on `baseline`, with kerning within each font,
+    /// and returns where it ends.
+    fn layout(&mut self, text: &str, size: f32, x: f32, baseline: f32, mut f: impl FnMut(PositionedGlyph)) -> f32 {
+        let scale = Scale::uniform(size);
+        let mut caret = x;
+        let mut last: Option<(usize, GlyphId)> = None;
+        for ch in text.chars() {
+            let index = self.font_for(ch);
+            let font = &self.fonts[index];
+            let glyph = font.glyph(ch).scaled(scale);
+            if let Some((last_index, last_id)) = last {
+                if last_index == index {
+                    caret += font.pair_kerning(scale, last_id, glyph.id());
+                }
+            }
+            last = Some((index, glyph.id()));
+            let advance = glyph.h_metrics().advance_width;
+            f(glyph.positioned(point(caret, baseline)));
+            caret += advance;
+        }
+        caret
+    }
+}
+
+/// Runs `f` with the fonts, finding them on first use.
+fn with_fonts<R>(f: impl FnOnce(&mut Fonts) -> R) -> Result<R, AnyError> {
+    let mut fonts = FONTS.lock().unwrap();
+    if fonts.is_none() {
+        *fonts = Some(Fonts::load()?);
+    }
+    Ok(f(fonts.as_mut().unwrap()))
+}
+
+/// Fails if there is no font to draw text with.
+pub fn check_fonts() -> Result<(), AnyError> {
+    with_fonts(|_| ())
+}
+
+/// How wide `text` is drawn at `size` pixels.
+pub fn text_width(text: &str, size: f32) -> f32 {
+    with_fonts(|fonts| fonts.layout(text, size, 0.0, 0.0, |_| {})).unwrap_or(0.0)
+}
+
+/// How far glyphs reach above and below the baseline at `size` pixels.
+pub fn vertical_metrics(size: f32) -> (f32, f32) {
+    with_fonts(|fonts| {
+        let metrics = fonts.fonts[0].v_metrics(Scale::uniform(size));
+        (metrics.ascent, -metrics.descent)
+    })
+    .unwrap_or((size, 0.0))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x as f64
+            && y >= self.y as f64
+            && x < (self.x + self.width) as f64
+            && y < (self.y + self.height) as f64
+    }
+}
+
+/// `0xRRGGBB` pixels, drawn in software and then put on a window.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+}
+
+fn blend(under: u32, over: u32, alpha: f32) -> u32 {
+    let channel = |shift: u32| {
+        let under = ((under >> shift) & 0xff) as f32;
+        let over = ((over >> shift) & 0xff) as f32;
+        ((under + (over - under) * alpha).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
+
+impl Canvas {
+    pub fn new(size: PhysicalSize<u32>, background: u32) -> Canvas {
+        let (width, height) = (size.width.max(1), size.height.max(1));
+        Canvas { width, height, pixels: vec![background; (width * height) as usize] }
+    }
+
+    fn blend_pixel(&mut self, x: i32, y: i32, color: u32, alpha: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 || alpha <= 0.0 {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.width as usize + x as usize];
+        *pixel = blend(*pixel, color, alpha.min(1.0));
+    }
+
+    pub fn fill(&mut self, rect: Rect, color: u32) {
+        let x0 = rect.x.clamp(0, self.width as i32) as usize;
+        let x1 = (rect.x + rect.width).clamp(0, self.width as i32) as usize;
+        let y0 = rect.y.clamp(0, self.height as i32) as usize;
+        let y1 = (rect.y + rect.height).clamp(0, self.height as i32) as usize;
+        if x0 >= x1 {
+            return;
+        }
+        for y in y0..y1 {
+            let row = y * self.width as usize;
+            self.pixels[row + x0..row + x1].fill(color);
+        }
+    }
+
+    /// Draws a `thickness` pixels wide border just inside `rect`.
+    pub fn stroke(&mut self, rect: Rect, thickness: i32, color: u32) {
+        let Rect { x, y, width, height } = rect;
+        self.fill(Rect { x, y, width, height: thickness }, color);
+        self.fill(Rect { x, y: y + height - thickness, width, height: thickness }, color);
+        self.fill(Rect { x, y, width: thickness, height }, color);
+        self.fill(Rect { x: x + width - thickness, y, width: thickness, height }, color);
+    }
+
+    /// Draws a disc of `radius` pixels centered on `(x, y)`, with a smooth
+    /// edge.
+    pub fn disc(&mut self, x: f32, y: f32, radius: f32, color: u32) {
+        let (x0, x1) = ((x - radius).floor() as i32, (x + radius).ceil() as i32);
+        let (y0, y1) = ((y - radius).floor() as i32, (y + radius).ceil() as i32);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let distance = (px as f32 + 0.5 - x).hypot(py as f32 + 0.5 - y);
+                self.blend_pixel(px, py, color, radius + 0.5 - distance);
+            }
+        }
+    }
+
+    /// Draws `text` at `size` pixels from `x` on `baseline`, and returns where
+    /// it ends.
+    pub fn text(&mut self, text: &str, size: f32, x: f32, baseline: f32, color: u32) -> f32 {
+        let mut glyphs = vec![];
+        let end = with_fonts(|fonts| fonts.layout(text, size, x, baseline, |glyph| glyphs.push(glyph))).unwrap_or(x);
+        for glyph in glyphs {
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|gx, gy, coverage| {
+                    self.blend_pixel(bounds.min.x + gx as i32, bounds.min.y + gy as i32, color, coverage)
+                });
+            }
+        }
+        end
+    }
+
+    /// Puts the pixels on `window`, whose size they should have.
+    pub fn present(&self, window: &Window) -> Result<(), AnyError> {
+        if let Some(xlib_window) = window.xlib_window() {
+            return x11::put_image(xlib_window as u32, self.width as u16, self.height as u16, &self.pixels);
+        }
+        match (window.wayland_display(), window.wayland_surface()) {
+            (Some(display), Some(surface)) => wayland::present(display, surface, self.width, self.height, &self.pixels),
+            _ => Err(custom_error("NotSupported", "Can't draw on this kind of window")),
+        }
+    }
+}
+
+/// Lets go of the buffers drawn for `window`, once it's closed.
+pub fn release(window: &Window) {
+    if let Some(surface) = window.wayland_surface() {
+        wayland::release(surface);
+    }
+}
+
+pub use wayland::Subsurface;
+
+/// Buffers on winit's `wl_display`, through an event queue of our own. Only
+/// the event pump draws, so this lives on its thread.
+mod wayland {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::collections::hash_map::Entry;
+    use std::os::raw::c_void;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use sctk::reexports::client::Display;
+    use sctk::reexports::client::EventQueue;
+    use sctk::reexports::client::GlobalManager;
+    use sctk::reexports::client::Main;
+    use sctk::reexports::client::Proxy;
+    use sctk::reexports::client::protocol::wl_compositor::WlCompositor;
+    use sctk::reexports::client::protocol::wl_shm;
+    use sctk::reexports::client::protocol::wl_shm::WlShm;
+    use sctk::reexports::client::protocol::wl_subcompositor::WlSubcompositor;
+    use sctk::reexports::client::protocol::wl_subsurface::WlSubsurface;
+    use sctk::reexports::client::protocol::wl_surface::WlSurface;
+    use sctk::shm::DoubleMemPool;
+
+    use super::Canvas;
+
+    struct Painter {
+        queue: EventQueue,
+        globals: GlobalManager,
+        shm: Main<WlShm>,
+        /// Bound the first time a subsurface is made.
+        compositors: Option<(Main<WlCompositor>, Main<WlSubcompositor>)>,
+        /// Two pools per surface, so one can be drawn in while the
+        /// compositor still reads the other.
+        pools: HashMap<usize, DoubleMemPool>,
+    }
+
+    thread_local! {
+        static PAINTER: RefCell<Option<Painter>> = RefCell::new(None);
+    }
+
+    fn missing(global: &str, err: impl std::fmt::Display) -> AnyError {
+        custom_error("NotSupported", format!("The compositor has no {}: {}", global, err))
+    }
+
+    impl Painter {
+        fn new(display: *mut c_void) -> Result<Painter, AnyError> {
+            // The display lives as long as the event loop, which is as long
+            // as the process.
+            let display = unsafe { Display::from_external_display(display as *mut _) };
+            let mut queue = display.create_event_queue();
+            let globals = GlobalManager::new(&display.attach(queue.token()));
+            queue.sync_roundtrip(&mut (), |_, _, _| {})?;
+            let shm = globals.instantiate_exact::<WlShm>(1).map_err(|err| missing("wl_shm", err))?;
+            Ok(Painter { queue, globals, shm, compositors: None, pools: HashMap::new() })
+        }
+
+        fn compositors(&mut self) -> Result<&(Main<WlCompositor>, Main<WlSubcompositor>), AnyError> {
+            if self.compositors.is_none() {
+                // Version 3 has buffer scales.
+                let globals = &self.globals;
+                let compositor =
+                    globals.instantiate_exact::<WlCompositor>(3).map_err(|err| missing("wl_compositor", err))?;
+                let subcompositor =
+                    globals.instantiate_exact::<WlSubcompositor>(1).map_err(|err| missing("wl_subcompositor", err))?;
+                self.compositors = Some((compositor, subcompositor));
+            }
+            Ok(self.compositors.as_ref().unwrap())
+        }
+
+        fn present(&mut self, surface: &WlSurface, width: u32, height: u32, pixels: &[u32]) -> Result<(), AnyError> {
+            // Catch up on buffers the compositor is done with.
+            self.queue.dispatch_pending(&mut (), |_, _, _| {})?;
+            let shm = &self.shm;
+            let pools = match self.pools.entry(surface.as_ref().c_ptr() as usize) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(DoubleMemPool::new((**shm).clone(), |_| {})?),
+            };
+            if pools.pool().is_none() {
+                self.queue.sync_roundtrip(&mut (), |_, _, _| {})?;
+            }
+            let pool = match pools.pool() {
+                Some(pool) => pool,
+                // Still busy, the next redraw will do.
+                None => return Ok(()),
+            };
+
+            let len = pixels.len() * 4;
+            pool.resize(len)?;
+            for (bytes, pixel) in pool.mmap()[..len].chunks_exact_mut(4).zip(pixels) {
+                bytes.copy_from_slice(&pixel.to_le_bytes());
+            }
+            let buffer = pool.buffer(0, width as i32, height as i32, width as i32 * 4, wl_shm::Format::Xrgb8888);
+
+            surface.attach(Some(&buffer), 0, 0);
+            surface.damage(0, 0, i32::MAX, i32::MAX);
+            surface.commit();
+            self.queue.display().flush()?;
+            Ok(())
+        }
+    }
+
+    /// Runs `f` with the painter for `display`, made on first use.
+    fn with_painter<R>(
+        display: *mut c_void,
+        f: impl FnOnce(&mut Painter) -> Result<R, AnyError>,
+    ) -> Result<R, AnyError> {
+        PAINTER.with(|painter| {
+            let mut painter = painter.borrow_mut();
+            if painter.is_none() {
+                *painter = Some(Painter::new(display)?);
+            }
+            f(painter.as_mut().unwrap())
+        })
+    }
+
+    /// A surface winit made, which lives as long as its window.
+    fn window_surface(surface: *mut c_void) -> WlSurface {
+        WlSurface::from(unsafe { Proxy::<WlSurface>::from_c_ptr(surface as *mut _) })
+    }
+
+    pub fn present(
+        display: *mut c_void,
+        surface: *mut c_void,
+        width: u32,
+        height: u32,
+        pixels: &[u32],
+    ) -> Result<(), AnyError> {
+        with_painter(display, |painter| painter.present(&window_surface(surface), width, height, pixels))
+    }
+
+    pub fn release(surface: *mut c_void) {
+        PAINTER.with(|painter| {
+            if let Some(painter) = painter.borrow_mut().as_mut() {
+                painter.pools.remove(&(surface as usize));
+            }
+        });
+    }
+
+    /// A surface of our own over part of a window. It takes no input, so the
+    /// pointer goes through to the window, which gets to say what was hit.
+    /// Destroyed when dropped.
+    pub struct Subsurface {
+        display: *mut c_void,
+        surface: Main<WlSurface>,
+        subsurface: Main<WlSubsurface>,
+        parent: WlSurface,
+    }
+
+    impl Subsurface {
+        /// Makes a hidden subsurface of winit's `parent` surface.
+        pub fn new(display: *mut c_void, parent: *mut c_void) -> Result<Subsurface, AnyError> {
+            with_painter(display, |painter| {
+                let (compositor, subcompositor) = painter.compositors()?;
+                let parent = window_surface(parent);
+                let surface = compositor.create_surface();
+                let subsurface = subcompositor.get_subsurface(&surface, &parent);
+                // Drawn as soon as it's committed, not with the window's next
+                // frame, which may be a long time coming.
+                subsurface.set_desync();
+                let region = compositor.create_region();
+                surface.set_input_region(Some(&region));
+                region.destroy();
+                Ok(Subsurface { display, surface, subsurface, parent })
+            })
+        }
+
+        /// Shows `canvas` with its top left corner at `(x, y)` in the window,
+        /// in buffer pixels, `scale` of which make a surface pixel.
+        pub fn show(&self, x: i32, y: i32, scale: i32, canvas: &Canvas) -> Result<(), AnyError> {
+            self.surface.set_buffer_scale(scale);
+            self.subsurface.set_position(x / scale, y / scale);
+            with_painter(self.display, |painter| {
+                painter.present(&self.surface, canvas.width, canvas.height, &canvas.pixels)
+            })?;
+            // The position only applies once the window commits, which it
+            // doesn't on its own until the app draws again.
+            self.parent.commit();
+            with_painter(self.display, |painter| Ok(painter.queue.display().flush()?))
+        }
+
+        pub fn hide(&self) -> Result<(), AnyError> {
+            self.surface.attach(None, 0, 0);
+            self.surface.commit();
+            with_painter(self.display, |painter| Ok(painter.queue.display().flush()?))
+        }
+    }
+
+    impl Drop for Subsurface {
+        fn drop(&mut self) {
+            release(self.surface.as_ref().c_ptr() as *mut c_void);
+            self.subsurface.destroy();
+            self.surface.destroy();
+        }
+    }
+}
@@ -0,0 +1,1006 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_async;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::ZeroCopyBuf;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::custom_error;
+use deno_runtime::deno_core::error::type_error;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[cfg(target_os = "linux")]
+use crate::event_loop::focused_window;
+use crate::flags::Flags;
+#[cfg(target_os = "linux")]
+use crate::ids;
+#[cfg(target_os = "linux")]
+use crate::window::WindowResource;
+use crate::window::WindowRef;
+
+/// What we last put on the clipboard. Headless mode keeps the clipboard
+/// here, on X11 it's what the selection owner hands out until another client
+/// takes over.
+#[derive(Clone)]
+enum Contents {
+    Text(String),
+    Png(Vec<u8>),
+}
+
+lazy_static! {
+    static ref CONTENTS: Mutex<Option<Contents>> = Mutex::new(None);
+}
+
+fn encode_png(rgba: &[u8], width: u32, height: u32) -> Result<Vec<u8>, AnyError> {
+    if width == 0 || height == 0 || rgba.len() != width as usize * height as usize * 4 {
+        return Err(type_error(format!(
+            "Expected {} bytes of RGBA data for a {}x{} image, got {}",
+            width as usize * height as usize * 4,
+            width,
+            height,
+            rgba.len()
+        )));
+    }
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+/// Decodes any PNG into 8-bit RGBA pixels.
+fn decode_png(data: &[u8]) -> Result<(u32, u32, Vec<u8>), AnyError> {
+    let mut decoder = png::Decoder::new(data);
+    // Palettes, low bit depths and 16-bit channels all become 8-bit.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => unreachable!("EXPAND turns palettes into RGB(A)"),
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Rgba,
+    Png,
+}
+
+#[derive(Serialize)]
+pub struct ClipboardImage {
+    width: u32,
+    height: u32,
+    /// RGBA pixels or PNG data, depending on the requested format.
+    data: ZeroCopyBuf,
+}
+
+fn clipboard_image(png: Vec<u8>, format: ImageFormat) -> Result<ClipboardImage, AnyError> {
+    Ok(match format {
+        ImageFormat::Rgba => {
+            let (width, height, rgba) = decode_png(&png)?;
+            ClipboardImage { width, height, data: rgba.into() }
+        }
+        ImageFormat::Png => {
+            let (width, height) = png::Decoder::new(png.as_slice()).read_info()?.info().size();
+            ClipboardImage { width, height, data: png.into() }
+        }
+    })
+}
+
+impl Contents {
+    fn data(&self) -> &[u8] {
+        match self {
+            Contents::Text(text) => text.as_bytes(),
+            Contents::Png(png) => png,
+        }
+    }
+}
+
+/// Where clipboard data goes, picked once per op.
+enum Backend {
+    /// The in-memory `CONTENTS`.
+    Headless,
+    #[cfg(target_os = "linux")]
+    Wayland,
+    /// X11 selections, owned by the given window.
+    #[cfg(target_os = "linux")]
+    X11(Option<u32>),
+}
+
+/// Picks the backend for an op called for `window`, or for the focused window
+/// when there is none. That window owns what is written.
+#[cfg(target_os = "linux")]
+fn backend(state: &OpState, window: Option<&WindowRef>) -> Result<Backend, AnyError> {
+    use winit_main::reexports::platform::unix::WindowExtUnix;
+
+    if state.borrow::<Flags>().headless {
+        return Ok(Backend::Headless);
+    }
+    let owner = match window {
+        Some(window) => Some(WindowResource::get(state, window)?),
+        None => focused_window()
+            .and_then(ids::window_rid)
+            .and_then(|rid| state.resource_table.get::<WindowResource>(rid).ok()),
+    };
+    if let Some(WindowResource::Native(window)) = owner.as_deref() {
+        return Ok(match window.wayland_display() {
+            Some(display) => {
+                wayland::init(display);
+                Backend::Wayland
+            }
+            None => Backend::X11(window.xlib_window().map(|window| window as u32)),
+        });
+    }
+
+    // Reading works without an owner. All windows share winit's connection,
+    // so any of them tells whether it's to a Wayland compositor.
+    let wayland_display = state
+        .resource_table
+        .names()
+        .filter_map(|(rid, _)| state.resource_table.get::<WindowResource>(rid).ok())
+        .find_map(|resource| match &*resource {
+            WindowResource::Native(window) => window.wayland_display(),
+            WindowResource::Headless(_) => None,
+        });
+    if let Some(display) = wayland_display {
+        wayland::init(display);
+    }
+    if wayland::is_initialized() {
+        return Ok(Backend::Wayland);
+    }
+    Ok(Backend::X11(None))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn backend(state: &OpState, _window: Option<&WindowRef>) -> Result<Backend, AnyError> {
+    if state.borrow::<Flags>().headless {
+        return Ok(Backend::Headless);
+    }
+    Err(custom_error("NotSupported", "The clipboard is only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn no_owner() -> AnyError {
+    custom_error("NotFound", "Writing to the clipboard needs a window, pass `window` or focus one")
+}
+
+fn write(state: &OpState, window: Option<&WindowRef>, contents: Contents) -> Result<(), AnyError> {
+    match backend(state, window)? {
+        Backend::Headless => {
+            *CONTENTS.lock().unwrap() = Some(contents);
+            Ok(())
+        }
+        #[cfg(target_os = "linux")]
+        Backend::Wayland => wayland::write(contents),
+        #[cfg(target_os = "linux")]
+        Backend::X11(owner) => x11::write(owner.ok_or_else(no_owner)?, contents),
+    }
+}
+
+pub async fn op_clipboard_read_text(
+    state: Rc<RefCell<OpState>>,
+    window: Option<WindowRef>,
+    _: (),
+) -> Result<String, AnyError> {
+    let backend = backend(&state.borrow(), window.as_ref())?;
+    match backend {
+        Backend::Headless => Ok(match &*CONTENTS.lock().unwrap() {
+            Some(Contents::Text(text)) => text.clone(),
+            _ => String::new(),
+        }),
+        #[cfg(target_os = "linux")]
+        Backend::Wayland => tokio::task::spawn_blocking(wayland::read_text).await?,
+        #[cfg(target_os = "linux")]
+        Backend::X11(_) => tokio::task::spawn_blocking(x11::read_text).await?,
+    }
+}
+
+pub fn op_clipboard_write_text(
+    state: &mut OpState,
+    args: (String, Option<WindowRef>),
+    _: (),
+) -> Result<(), AnyError> {
+    let (text, window) = args;
+    write(state, window.as_ref(), Contents::Text(text))
+}
+
+/// Reads the PNG image on the clipboard, as RGBA pixels or as is.
+pub async fn op_clipboard_read_image(
+    state: Rc<RefCell<OpState>>,
+    args: (ImageFormat, Option<WindowRef>),
+    _: (),
+) -> Result<Option<ClipboardImage>, AnyError> {
+    let (format, window) = args;
+    let backend = backend(&state.borrow(), window.as_ref())?;
+    let png = match backend {
+        Backend::Headless => match &*CONTENTS.lock().unwrap() {
+            Some(Contents::Png(png)) => Some(png.clone()),
+            _ => None,
+        },
+        #[cfg(target_os = "linux")]
+        Backend::Wayland => tokio::task::spawn_blocking(wayland::read_png).await??,
+        #[cfg(target_os = "linux")]
+        Backend::X11(_) => tokio::task::spawn_blocking(x11::read_png).await??,
+    };
+    match png {
+        Some(png) => Ok(Some(clipboard_image(png, format)?)),
+        None => Ok(None),
+    }
+}
+
+/// Puts an image on the clipboard. With a size, `data` holds RGBA pixels,
+/// otherwise PNG data.
+pub fn op_clipboard_write_image(
+    state: &mut OpState,
+    args: (Option<(u32, u32)>, Option<WindowRef>),
+    data: ZeroCopyBuf,
+) -> Result<(), AnyError> {
+    let (size, window) = args;
+    let png = match size {
+        Some((width, height)) => encode_png(&data, width, height)?,
+        None => {
+            // Catch bad data here rather than in whoever pastes it.
+            decode_png(&data).map_err(|err| type_error(format!("Invalid PNG: {}", err)))?;
+            data.to_vec()
+        }
+    };
+    write(state, window.as_ref(), Contents::Png(png))
+}
+
+/// The Wayland clipboard, on winit's own connection since the data device
+/// protocol only serves clients that have the seat's focus. A worker thread
+/// with its own event queue takes requests from the ops, and serves what we
+/// copied to other clients in between.
+#[cfg(target_os = "linux")]
+mod wayland {
+    use std::cell::Cell;
+    use std::cell::RefCell;
+    use std::fs::File;
+    use std::io::Read;
+    use std::io::Write;
+    use std::os::raw::c_void;
+    use std::os::unix::io::FromRawFd;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+    use std::sync::mpsc;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use sctk::data_device::DataDevice;
+    use sctk::data_device::DataDeviceHandler;
+    use sctk::data_device::DataDeviceHandling;
+    use sctk::data_device::DataSourceEvent;
+    use sctk::data_device::DndEvent;
+    use sctk::environment::Environment;
+    use sctk::reexports::client::Attached;
+    use sctk::reexports::client::DispatchData;
+    use sctk::reexports::client::Display;
+    use sctk::reexports::client::EventQueue;
+    use sctk::reexports::client::protocol::wl_data_device_manager::WlDataDeviceManager;
+    use sctk::reexports::client::protocol::wl_keyboard;
+    use sctk::reexports::client::protocol::wl_keyboard::WlKeyboard;
+    use sctk::reexports::client::protocol::wl_pointer;
+    use sctk::reexports::client::protocol::wl_pointer::WlPointer;
+    use sctk::reexports::client::protocol::wl_seat::WlSeat;
+    use sctk::seat::SeatData;
+    use sctk::seat::SeatHandler;
+    use sctk::seat::SeatHandling;
+    use sctk::seat::SeatListener;
+
+    use super::CONTENTS;
+    use super::Contents;
+
+    const TEXT_MIME_TYPES: &[&str] = &["text/plain;charset=utf-8", "UTF8_STRING"];
+    const PNG_MIME_TYPES: &[&str] = &["image/png"];
+    /// How often the worker dispatches its queue between requests.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    impl Contents {
+        fn mime_types(&self) -> &'static [&'static str] {
+            match self {
+                Contents::Text(_) => TEXT_MIME_TYPES,
+                Contents::Png(_) => PNG_MIME_TYPES,
+            }
+        }
+    }
+
+    enum Command {
+        /// Reads the first of the MIME types the clipboard has.
+        Load(&'static [&'static str], mpsc::Sender<Result<Option<Vec<u8>>, AnyError>>),
+        Store(Contents, mpsc::Sender<Result<(), AnyError>>),
+    }
+
+    lazy_static! {
+        static ref WORKER: Mutex<Option<mpsc::Sender<Command>>> = Mutex::new(None);
+    }
+
+    struct ClipboardEnv {
+        seats: SeatHandler,
+        data_device_manager: DataDeviceHandler,
+    }
+
+    impl SeatHandling for ClipboardEnv {
+        fn listen<F: FnMut(Attached<WlSeat>, &SeatData, DispatchData) + 'static>(&mut self, f: F) -> SeatListener {
+            self.seats.listen(f)
+        }
+    }
+
+    impl DataDeviceHandling for ClipboardEnv {
+        fn set_callback<F: FnMut(WlSeat, DndEvent, DispatchData) + 'static>(&mut self, callback: F) -> Result<(), ()> {
+            self.data_device_manager.set_callback(callback)
+        }
+
+        fn with_device<F: FnOnce(&DataDevice)>(&self, seat: &WlSeat, f: F) -> Result<(), ()> {
+            self.data_device_manager.with_device(seat, f)
+        }
+    }
+
+    sctk::environment!(ClipboardEnv,
+        singles = [WlDataDeviceManager => data_device_manager],
+        multis = [WlSeat => seats],
+    );
+
+    /// The seat that last had input for us, and the serial of that input,
+    /// which setting the selection needs.
+    type LastInput = Rc<RefCell<Option<(WlSeat, u32)>>>;
+
+    struct Seat {
+        seat: WlSeat,
+        keyboard: Option<WlKeyboard>,
+        pointer: Option<WlPointer>,
+    }
+
+    /// Listens to the keyboard and pointer of `seat`, as its capabilities
+    /// come and go, to know the last input serial.
+    fn update_seat(seats: &mut Vec<Seat>, seat: &Attached<WlSeat>, data: &SeatData, last_input: &LastInput) {
+        let index = match seats.iter().position(|known| known.seat == **seat) {
+            Some(index) => index,
+            None => {
+                seats.push(Seat { seat: seat.detach(), keyboard: None, pointer: None });
+                seats.len() - 1
+            }
+        };
+        let known = &mut seats[index];
+
+        if data.has_keyboard && !data.defunct {
+            if known.keyboard.is_none() {
+                let keyboard = seat.get_keyboard();
+                let (seat, last_input) = (seat.detach(), last_input.clone());
+                keyboard.quick_assign(move |_, event, _| match event {
+                    wl_keyboard::Event::Enter { serial, .. } | wl_keyboard::Event::Key { serial, .. } => {
+                        *last_input.borrow_mut() = Some((seat.clone(), serial));
+                    }
+                    wl_keyboard::Event::Leave { .. } => {
+                        let mut last_input = last_input.borrow_mut();
+                        if last_input.as_ref().map_or(false, |(last, _)| *last == seat) {
+                            *last_input = None;
+                        }
+                    }
+                    wl_keyboard::Event::Keymap { fd, .. } => {
+                        // Only winit needs the keymap, this is our copy of
+                        // the file descriptor.
+                        drop(unsafe { File::from_raw_fd(fd) });
+                    }
+                    _ => {}
+                });
+                known.keyboard = Some(keyboard.detach());
+            }
+        } else if let Some(keyboard) = known.keyboard.take() {
+            if keyboard.as_ref().version() >= 3 {
+                keyboard.release();
+            }
+        }
+
+        if data.has_pointer && !data.defunct {
+            if known.pointer.is_none() {
+                let pointer = seat.get_pointer();
+                let (seat, last_input) = (seat.detach(), last_input.clone());
+                pointer.quick_assign(move |_, event, _| match event {
+                    wl_pointer::Event::Enter { serial, .. } | wl_pointer::Event::Button { serial, .. } => {
+                        *last_input.borrow_mut() = Some((seat.clone(), serial));
+                    }
+                    _ => {}
+                });
+                known.pointer = Some(pointer.detach());
+            }
+        } else if let Some(pointer) = known.pointer.take() {
+            if pointer.as_ref().version() >= 3 {
+                pointer.release();
+            }
+        }
+    }
+
+    fn no_focus() -> AnyError {
+        custom_error("NotFound", "The clipboard needs a window with keyboard or pointer focus on Wayland")
+    }
+
+    struct Worker {
+        env: Environment<ClipboardEnv>,
+        queue: EventQueue,
+        last_input: LastInput,
+        /// Counts the selections we set, so that a late `Cancelled` for an
+        /// older one is told apart.
+        selections: Rc<Cell<u64>>,
+        /// Whether our latest selection is still the clipboard's.
+        owned: Rc<Cell<bool>>,
+    }
+
+    impl Worker {
+        fn load(&mut self, mime_types: &[&str]) -> Result<Option<Vec<u8>>, AnyError> {
+            // Receiving from our own source would have this thread write
+            // to itself.
+            if self.owned.get() {
+                let contents = CONTENTS.lock().unwrap();
+                return Ok(contents
+                    .as_ref()
+                    .filter(|contents| contents.mime_types().iter().any(|mime_type| mime_types.contains(mime_type)))
+                    .map(|contents| contents.data().to_vec()));
+            }
+
+            let seat = match &*self.last_input.borrow() {
+                Some((seat, _)) => seat.clone(),
+                None => return Err(no_focus()),
+            };
+            let mut pipe = None;
+            self.env
+                .with_data_device(&seat, |device| {
+                    pipe = device.with_selection(|offer| {
+                        let offer = offer?;
+                        let mime_type = offer.with_mime_types(|offered| {
+                            mime_types.iter().find(|mime_type| offered.iter().any(|offered| offered == *mime_type)).copied()
+                        })?;
+                        offer.receive(mime_type.to_string()).ok()
+                    });
+                })
+                .map_err(|_| no_focus())?;
+            let mut pipe = match pipe {
+                Some(pipe) => pipe,
+                None => return Ok(None),
+            };
+            self.queue.display().flush()?;
+
+            let mut data = Vec::new();
+            pipe.read_to_end(&mut data)?;
+            Ok(Some(data))
+        }
+
+        fn store(&mut self, contents: Contents) -> Result<(), AnyError> {
+            let (seat, serial) = match &*self.last_input.borrow() {
+                Some((seat, serial)) => (seat.clone(), *serial),
+                None => return Err(no_focus()),
+            };
+
+            let selection = self.selections.get() + 1;
+            self.selections.set(selection);
+            let (selections, owned) = (self.selections.clone(), self.owned.clone());
+            let source = self.env.new_data_source(
+                contents.mime_types().iter().map(|mime_type| mime_type.to_string()).collect(),
+                move |event, _| match event {
+                    DataSourceEvent::Send { mut pipe, .. } => {
+                        // Writing blocks until the other client reads, so it
+                        // doesn't hold up the queue.
+                        let data = CONTENTS.lock().unwrap().as_ref().map(|contents| contents.data().to_vec());
+                        if let Some(data) = data {
+                            std::thread::spawn(move || pipe.write_all(&data));
+                        }
+                    }
+                    DataSourceEvent::Cancelled if selections.get() == selection => {
+                        owned.set(false);
+                        *CONTENTS.lock().unwrap() = None;
+                    }
+                    _ => {}
+                },
+            );
+
+            *CONTENTS.lock().unwrap() = Some(contents);
+            self.owned.set(true);
+            self.env
+                .with_data_device(&seat, |device| device.set_selection(&Some(source), serial))
+                .map_err(|_| no_focus())?;
+            self.queue.sync_roundtrip(&mut (), |_, _, _| {})?;
+            Ok(())
+        }
+    }
+
+    fn run(display: Display, commands: mpsc::Receiver<Command>) {
+        let mut queue = display.create_event_queue();
+        let attached = display.attach(queue.token());
+        let mut seats = SeatHandler::new();
+        let data_device_manager = DataDeviceHandler::init(&mut seats);
+        let env = match Environment::new(&attached, &mut queue, ClipboardEnv { seats, data_device_manager }) {
+            Ok(env) => env,
+            Err(err) => {
+                eprintln!("Failed to set up the Wayland clipboard: {}", err);
+                return;
+            }
+        };
+        if env.get_global::<WlDataDeviceManager>().is_none() {
+            eprintln!("The compositor has no clipboard");
+            return;
+        }
+
+        let last_input = LastInput::default();
+        let seats = Rc::new(RefCell::new(Vec::new()));
+        for seat in env.get_all_seats() {
+            if let Some(data) = sctk::seat::clone_seat_data(&seat) {
+                update_seat(&mut seats.borrow_mut(), &seat, &data, &last_input);
+            }
+        }
+        let _listener = {
+            let (seats, last_input) = (seats.clone(), last_input.clone());
+            env.listen_for_seats(move |seat, data, _| update_seat(&mut seats.borrow_mut(), &seat, data, &last_input))
+        };
+
+        let mut worker = Worker {
+            env,
+            queue,
+            last_input,
+            selections: Rc::new(Cell::new(0)),
+            owned: Rc::new(Cell::new(false)),
+        };
+        loop {
+            match commands.recv_timeout(POLL_INTERVAL) {
+                Ok(command) => {
+                    // Catch up on focus and selection changes first.
+                    if let Err(err) = worker.queue.sync_roundtrip(&mut (), |_, _, _| {}) {
+                        eprintln!("Lost the Wayland clipboard: {}", err);
+                        return;
+                    }
+                    match command {
+                        Command::Load(mime_types, reply) => drop(reply.send(worker.load(mime_types))),
+                        Command::Store(contents, reply) => drop(reply.send(worker.store(contents))),
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            if worker.queue.dispatch_pending(&mut (), |_, _, _| {}).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Starts the worker on winit's `wl_display`, once.
+    pub fn init(display: *mut c_void) {
+        let mut worker = WORKER.lock().unwrap();
+        if worker.is_none() {
+            // The display lives as long as the event loop, which is as long
+            // as the process.
+            let display = unsafe { Display::from_external_display(display as *mut _) };
+            let (sender, commands) = mpsc::channel();
+            std::thread::spawn(move || run(display, commands));
+            *worker = Some(sender);
+        }
+    }
+
+    pub fn is_initialized() -> bool {
+        WORKER.lock().unwrap().is_some()
+    }
+
+    fn request<T>(command: impl FnOnce(mpsc::Sender<Result<T, AnyError>>) -> Command) -> Result<T, AnyError> {
+        let (reply, result) = mpsc::channel();
+        let sent = match &*WORKER.lock().unwrap() {
+            Some(worker) => worker.send(command(reply)).is_ok(),
+            None => false,
+        };
+        if !sent {
+            return Err(custom_error("NotSupported", "The Wayland clipboard is not available"));
+        }
+        result.recv().map_err(|_| custom_error("NotSupported", "The Wayland clipboard is not available"))?
+    }
+
+    pub fn read_text() -> Result<String, AnyError> {
+        let data = request(|reply| Command::Load(TEXT_MIME_TYPES, reply))?;
+        // Reading an empty clipboard gives "" on the web.
+        Ok(data.map(|data| String::from_utf8_lossy(&data).into_owned()).unwrap_or_default())
+    }
+
+    pub fn read_png() -> Result<Option<Vec<u8>>, AnyError> {
+        request(|reply| Command::Load(PNG_MIME_TYPES, reply))
+    }
+
+    pub fn write(contents: Contents) -> Result<(), AnyError> {
+        request(|reply| Command::Store(contents, reply))
+    }
+}
+
+/// X11 clipboard selections. winit 0.24 doesn't deliver selection events, so
+/// a connection of our own serves the selection on a background thread. The
+/// app's window is still the owner, which makes the X server drop the
+/// selection when that window goes away, as it would for any other app.
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use x11rb::CURRENT_TIME;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+    use x11rb::NONE;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::Event;
+    use x11rb::protocol::xproto::Atom;
+    use x11rb::protocol::xproto::AtomEnum;
+    use x11rb::protocol::xproto::ChangeWindowAttributesAux;
+    use x11rb::protocol::xproto::ConnectionExt as _;
+    use x11rb::protocol::xproto::CreateWindowAux;
+    use x11rb::protocol::xproto::EventMask;
+    use x11rb::protocol::xproto::PropMode;
+    use x11rb::protocol::xproto::Property;
+    use x11rb::protocol::xproto::SELECTION_NOTIFY_EVENT;
+    use x11rb::protocol::xproto::SelectionNotifyEvent;
+    use x11rb::protocol::xproto::SelectionRequestEvent;
+    use x11rb::protocol::xproto::Window;
+    use x11rb::protocol::xproto::WindowClass;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+
+    use super::CONTENTS;
+    use super::Contents;
+
+    /// How long to wait for the selection owner to answer.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    const POLL_INTERVAL: Duration = Duration::from_millis(5);
+    /// Size of the pieces of incremental (INCR) transfers.
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            CLIPBOARD,
+            TARGETS,
+            INCR,
+            TEXT,
+            UTF8_STRING,
+            TEXT_PLAIN_UTF8: b"text/plain;charset=utf-8",
+            IMAGE_PNG: b"image/png",
+            DENO_DESKTOP_CLIPBOARD,
+        }
+    }
+
+    impl Contents {
+        /// The targets this can be converted to, besides `TARGETS`.
+        fn targets(&self, atoms: &Atoms) -> Vec<Atom> {
+            match self {
+                Contents::Text(_) => vec![atoms.UTF8_STRING, atoms.TEXT_PLAIN_UTF8, atoms.TEXT],
+                Contents::Png(_) => vec![atoms.IMAGE_PNG],
+            }
+        }
+    }
+
+    /// An INCR transfer to a requestor, continued each time it deletes the
+    /// property.
+    struct Transfer {
+        target: Atom,
+        data: Vec<u8>,
+        offset: usize,
+    }
+
+    struct Owner {
+        conn: RustConnection,
+        atoms: Atoms,
+        /// The window we last took the selection for.
+        window: Mutex<Option<Window>>,
+    }
+
+    lazy_static! {
+        static ref OWNER: Mutex<Option<Arc<Owner>>> = Mutex::new(None);
+    }
+
+    impl Owner {
+        fn serve(&self) {
+            let mut transfers: HashMap<(Window, Atom), Transfer> = HashMap::new();
+            while let Ok(event) = self.conn.wait_for_event() {
+                // A requestor going away mid-transfer only affects that
+                // transfer.
+                let _ = match event {
+                    Event::SelectionRequest(request) => self.respond(&request, &mut transfers),
+                    Event::SelectionClear(clear) if clear.selection == self.atoms.CLIPBOARD => {
+                        *CONTENTS.lock().unwrap() = None;
+                        *self.window.lock().unwrap() = None;
+                        Ok(())
+                    }
+                    Event::PropertyNotify(notify) if notify.state == Property::DELETE => {
+                        self.continue_transfer(notify.window, notify.atom, &mut transfers)
+                    }
+                    _ => Ok(()),
+                };
+            }
+        }
+
+        fn respond(
+            &self,
+            request: &SelectionRequestEvent,
+            transfers: &mut HashMap<(Window, Atom), Transfer>,
+        ) -> Result<(), AnyError> {
+            let atoms = &self.atoms;
+            // Obsolete clients leave the property unset and expect the target.
+            let property = if request.property == NONE { request.target } else { request.property };
+            let contents = CONTENTS.lock().unwrap().clone();
+
+            let converted = match contents {
+                Some(contents) if request.selection == atoms.CLIPBOARD => {
+                    let targets = contents.targets(atoms);
+                    if request.target == atoms.TARGETS {
+                        let mut all = vec![atoms.TARGETS];
+                        all.extend(targets);
+                        self.conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &all)?;
+                        true
+                    } else if targets.contains(&request.target) {
+                        let data = contents.data();
+                        if data.len() > self.conn.maximum_request_bytes() - 32 {
+                            // Too big for one request, so send it in pieces
+                            // as the requestor deletes the property.
+                            self.conn.change_window_attributes(
+                                request.requestor,
+                                &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                            )?;
+                            self.conn.change_property32(
+                                PropMode::REPLACE,
+                                request.requestor,
+                                property,
+                                atoms.INCR,
+                                &[data.len() as u32],
+                            )?;
+                            transfers.insert((request.requestor, property), Transfer {
+                                target: request.target,
+                                data: data.to_vec(),
+                                offset: 0,
+                            });
+                        } else {
+                            self.conn.change_property8(PropMode::REPLACE, request.requestor, property, request.target, data)?;
+                        }
+                        true
+                    } else {
+                        false
+                    }
+                }
+                _ => false,
+            };
+
+            let notify = SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if converted { property } else { NONE },
+            };
+            self.conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)?;
+            self.conn.flush()?;
+            Ok(())
+        }
+
+        fn continue_transfer(
+            &self,
+            requestor: Window,
+            property: Atom,
+            transfers: &mut HashMap<(Window, Atom), Transfer>,
+        ) -> Result<(), AnyError> {
+            let transfer = match transfers.get_mut(&(requestor, property)) {
+                Some(transfer) => transfer,
+                None => return Ok(()),
+            };
+            let end = (transfer.offset + CHUNK_SIZE).min(transfer.data.len());
+            let chunk = &transfer.data[transfer.offset..end];
+            // The empty piece after the data ends the transfer.
+            let done = chunk.is_empty();
+            let result = self.conn.change_property8(PropMode::REPLACE, requestor, property, transfer.target, chunk);
+            transfer.offset = end;
+            if done || result.is_err() {
+                transfers.remove(&(requestor, property));
+            }
+            result?;
+            self.conn.flush()?;
+            Ok(())
+        }
+    }
+
+    fn owner() -> Result<Arc<Owner>, AnyError> {
+        let mut owner = OWNER.lock().unwrap();
+        if let Some(owner) = &*owner {
+            return Ok(owner.clone());
+        }
+        let (conn, _) = x11rb::connect(None)?;
+        let atoms = Atoms::new(&conn)?.reply()?;
+        let new = Arc::new(Owner { conn, atoms, window: Mutex::new(None) });
+        let serving = new.clone();
+        std::thread::spawn(move || serving.serve());
+        *owner = Some(new.clone());
+        Ok(new)
+    }
+
+    /// Makes `window` the clipboard owner, handing out `contents`. The X
+    /// server sends requests for the selection to the connection that took
+    /// it, which is the owner's rather than winit's.
+    pub fn write(window: Window, contents: Contents) -> Result<(), AnyError> {
+        let owner = owner()?;
+        *CONTENTS.lock().unwrap() = Some(contents);
+        owner.conn.set_selection_owner(window, owner.atoms.CLIPBOARD, CURRENT_TIME)?;
+        if owner.conn.get_selection_owner(owner.atoms.CLIPBOARD)?.reply()?.owner != window {
+            *CONTENTS.lock().unwrap() = None;
+            return Err(custom_error("PermissionDenied", "Couldn't take ownership of the clipboard"));
+        }
+        *owner.window.lock().unwrap() = Some(window);
+        Ok(())
+    }
+
+    /// Our own contents, if we're still the owner. Closing the owner window
+    /// drops the selection without a `SelectionClear`, so this asks the
+    /// server.
+    fn own_contents(conn: &RustConnection, atoms: &Atoms) -> Result<Option<Contents>, AnyError> {
+        let owner = match &*OWNER.lock().unwrap() {
+            Some(owner) => *owner.window.lock().unwrap(),
+            None => None,
+        };
+        match owner {
+            Some(window) if conn.get_selection_owner(atoms.CLIPBOARD)?.reply()?.owner == window => {
+                Ok(CONTENTS.lock().unwrap().clone())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Waits for an event `f` picks, polling so that a stuck owner can't hang
+    /// the read.
+    fn wait_for<T>(conn: &RustConnection, mut f: impl FnMut(Event) -> Option<T>) -> Result<T, AnyError> {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            while let Some(event) = conn.poll_for_event()? {
+                if let Some(value) = f(event) {
+                    return Ok(value);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(custom_error("TimedOut", "The clipboard owner didn't respond"));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Asks the clipboard owner for `target`, `None` if it can't provide it.
+    fn convert(conn: &RustConnection, window: Window, atoms: &Atoms, target: Atom) -> Result<Option<Vec<u8>>, AnyError> {
+        let property = atoms.DENO_DESKTOP_CLIPBOARD;
+        conn.convert_selection(window, atoms.CLIPBOARD, target, property, CURRENT_TIME)?;
+        conn.flush()?;
+
+        let notify = wait_for(conn, |event| match event {
+            Event::SelectionNotify(notify) if notify.requestor == window => Some(notify),
+            _ => None,
+        })?;
+        if notify.property == NONE {
+            return Ok(None);
+        }
+
+        let reply = conn.get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX)?.reply()?;
+        if reply.type_ != atoms.INCR {
+            return Ok(Some(reply.value));
+        }
+
+        // Deleting the property above asked for the first piece.
+        let mut data = Vec::new();
+        loop {
+            wait_for(conn, |event| match event {
+                Event::PropertyNotify(notify)
+                    if notify.window == window && notify.atom == property && notify.state == Property::NEW_VALUE =>
+                {
+                    Some(())
+                }
+                _ => None,
+            })?;
+            let reply = conn.get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX)?.reply()?;
+            if reply.value.is_empty() {
+                return Ok(Some(data));
+            }
+            data.extend(reply.value);
+        }
+    }
+
+    /// Reads the first of `targets` the clipboard has, turned into a `T` by
+    /// `from_data`. `from_own` handles the case where we own the clipboard.
+    fn read<T>(
+        targets: impl Fn(&Atoms) -> Vec<Atom>,
+        from_own: impl FnOnce(Contents) -> Option<T>,
+        from_data: impl FnOnce(Atom, Vec<u8>) -> T,
+    ) -> Result<Option<T>, AnyError> {
+        // A connection of our own, so that its events can be waited for.
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let atoms = Atoms::new(&conn)?.reply()?;
+        if let Some(contents) = own_contents(&conn, &atoms)? {
+            return Ok(from_own(contents));
+        }
+        if conn.get_selection_owner(atoms.CLIPBOARD)?.reply()?.owner == NONE {
+            return Ok(None);
+        }
+
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+
+        let mut result = None;
+        for target in targets(&atoms) {
+            if let Some(data) = convert(&conn, window, &atoms, target)? {
+                result = Some(from_data(target, data));
+                break;
+            }
+        }
+        conn.destroy_window(window)?;
+        conn.flush()?;
+        Ok(result)
+    }
+
+    pub fn read_text() -> Result<String, AnyError> {
+        let text = read(
+            |atoms| vec![atoms.UTF8_STRING, atoms.TEXT_PLAIN_UTF8, AtomEnum::STRING.into()],
+            |contents| match contents {
+                Contents::Text(text) => Some(text),
+                Contents::Png(_) => None,
+            },
+            |target, data| {
+                if target == Atom::from(AtomEnum::STRING) {
+                    // STRING is Latin-1.
+                    data.iter().map(|&byte| byte as char).collect()
+                } else {
+                    String::from_utf8_lossy(&data).into_owned()
+                }
+            },
+        )?;
+        Ok(text.unwrap_or_default())
+    }
+
+    pub fn read_png() -> Result<Option<Vec<u8>>, AnyError> {
+        read(
+            |atoms| vec![atoms.IMAGE_PNG],
+            |contents| match contents {
+                Contents::Png(png) => Some(png),
+                Contents::Text(_) => None,
+            },
+            |_, data| data,
+        )
+    }
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_clipboard_read_text", op_async(op_clipboard_read_text)),
+            ("op_clipboard_write_text", op_sync(op_clipboard_write_text)),
+            ("op_clipboard_read_image", op_async(op_clipboard_read_image)),
+            ("op_clipboard_write_image", op_sync(op_clipboard_write_image)),
+        ])
+        .build()
+}
@@ -0,0 +1,24 @@
+use std::time::Instant;
+
+lazy_static! {
+    static ref ORIGIN: Instant = Instant::now();
+}
+
+/// Pins the clock origin. Call this as early as possible so that timestamps
+/// are never taken relative to a later first use.
+pub fn init() {
+    lazy_static::initialize(&ORIGIN);
+}
+
+/// Milliseconds between the clock origin and `instant`. Instants from before
+/// the origin are reported as negative values.
+pub fn millis(instant: Instant) -> f64 {
+    match instant.checked_duration_since(*ORIGIN) {
+        Some(elapsed) => elapsed.as_secs_f64() * 1000.0,
+        None => -(ORIGIN.duration_since(instant).as_secs_f64() * 1000.0),
+    }
+}
+
+pub fn now() -> f64 {
+    millis(Instant::now())
+}
@@ -1,19 +1,532 @@
-Deno.nextEvent = function nextEvent() {
-  const promise = Deno.core.opAsync("op_next_event");
+// Events are stamped on the runtime's own monotonic clock. Measure its offset
+// once so timestamps can be reported on the `performance.now()` timeline.
+const eventClockOffset = performance.now() - Deno.core.opSync("op_event_loop_now");
+
+function toPerformanceTime(time) {
+  return time + eventClockOffset;
+}
+
+function adjustEventTimes(event) {
+  if (event === null) {
+    return event;
+  }
+  event.timestamp = toPerformanceTime(event.timestamp);
+  if (event.gamepad) {
+    event.gamepad.timestamp = toPerformanceTime(event.gamepad.timestamp);
+  }
+  if (event.type === "newEvents") {
+    const cause = event.cause;
+    if (cause.start !== undefined) {
+      cause.start = toPerformanceTime(cause.start);
+    }
+    if (cause.requestedResume != null) {
+      cause.requestedResume = toPerformanceTime(cause.requestedResume);
+    }
+  }
+  return event;
+}
+
+// The runtime releases pointer lock by itself when the window loses focus,
+// but only the window's owner can show the cursor again.
+function restoreCursorOnUnlock(event) {
+  if (event?.event?.type === "pointerlockchange" && !event.event.locked) {
+    try {
+      Deno.core.opSync("op_window_exit_pointer_lock", { id: event.windowID });
+    } catch {
+      // The window is already closed.
+    }
+  }
+  return event;
+}
+
+function nextEventFrom(op, arg) {
+  const promise = Deno.core.opAsync(op, arg);
   // Unref the op so that it does not keep event loop alive.
   Deno.core.unrefOp(promise[Symbol.for("Deno.core.internalPromiseId")]);
-  return promise;
+  return promise.then(adjustEventTimes).then(restoreCursorOnUnlock);
+}
+
+Deno.nextEvent = function nextEvent() {
+  return nextEventFrom("op_next_event");
+}
+
+Deno.setEventFilter = function setEventFilter(filter = {}) {
+  Deno.core.opSync("op_set_event_filter", filter);
+};
+
+// Windows with an active composition, used to turn `ime` window events into
+// web-style `compositionstart`, `compositionupdate` and `compositionend`.
+const composingWindows = new Set();
+
+function compositionEvent(event, type, data, cursorRange = null) {
+  return {
+    type: "windowEvent",
+    windowID: event.windowID,
+    timestamp: event.timestamp,
+    event: { type, data, cursorRange },
+  };
+}
+
+function* compositionEventsFor(event) {
+  if (event.type !== "windowEvent" || event.event.type !== "ime") {
+    return;
+  }
+
+  const { kind, text, cursorRange } = event.event;
+  const composing = composingWindows.has(event.windowID);
+
+  if (kind === "preedit") {
+    // Input methods clear the preedit right before committing, so an empty
+    // preedit only updates the composition, it is the commit that ends it.
+    if (!composing && text) {
+      composingWindows.add(event.windowID);
+      yield compositionEvent(event, "compositionstart", "");
+    }
+    if (composing || text) {
+      yield compositionEvent(event, "compositionupdate", text, cursorRange);
+    }
+  } else if (kind === "commit" && composing) {
+    composingWindows.delete(event.windowID);
+    yield compositionEvent(event, "compositionend", text);
+  } else if (kind === "disabled" && composing) {
+    composingWindows.delete(event.windowID);
+    yield compositionEvent(event, "compositionend", "");
+  }
 }
 
 Deno.eventLoop = async function* eventLoop() {
   let event;
   while ((event = (await Deno.nextEvent()))) {
     yield event;
+    yield* compositionEventsFor(event);
   }
 };
 
-Deno.createWindow = function createWindow(options) {
-  return new WinitWindow(Deno.core.opSync("op_create_window", options));
+// Shortcuts fire `{ type: "shortcut", id, windowID }` events instead of the
+// key events they are made of.
+Deno.registerShortcut = function registerShortcut(id, accelerator) {
+  Deno.core.opSync("op_register_shortcut", { id, accelerator });
+};
+
+Deno.unregisterShortcut = function unregisterShortcut(id) {
+  return Deno.core.opSync("op_unregister_shortcut", id);
+};
+
+// Promise based like the web's async clipboard API. Images are
+// `{ width, height, data }`, with `data` holding RGBA pixels, or PNG data when
+// reading with `{ format: "png" }`. The `window` option says which window the
+// copy or paste is for, by default the focused one.
+navigator.clipboard = {
+  readText({ window } = {}) {
+    return Deno.core.opAsync("op_clipboard_read_text", window?.rid ?? null);
+  },
+
+  async writeText(text, { window } = {}) {
+    Deno.core.opSync("op_clipboard_write_text", [String(text), window?.rid ?? null]);
+  },
+
+  // Resolves to `null` when there is no image on the clipboard.
+  readImage({ format = "rgba", window } = {}) {
+    return Deno.core.opAsync("op_clipboard_read_image", [format, window?.rid ?? null]);
+  },
+
+  // Takes an RGBA image or PNG data as a `Uint8Array`.
+  async writeImage(image, { window } = {}) {
+    const rid = window?.rid ?? null;
+    if (image instanceof Uint8Array) {
+      Deno.core.opSync("op_clipboard_write_image", [null, rid], image);
+    } else {
+      Deno.core.opSync("op_clipboard_write_image", [[image.width, image.height], rid], image.data);
+    }
+  },
+};
+
+// `alert`, `confirm` and `prompt` show modal message boxes and return
+// promises, instead of blocking on the terminal. A box belongs to the last
+// focused window, or to the window whose `alert()` etc. was called. Where
+// message boxes can't be shown, they still ask on the terminal.
+const terminalDialogs = {
+  alert: globalThis.alert,
+  confirm: globalThis.confirm,
+  prompt: globalThis.prompt,
+};
+
+async function showMessageBox(kind, message, defaultValue, window) {
+  const answer = await Deno.core.opAsync("op_show_message_box", {
+    kind,
+    message: String(message ?? ""),
+    defaultValue: defaultValue === undefined ? "" : String(defaultValue),
+    window: window?.rid ?? null,
+  });
+  if (answer === null) {
+    return terminalDialogs[kind](message, defaultValue);
+  }
+  return kind === "alert" ? undefined : answer.value;
+}
+
+globalThis.alert = function alert(message) {
+  return showMessageBox("alert", message);
+};
+
+globalThis.confirm = function confirm(message) {
+  return showMessageBox("confirm", message);
+};
+
+globalThis.prompt = function prompt(message, defaultValue) {
+  return showMessageBox("prompt", message, defaultValue);
+};
+
+// Notifications showing, by ID. `notificationclick` and `notificationclose`
+// events are also fired at their `Notification` objects as `click` and
+// `close`, whether or not the app takes events with `Deno.nextEvent()`.
+const notifications = new Map();
+let notificationPermission = "default";
+let notificationPump = null;
+
+class NotificationEvent extends Event {
+  #action;
+
+  constructor(type, action = "") {
+    super(type);
+    this.#action = action;
+  }
+
+  // The `action` of the button clicked, empty for the notification itself.
+  get action() {
+    return this.#action;
+  }
+}
+
+function fireNotificationEvent(notification, event) {
+  notification.dispatchEvent(event);
+  notification[`on${event.type}`]?.call(notification, event);
+}
+
+function dispatchNotificationEvent(event) {
+  const notification = notifications.get(event.notificationID);
+  if (event.type === "notificationclick" && notification) {
+    fireNotificationEvent(notification, new NotificationEvent("click", event.action));
+  } else if (event.type === "notificationclose" && notification) {
+    notifications.delete(event.notificationID);
+    fireNotificationEvent(notification, new NotificationEvent("close"));
+  }
+}
+
+// Takes clicks and closes for as long as any notification is showing. Like
+// `Deno.nextEvent()`, this doesn't keep the event loop alive.
+function pumpNotificationEvents() {
+  if (notificationPump) {
+    return;
+  }
+  notificationPump = (async () => {
+    while (notifications.size > 0) {
+      const promise = Deno.core.opAsync("op_notification_next_event");
+      Deno.core.unrefOp(promise[Symbol.for("Deno.core.internalPromiseId")]);
+      dispatchNotificationEvent(await promise);
+    }
+    notificationPump = null;
+  })();
+}
+
+// The web's `Notification`, shown through the desktop's notification server.
+// There is nothing to ask the user, `requestPermission()` grants permission
+// whenever a server is running.
+class Notification extends EventTarget {
+  #id = null;
+  #closed = false;
+  #title;
+  #options;
+
+  onclick = null;
+  onclose = null;
+  onshow = null;
+  onerror = null;
+
+  static get permission() {
+    return notificationPermission;
+  }
+
+  static async requestPermission(callback) {
+    notificationPermission = await Deno.core.opAsync("op_notification_request_permission");
+    callback?.(notificationPermission);
+    return notificationPermission;
+  }
+
+  constructor(title, options = {}) {
+    super();
+    this.#title = String(title);
+    this.#options = {
+      body: String(options.body ?? ""),
+      icon: String(options.icon ?? ""),
+      tag: String(options.tag ?? ""),
+      data: options.data ?? null,
+      actions: (options.actions ?? []).map(({ action, title }) => ({ action: String(action), title: String(title) })),
+      silent: Boolean(options.silent),
+      requireInteraction: Boolean(options.requireInteraction),
+    };
+
+    if (notificationPermission === "denied") {
+      queueMicrotask(() => fireNotificationEvent(this, new Event("error")));
+      return;
+    }
+
+    // A notification with the same tag takes the place of the old one.
+    let replaces = null;
+    for (const [id, notification] of notifications) {
+      if (this.tag && notification.tag === this.tag) {
+        replaces = id;
+      }
+    }
+
+    Deno.core.opAsync("op_notification_show", {
+      title: this.#title,
+      body: this.body,
+      icon: this.icon || null,
+      actions: this.actions,
+      silent: this.silent,
+      requireInteraction: this.requireInteraction,
+      replaces,
+    }).then((id) => {
+      this.#id = id;
+      notifications.set(id, this);
+      pumpNotificationEvents();
+      if (this.#closed) {
+        Deno.core.opAsync("op_notification_close", id);
+      } else {
+        fireNotificationEvent(this, new Event("show"));
+      }
+    }, () => fireNotificationEvent(this, new Event("error")));
+  }
+
+  get title() {
+    return this.#title;
+  }
+
+  get body() {
+    return this.#options.body;
+  }
+
+  get icon() {
+    return this.#options.icon;
+  }
+
+  get tag() {
+    return this.#options.tag;
+  }
+
+  get data() {
+    return this.#options.data;
+  }
+
+  get actions() {
+    return this.#options.actions.map((action) => ({ ...action }));
+  }
+
+  get silent() {
+    return this.#options.silent;
+  }
+
+  get requireInteraction() {
+    return this.#options.requireInteraction;
+  }
+
+  close() {
+    if (this.#closed) {
+      return;
+    }
+    this.#closed = true;
+    if (this.#id !== null) {
+      Deno.core.opAsync("op_notification_close", this.#id);
+    }
+  }
+}
+
+globalThis.Notification = Notification;
+
+// An icon in the desktop's tray, made with `Deno.desktop.createTrayIcon()`.
+// Closing it removes it from the tray.
+class TrayIcon {
+  #id;
+  #rid;
+
+  constructor([id, rid]) {
+    this.#id = id;
+    this.#rid = rid;
+  }
+
+  get id() {
+    return this.#id;
+  }
+
+  get rid() {
+    return this.#rid;
+  }
+
+  // `{ data, width, height }` with RGBA pixels, like `WinitWindow.setIcon`.
+  setIcon({ data, width, height }) {
+    Deno.core.opSync("op_tray_icon_set_icon", [this.#rid, { width, height }], data);
+  }
+
+  setTitle(title) {
+    Deno.core.opSync("op_tray_icon_set_title", [this.#rid, String(title)]);
+  }
+
+  setTooltip(tooltip) {
+    Deno.core.opSync("op_tray_icon_set_tooltip", [this.#rid, String(tooltip)]);
+  }
+
+  setMenu(menu) {
+    Deno.core.opSync("op_tray_icon_set_menu", [this.#rid, menu]);
+  }
+
+  close() {
+    Deno.close(this.#rid);
+  }
+}
+
+// Desktop integration that has no web equivalent.
+Deno.desktop = {
+  // File dialogs, shown by the desktop's file chooser portal. `filters` are
+  // `{ name, extensions, mimeTypes }`. Passing `window` makes the dialog
+  // modal to it.
+  showOpenDialog({ window, title = "Open", filters = [], multiple = false, directory = false, acceptLabel } = {}) {
+    return Deno.core.opAsync("op_show_open_dialog", {
+      window: window?.rid ?? null,
+      title,
+      filters,
+      multiple,
+      directory,
+      acceptLabel: acceptLabel ?? null,
+    });
+  },
+
+  showSaveDialog({ window, title = "Save As", filters = [], defaultName, defaultFolder, acceptLabel } = {}) {
+    return Deno.core.opAsync("op_show_save_dialog", {
+      window: window?.rid ?? null,
+      title,
+      filters,
+      defaultName: defaultName ?? null,
+      defaultFolder: defaultFolder ?? null,
+      acceptLabel: acceptLabel ?? null,
+    });
+  },
+
+  // Shows a menu at `x` and `y` in `window`, or at the cursor without them.
+  // `menu` is a list of `{ id, label, type, accelerator, enabled, checked,
+  // submenu }` items, `type` being "normal", "separator", "checkbox" or
+  // "radio". Resolves to the ID of the chosen item, or null if dismissed.
+  // Choosing an item also queues a `menuItemActivated` event.
+  showContextMenu(menu, { window, x, y }) {
+    return Deno.core.opAsync("op_show_context_menu", [menu, { window: window.rid, x: x ?? null, y: y ?? null }]);
+  },
+
+  // Resolves to a `TrayIcon` once the tray shows it. `menu` is a template
+  // like `showContextMenu` takes. Clicks come out of `Deno.eventLoop()` as
+  // `trayIconClicked` and `trayIconScrolled` events, and picking a menu item
+  // as `menuItemActivated`.
+  async createTrayIcon({ icon, title = "", tooltip = "", menu = [] } = {}) {
+    const args = { icon: icon ? { width: icon.width, height: icon.height } : null, title, tooltip, menu };
+    return new TrayIcon(await Deno.core.opAsync("op_create_tray_icon", args, icon?.data ?? null));
+  },
+};
+
+// Snapshots of the connected gamepads, indexed by `gamepad.index`. Like in
+// browsers they don't update in place, call this again every frame.
+navigator.getGamepads = function getGamepads() {
+  return Deno.core.opSync("op_gamepads").map((gamepad) => {
+    if (gamepad !== null) {
+      gamepad.timestamp = toPerformanceTime(gamepad.timestamp);
+    }
+    return gamepad;
+  });
+};
+
+Deno.headless = {
+  // Queues an event for `Deno.eventLoop()`. When running with `--headless`
+  // the loop ends once the queue is empty.
+  pushEvent(event) {
+    Deno.core.opSync("op_headless_push_event", event);
+  },
+
+  getWindowState(window) {
+    return Deno.core.opSync("op_headless_window_state", window.rid);
+  },
+
+  // Simulates moving `window` to a display with another scale factor.
+  setScaleFactor(window, scaleFactor) {
+    Deno.core.opSync("op_headless_set_scale_factor", [window.rid, scaleFactor]);
+  },
+
+  // Drags `paths` onto `window` and drops them at the cursor.
+  dropFiles(window, paths) {
+    Deno.core.opSync("op_headless_drop_files", [window.rid, paths]);
+  },
+
+  // A virtual gamepad with the standard mapping. Returns its index.
+  connectGamepad(id = "Virtual gamepad") {
+    return Deno.core.opSync("op_headless_connect_gamepad", id);
+  },
+
+  // `buttons` and `axes` are arrays of values in standard mapping order,
+  // missing entries keep their previous value.
+  updateGamepad(index, { buttons = [], axes = [] } = {}) {
+    Deno.core.opSync("op_headless_update_gamepad", [index, { buttons, axes }]);
+  },
+
+  disconnectGamepad(index) {
+    Deno.core.opSync("op_headless_disconnect_gamepad", index);
+  },
+
+  // Headless runs have their own notification server. These list the
+  // notifications showing, as `{ id, title, body, icon, actions, silent,
+  // requireInteraction }`, and act on them like a user would.
+  notifications() {
+    return Deno.core.opSync("op_headless_notifications");
+  },
+
+  // An empty `action` clicks the notification itself. Clicking dismisses it.
+  clickNotification(id, action = "") {
+    Deno.core.opSync("op_headless_click_notification", [id, action]);
+  },
+
+  dismissNotification(id) {
+    Deno.core.opSync("op_headless_dismiss_notification", id);
+  },
+
+  // Headless tray icons show nowhere, these stand in for the user.
+  clickTrayIcon(trayIcon, button = "primary") {
+    Deno.core.opSync("op_headless_click_tray_icon", [trayIcon.rid, button]);
+  },
+
+  activateTrayMenuItem(trayIcon, itemID) {
+    Deno.core.opSync("op_headless_activate_tray_menu_item", [trayIcon.rid, itemID]);
+  },
+
+  // Picks an item of the context menu open on `window`, or else of the
+  // window's menu.
+  activateMenuItem(window, itemID) {
+    Deno.core.opSync("op_headless_activate_menu_item", [window.rid, itemID]);
+  },
+};
+
+// Each monitor has `name`, `position`, `size`, `scaleFactor`, `primary` and
+// the `videoModes` it supports for exclusive fullscreen.
+Deno.monitors = function monitors() {
+  return Deno.core.opSync("op_monitors");
+};
+
+Deno.primaryMonitor = function primaryMonitor() {
+  return Deno.core.opSync("op_primary_monitor");
+};
+
+Deno.createWindow = function createWindow(options = {}) {
+  // The icon's pixels travel as the op's buffer, like in `setIcon`.
+  const { icon, parent, ...rest } = options;
+  const args = { ...rest, parent: parent?.rid ?? null };
+  if (icon) {
+    args.icon = { width: icon.width, height: icon.height };
+  }
+  return new WinitWindow(Deno.core.opSync("op_create_window", args, icon?.data ?? null));
 };
 
 let symbolCache = {};
@@ -67,12 +580,14 @@ class GPUCanvasContext {
   }
 
   configure(options = {}) {
+    // Surfaces are sized in physical pixels whatever the window's units.
+    const scale = this.#window.units === "logical" ? this.#window.getScaleFactor() : 1;
     Deno.core.opSync("op_webgpu_configure_surface", {
       surfaceRid: this.#rid,
       deviceRid: this.#rids.device,
       format: options.format ?? this.getPreferredFormat(),
-      width: options.width ?? this.#window.width,
-      height: options.height ?? this.#window.height,
+      width: options.width ?? Math.round(this.#window.width * scale),
+      height: options.height ?? Math.round(this.#window.height * scale),
       usage: options.usage ?? GPUTextureUsage.RENDER_ATTACHMENT,
     });
     this.#currentTexture = null;
@@ -125,6 +640,16 @@ class WinitWindow {
     return this.#id;
   }
 
+  // "physical" (the default) or "logical": the units sizes and positions
+  // are given and returned in.
+  get units() {
+    return Deno.core.opSync("op_window_units", this.#rid);
+  }
+
+  setUnits(units) {
+    Deno.core.opSync("op_window_set_units", [this.#rid, units]);
+  }
+
   get width() {
     return this.getSize().width;
   }
@@ -137,10 +662,46 @@ class WinitWindow {
     Deno.core.opSync("op_window_request_redraw", this.#rid);
   }
 
+  registerShortcut(id, accelerator) {
+    Deno.core.opSync("op_register_shortcut", { id, accelerator, window: this.#rid });
+  }
+
+  // The window's menu, a template like `Deno.desktop.showContextMenu` takes,
+  // or null to remove it. It shows as a bar over the top of the window, which
+  // clicks and F10 open, and the accelerators of its items work while the
+  // window is focused.
+  setMenu(menu) {
+    Deno.core.opSync("op_window_set_menu", [this.#rid, menu ?? null]);
+  }
+
+  requestPointerLock() {
+    Deno.core.opSync("op_window_request_pointer_lock", this.#rid);
+  }
+
+  exitPointerLock() {
+    Deno.core.opSync("op_window_exit_pointer_lock", this.#rid);
+  }
+
+  alert(message) {
+    return showMessageBox("alert", message, undefined, this);
+  }
+
+  confirm(message) {
+    return showMessageBox("confirm", message, undefined, this);
+  }
+
+  prompt(message, defaultValue) {
+    return showMessageBox("prompt", message, defaultValue, this);
+  }
+
   requestUserAttention(type) {
     Deno.core.opSync("op_window_request_user_attention", [this.#rid, type ?? null]);
   }
 
+  currentMonitor() {
+    return Deno.core.opSync("op_window_current_monitor", this.#rid);
+  }
+
   getFullscreen() {
     return Deno.core.opSync("op_window_fullscreen", this.#rid);
   }
@@ -149,6 +710,10 @@ class WinitWindow {
     return Deno.core.opSync("op_window_inner_position", this.#rid);
   }
 
+  getPosition() {
+    return Deno.core.opSync("op_window_outer_position", this.#rid);
+  }
+
   getSize() {
     return Deno.core.opSync("op_window_inner_size", this.#rid);
   }
@@ -169,10 +734,15 @@ class WinitWindow {
     Deno.core.opSync("op_window_set_cursor_grab", [this.#rid, value]);
   }
 
+  // `icon` is a CSS cursor name such as "text", "grab" or "ew-resize".
   setCursorIcon(icon) {
     Deno.core.opSync("op_window_set_cursor_icon", [this.#rid, icon]);
   }
 
+  setCustomCursor({ data, width, height, hotspotX = 0, hotspotY = 0 }) {
+    Deno.core.opSync("op_window_set_custom_cursor", [this.#rid, width, height, hotspotX, hotspotY], data);
+  }
+
   setCursorPosition(pos) {
     Deno.core.opSync("op_window_set_cursor_position", [this.#rid, pos]);
   }
@@ -185,8 +755,16 @@ class WinitWindow {
     Deno.core.opSync("op_window_set_decorations", [this.#rid, value]);
   }
 
+  // `fullscreen` is `false`, `true` (borderless on the current monitor) or
+  // `{ mode: "borderless" | "exclusive", monitor, videoMode }`.
   setFullscreen(fullscreen) {
-    Deno.core.opSync("op_window_set_fullscreen", [this.#rid, fullscreen]);
+    return Deno.core.opSync("op_window_set_fullscreen", [this.#rid, fullscreen ?? null]);
+  }
+
+  // Lets the input method compose text for the window. Its `ime` events come
+  // with `compositionstart`, `compositionupdate` and `compositionend`.
+  setImeAllowed(allowed) {
+    Deno.core.opSync("op_window_set_ime_allowed", [this.#rid, allowed]);
   }
 
   setImePosition(pos) {
@@ -197,16 +775,17 @@ class WinitWindow {
     Deno.core.opSync("op_window_set_inner_size", [this.#rid, size]);
   }
 
-  setMaxSize({ width, height }) {
-    Deno.core.opSync("op_window_set_max_inner_size", [this.#rid, width, height]);
+  // `null` removes the limit.
+  setMaxSize(size) {
+    Deno.core.opSync("op_window_set_max_inner_size", [this.#rid, size ?? null]);
   }
 
   setMaximized(value) {
     Deno.core.opSync("op_window_set_maximized", [this.#rid, value]);
   }
 
-  setMinSize({ width, height }) {
-    Deno.core.opSync("op_window_set_min_inner_size", [this.#rid, width, height]);
+  setMinSize(size) {
+    Deno.core.opSync("op_window_set_min_inner_size", [this.#rid, size ?? null]);
   }
 
   setMinimized(value) {
@@ -233,6 +812,21 @@ class WinitWindow {
     Deno.core.opSync("op_window_set_window_icon", [this.#rid, width, height], data);
   }
 
+  // Only this window's `windowEvent`s and `redrawRequested`, which stop
+  // showing up in `Deno.eventLoop()` while the iterator is running.
+  async *events() {
+    const id = Deno.core.opSync("op_window_subscribe_events", this.#rid);
+    try {
+      let event;
+      while ((event = (await nextEventFrom("op_window_next_event", id)))) {
+        yield event;
+        yield* compositionEventsFor(event);
+      }
+    } finally {
+      Deno.core.opSync("op_window_unsubscribe_events", id);
+    }
+  }
+
   createSurface(device) {
     const rid = Deno.core.opSync("op_webgpu_create_surface", { windowRid: this.#rid });
     const adapter = device[getSymbolOf(device, "[[device]]")].adapter;
@@ -242,8 +836,9 @@ class WinitWindow {
     }, device);
   }
 
+  // Also closes the windows it owns.
   close() {
-    Deno.close(this.rid);
+    Deno.core.opSync("op_window_close", this.#rid);
   }
 }
 
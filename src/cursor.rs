@@ -0,0 +1,206 @@
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::type_error;
+use winit_main::reexports::window::CursorIcon;
+
+/// Maps a CSS `cursor` keyword to winit's cursor icon, or `None` for `"none"`,
+/// which hides the cursor.
+pub fn parse_cursor_icon(name: &str) -> Result<Option<CursorIcon>, AnyError> {
+    Ok(Some(match name {
+        "auto" | "default" => CursorIcon::Default,
+        "context-menu" => CursorIcon::ContextMenu,
+        "help" => CursorIcon::Help,
+        "pointer" => CursorIcon::Hand,
+        "progress" => CursorIcon::Progress,
+        "wait" => CursorIcon::Wait,
+        "cell" => CursorIcon::Cell,
+        "crosshair" => CursorIcon::Crosshair,
+        "text" => CursorIcon::Text,
+        "vertical-text" => CursorIcon::VerticalText,
+        "alias" => CursorIcon::Alias,
+        "copy" => CursorIcon::Copy,
+        "move" => CursorIcon::Move,
+        "no-drop" => CursorIcon::NoDrop,
+        "not-allowed" => CursorIcon::NotAllowed,
+        "grab" => CursorIcon::Grab,
+        "grabbing" => CursorIcon::Grabbing,
+        "all-scroll" => CursorIcon::AllScroll,
+        "col-resize" => CursorIcon::ColResize,
+        "row-resize" => CursorIcon::RowResize,
+        "n-resize" => CursorIcon::NResize,
+        "e-resize" => CursorIcon::EResize,
+        "s-resize" => CursorIcon::SResize,
+        "w-resize" => CursorIcon::WResize,
+        "ne-resize" => CursorIcon::NeResize,
+        "nw-resize" => CursorIcon::NwResize,
+        "se-resize" => CursorIcon::SeResize,
+        "sw-resize" => CursorIcon::SwResize,
+        "ew-resize" => CursorIcon::EwResize,
+        "ns-resize" => CursorIcon::NsResize,
+        "nesw-resize" => CursorIcon::NeswResize,
+        "nwse-resize" => CursorIcon::NwseResize,
+        "zoom-in" => CursorIcon::ZoomIn,
+        "zoom-out" => CursorIcon::ZoomOut,
+        "none" => return Ok(None),
+        _ => return Err(type_error(format!("Unknown cursor: {:?}", name))),
+    }))
+}
+
+/// Largest cursor image, in bytes. X11 sends the image in a single
+/// `PutImage` request, and servers without BIG-REQUESTS take requests of up
+/// to 65535 words, 6 of which are the request header.
+const MAX_CURSOR_BYTES: usize = (u16::MAX as usize - 6) * 4;
+
+/// Checks the size of an RGBA cursor image and that its hotspot is inside it.
+pub fn validate_cursor_image(rgba: &[u8], width: u32, height: u32, hotspot: (u32, u32)) -> Result<(), AnyError> {
+    if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(type_error("Invalid cursor size"));
+    }
+    let len = match (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(4)) {
+        Some(len) if len <= MAX_CURSOR_BYTES => len,
+        _ => return Err(type_error(format!("A {}x{} cursor is too large", width, height))),
+    };
+    if rgba.len() != len {
+        return Err(type_error(format!(
+            "Expected {} bytes of RGBA data for a {}x{} cursor, got {}",
+            len,
+            width,
+            height,
+            rgba.len()
+        )));
+    }
+    if hotspot.0 >= width || hotspot.1 >= height {
+        return Err(type_error("Cursor hotspot is outside of the image"));
+    }
+    Ok(())
+}
+
+/// Custom cursors use the X Render extension, since winit 0.24 only knows the
+/// system cursors.
+#[cfg(target_os = "linux")]
+pub mod x11 {
+    use std::sync::Mutex;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::generic_error;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::render::ConnectionExt as _;
+    use x11rb::protocol::render::CreatePictureAux;
+    use x11rb::protocol::render::PictType;
+    use x11rb::protocol::render::Pictformat;
+    use x11rb::protocol::xproto::ChangeWindowAttributesAux;
+    use x11rb::protocol::xproto::ConnectionExt as _;
+    use x11rb::protocol::xproto::CreateGCAux;
+    use x11rb::protocol::xproto::ImageFormat;
+    use x11rb::rust_connection::RustConnection;
+
+    use crate::x11::with_connection;
+
+    lazy_static! {
+        /// The ARGB32 format of the connection `with_connection` keeps open.
+        static ref ARGB32_FORMAT: Mutex<Option<Pictformat>> = Mutex::new(None);
+    }
+
+    /// The 32-bit ARGB picture format cursors are made from, looked up on the
+    /// first custom cursor.
+    fn argb32_format(conn: &RustConnection) -> Result<Pictformat, AnyError> {
+        let mut argb32 = ARGB32_FORMAT.lock().unwrap();
+        if argb32.is_none() {
+            *argb32 = Some(find_argb32_format(conn)?);
+        }
+        Ok(argb32.unwrap())
+    }
+
+    fn find_argb32_format(conn: &RustConnection) -> Result<Pictformat, AnyError> {
+        // The server expects a version check before any other Render request.
+        conn.render_query_version(0, 11)?.reply()?;
+        let formats = conn.render_query_pict_formats()?.reply()?;
+        formats
+            .formats
+            .iter()
+            .find(|format| {
+                let direct = &format.direct;
+                format.type_ == PictType::DIRECT
+                    && format.depth == 32
+                    && direct.alpha_shift == 24
+                    && direct.alpha_mask == 0xff
+                    && direct.red_shift == 16
+                    && direct.green_shift == 8
+                    && direct.blue_shift == 0
+            })
+            .map(|format| format.id)
+            .ok_or_else(|| generic_error("The X server has no ARGB32 picture format"))
+    }
+
+    /// Sets an RGBA image as the cursor of X11 window `window`. The image must
+    /// have been checked with `validate_cursor_image`.
+    pub fn set_cursor(window: u32, rgba: &[u8], width: u32, height: u32, hotspot: (u32, u32)) -> Result<(), AnyError> {
+        with_connection(|conn| {
+            let argb32 = argb32_format(conn)?;
+
+            // ARGB32 pixels are premultiplied and stored as little endian
+            // words, so BGRA in memory.
+            let mut data = Vec::with_capacity(rgba.len());
+            for pixel in rgba.chunks_exact(4) {
+                let alpha = pixel[3] as u32;
+                let premultiply = |channel: u8| ((channel as u32 * alpha + 127) / 255) as u8;
+                let bgra = [premultiply(pixel[2]), premultiply(pixel[1]), premultiply(pixel[0]), pixel[3]];
+                data.extend_from_slice(&bgra);
+            }
+
+            let (width, height) = (width as u16, height as u16);
+            let pixmap = conn.generate_id()?;
+            let gc = conn.generate_id()?;
+            let picture = conn.generate_id()?;
+            let cursor = conn.generate_id()?;
+
+            conn.create_pixmap(32, pixmap, window, width, height)?;
+            conn.create_gc(gc, pixmap, &CreateGCAux::new())?;
+            conn.put_image(ImageFormat::Z_PIXMAP, pixmap, gc, width, height, 0, 0, 0, 32, &data)?;
+            conn.render_create_picture(picture, pixmap, argb32, &CreatePictureAux::new())?;
+            conn.render_create_cursor(cursor, picture, hotspot.0 as u16, hotspot.1 as u16)?;
+            let result = conn.change_window_attributes(window, &ChangeWindowAttributesAux::new().cursor(cursor))?;
+
+            // The window keeps the cursor alive on its own.
+            conn.free_cursor(cursor)?;
+            conn.render_free_picture(picture)?;
+            conn.free_gc(gc)?;
+            conn.free_pixmap(pixmap)?;
+            result.check()?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_css_cursor_keywords() {
+        assert_eq!(parse_cursor_icon("auto").unwrap(), Some(CursorIcon::Default));
+        assert_eq!(parse_cursor_icon("pointer").unwrap(), Some(CursorIcon::Hand));
+        assert_eq!(parse_cursor_icon("nwse-resize").unwrap(), Some(CursorIcon::NwseResize));
+        assert_eq!(parse_cursor_icon("none").unwrap(), None);
+        assert!(parse_cursor_icon("Pointer").is_err());
+        assert!(parse_cursor_icon("hand").is_err());
+    }
+
+    #[test]
+    fn validates_cursor_images() {
+        assert!(validate_cursor_image(&[0; 16], 2, 2, (1, 1)).is_ok());
+        assert!(validate_cursor_image(&[0; 12], 2, 2, (0, 0)).is_err());
+        assert!(validate_cursor_image(&[0; 16], 2, 2, (2, 0)).is_err());
+        assert!(validate_cursor_image(&[], 0, 2, (0, 0)).is_err());
+        assert!(validate_cursor_image(&[], 65536, 1, (0, 0)).is_err());
+    }
+
+    #[test]
+    fn rejects_cursors_too_large_for_one_request() {
+        // Would overflow `width * height * 4` in 32 bits.
+        assert!(validate_cursor_image(&[], 65535, 65535, (0, 0)).is_err());
+        let rgba = vec![0; 256 * 256 * 4];
+        assert!(validate_cursor_image(&rgba, 256, 256, (0, 0)).is_err());
+        let rgba = vec![0; 256 * 255 * 4];
+        assert!(validate_cursor_image(&rgba, 256, 255, (0, 0)).is_ok());
+    }
+}
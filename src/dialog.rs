@@ -0,0 +1,869 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_async;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::Value;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::flags::Flags;
+use crate::window::WindowRef;
+
+pub use message_box::handle_event;
+pub use message_box::has_message_box;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileFilter {
+    name: String,
+    /// Without the dot, e.g. `"png"`.
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    mime_types: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenDialogArgs {
+    window: Option<WindowRef>,
+    title: String,
+    #[serde(default)]
+    filters: Vec<FileFilter>,
+    #[serde(default)]
+    multiple: bool,
+    #[serde(default)]
+    directory: bool,
+    accept_label: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveDialogArgs {
+    window: Option<WindowRef>,
+    title: String,
+    #[serde(default)]
+    filters: Vec<FileFilter>,
+    default_name: Option<String>,
+    default_folder: Option<String>,
+    accept_label: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageBoxKind {
+    Alert,
+    Confirm,
+    Prompt,
+}
+
+impl MessageBoxKind {
+    /// The answer when the box is cancelled, which is also what browsers that
+    /// don't show dialogs answer.
+    fn dismissed(self) -> Value {
+        match self {
+            MessageBoxKind::Confirm => Value::Bool(false),
+            MessageBoxKind::Alert | MessageBoxKind::Prompt => Value::Null,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageBoxArgs {
+    kind: MessageBoxKind,
+    message: String,
+    #[serde(default)]
+    default_value: String,
+    window: Option<WindowRef>,
+}
+
+#[derive(Serialize)]
+pub struct MessageBoxAnswer {
+    value: Value,
+}
+
+/// Resolves to `{ value }` with the user's answer, or `None` if message boxes
+/// can't be shown here, in which case JS falls back to the terminal.
+pub async fn op_show_message_box(
+    state: Rc<RefCell<OpState>>,
+    args: MessageBoxArgs,
+    _: (),
+) -> Result<Option<MessageBoxAnswer>, AnyError> {
+    let kind = args.kind;
+    let answer = {
+        let state = state.borrow();
+        // Nobody is there to answer in headless runs, and tests must not hang.
+        if state.borrow::<Flags>().headless {
+            return Ok(Some(MessageBoxAnswer { value: kind.dismissed() }));
+        }
+        match message_box::show(&state, args)? {
+            Some(answer) => answer,
+            None => return Ok(None),
+        }
+    };
+    // The sender only goes away unanswered if the event loop does.
+    let value = answer.await.unwrap_or_else(|_| kind.dismissed());
+    Ok(Some(MessageBoxAnswer { value }))
+}
+
+/// Resolves to the chosen paths, or `None` if the dialog was cancelled. Paths
+/// are serialized like dropped files are, see `drag::serialize_path`.
+pub async fn op_show_open_dialog(
+    state: Rc<RefCell<OpState>>,
+    args: OpenDialogArgs,
+    _: (),
+) -> Result<Option<Vec<Value>>, AnyError> {
+    let parent = portal::parent_window(&state.borrow(), args.window.as_ref())?;
+    tokio::task::spawn_blocking(move || portal::open_file(parent, args)).await?
+}
+
+pub async fn op_show_save_dialog(
+    state: Rc<RefCell<OpState>>,
+    args: SaveDialogArgs,
+    _: (),
+) -> Result<Option<Value>, AnyError> {
+    let parent = portal::parent_window(&state.borrow(), args.window.as_ref())?;
+    let paths = tokio::task::spawn_blocking(move || portal::save_file(parent, args)).await??;
+    Ok(paths.and_then(|paths| paths.into_iter().next()))
+}
+
+/// File dialogs through `org.freedesktop.portal.FileChooser`, which works
+/// the same in and out of sandboxes and picks the desktop's own dialog.
+/// https://flatpak.github.io/xdg-desktop-portal/#gdbus-org.freedesktop.portal.FileChooser
+#[cfg(target_os = "linux")]
+mod portal {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use deno_runtime::deno_core::OpState;
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use deno_runtime::deno_core::error::generic_error;
+    use deno_runtime::deno_core::serde_json::Value;
+    use raw_window_handle::HasRawWindowHandle;
+    use raw_window_handle::RawWindowHandle;
+    use serde::Serialize;
+    use zbus::Connection;
+    use zbus::MessageType;
+    use zbus::fdo::DBusProxy;
+    use zvariant::DeserializeDict;
+    use zvariant::OwnedObjectPath;
+    use zvariant::SerializeDict;
+    use zvariant::Type;
+    use zvariant::TypeDict;
+
+    use super::FileFilter;
+    use super::OpenDialogArgs;
+    use super::SaveDialogArgs;
+    use crate::drag::serialize_path;
+    use crate::window::WindowRef;
+    use crate::window::WindowResource;
+
+    const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+    const PATH: &str = "/org/freedesktop/portal/desktop";
+    const FILE_CHOOSER: &str = "org.freedesktop.portal.FileChooser";
+
+    static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+
+    /// `(name, [(0 for a glob or 1 for a MIME type, pattern)])`
+    type Filter = (String, Vec<(u32, String)>);
+
+    #[derive(SerializeDict, TypeDict)]
+    struct OpenFileOptions {
+        handle_token: String,
+        accept_label: Option<String>,
+        multiple: bool,
+        directory: bool,
+        filters: Option<Vec<Filter>>,
+    }
+
+    #[derive(SerializeDict, TypeDict)]
+    struct SaveFileOptions {
+        handle_token: String,
+        accept_label: Option<String>,
+        current_name: Option<String>,
+        /// A NUL terminated path.
+        current_folder: Option<Vec<u8>>,
+        filters: Option<Vec<Filter>>,
+    }
+
+    #[derive(DeserializeDict, TypeDict)]
+    struct Results {
+        uris: Option<Vec<String>>,
+    }
+
+    /// The portal's `parent_window` string for `window`, or `""` for none.
+    pub fn parent_window(state: &OpState, window: Option<&WindowRef>) -> Result<String, AnyError> {
+        let window = match window {
+            Some(window) => WindowResource::get(state, window)?,
+            None => return Ok(String::new()),
+        };
+        Ok(match &*window {
+            WindowResource::Native(window) => match window.raw_window_handle() {
+                RawWindowHandle::Xlib(handle) => format!("x11:{:x}", handle.window),
+                // Wayland parents need an exported xdg-foreign handle, which
+                // winit 0.24 has no way to make.
+                _ => String::new(),
+            },
+            WindowResource::Headless(_) => String::new(),
+        })
+    }
+
+    fn filters(filters: Vec<FileFilter>) -> Option<Vec<Filter>> {
+        if filters.is_empty() {
+            return None;
+        }
+        Some(
+            filters
+                .into_iter()
+                .map(|filter| {
+                    let globs = filter.extensions.into_iter().map(|ext| (0, format!("*.{}", ext)));
+                    let mime_types = filter.mime_types.into_iter().map(|mime| (1, mime));
+                    (filter.name, globs.chain(mime_types).collect())
+                })
+                .collect(),
+        )
+    }
+
+    fn token() -> String {
+        format!("deno_desktop_{}_{}", std::process::id(), NEXT_TOKEN.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// Portals hand back `file://` URIs, percent-encoding the path's bytes,
+    /// which need not be UTF-8.
+    fn uri_to_path(uri: &str) -> Result<PathBuf, AnyError> {
+        let encoded = uri
+            .strip_prefix("file://")
+            .ok_or_else(|| generic_error(format!("The portal returned a non-file URI: {}", uri)))?;
+        let mut bytes = Vec::with_capacity(encoded.len());
+        let mut rest = encoded.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let escaped = match tail {
+                [hi, lo, ..] if byte == b'%' => std::str::from_utf8(&[*hi, *lo])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                _ => None,
+            };
+            match escaped {
+                Some(decoded) => {
+                    bytes.push(decoded);
+                    rest = &tail[2..];
+                }
+                None => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+        Ok(PathBuf::from(OsString::from_vec(bytes)))
+    }
+
+    /// Calls `method` and waits for the user to finish with the dialog.
+    fn request<O: Serialize + Type>(
+        method: &str,
+        parent: &str,
+        title: &str,
+        options: O,
+    ) -> Result<Option<Vec<Value>>, AnyError> {
+        let conn = Connection::new_session()?;
+        // Listen before calling, the response may come right away.
+        DBusProxy::new(&conn)?.add_match(
+            "type='signal',interface='org.freedesktop.portal.Request',member='Response'",
+        )?;
+        let handle: OwnedObjectPath = conn
+            .call_method(Some(DESTINATION), PATH, Some(FILE_CHOOSER), method, &(parent, title, options))?
+            .body()?;
+
+        loop {
+            let message = conn.receive_message()?;
+            let header = message.header()?;
+            if header.message_type()? != MessageType::Signal
+                || header.member()? != Some("Response")
+                || header.path()?.map(|path| path.as_str()) != Some(handle.as_str())
+            {
+                continue;
+            }
+            let (response, results): (u32, Results) = message.body()?;
+            return match response {
+                0 => Ok(Some(
+                    results
+                        .uris
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|uri| uri_to_path(uri).map(|path| serialize_path(&path)))
+                        .collect::<Result<_, _>>()?,
+                )),
+                1 => Ok(None),
+                _ => Err(custom_error("Interrupted", "The file dialog was closed unexpectedly")),
+            };
+        }
+    }
+
+    pub fn open_file(parent: String, args: OpenDialogArgs) -> Result<Option<Vec<Value>>, AnyError> {
+        let options = OpenFileOptions {
+            handle_token: token(),
+            accept_label: args.accept_label,
+            multiple: args.multiple,
+            directory: args.directory,
+            filters: filters(args.filters),
+        };
+        request("OpenFile", &parent, &args.title, options)
+    }
+
+    pub fn save_file(parent: String, args: SaveDialogArgs) -> Result<Option<Vec<Value>>, AnyError> {
+        let options = SaveFileOptions {
+            handle_token: token(),
+            accept_label: args.accept_label,
+            current_name: args.default_name,
+            current_folder: args.default_folder.map(|folder| {
+                let mut bytes = folder.into_bytes();
+                bytes.push(0);
+                bytes
+            }),
+            filters: filters(args.filters),
+        };
+        request("SaveFile", &parent, &args.title, options)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod portal {
+    use deno_runtime::deno_core::OpState;
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use deno_runtime::deno_core::serde_json::Value;
+
+    use super::OpenDialogArgs;
+    use super::SaveDialogArgs;
+    use crate::window::WindowRef;
+
+    pub fn parent_window(_state: &OpState, _window: Option<&WindowRef>) -> Result<String, AnyError> {
+        Ok(String::new())
+    }
+
+    fn not_supported() -> AnyError {
+        custom_error("NotSupported", "File dialogs are only supported on Linux")
+    }
+
+    pub fn open_file(_parent: String, _args: OpenDialogArgs) -> Result<Option<Vec<Value>>, AnyError> {
+        Err(not_supported())
+    }
+
+    pub fn save_file(_parent: String, _args: SaveDialogArgs) -> Result<Option<Vec<Value>>, AnyError> {
+        Err(not_supported())
+    }
+}
+
+/// `alert`, `confirm` and `prompt` boxes. Neither winit nor the portals have
+/// message boxes, so these are our own windows, drawn in software by `canvas`
+/// at the window's scale factor. Their events never reach JS, they are
+/// handled right in the event pump by `handle_event`.
+#[cfg(target_os = "linux")]
+mod message_box {
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::sync::Once;
+
+    use deno_runtime::deno_core::OpState;
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::serde_json::Value;
+    use tokio::sync::oneshot;
+    use winit_main::Blocker;
+    use winit_main::reexports::dpi::PhysicalPosition;
+    use winit_main::reexports::dpi::PhysicalSize;
+    use winit_main::reexports::event::ElementState;
+    use winit_main::reexports::event::Event;
+    use winit_main::reexports::event::KeyboardInput;
+    use winit_main::reexports::event::MouseButton;
+    use winit_main::reexports::event::VirtualKeyCode;
+    use winit_main::reexports::event::WindowEvent;
+    use winit_main::reexports::platform::unix::WindowExtUnix;
+    use winit_main::reexports::window::Window;
+    use winit_main::reexports::window::WindowAttributes;
+
+    use super::MessageBoxArgs;
+    use super::MessageBoxKind;
+    use crate::EVENT_LOOP;
+    use crate::canvas;
+    use crate::canvas::Canvas;
+    use crate::canvas::Rect;
+    use crate::ids;
+    use crate::owned::is_input;
+    use crate::window::WindowResource;
+    use crate::window::centered_position;
+    use crate::x11;
+
+    // In logical pixels, scaled by the box's scale factor.
+    const FONT_SIZE: f64 = 13.0;
+    const LINE_HEIGHT: f64 = 18.0;
+    const MAX_TEXT_WIDTH: f64 = 440.0;
+    const MAX_LINES: usize = 30;
+    const PADDING: f64 = 16.0;
+    const MIN_WIDTH: f64 = 320.0;
+    const BUTTON_WIDTH: f64 = 72.0;
+    const BUTTON_HEIGHT: f64 = 26.0;
+    const BUTTON_GAP: f64 = 8.0;
+    const FIELD_INSET: f64 = 6.0;
+
+    const BACKGROUND: u32 = 0xf0f0f0;
+    const FOREGROUND: u32 = 0x202020;
+    const FIELD: u32 = 0xffffff;
+    const ACCENT: u32 = 0x3874d8;
+    const ACCENT_TEXT: u32 = 0xffffff;
+
+    lazy_static! {
+        static ref OPEN: Mutex<HashMap<u32, MessageBox>> = Mutex::new(HashMap::new());
+        /// Answered boxes whose windows winit hasn't reported destroyed yet.
+        static ref CLOSED: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+        /// The app window boxes without an explicit parent belong to.
+        static ref LAST_FOCUSED: Mutex<Option<u32>> = Mutex::new(None);
+    }
+
+    static NO_FONTS: Once = Once::new();
+
+    enum Answer {
+        Ok,
+        Cancel,
+    }
+
+    struct Layout {
+        scale: f64,
+        font_size: f32,
+        lines: Vec<String>,
+        line_height: i32,
+        padding: i32,
+        size: PhysicalSize<u32>,
+        field: Option<Rect>,
+        ok: Rect,
+        cancel: Option<Rect>,
+    }
+
+    struct MessageBox {
+        window: Window,
+        kind: MessageBoxKind,
+        message: String,
+        layout: Layout,
+        input: String,
+        cursor: Option<PhysicalPosition<f64>>,
+        /// The window that takes no input while the box is open.
+        parent: Option<u32>,
+        respond: oneshot::Sender<Value>,
+    }
+
+    /// The byte index `text` has to be cut at for the part before it to be
+    /// at most `max_width` wide, always keeping the first character.
+    fn fitting_prefix(text: &str, max_width: f32, measure: &impl Fn(&str) -> f32) -> usize {
+        let ends: Vec<_> = text.char_indices().skip(1).map(|(i, _)| i).chain(Some(text.len())).collect();
+        let fits = ends.partition_point(|&end| measure(&text[..end]) <= max_width);
+        ends[fits.saturating_sub(1)]
+    }
+
+    /// Breaks `message` into lines at most `max_width` wide as measured by
+    /// `measure`, at spaces where possible.
+    fn wrap(message: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        for paragraph in message.lines() {
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                if line.is_empty() {
+                    line.push_str(word);
+                } else if measure(&format!("{} {}", line, word)) <= max_width {
+                    line.push(' ');
+                    line.push_str(word);
+                } else {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                }
+                // Words longer than a line are cut wherever they have to be.
+                while measure(&line) > max_width && line.chars().nth(1).is_some() {
+                    let rest = line.split_off(fitting_prefix(&line, max_width, &measure));
+                    lines.push(std::mem::replace(&mut line, rest));
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn layout(kind: MessageBoxKind, message: &str, scale: f64) -> Layout {
+        let px = |logical: f64| (logical * scale).round() as i32;
+        let font_size = (FONT_SIZE * scale) as f32;
+        let padding = px(PADDING);
+        let line_height = px(LINE_HEIGHT);
+
+        let mut lines = wrap(message, px(MAX_TEXT_WIDTH) as f32, |text| canvas::text_width(text, font_size));
+        lines.truncate(MAX_LINES);
+        let text_width = lines.iter().map(|line| canvas::text_width(line, font_size).ceil() as i32).max().unwrap_or(0);
+        let width = (text_width + 2 * padding).max(px(MIN_WIDTH));
+
+        let mut bottom = padding + lines.len() as i32 * line_height;
+        let field = match kind {
+            MessageBoxKind::Prompt => {
+                let field = Rect {
+                    x: padding,
+                    y: bottom + px(BUTTON_GAP),
+                    width: width - 2 * padding,
+                    height: px(BUTTON_HEIGHT),
+                };
+                bottom = field.y + field.height;
+                Some(field)
+            }
+            MessageBoxKind::Alert | MessageBoxKind::Confirm => None,
+        };
+
+        let ok = Rect {
+            x: width - padding - px(BUTTON_WIDTH),
+            y: bottom + padding,
+            width: px(BUTTON_WIDTH),
+            height: px(BUTTON_HEIGHT),
+        };
+        let cancel = match kind {
+            MessageBoxKind::Alert => None,
+            MessageBoxKind::Confirm | MessageBoxKind::Prompt => {
+                Some(Rect { x: ok.x - px(BUTTON_GAP) - ok.width, ..ok })
+            }
+        };
+        let height = ok.y + ok.height + padding;
+
+        Layout {
+            scale,
+            font_size,
+            lines,
+            line_height,
+            padding,
+            size: PhysicalSize::new(width as u32, height as u32),
+            field,
+            ok,
+            cancel,
+        }
+    }
+
+    fn contains(rect: &Rect, position: PhysicalPosition<f64>) -> bool {
+        rect.contains(position.x, position.y)
+    }
+
+    impl Layout {
+        fn border(&self) -> i32 {
+            (self.scale.round() as i32).max(1)
+        }
+
+        /// The baseline that centres a line of text vertically in `rect`.
+        fn baseline(&self, rect: Rect) -> f32 {
+            let (ascent, descent) = canvas::vertical_metrics(self.font_size);
+            rect.y as f32 + (rect.height as f32 + ascent - descent) / 2.0
+        }
+
+        /// Draws `rect` filled with `fill` and `text` centred in it.
+        fn draw_button(&self, canvas: &mut Canvas, rect: Rect, fill: u32, text_color: u32, text: &str) {
+            canvas.fill(rect, fill);
+            canvas.stroke(rect, self.border(), FOREGROUND);
+            let x = rect.x as f32 + (rect.width as f32 - canvas::text_width(text, self.font_size)) / 2.0;
+            canvas.text(text, self.font_size, x, self.baseline(rect), text_color);
+        }
+
+        /// Draws the prompt's field with the end of `input` in view, since
+        /// that's what's being typed, followed by the caret.
+        fn draw_field(&self, canvas: &mut Canvas, field: Rect, input: &str) {
+            canvas.fill(field, FIELD);
+            canvas.stroke(field, self.border(), FOREGROUND);
+            let inset = (FIELD_INSET * self.scale).round() as i32;
+            let available = (field.width - 2 * inset - self.border()) as f32;
+            let starts: Vec<_> = input.char_indices().map(|(i, _)| i).chain(Some(input.len())).collect();
+            let fits = starts.partition_point(|&start| canvas::text_width(&input[start..], self.font_size) > available);
+            let start = starts[fits.min(starts.len() - 1)];
+            let baseline = self.baseline(field);
+            let end = canvas.text(&input[start..], self.font_size, (field.x + inset) as f32, baseline, FOREGROUND);
+            let (ascent, descent) = canvas::vertical_metrics(self.font_size);
+            let caret = Rect {
+                x: end.round() as i32,
+                y: (baseline - ascent).round() as i32,
+                width: self.border(),
+                height: (ascent + descent).round() as i32,
+            };
+            canvas.fill(caret, FOREGROUND);
+        }
+    }
+
+    impl MessageBox {
+        fn draw(&mut self) -> Result<(), AnyError> {
+            // Moving to a monitor with another scale factor redoes the layout
+            // at that scale, and the resize that follows redraws.
+            let scale = self.window.scale_factor();
+            if scale != self.layout.scale {
+                self.layout = layout(self.kind, &self.message, scale);
+                self.window.set_inner_size(self.layout.size);
+            }
+
+            let layout = &self.layout;
+            let mut canvas = Canvas::new(self.window.inner_size(), BACKGROUND);
+            let (ascent, _) = canvas::vertical_metrics(layout.font_size);
+            for (i, line) in layout.lines.iter().enumerate() {
+                let baseline = (layout.padding + i as i32 * layout.line_height) as f32 + ascent;
+                canvas.text(line, layout.font_size, layout.padding as f32, baseline, FOREGROUND);
+            }
+            if let Some(field) = layout.field {
+                layout.draw_field(&mut canvas, field, &self.input);
+            }
+            if let Some(cancel) = layout.cancel {
+                layout.draw_button(&mut canvas, cancel, BACKGROUND, FOREGROUND, "Cancel");
+            }
+            layout.draw_button(&mut canvas, layout.ok, ACCENT, ACCENT_TEXT, "OK");
+            canvas.present(&self.window)
+        }
+
+        fn handle(&mut self, event: &WindowEvent) -> Option<Answer> {
+            match event {
+                WindowEvent::CloseRequested => return Some(Answer::Cancel),
+                WindowEvent::CursorMoved { position, .. } => self.cursor = Some(*position),
+                WindowEvent::CursorLeft { .. } => self.cursor = None,
+                WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } => {
+                    let cursor = self.cursor?;
+                    if contains(&self.layout.ok, cursor) {
+                        return Some(Answer::Ok);
+                    }
+                    if self.layout.cancel.map_or(false, |cancel| contains(&cancel, cursor)) {
+                        return Some(Answer::Cancel);
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+                    ..
+                } => match key {
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => return Some(Answer::Ok),
+                    VirtualKeyCode::Escape => return Some(Answer::Cancel),
+                    VirtualKeyCode::Back if self.kind == MessageBoxKind::Prompt => {
+                        if self.input.pop().is_some() {
+                            self.window.request_redraw();
+                        }
+                    }
+                    _ => {}
+                },
+                WindowEvent::ReceivedCharacter(ch) if self.kind == MessageBoxKind::Prompt && !ch.is_control() => {
+                    self.input.push(*ch);
+                    self.window.request_redraw();
+                }
+                WindowEvent::Resized(_) => self.window.request_redraw(),
+                _ => {}
+            }
+            None
+        }
+
+        /// Sends the answer, and closes the window by dropping it.
+        fn answer(self, answer: Answer) {
+            canvas::release(&self.window);
+            let value = match (self.kind, answer) {
+                (MessageBoxKind::Confirm, Answer::Ok) => Value::Bool(true),
+                (MessageBoxKind::Prompt, Answer::Ok) => Value::String(self.input),
+                (kind, _) => kind.dismissed(),
+            };
+            // JS may have stopped waiting.
+            let _ = self.respond.send(value);
+        }
+    }
+
+    pub fn show(state: &OpState, args: MessageBoxArgs) -> Result<Option<oneshot::Receiver<Value>>, AnyError> {
+        if let Err(err) = canvas::check_fonts() {
+            NO_FONTS.call_once(|| eprintln!("Message boxes can't be drawn, asking on the terminal instead: {}", err));
+            return Ok(None);
+        }
+
+        let parent = match &args.window {
+            Some(window) => Some(WindowResource::get(state, window)?),
+            None => LAST_FOCUSED
+                .lock()
+                .unwrap()
+                .and_then(ids::window_rid)
+                .and_then(|rid| state.resource_table.get::<WindowResource>(rid).ok()),
+        };
+        let parent = match parent.as_deref() {
+            Some(WindowResource::Native(window)) => Some(window),
+            _ => None,
+        };
+
+        // Laid out at the parent's scale to start with, the box redoes it if
+        // it ends up on a monitor with another.
+        let scale = parent.map_or(1.0, |parent| parent.scale_factor());
+        let layout = layout(args.kind, &args.message, scale);
+        let mut attribs = WindowAttributes::default();
+        attribs.title = match args.kind {
+            MessageBoxKind::Alert => "Alert",
+            MessageBoxKind::Confirm => "Confirm",
+            MessageBoxKind::Prompt => "Prompt",
+        }
+        .to_string();
+        attribs.inner_size = Some(layout.size.to_logical::<f64>(scale).into());
+        attribs.resizable = false;
+        attribs.visible = false;
+        let window = {
+            let ev = EVENT_LOOP.lock().unwrap();
+            let ev = ev.as_ref().unwrap().lock().unwrap();
+            ev.create_window(attribs)?
+        };
+        let id = ids::window_id(window.id());
+
+        // Wayland has no equivalent of these hints in winit 0.24.
+        if let Some(xlib_window) = window.xlib_window() {
+            let xlib_window = xlib_window as u32;
+            let parent_xlib_window = parent.and_then(|parent| parent.xlib_window());
+            x11::with_connection(|conn| {
+                x11::set_atoms_property(conn, xlib_window, "_NET_WM_WINDOW_TYPE", &["_NET_WM_WINDOW_TYPE_DIALOG"])?;
+                if let Some(parent) = parent_xlib_window {
+                    x11::set_transient_for(conn, xlib_window, parent as u32)?;
+                    x11::set_atoms_property(conn, xlib_window, "_NET_WM_STATE", &["_NET_WM_STATE_MODAL"])?;
+                }
+                Ok(())
+            })?;
+        }
+        if let Some(parent) = parent {
+            if let Ok(position) = parent.outer_position() {
+                window.set_outer_position(centered_position(position, parent.outer_size(), layout.size));
+            }
+        }
+
+        let (respond, answer) = oneshot::channel();
+        let message_box = MessageBox {
+            window,
+            kind: args.kind,
+            message: args.message,
+            layout,
+            input: args.default_value,
+            cursor: None,
+            parent: parent.map(|parent| ids::window_id(parent.id())),
+            respond,
+        };
+        let mut open = OPEN.lock().unwrap();
+        // Only shown once registered, so the event pump never mistakes it for
+        // an app window.
+        message_box.window.set_visible(true);
+        // Wayland windows only appear once something is drawn in them.
+        message_box.window.request_redraw();
+        open.insert(id, message_box);
+        Ok(Some(answer))
+    }
+
+    /// Whether window `id` has a message box open, which keeps input from it.
+    pub fn has_message_box(id: u32) -> bool {
+        OPEN.lock().unwrap().values().any(|message_box| message_box.parent == Some(id))
+    }
+
+    /// Handles `event` if it's for a message box or is input to a window that
+    /// has one open, in which case it must not be delivered.
+    pub fn handle_event(event: &Event<Blocker>) -> bool {
+        let (window_id, window_event) = match event {
+            Event::WindowEvent { window_id, event } => (*window_id, Some(event)),
+            Event::RedrawRequested(window_id) => (*window_id, None),
+            _ => return false,
+        };
+        let id = ids::window_id(window_id);
+
+        let mut closed = CLOSED.lock().unwrap();
+        if closed.contains(&id) {
+            if let Some(WindowEvent::Destroyed) = window_event {
+                closed.remove(&id);
+                ids::remove_window(window_id);
+            }
+            return true;
+        }
+
+        let mut open = OPEN.lock().unwrap();
+        if let Some(message_box) = open.get_mut(&id) {
+            match window_event {
+                Some(event) => {
+                    if let Some(answer) = message_box.handle(event) {
+                        closed.insert(id);
+                        open.remove(&id).unwrap().answer(answer);
+                    }
+                }
+                None => {
+                    if let Err(err) = message_box.draw() {
+                        eprintln!("Failed to draw message box: {}", err);
+                    }
+                }
+            }
+            return true;
+        }
+
+        match window_event {
+            Some(WindowEvent::Focused(true)) => {
+                *LAST_FOCUSED.lock().unwrap() = Some(id);
+                false
+            }
+            Some(event) => is_input(event) && open.values().any(|message_box| message_box.parent == Some(id)),
+            None => false,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Measures like a monospaced font, one unit per character.
+        fn chars(text: &str) -> f32 {
+            text.chars().count() as f32
+        }
+
+        #[test]
+        fn wraps_at_spaces() {
+            assert_eq!(wrap("one two three", 7.0, chars), ["one two", "three"]);
+            assert_eq!(wrap("first\n\nthird", 7.0, chars), ["first", "", "third"]);
+        }
+
+        #[test]
+        fn cuts_words_longer_than_a_line() {
+            assert_eq!(wrap("abcdefghij", 4.0, chars), ["abcd", "efgh", "ij"]);
+            assert_eq!(wrap("a bcdefg", 4.0, chars), ["a", "bcde", "fg"]);
+        }
+
+        #[test]
+        fn wraps_by_width_rather_than_characters() {
+            let wide = |text: &str| 2.0 * chars(text);
+            assert_eq!(wrap("日本語のテキスト", 6.0, wide), ["日本語", "のテキ", "スト"]);
+        }
+
+        #[test]
+        fn keeps_at_least_a_character_per_line() {
+            assert_eq!(wrap("ab", 0.5, chars), ["a", "b"]);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod message_box {
+    use deno_runtime::deno_core::OpState;
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::serde_json::Value;
+    use tokio::sync::oneshot;
+    use winit_main::Blocker;
+    use winit_main::reexports::event::Event;
+
+    use super::MessageBoxArgs;
+
+    pub fn show(_state: &OpState, _args: MessageBoxArgs) -> Result<Option<oneshot::Receiver<Value>>, AnyError> {
+        Ok(None)
+    }
+
+    pub fn handle_event(_event: &Event<Blocker>) -> bool {
+        false
+    }
+
+    pub fn has_message_box(_id: u32) -> bool {
+        false
+    }
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_show_message_box", op_async(op_show_message_box)),
+            ("op_show_open_dialog", op_async(op_show_open_dialog)),
+            ("op_show_save_dialog", op_async(op_show_save_dialog)),
+        ])
+        .build()
+}
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use winit_main::Blocker;
+use winit_main::reexports::dpi::PhysicalPosition;
+use winit_main::reexports::event::Event;
+use winit_main::reexports::event::WindowEvent;
+
+use crate::event_loop::serialize_physical_position;
+use crate::event_loop::window_scale_factor;
+use crate::ids;
+use crate::pointer::PointerTracker;
+#[cfg(target_os = "linux")]
+use crate::x11;
+
+/// How often the pointer is queried while files are dragged over a window.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Sends `path` as a string when it is valid Unicode, and as its raw
+/// encoding otherwise so that it can still be passed back to the OS.
+pub fn serialize_path(path: &Path) -> Value {
+    match path.to_str() {
+        Some(path) => json!(path),
+        None => serialize_raw_path(path),
+    }
+}
+
+#[cfg(unix)]
+fn serialize_raw_path(path: &Path) -> Value {
+    use std::os::unix::ffi::OsStrExt;
+    json!({ "bytes": path.as_os_str().as_bytes() })
+}
+
+#[cfg(windows)]
+fn serialize_raw_path(path: &Path) -> Value {
+    use std::os::windows::ffi::OsStrExt;
+    json!({ "wide": path.as_os_str().encode_wide().collect::<Vec<u16>>() })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn serialize_raw_path(path: &Path) -> Value {
+    json!(path.to_string_lossy())
+}
+
+/// Whether `event` is one of winit's per-file drag and drop events, which
+/// are replaced by the ones `DragTracker` produces.
+pub fn is_file_drag(event: &Event<Blocker>) -> bool {
+    matches!(
+        event,
+        Event::WindowEvent {
+            event: WindowEvent::HoveredFile(_)
+                | WindowEvent::DroppedFile(_)
+                | WindowEvent::HoveredFileCancelled,
+            ..
+        }
+    )
+}
+
+#[derive(PartialEq)]
+enum DragKind {
+    Hover,
+    Drop,
+}
+
+struct PendingDrag {
+    window_id: u32,
+    kind: DragKind,
+    paths: Vec<Value>,
+}
+
+struct Hovering {
+    paths: Vec<Value>,
+    position: Option<PhysicalPosition<f64>>,
+}
+
+/// Groups winit's one-event-per-file `HoveredFile` and `DroppedFile` into
+/// single `dragenter` and `drop` events carrying every path, and reports
+/// pointer moves over a window files are being dragged over as `dragover`.
+#[derive(Default)]
+pub struct DragTracker {
+    pending: Option<PendingDrag>,
+    hovering: HashMap<u32, Hovering>,
+}
+
+/// Where the pointer is in `window_id`. X servers send the window no motion
+/// while another client drags over it, and winit keeps the positions from
+/// `XdndPosition` to itself, so the pointer is queried rather than taken from
+/// the last `CursorMoved`.
+fn pointer_position(window_id: u32, pointers: &PointerTracker) -> Option<PhysicalPosition<f64>> {
+    #[cfg(target_os = "linux")]
+    if let Some(window) = x11::window(window_id) {
+        if let Ok((x, y)) = x11::with_connection(|conn| x11::query_pointer(conn, window)) {
+            return Some(PhysicalPosition::new(x as f64, y as f64));
+        }
+    }
+    pointers.cursor_position(window_id)
+}
+
+pub fn drag_event(window_id: u32, kind: &str, paths: Vec<Value>, position: Option<PhysicalPosition<f64>>) -> Value {
+    let logical = position.map(|position| position.to_logical::<f64>(window_scale_factor(window_id)));
+    json!({
+        "type": "windowEvent",
+        "windowID": window_id,
+        "event": {
+            "type": kind,
+            "paths": paths,
+            "position": position.map(serialize_physical_position),
+            "logicalPosition": logical.map(|logical| json!({ "x": logical.x, "y": logical.y })),
+        },
+    })
+}
+
+impl DragTracker {
+    /// Returns the drag events to deliver before `event`.
+    pub fn handle(&mut self, event: &Event<Blocker>, pointers: &PointerTracker) -> Vec<Value> {
+        let mut events = vec![];
+
+        let (window_id, event) = match event {
+            Event::WindowEvent { window_id, event } => (ids::window_id(*window_id), event),
+            // winit sends all the files of a drag in one go, so anything else
+            // means the group is complete.
+            _ => {
+                events.extend(self.flush(pointers));
+                return events;
+            }
+        };
+
+        let (kind, path) = match event {
+            WindowEvent::HoveredFile(path) => (DragKind::Hover, path),
+            WindowEvent::DroppedFile(path) => (DragKind::Drop, path),
+            _ => {
+                events.extend(self.flush(pointers));
+                match event {
+                    WindowEvent::HoveredFileCancelled => {
+                        if let Some(hovering) = self.hovering.remove(&window_id) {
+                            let position = pointer_position(window_id, pointers);
+                            events.push(drag_event(window_id, "dragleave", hovering.paths, position));
+                        }
+                    }
+                    WindowEvent::CursorMoved { .. } => {
+                        events.extend(self.drag_over(window_id, pointers));
+                    }
+                    WindowEvent::Destroyed => {
+                        self.hovering.remove(&window_id);
+                    }
+                    _ => {}
+                }
+                return events;
+            }
+        };
+
+        if let Some(pending) = &mut self.pending {
+            if pending.window_id == window_id && pending.kind == kind {
+                pending.paths.push(serialize_path(path));
+                return events;
+            }
+        }
+
+        events.extend(self.flush(pointers));
+        self.pending = Some(PendingDrag { window_id, kind, paths: vec![serialize_path(path)] });
+        events
+    }
+
+    /// Whether the event pump has to call `poll` while it waits for events.
+    pub fn is_active(&self) -> bool {
+        self.pending.is_some() || !self.hovering.is_empty()
+    }
+
+    /// Returns the drag events that are due when no event came for
+    /// `POLL_INTERVAL`. winit sends the files of a drag back to back, so the
+    /// group is complete, and the pointer may have moved without a word from
+    /// the X server.
+    pub fn poll(&mut self, pointers: &PointerTracker) -> Vec<Value> {
+        let mut events: Vec<Value> = self.flush(pointers).into_iter().collect();
+        let mut windows: Vec<u32> = self.hovering.keys().copied().collect();
+        windows.sort_unstable();
+        for window_id in windows {
+            events.extend(self.drag_over(window_id, pointers));
+        }
+        events
+    }
+
+    /// Returns a `dragover` event if files hover over `window_id` and the
+    /// pointer moved since the last one.
+    fn drag_over(&mut self, window_id: u32, pointers: &PointerTracker) -> Option<Value> {
+        let hovering = self.hovering.get_mut(&window_id)?;
+        let position = pointer_position(window_id, pointers);
+        if position == hovering.position {
+            return None;
+        }
+        hovering.position = position;
+        Some(drag_event(window_id, "dragover", hovering.paths.clone(), position))
+    }
+
+    fn flush(&mut self, pointers: &PointerTracker) -> Option<Value> {
+        let pending = self.pending.take()?;
+        let position = pointer_position(pending.window_id, pointers);
+        Some(match pending.kind {
+            DragKind::Hover => {
+                let hovering = Hovering { paths: pending.paths.clone(), position };
+                self.hovering.insert(pending.window_id, hovering);
+                drag_event(pending.window_id, "dragenter", pending.paths, position)
+            }
+            DragKind::Drop => {
+                self.hovering.remove(&pending.window_id);
+                drag_event(pending.window_id, "drop", pending.paths, position)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use winit_main::reexports::event::DeviceId;
+    use winit_main::reexports::event::ModifiersState;
+    use winit_main::reexports::window::WindowId;
+
+    use super::*;
+
+    fn window_event(event: WindowEvent<'static>) -> Event<'static, Blocker> {
+        Event::WindowEvent { window_id: unsafe { WindowId::dummy() }, event }
+    }
+
+    #[allow(deprecated)]
+    fn cursor_moved(x: f64, y: f64) -> Event<'static, Blocker> {
+        window_event(WindowEvent::CursorMoved {
+            device_id: unsafe { DeviceId::dummy() },
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    fn types(events: &[Value]) -> Vec<&str> {
+        events.iter().map(|event| event["event"]["type"].as_str().unwrap()).collect()
+    }
+
+    /// Feeds `events` to a tracker the way the event pump does.
+    fn handle_all(drags: &mut DragTracker, pointers: &mut PointerTracker, events: Vec<Event<'static, Blocker>>) -> Vec<Value> {
+        let mut values = vec![];
+        for event in events {
+            pointers.handle(&event);
+            values.extend(drags.handle(&event, pointers));
+        }
+        values
+    }
+
+    #[test]
+    fn groups_the_files_of_a_drop() {
+        let mut drags = DragTracker::default();
+        let mut pointers = PointerTracker::default();
+        let events = handle_all(&mut drags, &mut pointers, vec![
+            cursor_moved(10.0, 20.0),
+            window_event(WindowEvent::DroppedFile(PathBuf::from("/a"))),
+            window_event(WindowEvent::DroppedFile(PathBuf::from("/b"))),
+            Event::MainEventsCleared,
+        ]);
+        assert_eq!(types(&events), ["drop"]);
+        assert_eq!(events[0]["windowID"], ids::window_id(unsafe { WindowId::dummy() }));
+        assert_eq!(events[0]["event"]["paths"], json!(["/a", "/b"]));
+        assert_eq!(events[0]["event"]["position"], json!({ "x": 10.0, "y": 20.0 }));
+    }
+
+    #[test]
+    fn completes_a_group_when_polled() {
+        let mut drags = DragTracker::default();
+        let mut pointers = PointerTracker::default();
+        let events = handle_all(&mut drags, &mut pointers, vec![
+            window_event(WindowEvent::HoveredFile(PathBuf::from("/a"))),
+            window_event(WindowEvent::HoveredFile(PathBuf::from("/b"))),
+        ]);
+        assert!(events.is_empty());
+        assert!(drags.is_active());
+
+        let events = drags.poll(&pointers);
+        assert_eq!(types(&events), ["dragenter"]);
+        assert_eq!(events[0]["event"]["paths"], json!(["/a", "/b"]));
+        assert_eq!(events[0]["event"]["position"], Value::Null);
+        // Files still hover, so polling goes on.
+        assert!(drags.is_active());
+    }
+
+    #[test]
+    fn reports_each_move_while_hovering() {
+        let mut drags = DragTracker::default();
+        let mut pointers = PointerTracker::default();
+        let events = handle_all(&mut drags, &mut pointers, vec![
+            window_event(WindowEvent::HoveredFile(PathBuf::from("/a"))),
+            cursor_moved(1.0, 2.0),
+            cursor_moved(3.0, 4.0),
+            cursor_moved(3.0, 4.0),
+        ]);
+        assert_eq!(types(&events), ["dragenter", "dragover"]);
+        assert_eq!(events[0]["event"]["position"], json!({ "x": 1.0, "y": 2.0 }));
+        assert_eq!(events[1]["event"]["position"], json!({ "x": 3.0, "y": 4.0 }));
+        assert!(drags.poll(&pointers).is_empty());
+    }
+
+    #[test]
+    fn ends_hovering_on_leave_or_drop() {
+        let mut drags = DragTracker::default();
+        let mut pointers = PointerTracker::default();
+        let events = handle_all(&mut drags, &mut pointers, vec![
+            window_event(WindowEvent::HoveredFile(PathBuf::from("/a"))),
+            window_event(WindowEvent::HoveredFileCancelled),
+        ]);
+        assert_eq!(types(&events), ["dragenter", "dragleave"]);
+        assert_eq!(events[1]["event"]["paths"], json!(["/a"]));
+        assert!(!drags.is_active());
+
+        let events = handle_all(&mut drags, &mut pointers, vec![
+            window_event(WindowEvent::HoveredFile(PathBuf::from("/a"))),
+            window_event(WindowEvent::DroppedFile(PathBuf::from("/a"))),
+            Event::MainEventsCleared,
+        ]);
+        assert_eq!(types(&events), ["dragenter", "drop"]);
+        assert!(!drags.is_active());
+    }
+}
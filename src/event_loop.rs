@@ -1,14 +1,26 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 use deno_runtime::deno_core::Extension;
 use deno_runtime::deno_core::op_async;
+use deno_runtime::deno_core::op_sync;
 use deno_runtime::deno_core::OpState;
 use deno_runtime::deno_core::error::AnyError;
+use serde::Deserialize;
 use serde::Serialize;
 use deno_runtime::deno_core::serde_json::Value;
 use deno_runtime::deno_core::serde_json::json;
+use tokio::sync::Notify;
 use winit_main::Blocker;
+use winit_main::EventReceiver;
 use winit_main::reexports::dpi::PhysicalPosition;
 use winit_main::reexports::dpi::PhysicalSize;
 use winit_main::reexports::event::DeviceEvent;
@@ -21,8 +33,93 @@ use winit_main::reexports::event::StartCause;
 use winit_main::reexports::event::TouchPhase;
 use winit_main::reexports::event::WindowEvent;
 use winit_main::reexports::window::Theme;
-use crate::util::hash;
-use crate::EVENT_RECEIVER;
+use crate::clock;
+use crate::dialog;
+use crate::drag;
+use crate::drag::DragTracker;
+use crate::flags::Flags;
+use crate::gamepad;
+use crate::record;
+use crate::record::Recorder;
+use crate::ids;
+#[cfg(target_os = "linux")]
+use crate::ime;
+use crate::menu;
+use crate::owned;
+use crate::pointer::PointerTracker;
+use crate::shortcuts::Matched;
+use crate::shortcuts::ShortcutMatcher;
+use crate::window;
+
+/// Number of sources that may still push events. Once it drops to zero and
+/// the queue is empty, `op_next_event` resolves to `null`, ending the loop.
+static EVENT_PRODUCERS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref EVENT_QUEUE: Mutex<EventQueue> = Mutex::new(EventQueue::default());
+    static ref EVENT_NOTIFY: Notify = Notify::new();
+    static ref EVENT_FILTER: RwLock<EventFilter> = RwLock::new(EventFilter::default());
+    static ref SCALE_FACTORS: Mutex<HashMap<u32, f64>> = Mutex::new(HashMap::new());
+    static ref WINDOW_QUEUES: Mutex<HashMap<u32, WindowQueue>> = Mutex::new(HashMap::new());
+    /// Native window with keyboard focus, as last reported by winit.
+    static ref FOCUSED_WINDOW: Mutex<Option<u32>> = Mutex::new(None);
+}
+
+/// Events of a window that has a `window.events()` stream. Each window has its
+/// own `Notify`, so a stream only wakes up for its own window's events.
+#[derive(Default)]
+struct WindowQueue {
+    events: EventQueue,
+    notify: Arc<Notify>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventFilter {
+    /// Top-level event types (such as `"deviceEvent"`) to drop before they
+    /// are serialized.
+    #[serde(default)]
+    exclude: HashSet<String>,
+    /// Merge `cursorMoved` and `pointermove` events and sum up `mouseMotion`
+    /// deltas while they are waiting in the queue, so that JS gets at most one
+    /// of each per window, pointer or device between other input.
+    #[serde(default)]
+    coalesce_motion: bool,
+    /// Deliver mouse, touch and touchpad pressure input as pointer events
+    /// (`pointerdown`, `pointermove`, ...) instead of the raw winit events.
+    #[serde(default)]
+    pointer_events: bool,
+}
+
+pub fn set_window_scale_factor(window_id: u32, scale_factor: f64) {
+    SCALE_FACTORS.lock().unwrap().insert(window_id, scale_factor);
+}
+
+pub fn window_scale_factor(window_id: u32) -> f64 {
+    SCALE_FACTORS.lock().unwrap().get(&window_id).copied().unwrap_or(1.0)
+}
+
+pub fn remove_window_scale_factor(window_id: u32) {
+    SCALE_FACTORS.lock().unwrap().remove(&window_id);
+}
+
+pub fn focused_window() -> Option<u32> {
+    *FOCUSED_WINDOW.lock().unwrap()
+}
+
+fn track_focus(event: &Event<Blocker>) {
+    let (id, focused) = match event {
+        Event::WindowEvent { window_id, event: WindowEvent::Focused(focused) } => (ids::window_id(*window_id), *focused),
+        Event::WindowEvent { window_id, event: WindowEvent::Destroyed } => (ids::window_id(*window_id), false),
+        _ => return,
+    };
+    let mut focused_window = FOCUSED_WINDOW.lock().unwrap();
+    if focused {
+        *focused_window = Some(id);
+    } else if *focused_window == Some(id) {
+        *focused_window = None;
+    }
+}
 
 pub fn serialize_physical_size<T: Serialize>(size: PhysicalSize<T>) -> Value {
     json!({ "width": size.width, "height": size.height })
@@ -32,6 +129,30 @@ pub fn serialize_physical_position<T: Serialize>(pos: PhysicalPosition<T>) -> Va
     json!({ "x": pos.x, "y": pos.y })
 }
 
+/// Adds the logical counterparts of the physical sizes and positions in a
+/// serialized window event.
+pub fn add_logical_values(event: &mut Value, scale_factor: f64) {
+    let size = |size: &Value, scale_factor: f64| json!({
+        "width": size["width"].as_f64().unwrap_or(0.0) / scale_factor,
+        "height": size["height"].as_f64().unwrap_or(0.0) / scale_factor,
+    });
+    let position = |position: &Value| json!({
+        "x": position["x"].as_f64().unwrap_or(0.0) / scale_factor,
+        "y": position["y"].as_f64().unwrap_or(0.0) / scale_factor,
+    });
+    match event["type"].as_str() {
+        Some("resized") => event["logicalSize"] = size(&event["size"], scale_factor),
+        Some("moved") | Some("cursorMoved") => event["logicalPosition"] = position(&event["position"]),
+        Some("touch") => event["logicalLocation"] = position(&event["location"]),
+        // The event carries the new scale factor, which isn't recorded yet.
+        Some("scaleFactorChanged") => {
+            let scale_factor = event["scaleFactor"].as_f64().unwrap_or(scale_factor);
+            event["logicalInnerSize"] = size(&event["newInnerSize"], scale_factor);
+        }
+        _ => {}
+    }
+}
+
 fn serialize_keyboard_input(input: KeyboardInput) -> Value {
     json!({
         "keyCode": input.scancode,
@@ -58,54 +179,97 @@ fn serialize_touch_phase(phase: TouchPhase) -> Value {
     }
 }
 
+fn event_type(event: &Event<Blocker>) -> &'static str {
+    match event {
+        Event::NewEvents(_) => "newEvents",
+        Event::WindowEvent { .. } => "windowEvent",
+        Event::DeviceEvent { .. } => "deviceEvent",
+        Event::UserEvent(_) => "blocker",
+        Event::Suspended => "suspended",
+        Event::Resumed => "resumed",
+        Event::MainEventsCleared => "mainEventsCleared",
+        Event::RedrawRequested(_) => "redrawRequested",
+        Event::RedrawEventsCleared => "redrawEventsCleared",
+        Event::LoopDestroyed => "loopDestroyed",
+    }
+}
+
+/// The raw window events pointer events are made from.
+const POINTER_SOURCES: [&str; 6] = ["cursorMoved", "cursorEntered", "cursorLeft", "mouseInput", "touchpadPressure", "touch"];
+const POINTER_EVENTS: [&str; 6] = ["pointerdown", "pointermove", "pointerup", "pointercancel", "pointerenter", "pointerleave"];
+
+impl EventFilter {
+    /// Whether serialized `event` gets through. Filtering works on serialized
+    /// events so that replayed ones go through the same filter as live ones.
+    fn allows(&self, event: &Value) -> bool {
+        if event["type"].as_str().map_or(false, |ty| self.exclude.contains(ty)) {
+            return false;
+        }
+        if event["type"] != "windowEvent" {
+            return true;
+        }
+        let event = &event["event"];
+        match event["type"].as_str() {
+            Some(ty) if POINTER_SOURCES.contains(&ty) => !self.pointer_events,
+            // Locked pointer moves are delivered either way.
+            Some(ty) if POINTER_EVENTS.contains(&ty) && event.get("movementX").is_none() => self.pointer_events,
+            _ => true,
+        }
+    }
+}
+
 pub fn serialize_event<'a>(event: Event<'a, Blocker>) -> Value {
     match event {
         Event::NewEvents(cause) => {
             let cause = match cause {
-                StartCause::ResumeTimeReached { start, requested_resume } => json!({ "type": "resumeTimeReached", "start": start.elapsed().as_millis().to_string(), "requestedResume": requested_resume.elapsed().as_millis().to_string() }),
-                StartCause::WaitCancelled { start, requested_resume } => json!({ "type": "waitCancelled", "start": start.elapsed().as_millis().to_string(), "requestedResume": if let Some(requested_resume) = requested_resume { Some(requested_resume.elapsed().as_millis().to_string()) } else { None }}),
+                StartCause::ResumeTimeReached { start, requested_resume } => json!({ "type": "resumeTimeReached", "start": clock::millis(start), "requestedResume": clock::millis(requested_resume) }),
+                StartCause::WaitCancelled { start, requested_resume } => json!({ "type": "waitCancelled", "start": clock::millis(start), "requestedResume": requested_resume.map(clock::millis) }),
                 StartCause::Poll => json!({ "type": "poll" }),
                 StartCause::Init => json!({ "type": "init" }),
             };
             json!({ "type": "newEvents", "cause": cause })
         },
         Event::WindowEvent { window_id, event } => {
-            let event = match event {
+            let id = ids::window_id(window_id);
+            let mut event = match event {
                 WindowEvent::Resized(size) => json!({ "type": "resized", "size": serialize_physical_size(size) }),
                 WindowEvent::Moved(pos) => json!({ "type": "moved", "position": serialize_physical_position(pos) }),
                 WindowEvent::CloseRequested => json!({ "type": "closeRequested" }),
-                WindowEvent::Destroyed => json!({ "type": "destroyed" }),
-                WindowEvent::DroppedFile(path) => json!({ "type": "droppedFile", "path": path.into_os_string().into_string() }),
-                WindowEvent::HoveredFile(path) => json!({ "type": "hoveredFile", "path": path.into_os_string().into_string() }),
-                WindowEvent::HoveredFileCancelled => json!({ "type": "hoveredFileCancelled" }),
+                WindowEvent::Destroyed => {
+                    ids::remove_window(window_id);
+                    json!({ "type": "destroyed" })
+                },
+                WindowEvent::DroppedFile(_) | WindowEvent::HoveredFile(_) | WindowEvent::HoveredFileCancelled => {
+                    unreachable!("file drag events are replaced by DragTracker")
+                },
                 WindowEvent::ReceivedCharacter(ch) => json!({ "type": "receivedCharacter", "char": ch }),
                 WindowEvent::Focused(focused) => json!({ "type": "focused", "focused": focused }),
                 WindowEvent::KeyboardInput { device_id, input, is_synthetic } => json!({
                     "type": "keyboardInput",
-                    "deviceID": hash(device_id),
+                    "deviceID": ids::device_id(device_id),
                     "isSynthetic": is_synthetic,
                     "input": serialize_keyboard_input(input)
                 }),
                 WindowEvent::ModifiersChanged(state) => json!({ "type": "modifiersChanged", "state": state.bits() }),
                 WindowEvent::CursorMoved { device_id, position, .. } => json!({
                     "type": "cursorMoved",
-                    "deviceID": hash(device_id),
+                    "deviceID": ids::device_id(device_id),
                     "position": serialize_physical_position(position)
                 }),
                 WindowEvent::CursorEntered { device_id } => json!({
                     "type": "cursorEntered",
-                    "deviceID": hash(device_id)
+                    "deviceID": ids::device_id(device_id)
                 }),
-                WindowEvent::CursorLeft { device_id } => json!({ "type": "cursorLeft", "deviceID": hash(device_id) }),
+                WindowEvent::CursorLeft { device_id } => json!({ "type": "cursorLeft", "deviceID": ids::device_id(device_id) }),
                 WindowEvent::MouseWheel { device_id, delta, phase, .. } => json!({
                     "type": "mouseWheel",
-                    "deviceID": hash(device_id),
+                    "deviceID": ids::device_id(device_id),
                     "delta": serialize_mouse_scroll_delta(delta),
                     "phase": serialize_touch_phase(phase),
                 }),
                 WindowEvent::MouseInput { device_id, state, button, .. } => json!({
                     "type": "mouseInput",
-                    "deviceID": hash(device_id),
+                    "deviceID": ids::device_id(device_id),
                     "state": match state {
                         ElementState::Pressed => "pressed",
                         ElementState::Released => "released"
@@ -119,29 +283,27 @@ pub fn serialize_event<'a>(event: Event<'a, Blocker>) -> Value {
                 }),
                 WindowEvent::TouchpadPressure { device_id, pressure, stage } => json!({
                     "type": "touchpadPressure",
-                    "deviceID": hash(device_id),
+                    "deviceID": ids::device_id(device_id),
                     "pressure": pressure,
                     "stage": stage,
                 }),
                 WindowEvent::AxisMotion { device_id, axis, value } => json!({
                     "type": "axisMotion",
-                    "deviceID": hash(device_id),
+                    "deviceID": ids::device_id(device_id),
                     "axis": axis,
                     "value": value,
                 }),
                 WindowEvent::Touch(input) => json!({
                     "type": "touch",
-                    "device_id": hash(input.device_id),
+                    "device_id": ids::device_id(input.device_id),
                     "phase": serialize_touch_phase(input.phase),
                     "location": serialize_physical_position(input.location),
-                    // leaving out input.force as that's ios specific for now
+                    "force": input.force.map(|force| force.normalized()),
                     "id": input.id,
                 }),
-                WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => json!({
-                    "type": "scaleFactorChanged",
-                    "scaleFactor": scale_factor,
-                    "newInnerSize": serialize_physical_size(*new_inner_size)
-                }),
+                WindowEvent::ScaleFactorChanged { .. } => {
+                    unreachable!("winit-main drops ScaleFactorChanged, see window::check_scale_factor")
+                },
                 WindowEvent::ThemeChanged(theme) => json!({
                     "type": "themeChanged",
                     "theme": match theme {
@@ -150,12 +312,17 @@ pub fn serialize_event<'a>(event: Event<'a, Blocker>) -> Value {
                     }
                 }),
             };
-            json!({ "type": "windowEvent", "windowID": hash(window_id), "event": event })
+            add_logical_values(&mut event, window_scale_factor(id));
+            json!({ "type": "windowEvent", "windowID": id, "event": event })
         },
         Event::DeviceEvent { device_id, event } => {
+            let id = ids::device_id(device_id);
             let event = match event {
                 DeviceEvent::Added => json!({ "type": "added" }),
-                DeviceEvent::Removed => json!({ "type": "removed" }),
+                DeviceEvent::Removed => {
+                    ids::remove_device(device_id);
+                    json!({ "type": "removed" })
+                },
                 DeviceEvent::MouseMotion { delta } => json!({ "type": "mouseMotion", "delta": delta }),
                 DeviceEvent::MouseWheel { delta } => json!({ "type": "mouseWheel", "delta": serialize_mouse_scroll_delta(delta) }),
                 DeviceEvent::Motion { axis, value } => json!({ "type": "motion", "axis": axis, "value": value }),
@@ -163,36 +330,565 @@ pub fn serialize_event<'a>(event: Event<'a, Blocker>) -> Value {
                 DeviceEvent::Key(input) => json!({ "type": "key", "input": serialize_keyboard_input(input) }),
                 DeviceEvent::Text { codepoint } => json!({ "type": "text", "codepoint": codepoint }),
             };
-            json!({ "type": "deviceEvent", "deviceID": hash(device_id), "event": event })
+            json!({ "type": "deviceEvent", "deviceID": id, "event": event })
         },
         Event::UserEvent(_) => json!({ "type": "blocker" }),
         Event::Suspended => json!({ "type": "suspended" }),
         Event::Resumed => json!({ "type": "resumed" }),
         Event::MainEventsCleared => json!({ "type": "mainEventsCleared" }),
-        Event::RedrawRequested(wid) => json!({ "type": "redrawRequested", "windowID": hash(wid) }),
+        Event::RedrawRequested(wid) => json!({ "type": "redrawRequested", "windowID": ids::window_id(wid) }),
         Event::RedrawEventsCleared => json!({ "type": "redrawEventsCleared" }),
         Event::LoopDestroyed => json!({ "type": "loopDestroyed" }),
     }
 }
 
-pub async fn op_next_event(_: Rc<RefCell<OpState>>, _: (), _: ()) -> Result<Value, AnyError> {
-    Ok(tokio::task::spawn_blocking(|| {
-        let arc = EVENT_RECEIVER.lock().unwrap();
-        let asref = arc.as_ref();
-        let arc = asref.unwrap();
-        let er = arc.lock().unwrap();
-        let next = er.recv();
+/// The window whose stream `event` belongs to, if it is subscribed.
+fn routed_window(event: &Value) -> Option<u32> {
+    match event["type"].as_str() {
+        Some("windowEvent" | "redrawRequested") => event["windowID"].as_u64().map(|id| id as u32),
+        _ => None,
+    }
+}
 
-        serialize_event(next)
-    })
-    .await
-    .unwrap())
+/// Queues `event` for `Deno.eventLoop()`, or for `window.events()` if its
+/// window has a stream.
+fn enqueue(event: Value, coalesce: bool) {
+    let event = match routed_window(&event) {
+        Some(id) => {
+            let mut queues = WINDOW_QUEUES.lock().unwrap();
+            match queues.get_mut(&id) {
+                Some(queue) => {
+                    queue.events.push(event, coalesce);
+                    queue.notify.notify_one();
+                    return;
+                }
+                None => event,
+            }
+        }
+        None => event,
+    };
+
+    EVENT_QUEUE.lock().unwrap().push(event, coalesce);
+    EVENT_NOTIFY.notify_one();
+}
+
+pub fn push_event(event: Value) {
+    enqueue(event, false);
+}
+
+/// Queues `event` the way live winit events are, that is unless the event
+/// filter drops it and with motion coalescing if enabled.
+pub fn push_filtered_event(event: Value) {
+    let filter = EVENT_FILTER.read().unwrap();
+    if filter.allows(&event) {
+        let coalesce = filter.coalesce_motion;
+        drop(filter);
+        enqueue(event, coalesce);
+    }
+}
+
+/// Routes the events of window `id` to its own queue from now on. Returns
+/// false if it already has one.
+pub fn subscribe_window(id: u32) -> bool {
+    let mut queues = WINDOW_QUEUES.lock().unwrap();
+    if queues.contains_key(&id) {
+        return false;
+    }
+    queues.insert(id, WindowQueue::default());
+    true
+}
+
+/// Ends the stream of window `id`. Queued events are dropped, later ones go
+/// to `Deno.eventLoop()` again.
+pub fn unsubscribe_window(id: u32) {
+    if let Some(queue) = WINDOW_QUEUES.lock().unwrap().remove(&id) {
+        queue.notify.notify_one();
+    }
+}
+
+pub fn add_producer() {
+    EVENT_PRODUCERS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn remove_producer() {
+    EVENT_PRODUCERS.fetch_sub(1, Ordering::SeqCst);
+    EVENT_NOTIFY.notify_one();
+    for queue in WINDOW_QUEUES.lock().unwrap().values() {
+        queue.notify.notify_one();
+    }
+}
+
+/// What a motion event can be merged with: moves of the same window, kind
+/// and pointer, or mouse motion of the same device.
+#[derive(PartialEq, Eq, Hash)]
+enum MotionSource {
+    Window(u64, String),
+    Device(u64),
+}
+
+impl MotionSource {
+    fn of(event: &Value) -> Option<MotionSource> {
+        match event["type"].as_str() {
+            Some("windowEvent") => {
+                let window = event["windowID"].as_u64()?;
+                let inner = &event["event"];
+                match inner["type"].as_str() {
+                    Some("cursorMoved") => Some(MotionSource::Window(window, format!("cursorMoved {}", inner["deviceID"]))),
+                    // Moves that change buttons carry a `button` and must
+                    // stay distinct, and locked moves are relative.
+                    Some("pointermove") if inner["button"] == -1 => Some(MotionSource::Window(
+                        window,
+                        format!("pointermove {} {} {}", inner["pointerId"], inner["buttons"], inner.get("movementX").is_some()),
+                    )),
+                    _ => None,
+                }
+            }
+            Some("deviceEvent") if event["event"]["type"] == "mouseMotion" => event["deviceID"].as_u64().map(MotionSource::Device),
+            _ => None,
+        }
+    }
+
+    fn is_from(&self, event: &Value) -> bool {
+        match (self, event["type"].as_str()) {
+            (MotionSource::Window(window, _), Some("windowEvent")) => event["windowID"].as_u64() == Some(*window),
+            (MotionSource::Device(device), Some("deviceEvent")) => event["deviceID"].as_u64() == Some(*device),
+            _ => false,
+        }
+    }
+}
+
+/// Events waiting for JS, which may merge motion into motion that is still
+/// waiting.
+#[derive(Default)]
+struct EventQueue {
+    events: VecDeque<Value>,
+    /// Where the last queued motion of each source is. Motion is merged into
+    /// it until JS takes events out, or until other input from the same
+    /// window or device comes in after it.
+    motion: HashMap<MotionSource, usize>,
+}
+
+impl EventQueue {
+    fn push(&mut self, event: Value, coalesce: bool) {
+        if !coalesce {
+            self.events.push_back(event);
+            return;
+        }
+        let source = match MotionSource::of(&event) {
+            Some(source) => source,
+            None => {
+                self.motion.retain(|source, _| !source.is_from(&event));
+                self.events.push_back(event);
+                return;
+            }
+        };
+        match self.motion.get(&source) {
+            Some(&index) => merge_motion(&mut self.events[index], event),
+            None => {
+                self.motion.insert(source, self.events.len());
+                self.events.push_back(event);
+            }
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<Value> {
+        self.motion.clear();
+        self.events.pop_front()
+    }
+
+    fn push_front(&mut self, event: Value) {
+        self.motion.clear();
+        self.events.push_front(event);
+    }
+}
+
+/// Folds `event` into `last`, queued motion from the same source.
+fn merge_motion(last: &mut Value, mut event: Value) {
+    if event["type"] == "deviceEvent" {
+        let delta = |value: &Value, i: usize| value["event"]["delta"][i].as_f64().unwrap_or(0.0);
+        let sum = json!([delta(last, 0) + delta(&event, 0), delta(last, 1) + delta(&event, 1)]);
+        last["event"]["delta"] = sum;
+        last["timestamp"] = event["timestamp"].take();
+        return;
+    }
+    // Locked pointer moves are relative, so they add up.
+    for key in ["movementX", "movementY"] {
+        if let (Some(a), Some(b)) = (last["event"][key].as_f64(), event["event"][key].as_f64()) {
+            event["event"][key] = json!(a + b);
+        }
+    }
+    *last = event;
+}
+
+/// Receives winit events on a dedicated thread, so that each one is stamped
+/// with the time it was delivered rather than the time JS got around to
+/// asking for it.
+fn spawn_event_pump(events: EventReceiver, mut recorder: Option<Recorder>, deliver: bool) {
+    add_producer();
+    std::thread::spawn(move || {
+        let mut pointers = PointerTracker::default();
+        let mut drags = DragTracker::default();
+        let mut shortcuts = ShortcutMatcher::default();
+
+        loop {
+            // Drags are polled, because nothing says when the files of a drop
+            // are all in and X servers send no motion during a drag.
+            let event = if drags.is_active() {
+                match events.recv_timeout(drag::POLL_INTERVAL) {
+                    Some(event) => event,
+                    None => {
+                        let values = drags.poll(&pointers);
+                        emit(values, clock::now(), &mut recorder, deliver);
+                        continue;
+                    }
+                }
+            } else {
+                events.recv()
+            };
+            let timestamp = clock::now();
+
+            // Message boxes and menus are the runtime's own windows, and the
+            // windows they belong to take no input while one is open. Nor do
+            // windows with a visible modal child.
+            if dialog::handle_event(&event) || menu::handle_event(&event) || owned::handle_event(&event) {
+                continue;
+            }
+            // Files dropped on such a window end the drag instead, for it to
+            // see a `dragleave` and the drag tracker to stop polling.
+            let event = match event {
+                Event::WindowEvent { window_id, event: WindowEvent::DroppedFile(_) } if is_blocked(ids::window_id(window_id)) => {
+                    Event::WindowEvent { window_id, event: WindowEvent::HoveredFileCancelled }
+                }
+                event => event,
+            };
+            // Keys go to the input method first, for what it composes of them
+            // to come right after them.
+            #[cfg(target_os = "linux")]
+            let composed = match ime::handle_event(&event) {
+                ime::Handled::Swallowed => continue,
+                ime::Handled::Passed(values) => values,
+            };
+
+            // The trackers have to see every event to know where the cursor
+            // is, even when they end up being filtered out.
+            track_focus(&event);
+            let pointer_event = pointers.handle(&event);
+            let mut values = drags.handle(&event, &pointers);
+            values.extend(pointers.handle_locked(&event));
+            let shortcut = shortcuts.handle(&event);
+
+            // Recordings get every event, the filter only applies on the way
+            // to JS. Excluded types aren't even serialized when not recording.
+            let excluded = EVENT_FILTER.read().unwrap().exclude.contains(event_type(&event));
+            if (recorder.is_some() || !excluded) && !drag::is_file_drag(&event) && matches!(shortcut, Matched::No) {
+                values.push(serialize_event(event));
+            }
+            values.extend(pointer_event);
+            if let Matched::Shortcut(value) = shortcut {
+                values.push(value);
+            }
+            #[cfg(target_os = "linux")]
+            values.extend(composed);
+            emit(values, timestamp, &mut recorder, deliver);
+        }
+    });
+}
+
+/// Whether window `id` takes no input because of a message box or modal
+/// window of its own.
+pub fn is_blocked(id: u32) -> bool {
+    dialog::has_message_box(id) || owned::has_modal_child(id)
+}
+
+/// Stamps the events the pump made of one winit event, records them and
+/// sends them on to JS.
+fn emit(values: Vec<Value>, timestamp: f64, recorder: &mut Option<Recorder>, deliver: bool) {
+    for mut event in values {
+        event["timestamp"] = json!(timestamp);
+
+        if let Some(rec) = recorder.as_mut() {
+            if let Err(err) = rec.write(&event) {
+                eprintln!("Failed to record event: {}", err);
+                *recorder = None;
+            }
+        }
+
+        if deliver {
+            push_filtered_event(event);
+        }
+    }
+}
+
+/// Starts feeding events to `Deno.eventLoop()`, from winit and gamepads (unless
+/// running headless) and, when asked for on the command line, from a recording.
+pub fn start(events: Option<EventReceiver>, flags: &Flags) -> Result<(), AnyError> {
+    if let Some(events) = events {
+        let recorder = match &flags.record_events {
+            Some(path) => Some(Recorder::create(path)?),
+            None => None,
+        };
+
+        // Live events are still drained while replaying so that winit is never
+        // left waiting on us, they just don't reach JS unless merging.
+        let deliver = flags.replay_events.is_none() || flags.replay_merge;
+        spawn_event_pump(events, recorder, deliver);
+
+        // Headless runs only see gamepads they connect themselves, so that a
+        // pad lying around on the machine can't interfere with tests.
+        gamepad::start();
+    }
+
+    if let Some(path) = &flags.replay_events {
+        record::load_replay(path)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the event to deliver in place of `event`, which is put back with
+/// `requeue` if a scale factor or fullscreen change has to be reported first.
+fn deliver(state: &Rc<RefCell<OpState>>, mut event: Value, requeue: impl FnOnce(Value)) -> Value {
+    let state = state.borrow();
+    let changed = window::check_scale_factor(&state, &mut event).or_else(|| window::check_fullscreen(&state, &event));
+    match changed {
+        Some(changed) => {
+            requeue(event);
+            changed
+        }
+        None => event,
+    }
+}
+
+pub async fn op_next_event(state: Rc<RefCell<OpState>>, _: (), _: ()) -> Result<Value, AnyError> {
+    loop {
+        let event = EVENT_QUEUE.lock().unwrap().pop_front();
+        if let Some(event) = event {
+            return Ok(deliver(&state, event, |event| EVENT_QUEUE.lock().unwrap().push_front(event)));
+        }
+        if EVENT_PRODUCERS.load(Ordering::SeqCst) == 0 {
+            return Ok(Value::Null);
+        }
+        EVENT_NOTIFY.notified().await;
+    }
+}
+
+pub async fn op_window_next_event(state: Rc<RefCell<OpState>>, id: u32, _: ()) -> Result<Value, AnyError> {
+    loop {
+        let (event, notify) = {
+            let mut queues = WINDOW_QUEUES.lock().unwrap();
+            let queue = match queues.get_mut(&id) {
+                Some(queue) => queue,
+                None => return Ok(Value::Null),
+            };
+            let event = queue.events.pop_front();
+            let notify = queue.notify.clone();
+            // Nothing comes after `destroyed`, so end the stream there.
+            if matches!(&event, Some(event) if event["event"]["type"] == "destroyed") {
+                queues.remove(&id);
+            }
+            (event, notify)
+        };
+        if let Some(event) = event {
+            return Ok(deliver(&state, event, |event| {
+                if let Some(queue) = WINDOW_QUEUES.lock().unwrap().get_mut(&id) {
+                    queue.events.push_front(event);
+                }
+            }));
+        }
+        if EVENT_PRODUCERS.load(Ordering::SeqCst) == 0 {
+            return Ok(Value::Null);
+        }
+        notify.notified().await;
+    }
+}
+
+pub fn op_window_unsubscribe_events(_: &mut OpState, id: u32, _: ()) -> Result<(), AnyError> {
+    unsubscribe_window(id);
+    Ok(())
+}
+
+pub fn op_event_loop_now(_: &mut OpState, _: (), _: ()) -> Result<f64, AnyError> {
+    Ok(clock::now())
+}
+
+pub fn op_set_event_filter(_: &mut OpState, filter: EventFilter, _: ()) -> Result<(), AnyError> {
+    *EVENT_FILTER.write().unwrap() = filter;
+    Ok(())
 }
 
 pub fn init() -> Extension {
     Extension::builder()
         .ops(vec![
             ("op_next_event", op_async(op_next_event)),
+            ("op_window_next_event", op_async(op_window_next_event)),
+            ("op_window_unsubscribe_events", op_sync(op_window_unsubscribe_events)),
+            ("op_event_loop_now", op_sync(op_event_loop_now)),
+            ("op_set_event_filter", op_sync(op_set_event_filter)),
         ])
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor_moved(window: u32, device: u32, x: f64) -> Value {
+        json!({
+            "type": "windowEvent",
+            "windowID": window,
+            "event": { "type": "cursorMoved", "deviceID": device, "position": { "x": x, "y": 0 } },
+        })
+    }
+
+    fn mouse_motion(device: u32, dx: f64, dy: f64, timestamp: f64) -> Value {
+        json!({
+            "type": "deviceEvent",
+            "deviceID": device,
+            "event": { "type": "mouseMotion", "delta": [dx, dy] },
+            "timestamp": timestamp,
+        })
+    }
+
+    fn pointer_move(window: u32, x: f64) -> Value {
+        json!({
+            "type": "windowEvent",
+            "windowID": window,
+            "event": { "type": "pointermove", "pointerId": 1, "buttons": 0, "button": -1, "position": { "x": x, "y": 0 } },
+        })
+    }
+
+    fn coalesce_all(events: Vec<Value>) -> VecDeque<Value> {
+        let mut queue = EventQueue::default();
+        for event in events {
+            queue.push(event, true);
+        }
+        queue.events
+    }
+
+    fn types(queue: &VecDeque<Value>) -> Vec<&str> {
+        queue.iter().map(|event| event["event"]["type"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn merges_cursor_moves_of_a_window() {
+        let queue = coalesce_all(vec![cursor_moved(1, 1, 1.0), cursor_moved(1, 1, 2.0), cursor_moved(1, 1, 3.0)]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0]["event"]["position"]["x"], 3.0);
+    }
+
+    #[test]
+    fn keeps_moves_of_other_windows_and_devices_apart() {
+        let queue = coalesce_all(vec![cursor_moved(1, 1, 1.0), cursor_moved(2, 1, 2.0), cursor_moved(2, 2, 3.0)]);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn merges_interleaved_moves_and_motion() {
+        // What the pump makes of a mouse moving over a window on X11.
+        let mut events = Vec::new();
+        for i in 1..=3 {
+            events.push(mouse_motion(1, 1.0, 2.0, i as f64));
+            events.push(cursor_moved(1, 1, i as f64));
+            events.push(pointer_move(1, i as f64));
+        }
+        let queue = coalesce_all(events);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue[0]["event"]["delta"], json!([3.0, 6.0]));
+        assert_eq!(queue[0]["timestamp"], 3.0);
+        assert_eq!(types(&queue), ["mouseMotion", "cursorMoved", "pointermove"]);
+        assert_eq!(queue[1]["event"]["position"]["x"], 3.0);
+        assert_eq!(queue[2]["event"]["position"]["x"], 3.0);
+    }
+
+    #[test]
+    fn merges_across_events_of_other_windows() {
+        let resized = json!({ "type": "windowEvent", "windowID": 2, "event": { "type": "resized" } });
+        let frame = json!({ "type": "mainEventsCleared" });
+        let queue = coalesce_all(vec![cursor_moved(1, 1, 1.0), resized, frame, cursor_moved(1, 1, 2.0)]);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue[0]["event"]["position"]["x"], 2.0);
+    }
+
+    #[test]
+    fn keeps_moves_on_either_side_of_other_input() {
+        let click = json!({ "type": "windowEvent", "windowID": 1, "event": { "type": "mouseInput", "state": "pressed" } });
+        let button = json!({ "type": "deviceEvent", "deviceID": 1, "event": { "type": "button", "button": 1 } });
+        let queue = coalesce_all(vec![
+            cursor_moved(1, 1, 1.0),
+            pointer_move(1, 1.0),
+            mouse_motion(1, 1.0, 0.0, 1.0),
+            click,
+            button,
+            cursor_moved(1, 1, 2.0),
+            pointer_move(1, 2.0),
+            mouse_motion(1, 1.0, 0.0, 2.0),
+        ]);
+        assert_eq!(queue.len(), 8);
+    }
+
+    #[test]
+    fn starts_over_once_events_are_taken() {
+        let mut queue = EventQueue::default();
+        queue.push(cursor_moved(1, 1, 1.0), true);
+        queue.push(cursor_moved(2, 1, 1.0), true);
+        let taken = queue.pop_front().unwrap();
+        queue.push(cursor_moved(1, 1, 2.0), true);
+        queue.push(cursor_moved(2, 1, 2.0), true);
+        // Put back, as when a scale factor change goes first.
+        queue.push_front(taken);
+        queue.push(cursor_moved(2, 1, 3.0), true);
+        let xs: Vec<_> = queue.events.iter().map(|event| event["event"]["position"]["x"].as_f64().unwrap()).collect();
+        assert_eq!(xs, [1.0, 1.0, 2.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn sums_mouse_motion_deltas() {
+        let queue = coalesce_all(vec![mouse_motion(1, 1.0, 2.0, 10.0), mouse_motion(1, 3.0, -1.0, 20.0), mouse_motion(2, 5.0, 5.0, 30.0)]);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0]["event"]["delta"], json!([4.0, 1.0]));
+        assert_eq!(queue[0]["timestamp"], 20.0);
+    }
+
+    #[test]
+    fn sums_locked_pointer_movement() {
+        let pointer_move = |dx: f64| json!({
+            "type": "windowEvent",
+            "windowID": 1,
+            "event": { "type": "pointermove", "pointerId": 1, "buttons": 0, "button": -1, "movementX": dx, "movementY": 1.0 },
+        });
+        let queue = coalesce_all(vec![pointer_move(2.0), pointer_move(3.0)]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0]["event"]["movementX"], 5.0);
+        assert_eq!(queue[0]["event"]["movementY"], 2.0);
+    }
+
+    #[test]
+    fn filters_serialized_events() {
+        let filter = EventFilter {
+            exclude: ["deviceEvent".to_string()].into_iter().collect(),
+            coalesce_motion: false,
+            pointer_events: true,
+        };
+        let window_event = |event: Value| json!({ "type": "windowEvent", "windowID": 1, "event": event });
+        assert!(!filter.allows(&mouse_motion(1, 1.0, 1.0, 0.0)));
+        assert!(!filter.allows(&cursor_moved(1, 1, 1.0)));
+        assert!(filter.allows(&window_event(json!({ "type": "pointermove", "button": -1 }))));
+        assert!(filter.allows(&window_event(json!({ "type": "resized" }))));
+
+        let filter = EventFilter::default();
+        assert!(filter.allows(&cursor_moved(1, 1, 1.0)));
+        assert!(!filter.allows(&window_event(json!({ "type": "pointerdown", "button": 0 }))));
+        assert!(filter.allows(&window_event(json!({ "type": "pointermove", "movementX": 1.0 }))));
+    }
+
+    #[test]
+    fn keeps_pointer_moves_that_change_buttons() {
+        let pointer_move = |buttons: u32, button: i32| json!({
+            "type": "windowEvent",
+            "windowID": 1,
+            "event": { "type": "pointermove", "pointerId": 1, "buttons": buttons, "button": button },
+        });
+        let queue = coalesce_all(vec![pointer_move(0, -1), pointer_move(1, 0), pointer_move(1, -1)]);
+        assert_eq!(queue.len(), 3);
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use deno_runtime::deno_core::anyhow::anyhow;
+use deno_runtime::deno_core::error::AnyError;
+
+#[derive(Clone)]
+pub struct Flags {
+    pub script: String,
+    /// Write every event to this file as NDJSON.
+    pub record_events: Option<PathBuf>,
+    /// Feed events from a recording made with `--record-events`.
+    pub replay_events: Option<PathBuf>,
+    /// Deliver live events alongside the replayed ones instead of dropping them.
+    pub replay_merge: bool,
+    /// Run without a display, using in-memory windows and only scripted or
+    /// replayed events.
+    pub headless: bool,
+}
+
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Flags, AnyError> {
+    let mut script = None;
+    let mut record_events = None;
+    let mut replay_events = None;
+    let mut replay_merge = false;
+    let mut headless = std::env::var("DENO_DESKTOP_HEADLESS").map_or(false, |var| !var.is_empty() && var != "0");
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-events" => record_events = Some(PathBuf::from(value_of(&arg, args.next())?)),
+            "--replay-events" => replay_events = Some(PathBuf::from(value_of(&arg, args.next())?)),
+            "--replay-merge" => replay_merge = true,
+            "--headless" => headless = true,
+            _ if arg.starts_with("--") => return Err(anyhow!("Unknown flag {}", arg)),
+            _ if script.is_none() => script = Some(arg),
+            _ => return Err(anyhow!("Unexpected argument {}", arg)),
+        }
+    }
+
+    Ok(Flags {
+        script: script.unwrap_or(String::from("test.js")),
+        record_events,
+        replay_events,
+        replay_merge,
+        headless,
+    })
+}
+
+fn value_of(flag: &str, value: Option<String>) -> Result<String, AnyError> {
+    value.ok_or_else(|| anyhow!("Missing value for {}", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Flags, AnyError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_recording_flags_around_the_script() {
+        let flags = parse_args(&["--record-events", "in.ndjson", "app.js", "--replay-events", "out.ndjson", "--replay-merge"]).unwrap();
+        assert_eq!(flags.script, "app.js");
+        assert_eq!(flags.record_events, Some(PathBuf::from("in.ndjson")));
+        assert_eq!(flags.replay_events, Some(PathBuf::from("out.ndjson")));
+        assert!(flags.replay_merge);
+    }
+
+    #[test]
+    fn defaults_to_test_js() {
+        let flags = parse_args(&["--headless"]).unwrap();
+        assert_eq!(flags.script, "test.js");
+        assert!(flags.headless);
+        assert_eq!(flags.record_events, None);
+        assert!(!flags.replay_merge);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse_args(&["--record-events"]).is_err());
+        assert!(parse_args(&["--frobnicate"]).is_err());
+        assert!(parse_args(&["a.js", "b.js"]).is_err());
+    }
+}
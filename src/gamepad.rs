@@ -0,0 +1,440 @@
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Serialize;
+
+use crate::clock;
+use crate::event_loop::push_event;
+
+/// Number of buttons and axes in the standard gamepad mapping.
+/// https://w3c.github.io/gamepad/#remapping
+pub const BUTTON_COUNT: usize = 17;
+pub const AXIS_COUNT: usize = 4;
+
+/// Analog buttons (the triggers) count as pressed past this value, same as
+/// Chromium.
+const PRESS_THRESHOLD: f64 = 30.0 / 255.0;
+
+lazy_static! {
+    /// Connected gamepads by `index`. Slots are freed on disconnect and
+    /// reused by the next gamepad, like in browsers.
+    static ref GAMEPADS: Mutex<Vec<Option<Gamepad>>> = Mutex::new(Vec::new());
+}
+
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct GamepadButton {
+    pressed: bool,
+    touched: bool,
+    value: f64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Gamepad {
+    id: String,
+    index: usize,
+    connected: bool,
+    mapping: &'static str,
+    timestamp: f64,
+    axes: [f64; AXIS_COUNT],
+    buttons: [GamepadButton; BUTTON_COUNT],
+}
+
+impl Gamepad {
+    pub fn set_button(&mut self, index: usize, value: f64) {
+        let pressed = value >= PRESS_THRESHOLD;
+        self.buttons[index] = GamepadButton { pressed, touched: pressed, value };
+    }
+
+    pub fn set_axis(&mut self, index: usize, value: f64) {
+        self.axes[index] = value;
+    }
+}
+
+/// Adds a gamepad with the standard mapping and queues `gamepadconnected`.
+pub fn connect(id: String) -> usize {
+    let mut gamepads = GAMEPADS.lock().unwrap();
+    let index = gamepads.iter().position(Option::is_none).unwrap_or(gamepads.len());
+    let gamepad = Gamepad {
+        id,
+        index,
+        connected: true,
+        mapping: "standard",
+        timestamp: clock::now(),
+        axes: [0.0; AXIS_COUNT],
+        buttons: [GamepadButton::default(); BUTTON_COUNT],
+    };
+
+    if index == gamepads.len() {
+        gamepads.push(None);
+    }
+    gamepads[index] = Some(gamepad.clone());
+    drop(gamepads);
+
+    push_event(json!({ "type": "gamepadconnected", "gamepad": gamepad, "timestamp": gamepad.timestamp }));
+    index
+}
+
+/// Applies `f` to the gamepad at `index`, returning false if there is none.
+/// State changes show up in `navigator.getGamepads()` and don't queue events.
+pub fn update(index: usize, f: impl FnOnce(&mut Gamepad)) -> bool {
+    let mut gamepads = GAMEPADS.lock().unwrap();
+    match gamepads.get_mut(index) {
+        Some(Some(gamepad)) => {
+            f(gamepad);
+            gamepad.timestamp = clock::now();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Removes the gamepad at `index` and queues `gamepaddisconnected`.
+pub fn disconnect(index: usize) -> bool {
+    let gamepad = match GAMEPADS.lock().unwrap().get_mut(index) {
+        Some(slot) => slot.take(),
+        None => None,
+    };
+
+    match gamepad {
+        Some(mut gamepad) => {
+            gamepad.connected = false;
+            gamepad.timestamp = clock::now();
+            push_event(json!({ "type": "gamepaddisconnected", "gamepad": gamepad, "timestamp": gamepad.timestamp }));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Starts watching for gamepads plugged into the machine.
+#[cfg(target_os = "linux")]
+pub fn start() {
+    evdev_backend::spawn();
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start() {}
+
+#[cfg(target_os = "linux")]
+mod evdev_backend {
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use evdev::AbsoluteAxisType;
+    use evdev::Device;
+    use evdev::InputEvent;
+    use evdev::InputEventKind;
+    use evdev::Key;
+    use nix::sys::inotify::AddWatchFlags;
+    use nix::sys::inotify::InitFlags;
+    use nix::sys::inotify::Inotify;
+
+    use super::Gamepad;
+
+    const INPUT_DIR: &str = "/dev/input";
+
+    /// Linux gamepad buttons and their index in the standard mapping.
+    /// https://www.kernel.org/doc/html/latest/input/gamepad.html
+    const BUTTONS: [(Key, usize); 17] = [
+        (Key::BTN_SOUTH, 0),
+        (Key::BTN_EAST, 1),
+        (Key::BTN_WEST, 2),
+        (Key::BTN_NORTH, 3),
+        (Key::BTN_TL, 4),
+        (Key::BTN_TR, 5),
+        (Key::BTN_TL2, 6),
+        (Key::BTN_TR2, 7),
+        (Key::BTN_SELECT, 8),
+        (Key::BTN_START, 9),
+        (Key::BTN_THUMBL, 10),
+        (Key::BTN_THUMBR, 11),
+        (Key::BTN_DPAD_UP, 12),
+        (Key::BTN_DPAD_DOWN, 13),
+        (Key::BTN_DPAD_LEFT, 14),
+        (Key::BTN_DPAD_RIGHT, 15),
+        (Key::BTN_MODE, 16),
+    ];
+
+    /// Value range of each absolute axis, by axis code.
+    type AxisRanges = HashMap<u16, (i32, i32)>;
+
+    fn is_gamepad(device: &Device) -> bool {
+        device.supported_keys().map_or(false, |keys| keys.contains(Key::BTN_SOUTH))
+    }
+
+    /// Maps `value` from the axis' range to 0..1, if the axis has one.
+    fn normalize(ranges: &AxisRanges, axis: AbsoluteAxisType, value: i32) -> Option<f64> {
+        match ranges.get(&axis.0) {
+            Some((min, max)) if max > min => Some((value - min) as f64 / (max - min) as f64),
+            _ => None,
+        }
+    }
+
+    fn apply_axis(gamepad: &mut Gamepad, ranges: &AxisRanges, axis: AbsoluteAxisType, value: i32) {
+        let normalized = normalize(ranges, axis, value);
+        match (axis, normalized) {
+            (AbsoluteAxisType::ABS_X, Some(normalized)) => gamepad.set_axis(0, normalized * 2.0 - 1.0),
+            (AbsoluteAxisType::ABS_Y, Some(normalized)) => gamepad.set_axis(1, normalized * 2.0 - 1.0),
+            (AbsoluteAxisType::ABS_RX, Some(normalized)) => gamepad.set_axis(2, normalized * 2.0 - 1.0),
+            (AbsoluteAxisType::ABS_RY, Some(normalized)) => gamepad.set_axis(3, normalized * 2.0 - 1.0),
+            // Analog triggers.
+            (AbsoluteAxisType::ABS_Z, Some(normalized)) => gamepad.set_button(6, normalized),
+            (AbsoluteAxisType::ABS_RZ, Some(normalized)) => gamepad.set_button(7, normalized),
+            // Most pads report the d-pad as a hat rather than buttons.
+            (AbsoluteAxisType::ABS_HAT0X, _) => {
+                gamepad.set_button(14, (value < 0) as u8 as f64);
+                gamepad.set_button(15, (value > 0) as u8 as f64);
+            }
+            (AbsoluteAxisType::ABS_HAT0Y, _) => {
+                gamepad.set_button(12, (value < 0) as u8 as f64);
+                gamepad.set_button(13, (value > 0) as u8 as f64);
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_event(gamepad: &mut Gamepad, ranges: &AxisRanges, event: InputEvent) {
+        match event.kind() {
+            InputEventKind::Key(key) => {
+                if let Some((_, index)) = BUTTONS.iter().find(|(button, _)| *button == key) {
+                    gamepad.set_button(*index, (event.value() != 0) as u8 as f64);
+                }
+            }
+            InputEventKind::AbsAxis(axis) => apply_axis(gamepad, ranges, axis, event.value()),
+            _ => {}
+        }
+    }
+
+    /// Reads `device` until it goes away, which for evdev means a read error.
+    fn read_device(mut device: Device) {
+        let input_id = device.input_id();
+        // Same id format as Chromium, which some libraries parse.
+        let id = format!(
+            "{} (Vendor: {:04x} Product: {:04x})",
+            device.name().unwrap_or("Unknown gamepad"),
+            input_id.vendor(),
+            input_id.product(),
+        );
+
+        let abs_state = device.get_abs_state().ok();
+        let mut ranges = AxisRanges::new();
+        let mut initial = Vec::new();
+        if let (Some(axes), Some(abs_state)) = (device.supported_absolute_axes(), abs_state) {
+            for axis in axes.iter() {
+                let info = abs_state[axis.0 as usize];
+                ranges.insert(axis.0, (info.minimum, info.maximum));
+                initial.push((axis, info.value));
+            }
+        }
+        let pressed = device.get_key_state().ok();
+
+        let index = super::connect(id);
+        super::update(index, |gamepad| {
+            for (axis, value) in initial {
+                apply_axis(gamepad, &ranges, axis, value);
+            }
+            if let Some(pressed) = pressed {
+                for (button, index) in BUTTONS {
+                    if pressed.contains(button) {
+                        gamepad.set_button(index, 1.0);
+                    }
+                }
+            }
+        });
+
+        loop {
+            let events: Vec<InputEvent> = match device.fetch_events() {
+                Ok(events) => events.collect(),
+                Err(_) => break,
+            };
+            super::update(index, |gamepad| {
+                for event in events {
+                    apply_event(gamepad, &ranges, event);
+                }
+            });
+        }
+
+        super::disconnect(index);
+    }
+
+    /// Starts reading `device` unless it is already being read or isn't a
+    /// gamepad.
+    fn add_device(open: &Arc<Mutex<HashSet<PathBuf>>>, path: PathBuf, device: Device) {
+        if !is_gamepad(&device) || !open.lock().unwrap().insert(path.clone()) {
+            return;
+        }
+        let open = open.clone();
+        std::thread::spawn(move || {
+            read_device(device);
+            open.lock().unwrap().remove(&path);
+        });
+    }
+
+    /// Opens the device nodes that show up in /dev/input, until watching it
+    /// fails.
+    fn watch(open: &Arc<Mutex<HashSet<PathBuf>>>) -> nix::Result<()> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+        // udev only lets us read a node once it has set its permissions,
+        // which may be after creating it.
+        inotify.add_watch(INPUT_DIR, AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB)?;
+        // Watching first, so that no node is missed in between.
+        for (path, device) in evdev::enumerate() {
+            add_device(open, path, device);
+        }
+        loop {
+            for event in inotify.read_events()? {
+                let name = match &event.name {
+                    Some(name) if name.to_string_lossy().starts_with("event") => name,
+                    _ => continue,
+                };
+                let path = Path::new(INPUT_DIR).join(name);
+                if open.lock().unwrap().contains(&path) {
+                    continue;
+                }
+                if let Ok(device) = Device::open(&path) {
+                    add_device(open, path, device);
+                }
+            }
+        }
+    }
+
+    pub fn spawn() {
+        std::thread::spawn(|| {
+            let open: Arc<Mutex<HashSet<PathBuf>>> = Arc::default();
+            if let Err(err) = watch(&open) {
+                eprintln!("Stopped watching for gamepads: {}", err);
+            }
+        });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use evdev::EventType;
+
+        use super::*;
+        use crate::gamepad::AXIS_COUNT;
+        use crate::gamepad::BUTTON_COUNT;
+        use crate::gamepad::GamepadButton;
+
+        fn gamepad() -> Gamepad {
+            Gamepad {
+                id: String::new(),
+                index: 0,
+                connected: true,
+                mapping: "standard",
+                timestamp: 0.0,
+                axes: [0.0; AXIS_COUNT],
+                buttons: [GamepadButton::default(); BUTTON_COUNT],
+            }
+        }
+
+        fn key(key: Key, value: i32) -> InputEvent {
+            InputEvent::new(EventType::KEY, key.code(), value)
+        }
+
+        fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+            InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+        }
+
+        fn pressed(gamepad: &Gamepad) -> Vec<usize> {
+            (0..BUTTON_COUNT).filter(|index| gamepad.buttons[*index].pressed).collect()
+        }
+
+        #[test]
+        fn maps_buttons_to_the_standard_layout() {
+            let mut pad = gamepad();
+            let ranges = AxisRanges::new();
+            for (button, _) in BUTTONS {
+                apply_event(&mut pad, &ranges, key(button, 1));
+            }
+            assert_eq!(pressed(&pad), (0..BUTTON_COUNT).collect::<Vec<_>>());
+
+            apply_event(&mut pad, &ranges, key(Key::BTN_EAST, 0));
+            apply_event(&mut pad, &ranges, key(Key::BTN_MODE, 0));
+            assert!(!pad.buttons[1].pressed);
+            assert_eq!(pad.buttons[1].value, 0.0);
+            assert!(!pad.buttons[16].pressed);
+            // Keys that aren't gamepad buttons change nothing.
+            apply_event(&mut pad, &ranges, key(Key::KEY_A, 0));
+            assert_eq!(pressed(&pad).len(), BUTTON_COUNT - 2);
+        }
+
+        #[test]
+        fn scales_sticks_to_their_range() {
+            let mut pad = gamepad();
+            let mut ranges = AxisRanges::new();
+            ranges.insert(AbsoluteAxisType::ABS_X.0, (-32768, 32767));
+            ranges.insert(AbsoluteAxisType::ABS_Y.0, (0, 255));
+            ranges.insert(AbsoluteAxisType::ABS_RX.0, (0, 100));
+            ranges.insert(AbsoluteAxisType::ABS_RY.0, (0, 100));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_X, -32768));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_Y, 255));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_RX, 50));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_RY, 75));
+            assert_eq!(pad.axes, [-1.0, 1.0, 0.0, 0.5]);
+        }
+
+        #[test]
+        fn leaves_axes_without_a_range_alone() {
+            let mut pad = gamepad();
+            apply_event(&mut pad, &AxisRanges::new(), abs(AbsoluteAxisType::ABS_X, 1000));
+            apply_event(&mut pad, &AxisRanges::new(), abs(AbsoluteAxisType::ABS_Z, 1000));
+            assert_eq!(pad.axes, [0.0; AXIS_COUNT]);
+            assert_eq!(pad.buttons[6].value, 0.0);
+        }
+
+        #[test]
+        fn presses_analog_triggers_past_the_threshold() {
+            let mut pad = gamepad();
+            let mut ranges = AxisRanges::new();
+            ranges.insert(AbsoluteAxisType::ABS_Z.0, (0, 255));
+            ranges.insert(AbsoluteAxisType::ABS_RZ.0, (0, 1023));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_Z, 29));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_RZ, 1023));
+            assert_eq!(pad.buttons[6].value, 29.0 / 255.0);
+            assert!(!pad.buttons[6].pressed);
+            assert_eq!(pad.buttons[7].value, 1.0);
+            assert!(pad.buttons[7].pressed);
+
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_Z, 30));
+            assert!(pad.buttons[6].pressed);
+        }
+
+        #[test]
+        fn maps_the_hat_to_the_d_pad() {
+            let mut pad = gamepad();
+            let ranges = AxisRanges::new();
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_HAT0X, -1));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_HAT0Y, 1));
+            assert_eq!(pressed(&pad), [13, 14]);
+
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_HAT0X, 1));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_HAT0Y, -1));
+            assert_eq!(pressed(&pad), [12, 15]);
+
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_HAT0X, 0));
+            apply_event(&mut pad, &ranges, abs(AbsoluteAxisType::ABS_HAT0Y, 0));
+            assert!(pressed(&pad).is_empty());
+        }
+    }
+}
+
+pub fn op_gamepads(_: &mut OpState, _: (), _: ()) -> Result<Vec<Option<Gamepad>>, AnyError> {
+    Ok(GAMEPADS.lock().unwrap().clone())
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_gamepads", op_sync(op_gamepads)),
+        ])
+        .build()
+}
@@ -0,0 +1,552 @@
+use std::cell::RefCell;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::ResourceId;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::type_error;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Deserialize;
+use winit_main::reexports::dpi::PhysicalPosition;
+use winit_main::reexports::dpi::PhysicalSize;
+use winit_main::reexports::dpi::Position;
+use winit_main::reexports::dpi::Size;
+use winit_main::reexports::window::CursorIcon;
+use winit_main::reexports::window::Icon;
+use winit_main::reexports::window::UserAttentionType;
+use winit_main::reexports::window::WindowAttributes;
+
+use crate::clock;
+use crate::drag::drag_event;
+use crate::event_loop::add_logical_values;
+use crate::event_loop::is_blocked;
+use crate::event_loop::push_event;
+use crate::event_loop::serialize_physical_position;
+use crate::event_loop::serialize_physical_size;
+use crate::event_loop::set_window_scale_factor;
+use crate::gamepad;
+use crate::ids;
+use crate::menu;
+use crate::monitor::virtual_monitor;
+use crate::notification;
+use crate::owned;
+use crate::tray;
+use crate::tray::TrayResource;
+use crate::window::WindowResource;
+use crate::window::WindowRef;
+
+struct HeadlessWindowState {
+    title: String,
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+    min_size: Option<PhysicalSize<u32>>,
+    max_size: Option<PhysicalSize<u32>>,
+    scale_factor: f64,
+    resizable: bool,
+    decorations: bool,
+    maximized: bool,
+    minimized: bool,
+    visible: bool,
+    transparent: bool,
+    always_on_top: bool,
+    /// Same shape as `op_window_fullscreen` returns.
+    fullscreen: Value,
+    window_icon: Option<Icon>,
+    ime_position: PhysicalPosition<i32>,
+    user_attention: Option<UserAttentionType>,
+    cursor_icon: CursorIcon,
+    /// Size and hotspot of the custom cursor image, if one is set.
+    custom_cursor: Option<(PhysicalSize<u32>, PhysicalPosition<u32>)>,
+    cursor_position: PhysicalPosition<i32>,
+    cursor_grab: bool,
+    cursor_visible: bool,
+    theme: Option<&'static str>,
+}
+
+/// In-memory stand-in for a winit `Window`, used when running without a
+/// display. Its methods mirror the subset of winit's API used by the window
+/// ops, and setters queue the events a real window would produce.
+pub struct HeadlessWindow {
+    id: u32,
+    state: RefCell<HeadlessWindowState>,
+}
+
+impl HeadlessWindow {
+    pub fn new(attribs: WindowAttributes) -> Self {
+        let scale_factor = 1.0;
+        let physical = |size: Size| size.to_physical::<u32>(scale_factor);
+
+        HeadlessWindow {
+            id: ids::new_window_id(),
+            state: RefCell::new(HeadlessWindowState {
+                title: attribs.title,
+                position: PhysicalPosition::new(0, 0),
+                size: attribs.inner_size.map(physical).unwrap_or(PhysicalSize::new(800, 600)),
+                min_size: attribs.min_inner_size.map(physical),
+                max_size: attribs.max_inner_size.map(physical),
+                scale_factor,
+                resizable: attribs.resizable,
+                decorations: attribs.decorations,
+                maximized: attribs.maximized,
+                minimized: false,
+                visible: attribs.visible,
+                transparent: attribs.transparent,
+                always_on_top: attribs.always_on_top,
+                fullscreen: match attribs.fullscreen {
+                    Some(_) => json!({ "mode": "borderless", "monitor": virtual_monitor() }),
+                    None => Value::Null,
+                },
+                window_icon: attribs.window_icon,
+                ime_position: PhysicalPosition::new(0, 0),
+                user_attention: None,
+                cursor_icon: CursorIcon::Default,
+                custom_cursor: None,
+                cursor_position: PhysicalPosition::new(0, 0),
+                cursor_grab: false,
+                cursor_visible: true,
+                theme: None,
+            }),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn push_window_event(&self, mut event: Value) {
+        add_logical_values(&mut event, self.scale_factor());
+        push_event(json!({
+            "type": "windowEvent",
+            "windowID": self.id,
+            "event": event,
+            "timestamp": clock::now(),
+        }));
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.state.borrow().scale_factor
+    }
+
+    /// Simulates moving the window to a display with another scale factor.
+    /// Like winit, the logical size is kept.
+    pub fn set_scale_factor(&self, scale_factor: f64) {
+        let mut state = self.state.borrow_mut();
+        if state.scale_factor == scale_factor {
+            return;
+        }
+        let size = state.size.to_logical::<f64>(state.scale_factor).to_physical::<u32>(scale_factor);
+        state.scale_factor = scale_factor;
+        drop(state);
+        set_window_scale_factor(self.id, scale_factor);
+        self.push_window_event(json!({
+            "type": "scaleFactorChanged",
+            "scaleFactor": scale_factor,
+            "newInnerSize": serialize_physical_size(size),
+        }));
+        self.set_inner_size(size);
+    }
+
+    pub fn request_redraw(&self) {
+        push_event(json!({ "type": "redrawRequested", "windowID": self.id, "timestamp": clock::now() }));
+    }
+
+    pub fn inner_position(&self) -> Result<PhysicalPosition<i32>, AnyError> {
+        Ok(self.state.borrow().position)
+    }
+
+    pub fn outer_position(&self) -> Result<PhysicalPosition<i32>, AnyError> {
+        Ok(self.state.borrow().position)
+    }
+
+    pub fn set_outer_position<P: Into<Position>>(&self, position: P) {
+        let mut state = self.state.borrow_mut();
+        let position = Into::<Position>::into(position).to_physical(state.scale_factor);
+        if state.position != position {
+            state.position = position;
+            drop(state);
+            self.push_window_event(json!({ "type": "moved", "position": serialize_physical_position(position) }));
+        }
+    }
+
+    pub fn inner_size(&self) -> PhysicalSize<u32> {
+        self.state.borrow().size
+    }
+
+    pub fn outer_size(&self) -> PhysicalSize<u32> {
+        self.state.borrow().size
+    }
+
+    pub fn set_inner_size<S: Into<Size>>(&self, size: S) {
+        let mut state = self.state.borrow_mut();
+        let mut size: PhysicalSize<u32> = Into::<Size>::into(size).to_physical(state.scale_factor);
+        if let Some(min) = state.min_size {
+            size.width = size.width.max(min.width);
+            size.height = size.height.max(min.height);
+        }
+        if let Some(max) = state.max_size {
+            size.width = size.width.min(max.width);
+            size.height = size.height.min(max.height);
+        }
+        if state.size != size {
+            state.size = size;
+            drop(state);
+            self.push_window_event(json!({ "type": "resized", "size": serialize_physical_size(size) }));
+        }
+    }
+
+    pub fn set_min_inner_size<S: Into<Size>>(&self, min_size: Option<S>) {
+        let mut state = self.state.borrow_mut();
+        let scale_factor = state.scale_factor;
+        state.min_size = min_size.map(|size| Into::<Size>::into(size).to_physical(scale_factor));
+    }
+
+    pub fn set_max_inner_size<S: Into<Size>>(&self, max_size: Option<S>) {
+        let mut state = self.state.borrow_mut();
+        let scale_factor = state.scale_factor;
+        state.max_size = max_size.map(|size| Into::<Size>::into(size).to_physical(scale_factor));
+    }
+
+    pub fn set_title(&self, title: &str) {
+        self.state.borrow_mut().title = title.to_string();
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        self.state.borrow_mut().visible = visible;
+    }
+
+    pub fn set_resizable(&self, resizable: bool) {
+        self.state.borrow_mut().resizable = resizable;
+    }
+
+    pub fn set_minimized(&self, minimized: bool) {
+        self.state.borrow_mut().minimized = minimized;
+    }
+
+    pub fn set_maximized(&self, maximized: bool) {
+        self.state.borrow_mut().maximized = maximized;
+    }
+
+    pub fn set_fullscreen_state(&self, fullscreen: Value) {
+        self.state.borrow_mut().fullscreen = fullscreen;
+    }
+
+    pub fn fullscreen_state(&self) -> Value {
+        self.state.borrow().fullscreen.clone()
+    }
+
+    pub fn set_decorations(&self, decorations: bool) {
+        self.state.borrow_mut().decorations = decorations;
+    }
+
+    pub fn set_always_on_top(&self, always_on_top: bool) {
+        self.state.borrow_mut().always_on_top = always_on_top;
+    }
+
+    pub fn set_window_icon(&self, window_icon: Option<Icon>) {
+        self.state.borrow_mut().window_icon = window_icon;
+    }
+
+    pub fn set_ime_position<P: Into<Position>>(&self, position: P) {
+        let mut state = self.state.borrow_mut();
+        state.ime_position = Into::<Position>::into(position).to_physical(state.scale_factor);
+    }
+
+    pub fn request_user_attention(&self, request_type: Option<UserAttentionType>) {
+        self.state.borrow_mut().user_attention = request_type;
+    }
+
+    pub fn set_cursor_icon(&self, cursor: CursorIcon) {
+        let mut state = self.state.borrow_mut();
+        state.cursor_icon = cursor;
+        state.custom_cursor = None;
+    }
+
+    pub fn set_custom_cursor(&self, width: u32, height: u32, hotspot: (u32, u32)) {
+        let size = PhysicalSize::new(width, height);
+        let hotspot = PhysicalPosition::new(hotspot.0, hotspot.1);
+        self.state.borrow_mut().custom_cursor = Some((size, hotspot));
+    }
+
+    pub fn cursor_position(&self) -> PhysicalPosition<i32> {
+        self.state.borrow().cursor_position
+    }
+
+    pub fn set_cursor_position<P: Into<Position>>(&self, position: P) -> Result<(), AnyError> {
+        let mut state = self.state.borrow_mut();
+        state.cursor_position = Into::<Position>::into(position).to_physical(state.scale_factor);
+        Ok(())
+    }
+
+    pub fn set_cursor_grab(&self, grab: bool) -> Result<(), AnyError> {
+        self.state.borrow_mut().cursor_grab = grab;
+        Ok(())
+    }
+
+    pub fn set_theme(&self, theme: &'static str) {
+        self.state.borrow_mut().theme = Some(theme);
+    }
+
+    pub fn set_cursor_visible(&self, visible: bool) {
+        let mut state = self.state.borrow_mut();
+        state.cursor_visible = visible;
+        // Like winit on X11, which puts back its own cursor.
+        state.custom_cursor = None;
+    }
+
+    /// Everything the window knows about itself, including the properties
+    /// winit has no getters for, so tests can assert on setter calls.
+    fn serialize(&self) -> Value {
+        let state = self.state.borrow();
+        json!({
+            "id": self.id,
+            "title": state.title,
+            "position": serialize_physical_position(state.position),
+            "size": serialize_physical_size(state.size),
+            "minSize": state.min_size.map(serialize_physical_size),
+            "maxSize": state.max_size.map(serialize_physical_size),
+            "scaleFactor": state.scale_factor,
+            "resizable": state.resizable,
+            "decorations": state.decorations,
+            "maximized": state.maximized,
+            "minimized": state.minimized,
+            "visible": state.visible,
+            "transparent": state.transparent,
+            "alwaysOnTop": state.always_on_top,
+            "fullscreen": state.fullscreen,
+            "hasIcon": state.window_icon.is_some(),
+            "imePosition": serialize_physical_position(state.ime_position),
+            "userAttention": match state.user_attention {
+                Some(UserAttentionType::Critical) => Some("critical"),
+                Some(UserAttentionType::Informational) => Some("informational"),
+                None => None,
+            },
+            "cursorIcon": format!("{:?}", state.cursor_icon),
+            "customCursor": state.custom_cursor.map(|(size, hotspot)| json!({
+                "size": serialize_physical_size(size),
+                "hotspot": serialize_physical_position(hotspot),
+            })),
+            "cursorPosition": serialize_physical_position(state.cursor_position),
+            "cursorGrab": state.cursor_grab,
+            "cursorVisible": state.cursor_visible,
+            "theme": state.theme,
+        })
+    }
+}
+
+pub fn op_headless_push_event(
+    _state: &mut OpState,
+    mut event: Value,
+    _: (),
+) -> Result<(), AnyError> {
+    if !event.is_object() {
+        return Err(type_error("Event must be an object"));
+    }
+    event["timestamp"] = json!(clock::now());
+    push_event(event);
+    Ok(())
+}
+
+pub fn op_headless_window_state(
+    state: &mut OpState,
+    rid: ResourceId,
+    _: (),
+) -> Result<Value, AnyError> {
+    let window = WindowResource::get(state, &WindowRef::Rid(rid))?;
+    match &*window {
+        WindowResource::Headless(window) => {
+            let mut serialized = window.serialize();
+            owned::add_state(window.id(), &mut serialized);
+            Ok(serialized)
+        }
+        _ => Err(type_error("Not a headless window")),
+    }
+}
+
+pub fn op_headless_set_scale_factor(
+    state: &mut OpState,
+    args: (ResourceId, f64),
+    _: (),
+) -> Result<(), AnyError> {
+    let (rid, scale_factor) = args;
+    if !(scale_factor.is_finite() && scale_factor > 0.0) {
+        return Err(type_error("Scale factor must be a positive number"));
+    }
+    let window = WindowResource::get(state, &WindowRef::Rid(rid))?;
+    match &*window {
+        WindowResource::Headless(window) => window.set_scale_factor(scale_factor),
+        _ => return Err(type_error("Not a headless window")),
+    }
+    Ok(())
+}
+
+/// Drags `paths` onto the window and drops them at the cursor. A window that
+/// takes no input gets a `dragleave` instead of the `drop`, as from the
+/// event pump.
+pub fn op_headless_drop_files(
+    state: &mut OpState,
+    args: (ResourceId, Vec<String>),
+    _: (),
+) -> Result<(), AnyError> {
+    let (rid, paths) = args;
+    let window = WindowResource::get(state, &WindowRef::Rid(rid))?;
+    let window = match &*window {
+        WindowResource::Headless(window) => window,
+        _ => return Err(type_error("Not a headless window")),
+    };
+    let id = window.id();
+    let paths: Vec<Value> = paths.into_iter().map(Value::from).collect();
+    let position = Some(window.cursor_position().cast::<f64>());
+    let end = if is_blocked(id) { "dragleave" } else { "drop" };
+    for kind in ["dragenter", end] {
+        let mut event = drag_event(id, kind, paths.clone(), position);
+        event["timestamp"] = json!(clock::now());
+        push_event(event);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct GamepadUpdate {
+    /// Button values, starting at button 0 of the standard mapping.
+    #[serde(default)]
+    buttons: Vec<f64>,
+    #[serde(default)]
+    axes: Vec<f64>,
+}
+
+pub fn op_headless_connect_gamepad(
+    _state: &mut OpState,
+    id: String,
+    _: (),
+) -> Result<usize, AnyError> {
+    Ok(gamepad::connect(id))
+}
+
+pub fn op_headless_update_gamepad(
+    _state: &mut OpState,
+    args: (usize, GamepadUpdate),
+    _: (),
+) -> Result<(), AnyError> {
+    let (index, update) = args;
+    if update.buttons.len() > gamepad::BUTTON_COUNT || update.axes.len() > gamepad::AXIS_COUNT {
+        return Err(type_error("Too many buttons or axes for the standard mapping"));
+    }
+    let found = gamepad::update(index, |pad| {
+        for (i, value) in update.buttons.into_iter().enumerate() {
+            pad.set_button(i, value);
+        }
+        for (i, value) in update.axes.into_iter().enumerate() {
+            pad.set_axis(i, value);
+        }
+    });
+    if !found {
+        return Err(type_error("No gamepad at this index"));
+    }
+    Ok(())
+}
+
+pub fn op_headless_disconnect_gamepad(
+    _state: &mut OpState,
+    index: usize,
+    _: (),
+) -> Result<(), AnyError> {
+    if !gamepad::disconnect(index) {
+        return Err(type_error("No gamepad at this index"));
+    }
+    Ok(())
+}
+
+pub fn op_headless_notifications(
+    _state: &mut OpState,
+    _: (),
+    _: (),
+) -> Result<Vec<Value>, AnyError> {
+    Ok(notification::headless_notifications())
+}
+
+pub fn op_headless_click_notification(
+    _state: &mut OpState,
+    args: (u32, String),
+    _: (),
+) -> Result<(), AnyError> {
+    let (id, action) = args;
+    if !notification::headless_click(id, action) {
+        return Err(type_error("No notification with this ID is showing"));
+    }
+    Ok(())
+}
+
+pub fn op_headless_dismiss_notification(
+    _state: &mut OpState,
+    id: u32,
+    _: (),
+) -> Result<(), AnyError> {
+    if !notification::headless_dismiss(id) {
+        return Err(type_error("No notification with this ID is showing"));
+    }
+    Ok(())
+}
+
+/// Clicks a tray icon with `"primary"`, `"secondary"` or `"middle"`.
+pub fn op_headless_click_tray_icon(
+    state: &mut OpState,
+    args: (ResourceId, String),
+    _: (),
+) -> Result<(), AnyError> {
+    let (rid, button) = args;
+    if !matches!(button.as_str(), "primary" | "secondary" | "middle") {
+        return Err(type_error(format!("Unknown tray icon button: {}", button)));
+    }
+    let tray = TrayResource::get(state, rid)?;
+    tray::clicked(tray.id(), &button, 0, 0);
+    Ok(())
+}
+
+pub fn op_headless_activate_tray_menu_item(
+    state: &mut OpState,
+    args: (ResourceId, String),
+    _: (),
+) -> Result<(), AnyError> {
+    let (rid, item_id) = args;
+    if !TrayResource::get(state, rid)?.activate_menu_item(&item_id)? {
+        return Err(type_error(format!("No enabled menu item has ID {}", item_id)));
+    }
+    Ok(())
+}
+
+/// Activates an item of the context menu open on a window, or else of its
+/// window menu.
+pub fn op_headless_activate_menu_item(
+    state: &mut OpState,
+    args: (WindowRef, String),
+    _: (),
+) -> Result<(), AnyError> {
+    let (window, item_id) = args;
+    let id = WindowResource::get(state, &window)?.id();
+    if !menu::headless_activate(id, &item_id) {
+        return Err(type_error(format!("No enabled menu item has ID {}", item_id)));
+    }
+    Ok(())
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_headless_push_event", op_sync(op_headless_push_event)),
+            ("op_headless_window_state", op_sync(op_headless_window_state)),
+            ("op_headless_set_scale_factor", op_sync(op_headless_set_scale_factor)),
+            ("op_headless_drop_files", op_sync(op_headless_drop_files)),
+            ("op_headless_connect_gamepad", op_sync(op_headless_connect_gamepad)),
+            ("op_headless_update_gamepad", op_sync(op_headless_update_gamepad)),
+            ("op_headless_disconnect_gamepad", op_sync(op_headless_disconnect_gamepad)),
+            ("op_headless_notifications", op_sync(op_headless_notifications)),
+            ("op_headless_click_notification", op_sync(op_headless_click_notification)),
+            ("op_headless_dismiss_notification", op_sync(op_headless_dismiss_notification)),
+            ("op_headless_click_tray_icon", op_sync(op_headless_click_tray_icon)),
+            ("op_headless_activate_tray_menu_item", op_sync(op_headless_activate_tray_menu_item)),
+            ("op_headless_activate_menu_item", op_sync(op_headless_activate_menu_item)),
+        ])
+        .build()
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::ResourceId;
+use winit_main::reexports::event::DeviceId;
+use winit_main::reexports::window::WindowId;
+
+/// Hands out the IDs seen by JS for one kind of winit ID. IDs are assigned
+/// sequentially in the order things are first seen, so they never collide
+/// and a given program creating windows in the same order gets the same IDs
+/// on every run.
+struct Ids<K> {
+    next: u32,
+    ids: HashMap<K, u32>,
+}
+
+impl<K: Hash + Eq> Ids<K> {
+    fn new() -> Self {
+        Ids { next: 1, ids: HashMap::new() }
+    }
+
+    fn get(&mut self, key: K) -> u32 {
+        if let Some(id) = self.ids.get(&key) {
+            return *id;
+        }
+        let id = self.reserve();
+        self.ids.insert(key, id);
+        id
+    }
+
+    fn reserve(&mut self) -> u32 {
+        let id = self.next;
+        self.next += 1;
+        id
+    }
+
+    /// Forgets `key`. Its ID is not handed out again.
+    fn remove(&mut self, key: &K) {
+        self.ids.remove(key);
+    }
+}
+
+struct Registry {
+    windows: Ids<WindowId>,
+    window_rids: HashMap<u32, ResourceId>,
+    devices: Ids<DeviceId>,
+}
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+        windows: Ids::new(),
+        window_rids: HashMap::new(),
+        devices: Ids::new(),
+    });
+}
+
+pub fn window_id(window_id: WindowId) -> u32 {
+    REGISTRY.lock().unwrap().windows.get(window_id)
+}
+
+/// Reserves an ID for a window that has no winit `WindowId`, such as a
+/// headless one.
+pub fn new_window_id() -> u32 {
+    REGISTRY.lock().unwrap().windows.reserve()
+}
+
+/// Forgets `window_id` once winit is done with it. The numeric ID is not
+/// handed out again.
+pub fn remove_window(window_id: WindowId) {
+    REGISTRY.lock().unwrap().windows.remove(&window_id);
+}
+
+pub fn set_window_rid(id: u32, rid: ResourceId) {
+    REGISTRY.lock().unwrap().window_rids.insert(id, rid);
+}
+
+pub fn window_rid(id: u32) -> Option<ResourceId> {
+    REGISTRY.lock().unwrap().window_rids.get(&id).copied()
+}
+
+pub fn remove_window_rid(id: u32) {
+    REGISTRY.lock().unwrap().window_rids.remove(&id);
+}
+
+pub fn device_id(device_id: DeviceId) -> u32 {
+    REGISTRY.lock().unwrap().devices.get(device_id)
+}
+
+pub fn remove_device(device_id: DeviceId) {
+    REGISTRY.lock().unwrap().devices.remove(&device_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigns_ids_in_order_of_first_sight() {
+        let mut ids = Ids::new();
+        assert_eq!(ids.get("a"), 1);
+        assert_eq!(ids.get("b"), 2);
+        assert_eq!(ids.get("a"), 1);
+    }
+
+    #[test]
+    fn shares_the_sequence_with_reserved_ids() {
+        let mut ids = Ids::new();
+        assert_eq!(ids.get("a"), 1);
+        assert_eq!(ids.reserve(), 2);
+        assert_eq!(ids.get("b"), 3);
+    }
+
+    #[test]
+    fn never_reuses_ids() {
+        let mut ids = Ids::new();
+        assert_eq!(ids.get("a"), 1);
+        ids.remove(&"a");
+        assert_eq!(ids.get("a"), 2);
+    }
+}
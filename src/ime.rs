@@ -0,0 +1,530 @@
+//! Input methods for the windows that allow them, through an IBus input
+//! context for each. winit 0.24 opens an XIM input context per window too,
+//! but only reports the text it commits, so the runtime talks to IBus itself
+//! and keeps winit's XIM out of it.
+//! https://ibus.github.io/docs/ibus-1.5/IBusInputContext.html
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use winit_main::Blocker;
+use winit_main::reexports::event::ElementState;
+use winit_main::reexports::event::Event;
+use winit_main::reexports::event::ModifiersState;
+use winit_main::reexports::event::WindowEvent;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use zbus::Connection;
+use zbus::Message;
+use zbus::MessageType;
+use zbus::fdo::DBusProxy;
+use zvariant::OwnedObjectPath;
+
+use crate::bus;
+use crate::bus::Waker;
+use crate::clock;
+use crate::event_loop::add_producer;
+use crate::event_loop::push_event;
+use crate::event_loop::remove_producer;
+use crate::ids;
+use crate::x11;
+
+const IBUS: &str = "org.freedesktop.IBus";
+const IBUS_PATH: &str = "/org/freedesktop/IBus";
+const INPUT_CONTEXT: &str = "org.freedesktop.IBus.InputContext";
+
+/// `IBUS_CAP_PREEDIT_TEXT | IBUS_CAP_FOCUS`, so that IBus leaves showing the
+/// preedit text to us.
+const CAPABILITIES: u32 = 1 | 8;
+const SHIFT_MASK: u32 = 1 << 0;
+const CONTROL_MASK: u32 = 1 << 2;
+const MOD1_MASK: u32 = 1 << 3;
+const MOD4_MASK: u32 = 1 << 6;
+const RELEASE_MASK: u32 = 1 << 30;
+
+/// How long a key waits for IBus to say whether it took it, before it goes
+/// on as if there were no input method.
+const KEY_TIMEOUT: Duration = Duration::from_millis(200);
+
+lazy_static! {
+    /// Where the IBus daemon listens, if one is running.
+    static ref ADDRESS: Mutex<Option<String>> = Mutex::new(None);
+    static ref CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+    /// Windows that allow IME while connected to IBus, by window ID.
+    static ref ALLOWED: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+    static ref KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::default());
+}
+
+/// What the pump knows about the keyboard.
+#[derive(Default)]
+struct Keyboard {
+    modifiers: ModifiersState,
+    /// Set once IBus took a key, for the characters winit makes of it.
+    swallow_chars: bool,
+}
+
+/// The IBus connection, served on a thread of its own.
+struct Client {
+    commands: mpsc::Sender<Command>,
+    waker: Waker,
+}
+
+enum Command {
+    Allow { window: u32, allowed: bool, focused: bool },
+    Focus { window: u32, focused: bool },
+    /// Where the candidate window goes, in root window coordinates.
+    Move { window: u32, x: i32, y: i32 },
+    Key { window: u32, keyval: u32, keycode: u32, state: u32, reply: mpsc::Sender<(bool, Vec<Value>)> },
+    Close { window: u32, allowed: bool },
+}
+
+impl Command {
+    /// Whether the command takes away an event producer `set_allowed` added.
+    fn ends_producer(&self) -> bool {
+        matches!(self, Command::Allow { allowed: false, .. } | Command::Close { allowed: true, .. })
+    }
+}
+
+/// The input context of a window.
+struct Context {
+    window: u32,
+    path: String,
+    enabled: bool,
+    /// The last preedit text and cursor, shown again after IBus hides it.
+    preedit: (String, u32),
+}
+
+/// What became of an event handed to `handle_event`.
+pub enum Handled {
+    /// The text of a key the input method took, which comes as a commit.
+    Swallowed,
+    /// The event goes on as usual, followed by the `ime` events it led to.
+    Passed(Vec<Value>),
+}
+
+/// Looks for an IBus daemon. Headless runs only use the one in
+/// `IBUS_ADDRESS`, so that tests can't reach the desktop's. When there is
+/// one, winit is told to open no XIM input context of its own, because then
+/// both would get every key.
+pub fn find_input_method(headless: bool) {
+    let address = match std::env::var("IBUS_ADDRESS") {
+        Ok(address) => Some(address),
+        Err(_) if headless => None,
+        Err(_) => desktop_address(),
+    };
+    if address.is_some() && !headless {
+        std::env::set_var("XMODIFIERS", "@im=none");
+    }
+    *ADDRESS.lock().unwrap() = address;
+}
+
+/// The address of the IBus daemon of the X display, as written by the daemon
+/// to `~/.config/ibus/bus`.
+fn desktop_address() -> Option<String> {
+    let display = std::env::var("DISPLAY").ok()?;
+    let (host, screen) = display.split_once(':')?;
+    let number = screen.split('.').next()?;
+    let host = if host.is_empty() { "unix" } else { host };
+    let machine_id = std::fs::read_to_string("/etc/machine-id")
+        .or_else(|_| std::fs::read_to_string("/var/lib/dbus/machine-id"))
+        .ok()?;
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => Path::new(&std::env::var_os("HOME")?).join(".config"),
+    };
+    let name = format!("{}-{}-{}", machine_id.trim(), host, number);
+    let file = std::fs::read_to_string(config.join("ibus").join("bus").join(name)).ok()?;
+
+    let mut address = None;
+    let mut pid = None;
+    for line in file.lines() {
+        if let Some(value) = line.strip_prefix("IBUS_ADDRESS=") {
+            address = Some(value.to_string());
+        } else if let Some(value) = line.strip_prefix("IBUS_DAEMON_PID=") {
+            pid = Some(value.to_string());
+        }
+    }
+    // The file stays behind when the daemon exits.
+    if !Path::new("/proc").join(pid?).exists() {
+        return None;
+    }
+    address
+}
+
+/// Allows or disallows IME for window `window`, which gets an `ime` event
+/// with `kind: "enabled"` once its input context is ready. Without an input
+/// method nothing happens.
+pub fn set_allowed(window: u32, allowed: bool, focused: bool) {
+    let mut windows = ALLOWED.lock().unwrap();
+    if allowed {
+        if !connect() {
+            return;
+        }
+        // The input method may commit text any time, so the event loop
+        // doesn't end while a window allows it. The serving thread ends that
+        // once the window's last events are out.
+        if windows.insert(window) {
+            add_producer();
+        }
+    } else if !windows.remove(&window) {
+        return;
+    }
+    // Sent with the lock held, so as not to get lost in a disconnect.
+    send(Command::Allow { window, allowed, focused });
+}
+
+/// Puts the candidate window of `window` at `(x, y)`, in physical pixels
+/// relative to the window.
+pub fn set_position(window: u32, x: i32, y: i32) {
+    if !ALLOWED.lock().unwrap().contains(&window) {
+        return;
+    }
+    let (x, y) = match x11::window(window) {
+        Some(xlib_window) => {
+            match x11::with_connection(|conn| x11::to_root(conn, xlib_window, x as i16, y as i16)) {
+                Ok(position) => position,
+                Err(_) => return,
+            }
+        }
+        None => (x, y),
+    };
+    send(Command::Move { window, x, y });
+}
+
+pub fn remove_window(window: u32) {
+    let mut windows = ALLOWED.lock().unwrap();
+    let allowed = windows.remove(&window);
+    send(Command::Close { window, allowed });
+}
+
+/// Connects to IBus unless already connected, returning whether it is.
+fn connect() -> bool {
+    let mut client = CLIENT.lock().unwrap();
+    if client.is_some() {
+        return true;
+    }
+    let mut address = ADDRESS.lock().unwrap();
+    let connected = match &*address {
+        Some(address) => Client::connect(address),
+        None => return false,
+    };
+    match connected {
+        Ok(connected) => {
+            *client = Some(connected);
+            true
+        }
+        Err(err) => {
+            eprintln!("Failed to connect to IBus: {}", err);
+            *address = None;
+            false
+        }
+    }
+}
+
+fn send(command: Command) {
+    if let Some(client) = &*CLIENT.lock().unwrap() {
+        if client.commands.send(command).is_ok() {
+            let _ = client.waker.wake();
+        }
+    }
+}
+
+impl Client {
+    fn connect(address: &str) -> Result<Client, AnyError> {
+        let conn = Connection::new_for_address(address, true)?;
+        DBusProxy::new(&conn)?.add_match(&format!("type='signal',interface='{}'", INPUT_CONTEXT))?;
+        bus::set_nonblocking(&conn)?;
+        let waker = Waker::new()?;
+        let (commands, received) = mpsc::channel();
+        let serving = waker.clone();
+        std::thread::spawn(move || serve(conn, serving, received));
+        Ok(Client { commands, waker })
+    }
+}
+
+fn serve(conn: Connection, waker: Waker, commands: mpsc::Receiver<Command>) {
+    let mut contexts = HashMap::new();
+    let err = loop {
+        for command in commands.try_iter() {
+            let ends_producer = command.ends_producer();
+            if let Err(err) = run(&conn, &mut contexts, command) {
+                eprintln!("Failed to talk to IBus: {}", err);
+            }
+            if ends_producer {
+                remove_producer();
+            }
+        }
+        if let Err(err) = receive(&conn, &mut contexts, &mut push_event) {
+            break AnyError::from(err);
+        }
+        if let Err(err) = waker.wait(&conn) {
+            break err.into();
+        }
+    };
+    eprintln!("Lost the connection to IBus: {}", err);
+
+    // Without IBus no window allows IME.
+    let mut windows = ALLOWED.lock().unwrap();
+    for _ in windows.drain() {
+        remove_producer();
+    }
+    *CLIENT.lock().unwrap() = None;
+    drop(windows);
+    for command in commands.try_iter() {
+        if command.ends_producer() {
+            remove_producer();
+        }
+    }
+}
+
+/// Calls `method` on the input context at `path`.
+fn call<B>(conn: &Connection, path: &str, method: &str, body: &B) -> zbus::Result<Message>
+where
+    B: serde::Serialize + zvariant::Type,
+{
+    conn.call_method(Some(IBUS), path, Some(INPUT_CONTEXT), method, body)
+}
+
+fn run(conn: &Connection, contexts: &mut HashMap<u32, Context>, command: Command) -> Result<(), AnyError> {
+    match command {
+        Command::Allow { window, allowed: true, focused } => {
+            if !contexts.contains_key(&window) {
+                let reply = conn.call_method(Some(IBUS), IBUS_PATH, Some(IBUS), "CreateInputContext", &"deno_desktop")?;
+                let path = reply.body::<OwnedObjectPath>()?.as_str().to_string();
+                call(conn, &path, "SetCapabilities", &CAPABILITIES)?;
+                contexts.insert(window, Context { window, path, enabled: false, preedit: (String::new(), 0) });
+            }
+            let context = contexts.get_mut(&window).unwrap();
+            if !context.enabled {
+                context.enabled = true;
+                push_event(window_event(window, json!({ "type": "ime", "kind": "enabled" })));
+            }
+            if focused {
+                call(conn, &context.path, "FocusIn", &())?;
+            }
+        }
+        Command::Allow { window, allowed: false, .. } => {
+            if let Some(context) = contexts.get_mut(&window).filter(|context| context.enabled) {
+                context.enabled = false;
+                context.preedit = (String::new(), 0);
+                call(conn, &context.path, "Reset", &())?;
+                call(conn, &context.path, "FocusOut", &())?;
+                push_event(window_event(window, json!({ "type": "ime", "kind": "disabled" })));
+            }
+        }
+        Command::Focus { window, focused } => {
+            if let Some(context) = contexts.get(&window).filter(|context| context.enabled) {
+                call(conn, &context.path, if focused { "FocusIn" } else { "FocusOut" }, &())?;
+            }
+        }
+        Command::Move { window, x, y } => {
+            if let Some(context) = contexts.get(&window) {
+                call(conn, &context.path, "SetCursorLocation", &(x, y, 0, 0))?;
+            }
+        }
+        Command::Key { window, keyval, keycode, state, reply } => {
+            let path = match contexts.get(&window).filter(|context| context.enabled) {
+                Some(context) => context.path.clone(),
+                None => return Ok(()),
+            };
+            let handled = call(conn, &path, "ProcessKeyEvent", &(keyval, keycode, state))?.body::<bool>()?;
+            // What the key led to comes before the reply. The pump puts it
+            // after the key event.
+            let mut events = Vec::new();
+            receive(conn, contexts, &mut |event| events.push(event))?;
+            let _ = reply.send((handled, events));
+        }
+        Command::Close { window, .. } => {
+            if let Some(context) = contexts.remove(&window) {
+                conn.call_method(Some(IBUS), &context.path, Some("org.freedesktop.IBus.Service"), "Destroy", &())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hands the events of the signals that came in to `emit`, until there are
+/// no more.
+fn receive(conn: &Connection, contexts: &mut HashMap<u32, Context>, emit: &mut dyn FnMut(Value)) -> zbus::Result<()> {
+    loop {
+        // `receive_message` takes the messages queued during a method call
+        // last in, first out.
+        let message = match conn.receive_specific(|_| Ok(true)) {
+            Ok(message) => message,
+            Err(err) if bus::would_block(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if let Some(event) = signal_event(contexts, &message) {
+            emit(event);
+        }
+    }
+}
+
+fn signal_event(contexts: &mut HashMap<u32, Context>, message: &Message) -> Option<Value> {
+    let header = message.header().ok()?;
+    if header.message_type().ok()? != MessageType::Signal || header.interface().ok()? != Some(INPUT_CONTEXT) {
+        return None;
+    }
+    let path = header.path().ok()??;
+    let context = contexts.values_mut().find(|context| context.path == path.as_str() && context.enabled)?;
+    let event = match header.member().ok()?? {
+        "CommitText" => {
+            let text = ibus_text(&message.body::<zvariant::Value>().ok()?)?;
+            context.preedit = (String::new(), 0);
+            json!({ "type": "ime", "kind": "commit", "text": text })
+        }
+        "UpdatePreeditText" => {
+            let (text, cursor, visible): (zvariant::Value, u32, bool) = message.body().ok()?;
+            context.preedit = (ibus_text(&text)?, cursor);
+            if visible {
+                preedit_event(&context.preedit.0, cursor)
+            } else {
+                preedit_event("", 0)
+            }
+        }
+        "ShowPreeditText" => preedit_event(&context.preedit.0, context.preedit.1),
+        "HidePreeditText" => preedit_event("", 0),
+        _ => return None,
+    };
+    Some(window_event(context.window, event))
+}
+
+/// The string of an `IBusText`, which is a `(sa{sv}sv)` structure of type
+/// name, attachments, text and attributes.
+fn ibus_text(value: &zvariant::Value) -> Option<String> {
+    match value {
+        zvariant::Value::Value(value) => ibus_text(value),
+        zvariant::Value::Structure(text) => match text.fields().get(2) {
+            Some(zvariant::Value::Str(text)) => Some(text.as_str().to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A preedit event with the cursor at character `cursor` of `text`. Like in
+/// winit, the cursor range is in bytes, and empty text has none.
+fn preedit_event(text: &str, cursor: u32) -> Value {
+    let cursor = text.char_indices().nth(cursor as usize).map_or(text.len(), |(index, _)| index);
+    let range = if text.is_empty() { Value::Null } else { json!([cursor, cursor]) };
+    json!({ "type": "ime", "kind": "preedit", "text": text, "cursorRange": range })
+}
+
+fn window_event(window: u32, event: Value) -> Value {
+    json!({ "type": "windowEvent", "windowID": window, "event": event, "timestamp": clock::now() })
+}
+
+/// Hands the keys of X11 windows that allow IME to IBus, which either takes
+/// them, and the `receivedCharacter` events winit makes of them, or lets them
+/// through.
+pub fn handle_event(event: &Event<Blocker>) -> Handled {
+    let (id, event) = match event {
+        Event::WindowEvent { window_id, event } => (ids::window_id(*window_id), event),
+        _ => return Handled::Passed(Vec::new()),
+    };
+    let mut keyboard = KEYBOARD.lock().unwrap();
+    match event {
+        WindowEvent::ModifiersChanged(modifiers) => keyboard.modifiers = *modifiers,
+        WindowEvent::Focused(focused) => {
+            if ALLOWED.lock().unwrap().contains(&id) {
+                send(Command::Focus { window: id, focused: *focused });
+            }
+        }
+        WindowEvent::ReceivedCharacter(_) if keyboard.swallow_chars => return Handled::Swallowed,
+        WindowEvent::KeyboardInput { input, is_synthetic: false, .. } => {
+            keyboard.swallow_chars = false;
+            if ALLOWED.lock().unwrap().contains(&id) && x11::window(id).is_some() {
+                let (handled, events) = process_key(id, input.scancode, keyboard.modifiers, input.state);
+                keyboard.swallow_chars = handled;
+                return Handled::Passed(events);
+            }
+        }
+        _ => {}
+    }
+    Handled::Passed(Vec::new())
+}
+
+/// Asks IBus whether it takes a key, returning its answer and what the key
+/// led to.
+fn process_key(window: u32, scancode: u32, modifiers: ModifiersState, state: ElementState) -> (bool, Vec<Value>) {
+    // winit's scancodes are X keycodes less 8, which IBus takes as is.
+    let keysyms = x11::with_connection(|conn| {
+        Ok(conn.get_keyboard_mapping((scancode + 8) as u8, 1)?.reply()?.keysyms)
+    });
+    let (keyval, state) = match keysyms {
+        Ok(keysyms) => ibus_key(&keysyms, modifiers, state == ElementState::Pressed),
+        Err(_) => return (false, Vec::new()),
+    };
+    let (reply, result) = mpsc::channel();
+    send(Command::Key { window, keyval, keycode: scancode, state, reply });
+    result.recv_timeout(KEY_TIMEOUT).unwrap_or_default()
+}
+
+/// The keyval and state IBus takes for a key, given the keysyms X maps its
+/// keycode to, the first being the unshifted one and the second the shifted
+/// one.
+fn ibus_key(keysyms: &[u32], modifiers: ModifiersState, pressed: bool) -> (u32, u32) {
+    let unshifted = keysyms.first().copied().unwrap_or(0);
+    let keyval = match keysyms.get(1).copied() {
+        _ if !modifiers.shift() => unshifted,
+        Some(shifted) if shifted != 0 => shifted,
+        // Letters may leave the shifted keysym to the case rules.
+        _ if (0x61..=0x7a).contains(&unshifted) => unshifted - 0x20,
+        _ => unshifted,
+    };
+    let mut state = 0;
+    for (set, mask) in [
+        (modifiers.shift(), SHIFT_MASK),
+        (modifiers.ctrl(), CONTROL_MASK),
+        (modifiers.alt(), MOD1_MASK),
+        (modifiers.logo(), MOD4_MASK),
+        (!pressed, RELEASE_MASK),
+    ] {
+        if set {
+            state |= mask;
+        }
+    }
+    (keyval, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_keysym_for_shift() {
+        // a, A
+        let keysyms = [0x61, 0x41, 0x61, 0x41];
+        assert_eq!(ibus_key(&keysyms, ModifiersState::empty(), true), (0x61, 0));
+        assert_eq!(ibus_key(&keysyms, ModifiersState::SHIFT, true), (0x41, SHIFT_MASK));
+        // Without a shifted keysym, letters still get their capital.
+        assert_eq!(ibus_key(&[0x62, 0], ModifiersState::SHIFT, true), (0x42, SHIFT_MASK));
+        // 1 has no shifted keysym here, so shift keeps it.
+        assert_eq!(ibus_key(&[0x31], ModifiersState::SHIFT, true), (0x31, SHIFT_MASK));
+        assert_eq!(ibus_key(&[], ModifiersState::empty(), true), (0, 0));
+    }
+
+    #[test]
+    fn maps_modifiers_and_release_to_the_x_state() {
+        let keysyms = [0x20];
+        let all = ModifiersState::SHIFT | ModifiersState::CTRL | ModifiersState::ALT | ModifiersState::LOGO;
+        assert_eq!(ibus_key(&keysyms, ModifiersState::CTRL, true), (0x20, CONTROL_MASK));
+        assert_eq!(ibus_key(&keysyms, ModifiersState::ALT, false), (0x20, MOD1_MASK | RELEASE_MASK));
+        assert_eq!(ibus_key(&keysyms, all, true).1, SHIFT_MASK | CONTROL_MASK | MOD1_MASK | MOD4_MASK);
+    }
+
+    #[test]
+    fn puts_the_preedit_cursor_at_a_byte_offset() {
+        assert_eq!(preedit_event("にほん", 2)["cursorRange"], json!([6, 6]));
+        assert_eq!(preedit_event("にほん", 3)["cursorRange"], json!([9, 9]));
+        assert_eq!(preedit_event("にほん", 7)["cursorRange"], json!([9, 9]));
+        assert_eq!(preedit_event("", 0)["cursorRange"], Value::Null);
+    }
+}
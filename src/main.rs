@@ -1,36 +1,83 @@
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(target_os = "linux")]
+mod bus;
+#[cfg(target_os = "linux")]
+mod canvas;
+mod clipboard;
+mod clock;
+mod cursor;
+mod dialog;
+mod drag;
 mod runtime;
 mod event_loop;
+mod flags;
+mod gamepad;
+mod pointer;
+mod headless;
+#[cfg(target_os = "linux")]
+mod ime;
+mod menu;
+mod monitor;
+mod notification;
+mod owned;
+mod ids;
+mod record;
+mod shortcuts;
 mod surface;
-mod util;
+mod tray;
 mod window;
 mod extra;
+#[cfg(target_os = "linux")]
+mod x11;
 
+use deno_runtime::deno_core::error::AnyError;
 use winit_main::EventLoopHandle;
-use winit_main::EventReceiver;
 
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::flags::Flags;
+
 lazy_static! {
     pub static ref EVENT_LOOP: Mutex<Option<Arc<Mutex<EventLoopHandle>>>> = Mutex::new(None);
-    pub static ref EVENT_RECEIVER: Mutex<Option<Arc<Mutex<EventReceiver>>>> = Mutex::new(None);
 }
 
-#[winit_main::main]
-fn main(event_loop: EventLoopHandle, events: EventReceiver) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let arc_events = Arc::new(Mutex::new(events));
+fn exit_on_error<T>(result: Result<T, AnyError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
 
-    rt.block_on(async {
-        *EVENT_LOOP.lock().unwrap() = Some(Arc::new(Mutex::new(event_loop)));
-        *EVENT_RECEIVER.lock().unwrap() = Some(arc_events);
+fn main() {
+    clock::init();
 
-        match runtime::start().await {
-            Err(err) => eprintln!("{}", err.to_string()),
-            _ => {},
-        }
-    });
+    let flags = exit_on_error(flags::parse(std::env::args().skip(1)));
+    // Before winit reads XMODIFIERS.
+    #[cfg(target_os = "linux")]
+    ime::find_input_method(flags.headless);
+
+    if flags.headless {
+        // No winit event loop at all, so this also works without a display.
+        exit_on_error(event_loop::start(None, &flags));
+        run(flags);
+    } else {
+        winit_main::run(move |event_loop, events| {
+            exit_on_error(event_loop::start(Some(events), &flags));
+            *EVENT_LOOP.lock().unwrap() = Some(Arc::new(Mutex::new(event_loop)));
+            run(flags);
+        });
+    }
+}
+
+fn run(flags: Flags) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    // A script that throws fails the process, for tests and CI to notice.
+    exit_on_error(rt.block_on(runtime::start(flags)));
 }
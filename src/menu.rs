@@ -0,0 +1,1751 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::future::Future;
+use std::ops::Index;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_async;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Deserialize;
+use tokio::sync::oneshot;
+use winit_main::Blocker;
+use winit_main::reexports::dpi::PhysicalPosition;
+use winit_main::reexports::dpi::PhysicalSize;
+use winit_main::reexports::event::ElementState;
+use winit_main::reexports::event::Event;
+use winit_main::reexports::event::KeyboardInput;
+use winit_main::reexports::event::ModifiersState;
+use winit_main::reexports::event::MouseButton;
+use winit_main::reexports::event::VirtualKeyCode;
+use winit_main::reexports::event::WindowEvent;
+
+use crate::clock;
+use crate::event_loop::push_event;
+use crate::ids;
+use crate::shortcuts::Accelerator;
+use crate::shortcuts::accelerator_parts;
+use crate::shortcuts::parse_accelerator;
+use crate::window::WindowRef;
+use crate::window::WindowResource;
+use crate::window::window_units;
+use render::Surfaces;
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MenuItemType {
+    Normal,
+    Separator,
+    Checkbox,
+    /// Adjacent radio items form a group, of which one is checked.
+    Radio,
+}
+
+impl Default for MenuItemType {
+    fn default() -> Self {
+        MenuItemType::Normal
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuItem {
+    #[serde(default, rename = "type")]
+    kind: MenuItemType,
+    /// Reported by `menuItemActivated`. Items without one do nothing.
+    id: Option<String>,
+    #[serde(default)]
+    label: String,
+    /// Such as `"CmdOrCtrl+S"`, as for shortcuts.
+    accelerator: Option<String>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default)]
+    checked: bool,
+    #[serde(default)]
+    submenu: Vec<MenuItem>,
+}
+
+/// A menu item with its submenu replaced by the positions of its children in
+/// the flattened menu.
+#[derive(Clone)]
+pub struct MenuEntry {
+    pub id: Option<String>,
+    pub label: String,
+    pub kind: MenuItemType,
+    pub enabled: bool,
+    pub checked: bool,
+    pub accelerator: Option<Accelerator>,
+    /// The accelerator the way menus spell it, e.g. `["Control", "S"]`.
+    pub accelerator_parts: Vec<String>,
+    pub parent: usize,
+    pub children: Vec<usize>,
+}
+
+impl MenuEntry {
+    /// Whether the item can be hovered and clicked.
+    pub fn is_selectable(&self) -> bool {
+        self.enabled && self.kind != MenuItemType::Separator
+    }
+}
+
+/// A menu template flattened depth first behind a root entry, so that an
+/// item's position is its ID. DBusMenu clients know items by it.
+#[derive(Clone)]
+pub struct Menu {
+    entries: Vec<MenuEntry>,
+}
+
+impl Menu {
+    pub fn new(items: Vec<MenuItem>) -> Result<Menu, AnyError> {
+        fn add(entries: &mut Vec<MenuEntry>, parent: usize, items: Vec<MenuItem>) -> Result<Vec<usize>, AnyError> {
+            let mut children = Vec::new();
+            for item in items {
+                let index = entries.len();
+                let accelerator = match &item.accelerator {
+                    Some(accelerator) => Some(parse_accelerator(accelerator)?),
+                    None => None,
+                };
+                entries.push(MenuEntry {
+                    id: item.id,
+                    label: item.label,
+                    kind: item.kind,
+                    enabled: item.enabled,
+                    checked: item.checked,
+                    accelerator,
+                    accelerator_parts: item.accelerator.as_deref().map(accelerator_parts).unwrap_or_default(),
+                    parent,
+                    children: Vec::new(),
+                });
+                entries[index].children = add(entries, index, item.submenu)?;
+                children.push(index);
+            }
+            Ok(children)
+        }
+
+        let mut entries = vec![MenuEntry {
+            id: None,
+            label: String::new(),
+            kind: MenuItemType::Normal,
+            enabled: true,
+            checked: false,
+            accelerator: None,
+            accelerator_parts: Vec::new(),
+            parent: 0,
+            children: Vec::new(),
+        }];
+        entries[0].children = add(&mut entries, 0, items)?;
+        Ok(Menu { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&MenuEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn find(&self, item_id: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id.as_deref() == Some(item_id))
+    }
+
+    fn find_accelerator(&self, accelerator: Accelerator) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.accelerator == Some(accelerator) && entry.is_selectable())
+    }
+
+    /// How many levels of items there are, which is how many popups it can
+    /// take to show the menu.
+    fn depth(&self) -> usize {
+        fn depth(menu: &Menu, index: usize) -> usize {
+            menu[index].children.iter().map(|&child| 1 + depth(menu, child)).max().unwrap_or(0)
+        }
+        depth(self, 0)
+    }
+
+    /// Activates the item at `index` as if clicked: checkboxes toggle, and
+    /// radio items get checked with the rest of their group unchecked.
+    /// Returns the item's ID and whether it's now checked, or `None` if it
+    /// can't be activated.
+    pub fn activate(&mut self, index: usize) -> Option<(String, bool)> {
+        let entry = self.entries.get(index)?;
+        if !entry.is_selectable() || !entry.children.is_empty() {
+            return None;
+        }
+        let (id, kind, checked) = (entry.id.clone()?, entry.kind, entry.checked);
+        match kind {
+            MenuItemType::Checkbox => self.entries[index].checked = !checked,
+            MenuItemType::Radio => {
+                for sibling in self.radio_group(index) {
+                    self.entries[sibling].checked = sibling == index;
+                }
+            }
+            MenuItemType::Normal | MenuItemType::Separator => {}
+        }
+        Some((id, self.entries[index].checked))
+    }
+
+    /// The radio items next to the one at `index`, and it.
+    fn radio_group(&self, index: usize) -> Vec<usize> {
+        let siblings = &self.entries[self.entries[index].parent].children;
+        let position = siblings.iter().position(|&sibling| sibling == index).unwrap();
+        let is_radio = |sibling: &&usize| self.entries[**sibling].kind == MenuItemType::Radio;
+        let before = siblings[..position].iter().rev().take_while(is_radio).count();
+        let after = siblings[position..].iter().take_while(is_radio).count();
+        siblings[position - before..position + after].to_vec()
+    }
+}
+
+impl Index<usize> for Menu {
+    type Output = MenuEntry;
+
+    fn index(&self, index: usize) -> &MenuEntry {
+        &self.entries[index]
+    }
+}
+
+fn accelerator_label(entry: &MenuEntry) -> String {
+    let parts: Vec<_> = entry
+        .accelerator_parts
+        .iter()
+        .map(|part| if part == "Control" { "Ctrl" } else { part.as_str() })
+        .collect();
+    parts.join("+")
+}
+
+// In logical pixels, scaled by the owner's scale factor.
+const FONT_SIZE: f64 = 13.0;
+const ITEM_HEIGHT: f64 = 22.0;
+/// Including the border along its bottom.
+const BAR_HEIGHT: f64 = 24.0;
+const SEPARATOR_HEIGHT: f64 = 9.0;
+/// Above and below the items of menus, and either end of menu bars.
+const PADDING: f64 = 4.0;
+/// Room for check and radio marks.
+const MARK_WIDTH: f64 = 24.0;
+/// Between labels and accelerators.
+const GAP: f64 = 24.0;
+/// Room for the submenu arrow.
+const ARROW_WIDTH: f64 = 18.0;
+/// Either side of the labels of menu bar items.
+const BAR_ITEM_PADDING: f64 = 10.0;
+const MIN_WIDTH: f64 = 120.0;
+
+fn px(logical: f64, scale: f64) -> i32 {
+    (logical * scale).round() as i32
+}
+
+/// Borders are a whole number of pixels, at least one.
+fn border(scale: f64) -> i32 {
+    (scale.round() as i32).max(1)
+}
+
+fn font_size(scale: f64) -> f32 {
+    (FONT_SIZE * scale) as f32
+}
+
+/// Rounds `size` up to a multiple of the integer scale, which Wayland
+/// buffer sizes have to be.
+fn whole(size: i32, scale: f64) -> i32 {
+    let step = border(scale);
+    (size + step - 1) / step * step
+}
+
+fn text_width(text: &str, scale: f64) -> i32 {
+    render::text_width(text, font_size(scale)).ceil() as i32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Rect {
+    fn contains(&self, position: PhysicalPosition<f64>) -> bool {
+        let (x, y) = (position.x, position.y);
+        x >= self.x as f64
+            && y >= self.y as f64
+            && x < (self.x + self.width) as f64
+            && y < (self.y + self.height) as f64
+    }
+}
+
+/// Lays out the children of `entry`, as a menu or a menu bar. Returns them
+/// with where they are in the popup, and the popup's size.
+fn layout(menu: &Menu, entry: usize, horizontal: bool, scale: f64) -> (Vec<(usize, Rect)>, i32, i32) {
+    let children = &menu[entry].children;
+    let border = border(scale);
+    let item_height = px(ITEM_HEIGHT, scale);
+    let mut items = Vec::new();
+
+    if horizontal {
+        let height = whole(px(BAR_HEIGHT, scale), scale);
+        let mut x = px(PADDING, scale);
+        for &child in children.iter().filter(|&&child| menu[child].kind != MenuItemType::Separator) {
+            let width = text_width(&menu[child].label, scale) + 2 * px(BAR_ITEM_PADDING, scale);
+            items.push((child, Rect { x, y: 0, width, height: height - border }));
+            x += width;
+        }
+        return (items, whole(x + px(PADDING, scale), scale), height);
+    }
+
+    let labels = children.iter().map(|&child| text_width(&menu[child].label, scale)).max().unwrap_or(0);
+    let accelerators = children
+        .iter()
+        .map(|&child| text_width(&accelerator_label(&menu[child]), scale))
+        .max()
+        .unwrap_or(0);
+    let mut width = px(MARK_WIDTH, scale) + labels + px(ARROW_WIDTH, scale);
+    if accelerators > 0 {
+        width += px(GAP, scale) + accelerators;
+    }
+    // Inside a border.
+    let width = whole((width + 2 * border).max(px(MIN_WIDTH, scale)), scale);
+    let mut y = px(PADDING, scale);
+    for &child in children {
+        let height = match menu[child].kind {
+            MenuItemType::Separator => px(SEPARATOR_HEIGHT, scale),
+            _ => item_height,
+        };
+        items.push((child, Rect { x: border, y, width: width - 2 * border, height }));
+        y += height;
+    }
+    (items, width, whole(y + px(PADDING, scale), scale))
+}
+
+/// The menu bar of a window menu, along the top of a window `width` wide.
+fn strip(menu: &Menu, scale: f64, width: u32) -> Popup {
+    let (items, _, height) = layout(menu, 0, true, scale);
+    let bounds = Rect { x: 0, y: 0, width: width.max(1) as i32, height };
+    Popup { entry: 0, items, horizontal: true, bounds, hovered: None }
+}
+
+/// Where a popup goes, in menu coordinates.
+enum Anchor {
+    /// With its top left corner at a point.
+    Point(i32, i32),
+    /// Below a menu bar item.
+    Below(Rect),
+    /// Beside a menu item, lined up with it past the popup's padding.
+    Beside(Rect, i32),
+}
+
+/// Places a popup of the given size at `anchor`, flipped and pushed back
+/// onto a screen of `screen_width` by `screen_height` if it would go past its
+/// edges.
+fn place(anchor: Anchor, width: i32, height: i32, (screen_width, screen_height): (i32, i32)) -> Rect {
+    let (x, y) = match anchor {
+        Anchor::Point(x, y) => (
+            if x + width > screen_width { x - width } else { x },
+            if y + height > screen_height { y - height } else { y },
+        ),
+        Anchor::Below(item) => (
+            item.x,
+            if item.y + item.height + height > screen_height { item.y - height } else { item.y + item.height },
+        ),
+        Anchor::Beside(item, padding) => (
+            if item.x + item.width + width > screen_width { item.x - width } else { item.x + item.width },
+            item.y - padding,
+        ),
+    };
+    Rect {
+        x: x.min(screen_width - width).max(0),
+        y: y.min(screen_height - height).max(0),
+        width,
+        height,
+    }
+}
+
+/// One level of an open menu, or the menu bar of a window menu.
+#[derive(Clone)]
+struct Popup {
+    /// The entry whose children it shows.
+    entry: usize,
+    /// The children, with where they are in the popup.
+    items: Vec<(usize, Rect)>,
+    horizontal: bool,
+    /// Where the popup is, in menu coordinates.
+    bounds: Rect,
+    /// Index into `items`.
+    hovered: Option<usize>,
+}
+
+impl Popup {
+    fn item_at(&self, menu: &Menu, position: PhysicalPosition<f64>) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|(entry, rect)| rect.contains(position) && menu[*entry].is_selectable())
+    }
+
+    /// The next selectable item after `from`, or before it for a negative
+    /// `step`, wrapping around.
+    fn step(&self, menu: &Menu, from: Option<usize>, step: isize) -> Option<usize> {
+        let len = self.items.len() as isize;
+        let mut item = from.map_or(if step > 0 { -1 } else { len }, |item| item as isize);
+        for _ in 0..len {
+            item = (item + step).rem_euclid(len);
+            if menu[self.items[item as usize].0].is_selectable() {
+                return Some(item as usize);
+            }
+        }
+        None
+    }
+}
+
+enum MenuKind {
+    /// A window's menu, open from its menu bar.
+    Bar,
+    /// Answered with the ID of the chosen item.
+    Context(oneshot::Sender<Option<String>>),
+}
+
+/// What a popup did with input.
+enum Outcome {
+    None,
+    Activate(usize),
+    Dismiss,
+}
+
+struct OpenMenu {
+    /// The window the menu belongs to, which takes no input while it's open.
+    owner: u32,
+    menu: Menu,
+    kind: MenuKind,
+    surfaces: Surfaces,
+    scale: f64,
+    /// What popups have to fit in, in menu coordinates.
+    screen: (i32, i32),
+    /// The levels showing, each in the surface of the same index. A window
+    /// menu's bar is the first.
+    popups: Vec<Popup>,
+}
+
+impl OpenMenu {
+    /// Closes the popups from `level` down.
+    fn close_levels(&mut self, level: usize) {
+        self.popups.truncate(level);
+    }
+
+    /// Shows the children of `entry` at `level`, in place of what was there
+    /// and with the levels below closed.
+    fn show_level(&mut self, level: usize, entry: usize, anchor: Anchor) {
+        if !self.surfaces.has_level(level) {
+            return;
+        }
+        let horizontal = level == 0 && matches!(self.kind, MenuKind::Bar);
+        let (items, width, height) = layout(&self.menu, entry, horizontal, self.scale);
+        let bounds = place(anchor, width, height, self.screen);
+        self.popups.truncate(level);
+        self.popups.push(Popup { entry, items, horizontal, bounds, hovered: None });
+    }
+
+    fn select(&mut self, level: usize, item: Option<usize>) {
+        self.close_levels(level + 1);
+        self.popups[level].hovered = item;
+    }
+
+    /// Shows the submenu of `item` at `level`, if it has one.
+    fn open_submenu(&mut self, level: usize, item: usize, hover_first: bool) {
+        let popup = &self.popups[level];
+        let (entry, rect) = popup.items[item];
+        if self.menu[entry].children.is_empty() {
+            return;
+        }
+        let bounds = Rect { x: popup.bounds.x + rect.x, y: popup.bounds.y + rect.y, ..rect };
+        let anchor = if popup.horizontal {
+            Anchor::Below(bounds)
+        } else {
+            Anchor::Beside(bounds, px(PADDING, self.scale))
+        };
+        self.show_level(level + 1, entry, anchor);
+        if hover_first && self.popups.len() > level + 1 {
+            let first = self.popups[level + 1].step(&self.menu, None, 1);
+            self.select(level + 1, first);
+        }
+    }
+
+    /// Moves along the menu bar and drops down the menu there.
+    fn move_bar(&mut self, step: isize) {
+        let item = self.popups[0].step(&self.menu, self.popups[0].hovered, step);
+        self.select(0, item);
+        if let Some(item) = item {
+            self.open_submenu(0, item, true);
+        }
+    }
+
+    fn cursor_moved(&mut self, level: usize, position: PhysicalPosition<f64>) {
+        let popup = &self.popups[level];
+        let item = popup.item_at(&self.menu, position);
+        if item == popup.hovered {
+            return;
+        }
+        if popup.horizontal {
+            // The bar follows the cursor once one of its menus is open.
+            let item = match item {
+                Some(item) => item,
+                None => return,
+            };
+            let dropped = self.popups.len() > 1;
+            self.select(0, Some(item));
+            if dropped {
+                self.open_submenu(0, item, false);
+            }
+        } else {
+            // Crossing a separator on the way to an open submenu keeps it.
+            if item.is_none() && self.popups.len() > level + 1 {
+                return;
+            }
+            self.select(level, item);
+            if let Some(item) = item {
+                self.open_submenu(level, item, false);
+            }
+        }
+    }
+
+    fn pressed(&mut self, level: usize, position: PhysicalPosition<f64>) -> Outcome {
+        let item = match self.popups[level].item_at(&self.menu, position) {
+            Some(item) => item,
+            None => return Outcome::None,
+        };
+        let entry = self.popups[level].items[item].0;
+        if self.menu[entry].children.is_empty() {
+            return Outcome::Activate(entry);
+        }
+        if self.popups[level].horizontal && self.popups.len() > 1 && self.popups[0].hovered == Some(item) {
+            // Clicking the title of the menu that's down puts it away.
+            return Outcome::Dismiss;
+        }
+        self.select(level, Some(item));
+        self.open_submenu(level, item, false);
+        Outcome::None
+    }
+
+    /// Handles a key press, which the menu takes over from its owner.
+    fn key(&mut self, key: VirtualKeyCode) -> Outcome {
+        use VirtualKeyCode::*;
+
+        let level = match self.popups.len() {
+            0 => return Outcome::None,
+            len => len - 1,
+        };
+        let bar = self.popups[0].horizontal;
+        let horizontal = self.popups[level].horizontal;
+        let hovered = self.popups[level].hovered;
+        let has_submenu = |menu: &OpenMenu, item: usize| !menu.menu[menu.popups[level].items[item].0].children.is_empty();
+
+        match key {
+            Escape if level == 0 => return Outcome::Dismiss,
+            Escape => self.close_levels(level),
+            Up | Down if horizontal => {
+                if let Some(item) = hovered {
+                    self.open_submenu(level, item, true);
+                }
+            }
+            Up | Down => {
+                let item = self.popups[level].step(&self.menu, hovered, if key == Up { -1 } else { 1 });
+                self.select(level, item);
+            }
+            Left | Right if horizontal => {
+                let item = self.popups[level].step(&self.menu, hovered, if key == Left { -1 } else { 1 });
+                self.select(level, item);
+            }
+            Right => match hovered {
+                Some(item) if has_submenu(self, item) => self.open_submenu(level, item, true),
+                _ if bar => self.move_bar(1),
+                _ => {}
+            },
+            Left if bar && level == 1 => self.move_bar(-1),
+            Left if level > 0 => self.close_levels(level),
+            Return | NumpadEnter | Space => {
+                if let Some(item) = hovered {
+                    if has_submenu(self, item) {
+                        self.open_submenu(level, item, true);
+                    } else {
+                        return Outcome::Activate(self.popups[level].items[item].0);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Outcome::None
+    }
+}
+
+/// A menu set with `setMenu`.
+struct WindowMenu {
+    menu: Menu,
+    /// Lent to the menu while it's open.
+    surfaces: Surfaces,
+    /// The window's scale factor and inner size.
+    scale: f64,
+    size: PhysicalSize<u32>,
+    /// The menu bar while the menu is closed, `None` for headless windows
+    /// and where there's nothing to draw in.
+    bar: Option<Popup>,
+}
+
+#[derive(Default)]
+struct State {
+    window_menus: HashMap<u32, WindowMenu>,
+    /// There is only ever one menu open.
+    open: Option<OpenMenu>,
+    /// Every popup window, including dropped ones winit hasn't reported
+    /// destroyed yet. Their events are ours.
+    popup_windows: HashSet<u32>,
+    /// Where the cursor was last seen in each window.
+    cursors: HashMap<u32, PhysicalPosition<f64>>,
+    modifiers: ModifiersState,
+    /// The button that dismissed the last menu by being pressed outside of
+    /// it, whose release is dropped too.
+    dismissing: Option<(u32, MouseButton)>,
+    /// Windows whose menus changed since they were last drawn.
+    dirty: HashSet<u32>,
+    /// Subsurfaces of menus that are gone, which only the event pump can
+    /// destroy.
+    retired: Vec<Surfaces>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+/// Lets go of the surfaces of a menu that's gone. Popup windows go right
+/// away, so a strip goes before the window it's in does.
+fn retire(state: &mut State, surfaces: Surfaces) {
+    if surfaces.is_overlay() {
+        state.retired.push(surfaces);
+    }
+}
+
+/// Closes the open menu, answering context menus with `chosen`.
+fn close(state: &mut State, chosen: Option<String>) {
+    let open = match state.open.take() {
+        Some(open) => open,
+        None => return,
+    };
+    let OpenMenu { owner, menu, kind, surfaces, .. } = open;
+    state.dirty.insert(owner);
+    match kind {
+        MenuKind::Bar => match state.window_menus.get_mut(&owner) {
+            // Checkboxes and radio items keep their state.
+            Some(window_menu) => {
+                window_menu.menu = menu;
+                window_menu.surfaces = surfaces;
+            }
+            None => retire(state, surfaces),
+        },
+        MenuKind::Context(respond) => {
+            retire(state, surfaces);
+            // JS may have stopped waiting.
+            let _ = respond.send(chosen);
+        }
+    }
+}
+
+/// Activates an item of the open menu, which closes it.
+fn activate(state: &mut State, index: usize) {
+    let open = match state.open.as_mut() {
+        Some(open) => open,
+        None => return,
+    };
+    let chosen = open.menu.activate(index);
+    if let Some((item_id, checked)) = &chosen {
+        push_event(json!({
+            "type": "menuItemActivated",
+            "windowID": open.owner,
+            "itemID": item_id,
+            "checked": checked,
+            "timestamp": clock::now(),
+        }));
+    }
+    close(state, chosen.map(|(item_id, _)| item_id));
+}
+
+fn apply(state: &mut State, outcome: Outcome) {
+    match outcome {
+        Outcome::None => {}
+        Outcome::Activate(index) => activate(state, index),
+        Outcome::Dismiss => close(state, None),
+    }
+}
+
+/// Opens the menu bar of window `id`, with nothing dropped down yet.
+/// Returns false if there is no bar to open.
+fn open_bar(state: &mut State, id: u32) -> bool {
+    close(state, None);
+    let window_menu = match state.window_menus.get_mut(&id) {
+        Some(window_menu) => window_menu,
+        None => return false,
+    };
+    let mut bar = match &window_menu.bar {
+        Some(bar) => bar.clone(),
+        None => return false,
+    };
+    let (x, y) = match window_menu.surfaces.to_menu(0, 0) {
+        Ok(origin) => origin,
+        Err(err) => {
+            eprintln!("Failed to show menu: {}", err);
+            return false;
+        }
+    };
+    bar.bounds.x = x;
+    bar.bounds.y = y;
+    let open = OpenMenu {
+        owner: id,
+        menu: window_menu.menu.clone(),
+        kind: MenuKind::Bar,
+        screen: window_menu.surfaces.screen(window_menu.size),
+        surfaces: std::mem::take(&mut window_menu.surfaces),
+        scale: window_menu.scale,
+        popups: vec![bar],
+    };
+    state.open = Some(open);
+    state.dirty.insert(id);
+    true
+}
+
+/// Input the owner of an open menu doesn't get.
+fn is_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+    )
+}
+
+/// The part of a menu the pointer is on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    /// A level of the open menu.
+    Level(usize),
+    /// The bar of a window's menu while it's closed.
+    Bar(u32),
+}
+
+/// Handles pointer input on `target`, at `position` in it.
+fn pointer(state: &mut State, target: Target, position: PhysicalPosition<f64>, event: &WindowEvent) {
+    match target {
+        Target::Level(level) => {
+            let open = state.open.as_mut().unwrap();
+            let outcome = match event {
+                WindowEvent::CursorMoved { .. } => {
+                    open.cursor_moved(level, position);
+                    Outcome::None
+                }
+                WindowEvent::MouseInput { state: ElementState::Pressed, .. } => open.pressed(level, position),
+                _ => Outcome::None,
+            };
+            state.dirty.insert(open.owner);
+            apply(state, outcome);
+        }
+        Target::Bar(owner) => {
+            if !matches!(event, WindowEvent::MouseInput { state: ElementState::Pressed, .. }) {
+                return;
+            }
+            let window_menu = &state.window_menus[&owner];
+            let on_item = match &window_menu.bar {
+                Some(bar) => bar.item_at(&window_menu.menu, position).is_some(),
+                None => false,
+            };
+            if on_item && open_bar(state, owner) {
+                let outcome = state.open.as_mut().unwrap().pressed(0, position);
+                apply(state, outcome);
+            }
+        }
+    }
+}
+
+/// What popup window `id` shows.
+fn popup_target(state: &State, id: u32) -> Option<Target> {
+    if let Some(open) = &state.open {
+        if let Some(level) = open.surfaces.level_of(id).filter(|&level| level < open.popups.len()) {
+            return Some(Target::Level(level));
+        }
+    }
+    state
+        .window_menus
+        .iter()
+        .find(|(_, window_menu)| window_menu.surfaces.level_of(id) == Some(0))
+        .map(|(&owner, _)| Target::Bar(owner))
+}
+
+/// The window whose menu popup window `id` is for.
+fn popup_owner(state: &State, id: u32) -> Option<u32> {
+    if let Some(open) = state.open.as_ref().filter(|open| open.surfaces.level_of(id).is_some()) {
+        return Some(open.owner);
+    }
+    state
+        .window_menus
+        .iter()
+        .find(|(_, window_menu)| window_menu.surfaces.level_of(id).is_some())
+        .map(|(&owner, _)| owner)
+}
+
+/// What of the menus drawn over window `owner` is at `position` in it, and
+/// where in that it is. Only Wayland menus are drawn over their owner, which
+/// gets their input.
+fn overlay_target(
+    state: &State,
+    owner: u32,
+    position: PhysicalPosition<f64>,
+) -> Option<(Target, PhysicalPosition<f64>)> {
+    if let Some(open) = state.open.as_ref().filter(|open| open.owner == owner) {
+        if !open.surfaces.is_overlay() {
+            return None;
+        }
+        let level = open.popups.iter().rposition(|popup| popup.bounds.contains(position))?;
+        let bounds = open.popups[level].bounds;
+        let local = PhysicalPosition::new(position.x - bounds.x as f64, position.y - bounds.y as f64);
+        return Some((Target::Level(level), local));
+    }
+    let window_menu = state.window_menus.get(&owner).filter(|window_menu| window_menu.surfaces.is_overlay())?;
+    let bar = window_menu.bar.as_ref()?;
+    if bar.bounds.contains(position) {
+        Some((Target::Bar(owner), position))
+    } else {
+        None
+    }
+}
+
+fn handle_popup_event(state: &mut State, id: u32, event: &WindowEvent) {
+    if let WindowEvent::CursorMoved { position, .. } = event {
+        state.cursors.insert(id, *position);
+    }
+    let position = match state.cursors.get(&id) {
+        Some(position) => *position,
+        None => return,
+    };
+    if let Some(target) = popup_target(state, id) {
+        pointer(state, target, position, event);
+    }
+}
+
+/// Draws the menus that changed, after letting go of the subsurfaces of
+/// menus that are gone. Only the event pump draws, as that's where the
+/// subsurfaces live.
+fn render(state: &mut State) {
+    for surfaces in state.retired.drain(..) {
+        surfaces.release();
+    }
+    for owner in std::mem::take(&mut state.dirty) {
+        let mut shown = Ok(());
+        if let Some(window_menu) = state.window_menus.get(&owner) {
+            if let Some(bar) = &window_menu.bar {
+                // Nothing to draw in while the menu is open, which draws
+                // the bar itself.
+                shown = window_menu.surfaces.show(&window_menu.menu, &[(bar.bounds, bar)], window_menu.scale);
+            }
+        }
+        if let Some(open) = state.open.as_ref().filter(|open| open.owner == owner) {
+            let levels: Vec<_> = open
+                .popups
+                .iter()
+                .enumerate()
+                .map(|(level, popup)| match open.kind {
+                    // Where the bar is in the window.
+                    MenuKind::Bar if level == 0 => (Rect { x: 0, y: 0, ..popup.bounds }, popup),
+                    _ => (popup.bounds, popup),
+                })
+                .collect();
+            shown = shown.and(open.surfaces.show(&open.menu, &levels, open.scale));
+        }
+        if let Err(err) = shown {
+            eprintln!("Failed to draw menu: {}", err);
+        }
+    }
+}
+
+/// Handles `event` if it's for a popup or is input to the window whose menu
+/// is open, in which case it must not be delivered. F10 opens window menus,
+/// and so does a click on their bar.
+pub fn handle_event(event: &Event<Blocker>) -> bool {
+    let (window_id, window_event) = match event {
+        Event::WindowEvent { window_id, event } => (*window_id, Some(event)),
+        Event::RedrawRequested(window_id) => (*window_id, None),
+        _ => return false,
+    };
+    let id = ids::window_id(window_id);
+    let mut state = STATE.lock().unwrap();
+    let state = &mut *state;
+
+    let handled = if state.popup_windows.contains(&id) {
+        match window_event {
+            Some(WindowEvent::Destroyed) => {
+                state.popup_windows.remove(&id);
+                state.cursors.remove(&id);
+                ids::remove_window(window_id);
+            }
+            Some(event) => handle_popup_event(state, id, event),
+            None => {
+                if let Some(owner) = popup_owner(state, id) {
+                    state.dirty.insert(owner);
+                }
+            }
+        }
+        true
+    } else {
+        handle_window_event(state, id, window_event)
+    };
+    render(state);
+    handled
+}
+
+fn handle_window_event(state: &mut State, id: u32, window_event: Option<&WindowEvent>) -> bool {
+    let event = match window_event {
+        Some(event) => event,
+        None => {
+            // Redrawn along with the window, which is also how menus shown
+            // from JS first get drawn.
+            if state.window_menus.contains_key(&id) || state.open.as_ref().map(|open| open.owner) == Some(id) {
+                state.dirty.insert(id);
+            }
+            return false;
+        }
+    };
+    match event {
+        WindowEvent::CursorMoved { position, .. } => {
+            state.cursors.insert(id, *position);
+        }
+        WindowEvent::ModifiersChanged(modifiers) => state.modifiers = *modifiers,
+        WindowEvent::Resized(size) => {
+            if let Some(window_menu) = state.window_menus.get_mut(&id) {
+                window_menu.size = *size;
+                if let Some(bar) = &mut window_menu.bar {
+                    bar.bounds.width = size.width.max(1) as i32;
+                }
+                state.dirty.insert(id);
+            }
+        }
+        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+            if let Some(window_menu) = state.window_menus.get_mut(&id) {
+                window_menu.scale = *scale_factor;
+                if window_menu.bar.is_some() {
+                    window_menu.bar = Some(strip(&window_menu.menu, *scale_factor, window_menu.size.width));
+                }
+                state.dirty.insert(id);
+            }
+        }
+        WindowEvent::Destroyed => {
+            state.cursors.remove(&id);
+        }
+        _ => {}
+    }
+
+    if let WindowEvent::MouseInput { state: ElementState::Released, button, .. } = event {
+        if state.dismissing == Some((id, *button)) {
+            state.dismissing = None;
+            return true;
+        }
+    }
+
+    if let WindowEvent::CursorMoved { .. } | WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } = event {
+        let position = state.cursors.get(&id).copied();
+        if let Some((target, local)) = position.and_then(|position| overlay_target(state, id, position)) {
+            pointer(state, target, local, event);
+            // The app still sees where the cursor is.
+            return !matches!(event, WindowEvent::CursorMoved { .. });
+        }
+    }
+
+    if state.open.as_ref().map(|open| open.owner) != Some(id) {
+        return match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::F10), .. },
+                is_synthetic: false,
+                ..
+            } if state.modifiers.is_empty() => {
+                let opened = open_bar(state, id);
+                if opened {
+                    state.open.as_mut().unwrap().move_bar(1);
+                }
+                opened
+            }
+            _ => false,
+        };
+    }
+
+    match event {
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+            ..
+        } => {
+            let outcome = state.open.as_mut().unwrap().key(*key);
+            state.dirty.insert(id);
+            apply(state, outcome);
+            true
+        }
+        // Popups don't take focus, so pressing anywhere else goes either to
+        // the owner or to another app, which takes focus from the owner.
+        WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => {
+            state.dismissing = Some((id, *button));
+            close(state, None);
+            true
+        }
+        WindowEvent::Focused(false)
+        | WindowEvent::Moved(_)
+        | WindowEvent::Resized(_)
+        | WindowEvent::ScaleFactorChanged { .. }
+        | WindowEvent::Destroyed => {
+            close(state, None);
+            false
+        }
+        event => is_input(event),
+    }
+}
+
+/// Activates the item of window `window_id`'s menu that has `accelerator`,
+/// returning the `menuItemActivated` event for it.
+pub fn activate_accelerator(window_id: u32, accelerator: Accelerator) -> Option<Value> {
+    let mut state = STATE.lock().unwrap();
+    let menu = &mut state.window_menus.get_mut(&window_id)?.menu;
+    let (item_id, checked) = menu.activate(menu.find_accelerator(accelerator)?)?;
+    Some(json!({ "type": "menuItemActivated", "windowID": window_id, "itemID": item_id, "checked": checked }))
+}
+
+/// Activates an item of the context menu open on window `window_id`, or
+/// else of its window menu, like a user would. Returns false if there is no
+/// such item or it's disabled.
+pub fn headless_activate(window_id: u32, item_id: &str) -> bool {
+    let mut state = STATE.lock().unwrap();
+    let state = &mut *state;
+    if let Some(open) = state.open.as_ref().filter(|open| open.owner == window_id) {
+        let index = open.menu.find(item_id).filter(|&index| open.menu[index].is_selectable());
+        return match index {
+            Some(index) => {
+                activate(state, index);
+                true
+            }
+            None => false,
+        };
+    }
+
+    let window_menu = match state.window_menus.get_mut(&window_id) {
+        Some(window_menu) => window_menu,
+        None => return false,
+    };
+    let menu = &mut window_menu.menu;
+    let (item_id, checked) = match menu.find(item_id).and_then(|index| menu.activate(index)) {
+        Some(activated) => activated,
+        None => return false,
+    };
+    push_event(json!({
+        "type": "menuItemActivated",
+        "windowID": window_id,
+        "itemID": item_id,
+        "checked": checked,
+        "timestamp": clock::now(),
+    }));
+    true
+}
+
+/// Drops the menu of a closed window.
+pub fn remove_window(window_id: u32) {
+    let mut state = STATE.lock().unwrap();
+    let state = &mut *state;
+    if state.open.as_ref().map_or(false, |open| open.owner == window_id) {
+        close(state, None);
+    }
+    if let Some(window_menu) = state.window_menus.remove(&window_id) {
+        retire(state, window_menu.surfaces);
+    }
+    state.dirty.remove(&window_id);
+}
+
+/// Gives a window a menu, shown as a bar along its top that F10 and clicks
+/// open, and whose accelerators work while the window has focus.
+pub fn op_window_set_menu(
+    state: &mut OpState,
+    args: (WindowRef, Option<Vec<MenuItem>>),
+    _: (),
+) -> Result<(), AnyError> {
+    let window = WindowResource::get(state, &args.0)?;
+    let id = window.id();
+    let menu = match args.1 {
+        Some(items) => Some(Menu::new(items)?),
+        None => None,
+    };
+
+    // Made before taking the lock, as the event pump that may be waiting on
+    // it holds up the event loop that makes popup windows.
+    let window_menu = match (menu, &*window) {
+        (Some(menu), WindowResource::Native(window)) => {
+            let surfaces = render::bar_surfaces(window, menu.depth())?;
+            let scale = window.scale_factor();
+            let size = window.inner_size();
+            let bar = if surfaces.has_level(0) { Some(strip(&menu, scale, size.width)) } else { None };
+            Some(WindowMenu { menu, surfaces, scale, size, bar })
+        }
+        (Some(menu), WindowResource::Headless(_)) => {
+            let size = PhysicalSize::new(0, 0);
+            Some(WindowMenu { menu, surfaces: Surfaces::None, scale: 1.0, size, bar: None })
+        }
+        (None, _) => None,
+    };
+
+    {
+        let mut state = STATE.lock().unwrap();
+        let state = &mut *state;
+        if state.open.as_ref().map_or(false, |open| open.owner == id && matches!(open.kind, MenuKind::Bar)) {
+            close(state, None);
+        }
+        if let Some(old) = state.window_menus.remove(&id) {
+            retire(state, old.surfaces);
+        }
+        if let Some(window_menu) = window_menu {
+            state.window_menus.insert(id, window_menu);
+        }
+        state.dirty.insert(id);
+    }
+    // For the event pump to draw the bar.
+    if let WindowResource::Native(window) = &*window {
+        window.request_redraw();
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ContextMenuPosition {
+    window: WindowRef,
+    /// In the window's units. The cursor's position if left out.
+    x: Option<f64>,
+    y: Option<f64>,
+}
+
+fn show_context_menu(
+    state: &OpState,
+    items: Vec<MenuItem>,
+    position: ContextMenuPosition,
+) -> Result<oneshot::Receiver<Option<String>>, AnyError> {
+    let menu = Menu::new(items)?;
+    let window = WindowResource::get(state, &position.window)?;
+    let owner = window.id();
+    let (respond, chosen) = oneshot::channel();
+    if menu[0].children.is_empty() {
+        // Nothing to choose from.
+        let _ = respond.send(None);
+        return Ok(chosen);
+    }
+
+    let mut open = OpenMenu {
+        owner,
+        menu,
+        kind: MenuKind::Context(respond),
+        surfaces: Surfaces::None,
+        scale: 1.0,
+        screen: (0, 0),
+        popups: Vec::new(),
+    };
+    if let WindowResource::Native(window) = &*window {
+        let point = match (position.x, position.y) {
+            (Some(x), Some(y)) => window_units(state, &position.window)?
+                .position(x, y)
+                .to_physical::<i32>(window.scale_factor()),
+            _ => {
+                let cursor = STATE.lock().unwrap().cursors.get(&owner).copied();
+                cursor.unwrap_or_else(|| PhysicalPosition::new(0.0, 0.0)).cast::<i32>()
+            }
+        };
+        open.surfaces = render::popup_surfaces(window, open.menu.depth())?;
+        open.scale = window.scale_factor();
+        open.screen = open.surfaces.screen(window.inner_size());
+        let (x, y) = open.surfaces.to_menu(point.x, point.y)?;
+        open.show_level(0, 0, Anchor::Point(x, y));
+    }
+
+    {
+        let mut state = STATE.lock().unwrap();
+        close(&mut state, None);
+        state.dirty.insert(owner);
+        state.open = Some(open);
+    }
+    // For the event pump to draw the menu.
+    if let WindowResource::Native(window) = &*window {
+        window.request_redraw();
+    }
+    Ok(chosen)
+}
+
+/// Resolves to the ID of the chosen item, or null if the menu was dismissed.
+/// The menu is open by the time the op returns, rather than once its future
+/// is first polled.
+pub fn op_show_context_menu(
+    state: Rc<RefCell<OpState>>,
+    args: (Vec<MenuItem>, ContextMenuPosition),
+    _: (),
+) -> impl Future<Output = Result<Option<String>, AnyError>> {
+    let chosen = show_context_menu(&state.borrow(), args.0, args.1);
+    async move { Ok(chosen?.await.unwrap_or(None)) }
+}
+
+/// Menus drawn with `canvas`: in override-redirect windows on X11, which the
+/// window manager leaves alone, with the bar in a child of its window. On
+/// Wayland, where nothing can be put on the screen outside of a window,
+/// they're subsurfaces of their window and stay inside it.
+#[cfg(target_os = "linux")]
+mod render {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::ffi::c_void;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use winit_main::reexports::dpi::PhysicalSize;
+    use winit_main::reexports::platform::unix::WindowExtUnix;
+    use winit_main::reexports::window::Window;
+    use winit_main::reexports::window::WindowAttributes;
+
+    use super::ARROW_WIDTH;
+    use super::BAR_ITEM_PADDING;
+    use super::MARK_WIDTH;
+    use super::Menu;
+    use super::MenuItemType;
+    use super::PADDING;
+    use super::Popup;
+    use super::Rect;
+    use super::STATE;
+    use super::accelerator_label;
+    use super::border;
+    use super::font_size;
+    use super::px;
+    use crate::EVENT_LOOP;
+    use crate::canvas;
+    use crate::canvas::Canvas;
+    use crate::canvas::Subsurface;
+    use crate::ids;
+    use crate::x11;
+
+    const BACKGROUND: u32 = 0xf8f8f8;
+    const BORDER: u32 = 0x9a9a9a;
+    const FOREGROUND: u32 = 0x202020;
+    const DISABLED: u32 = 0xa0a0a0;
+    const SEPARATOR: u32 = 0xd0d0d0;
+    const ACCENT: u32 = 0x3874d8;
+    const ACCENT_TEXT: u32 = 0xffffff;
+
+    /// A hidden window to show a popup in. Created ahead of time, because the
+    /// event pump that opens popups can't have the event loop create windows
+    /// while it holds up the event loop.
+    pub struct PopupWindow {
+        window: Window,
+        id: u32,
+        xlib: u32,
+    }
+
+    thread_local! {
+        /// The subsurfaces of each `Surfaces::Wayland`, made as they're needed.
+        static SUBSURFACES: RefCell<HashMap<u64, Vec<Subsurface>>> = RefCell::new(HashMap::new());
+    }
+
+    static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+
+    /// Where a menu shows its levels, one surface each. Popups are placed in
+    /// menu coordinates, which are the screen's on X11 and the owner's on
+    /// Wayland.
+    pub enum Surfaces {
+        /// Nothing to show in, for headless windows.
+        None,
+        X11 {
+            owner: u32,
+            windows: Vec<PopupWindow>,
+        },
+        /// Subsurfaces of the owner, which the event pump makes on its
+        /// thread.
+        Wayland {
+            key: u64,
+            display: usize,
+            surface: usize,
+        },
+    }
+
+    impl Default for Surfaces {
+        fn default() -> Self {
+            Surfaces::None
+        }
+    }
+
+    impl Surfaces {
+        pub fn has_level(&self, level: usize) -> bool {
+            match self {
+                Surfaces::None => false,
+                Surfaces::X11 { windows, .. } => level < windows.len(),
+                Surfaces::Wayland { .. } => true,
+            }
+        }
+
+        /// The level shown in popup window `id`.
+        pub fn level_of(&self, id: u32) -> Option<usize> {
+            match self {
+                Surfaces::X11 { windows, .. } => windows.iter().position(|popup_window| popup_window.id == id),
+                Surfaces::None | Surfaces::Wayland { .. } => None,
+            }
+        }
+
+        /// Whether the menu is drawn over its owner, which gets its input.
+        pub fn is_overlay(&self) -> bool {
+            matches!(self, Surfaces::Wayland { .. })
+        }
+
+        /// `(x, y)` in the owner, in menu coordinates.
+        pub fn to_menu(&self, x: i32, y: i32) -> Result<(i32, i32), AnyError> {
+            match self {
+                Surfaces::X11 { owner, .. } => {
+                    x11::with_connection(|conn| x11::to_root(conn, *owner, x as i16, y as i16))
+                }
+                Surfaces::None | Surfaces::Wayland { .. } => Ok((x, y)),
+            }
+        }
+
+        /// What popups have to fit in, for an owner of `size`. Unbounded if
+        /// the X server can't be asked.
+        pub fn screen(&self, size: PhysicalSize<u32>) -> (i32, i32) {
+            match self {
+                Surfaces::X11 { .. } => {
+                    x11::with_connection(|conn| Ok(x11::screen_size(conn))).unwrap_or((i32::MAX / 2, i32::MAX / 2))
+                }
+                Surfaces::None | Surfaces::Wayland { .. } => (size.width as i32, size.height as i32),
+            }
+        }
+
+        /// Shows each of `levels` where it goes in the surface of the same
+        /// index, and hides the rest.
+        pub fn show(&self, menu: &Menu, levels: &[(Rect, &Popup)], scale: f64) -> Result<(), AnyError> {
+            match self {
+                Surfaces::None => Ok(()),
+                Surfaces::X11 { windows, .. } => {
+                    for (popup_window, &(bounds, popup)) in windows.iter().zip(levels) {
+                        x11::with_connection(|conn| {
+                            let (width, height) = (bounds.width as u32, bounds.height as u32);
+                            x11::configure(conn, popup_window.xlib, bounds.x, bounds.y, width, height)
+                        })?;
+                        popup_window.window.set_visible(true);
+                        // Lost if the window isn't mapped yet, in which case
+                        // it's drawn again once it's exposed.
+                        paint(menu, popup, scale).present(&popup_window.window)?;
+                    }
+                    for popup_window in windows.iter().skip(levels.len()) {
+                        popup_window.window.set_visible(false);
+                    }
+                    Ok(())
+                }
+                Surfaces::Wayland { key, display, surface } => SUBSURFACES.with(|subsurfaces| {
+                    let mut subsurfaces = subsurfaces.borrow_mut();
+                    let shown = subsurfaces.entry(*key).or_default();
+                    // Each goes on top of the ones made before it.
+                    while shown.len() < levels.len() {
+                        shown.push(Subsurface::new(*display as *mut c_void, *surface as *mut c_void)?);
+                    }
+                    for (subsurface, &(bounds, popup)) in shown.iter().zip(levels) {
+                        subsurface.show(bounds.x, bounds.y, border(scale), &paint(menu, popup, scale))?;
+                    }
+                    for subsurface in shown.iter().skip(levels.len()) {
+                        subsurface.hide()?;
+                    }
+                    Ok(())
+                }),
+            }
+        }
+
+        /// Destroys the subsurfaces, on the event pump.
+        pub fn release(self) {
+            if let Surfaces::Wayland { key, .. } = self {
+                SUBSURFACES.with(|subsurfaces| subsurfaces.borrow_mut().remove(&key));
+            }
+        }
+    }
+
+    fn not_supported() -> AnyError {
+        custom_error("NotSupported", "Menus can only be shown on X11 and Wayland")
+    }
+
+    fn xlib_window(window: &Window) -> Option<u32> {
+        window.xlib_window().map(|window| window as u32)
+    }
+
+    fn wayland_surfaces(window: &Window) -> Result<Surfaces, AnyError> {
+        match (window.wayland_display(), window.wayland_surface()) {
+            (Some(display), Some(surface)) => Ok(Surfaces::Wayland {
+                key: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+                display: display as usize,
+                surface: surface as usize,
+            }),
+            _ => Err(not_supported()),
+        }
+    }
+
+    /// Creates `count` hidden popup windows of `window_type`.
+    fn create_popups(count: usize, window_type: &str) -> Result<Vec<PopupWindow>, AnyError> {
+        (0..count)
+            .map(|_| {
+                let mut attribs = WindowAttributes::default();
+                attribs.title = "Menu".to_string();
+                attribs.inner_size = Some(PhysicalSize::new(1u32, 1u32).into());
+                attribs.resizable = false;
+                attribs.decorations = false;
+                attribs.visible = false;
+                let window = {
+                    let ev = EVENT_LOOP.lock().unwrap();
+                    let ev = ev.as_ref().unwrap().lock().unwrap();
+                    ev.create_window(attribs)?
+                };
+                let id = ids::window_id(window.id());
+                STATE.lock().unwrap().popup_windows.insert(id);
+
+                let xlib = xlib_window(&window).ok_or_else(not_supported)?;
+                x11::with_connection(|conn| {
+                    x11::set_override_redirect(conn, xlib)?;
+                    x11::set_atoms_property(conn, xlib, "_NET_WM_WINDOW_TYPE", &[window_type])
+                })?;
+                Ok(PopupWindow { window, id, xlib })
+            })
+            .collect()
+    }
+
+    /// Surfaces for the menu of `window`: its bar, and the `depth - 1`
+    /// levels of menus that drop down from it.
+    pub fn bar_surfaces(window: &Window, depth: usize) -> Result<Surfaces, AnyError> {
+        canvas::check_fonts()?;
+        if let Some(owner) = xlib_window(window) {
+            let windows = create_popups(depth.max(1), "_NET_WM_WINDOW_TYPE_DROPDOWN_MENU")?;
+            // The bar stays put in the window, and is clipped to it.
+            x11::with_connection(|conn| x11::reparent(conn, windows[0].xlib, owner))?;
+            return Ok(Surfaces::X11 { owner, windows });
+        }
+        wayland_surfaces(window)
+    }
+
+    /// Surfaces for a context menu `depth` levels deep over `window`.
+    pub fn popup_surfaces(window: &Window, depth: usize) -> Result<Surfaces, AnyError> {
+        canvas::check_fonts()?;
+        if let Some(owner) = xlib_window(window) {
+            let windows = create_popups(depth, "_NET_WM_WINDOW_TYPE_POPUP_MENU")?;
+            return Ok(Surfaces::X11 { owner, windows });
+        }
+        wayland_surfaces(window)
+    }
+
+    pub fn text_width(text: &str, size: f32) -> f32 {
+        canvas::text_width(text, size)
+    }
+
+    fn canvas_rect(rect: Rect) -> canvas::Rect {
+        canvas::Rect { x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
+
+    fn paint(menu: &Menu, popup: &Popup, scale: f64) -> Canvas {
+        let (width, height) = (popup.bounds.width, popup.bounds.height);
+        let mut canvas = Canvas::new(PhysicalSize::new(width as u32, height as u32), BACKGROUND);
+        let border = border(scale);
+        if popup.horizontal {
+            canvas.fill(canvas::Rect { x: 0, y: height - border, width, height: border }, BORDER);
+        } else {
+            canvas.stroke(canvas::Rect { x: 0, y: 0, width, height }, border, BORDER);
+        }
+
+        let font_size = font_size(scale);
+        let (ascent, descent) = canvas::vertical_metrics(font_size);
+        for (i, &(index, rect)) in popup.items.iter().enumerate() {
+            let entry = &menu[index];
+            let rect = canvas_rect(rect);
+            if entry.kind == MenuItemType::Separator {
+                let inset = px(PADDING, scale);
+                let line = canvas::Rect {
+                    x: rect.x + inset,
+                    y: rect.y + (rect.height - border) / 2,
+                    width: rect.width - 2 * inset,
+                    height: border,
+                };
+                canvas.fill(line, SEPARATOR);
+                continue;
+            }
+
+            let hovered = popup.hovered == Some(i);
+            let (fill, text) = match (hovered, entry.enabled) {
+                (true, _) => (ACCENT, ACCENT_TEXT),
+                (false, true) => (BACKGROUND, FOREGROUND),
+                (false, false) => (BACKGROUND, DISABLED),
+            };
+            if hovered {
+                canvas.fill(rect, fill);
+            }
+            let baseline = (rect.y as f32 + (rect.height as f32 + ascent - descent) / 2.0).round();
+            let left = px(if popup.horizontal { BAR_ITEM_PADDING } else { MARK_WIDTH }, scale);
+            canvas.text(&entry.label, font_size, (rect.x + left) as f32, baseline, text);
+            if popup.horizontal {
+                continue;
+            }
+
+            let mark = px(8.0, scale);
+            let mark_rect = canvas::Rect {
+                x: rect.x + mark,
+                y: rect.y + (rect.height - mark) / 2,
+                width: mark,
+                height: mark,
+            };
+            match entry.kind {
+                MenuItemType::Checkbox => {
+                    canvas.stroke(mark_rect, border, text);
+                    if entry.checked {
+                        let inset = 2 * border;
+                        let check = canvas::Rect {
+                            x: mark_rect.x + inset,
+                            y: mark_rect.y + inset,
+                            width: mark - 2 * inset,
+                            height: mark - 2 * inset,
+                        };
+                        canvas.fill(check, text);
+                    }
+                }
+                MenuItemType::Radio => {
+                    let radius = mark as f32 / 2.0;
+                    let (x, y) = (mark_rect.x as f32 + radius, mark_rect.y as f32 + radius);
+                    canvas.disc(x, y, radius, text);
+                    if !entry.checked {
+                        canvas.disc(x, y, radius - border as f32, fill);
+                    }
+                }
+                MenuItemType::Normal | MenuItemType::Separator => {}
+            }
+
+            let right = (rect.x + rect.width - px(ARROW_WIDTH, scale)) as f32;
+            let accelerator = accelerator_label(entry);
+            if !accelerator.is_empty() {
+                let x = right - canvas::text_width(&accelerator, font_size);
+                canvas.text(&accelerator, font_size, x, baseline, text);
+            }
+            if !entry.children.is_empty() {
+                canvas.text("\u{25b8}", font_size, right + px(PADDING, scale) as f32, baseline, text);
+            }
+        }
+        canvas
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod render {
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use winit_main::reexports::dpi::PhysicalSize;
+    use winit_main::reexports::window::Window;
+
+    use super::Menu;
+    use super::Popup;
+    use super::Rect;
+
+    /// Menus are never shown here, only their accelerators work.
+    pub enum Surfaces {
+        None,
+    }
+
+    impl Default for Surfaces {
+        fn default() -> Self {
+            Surfaces::None
+        }
+    }
+
+    impl Surfaces {
+        pub fn has_level(&self, _level: usize) -> bool {
+            false
+        }
+
+        pub fn level_of(&self, _id: u32) -> Option<usize> {
+            None
+        }
+
+        pub fn is_overlay(&self) -> bool {
+            false
+        }
+
+        pub fn to_menu(&self, x: i32, y: i32) -> Result<(i32, i32), AnyError> {
+            Ok((x, y))
+        }
+
+        pub fn screen(&self, size: PhysicalSize<u32>) -> (i32, i32) {
+            (size.width as i32, size.height as i32)
+        }
+
+        pub fn show(&self, _menu: &Menu, _levels: &[(Rect, &Popup)], _scale: f64) -> Result<(), AnyError> {
+            Ok(())
+        }
+
+        pub fn release(self) {}
+    }
+
+    pub fn bar_surfaces(_window: &Window, _depth: usize) -> Result<Surfaces, AnyError> {
+        Ok(Surfaces::None)
+    }
+
+    pub fn popup_surfaces(_window: &Window, _depth: usize) -> Result<Surfaces, AnyError> {
+        Err(custom_error("NotSupported", "Menus are only supported on Linux"))
+    }
+
+    /// Popups are never shown here, the layout code just needs it.
+    pub fn text_width(text: &str, size: f32) -> f32 {
+        text.chars().count() as f32 * size / 2.0
+    }
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_show_context_menu", op_async(op_show_context_menu)),
+            ("op_window_set_menu", op_sync(op_window_set_menu)),
+        ])
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use deno_runtime::deno_core::serde_json;
+
+    use super::*;
+
+    const SCREEN: (i32, i32) = (800, 600);
+
+    /// Flattened as 1 `check`, 2 `r1`, 3 `r2`, 4 a separator, 5 `r3`, 6 `r4`,
+    /// 7 `Sub` with 8 `s1` in it, 9 `off` and 10 an item without an ID.
+    fn menu() -> Menu {
+        let items = serde_json::from_value(json!([
+            { "id": "check", "label": "Check", "type": "checkbox" },
+            { "id": "r1", "label": "One", "type": "radio", "checked": true },
+            { "id": "r2", "label": "Two", "type": "radio" },
+            { "type": "separator" },
+            { "id": "r3", "label": "Three", "type": "radio", "checked": true },
+            { "id": "r4", "label": "Four", "type": "radio" },
+            { "label": "Sub", "submenu": [{ "id": "s1", "label": "Sub one", "type": "radio" }] },
+            { "id": "off", "label": "Off", "enabled": false },
+            { "label": "Nothing" },
+        ]))
+        .unwrap();
+        Menu::new(items).unwrap()
+    }
+
+    fn checked(menu: &Menu) -> Vec<usize> {
+        (0..menu.len()).filter(|&index| menu[index].checked).collect()
+    }
+
+    #[test]
+    fn toggles_checkboxes() {
+        let mut menu = menu();
+        assert_eq!(menu.activate(1), Some(("check".to_string(), true)));
+        assert_eq!(menu.activate(1), Some(("check".to_string(), false)));
+    }
+
+    #[test]
+    fn checks_radio_items_within_their_group() {
+        let mut menu = menu();
+        assert_eq!(menu.activate(3), Some(("r2".to_string(), true)));
+        assert_eq!(checked(&menu), [3, 5]);
+        // Activating a checked radio item keeps it checked.
+        assert_eq!(menu.activate(3), Some(("r2".to_string(), true)));
+        assert_eq!(menu.activate(6), Some(("r4".to_string(), true)));
+        assert_eq!(checked(&menu), [3, 6]);
+    }
+
+    #[test]
+    fn activates_only_items_that_can_be_clicked() {
+        let mut menu = menu();
+        // A separator, a submenu, a disabled item, one without an ID and one
+        // that doesn't exist.
+        for index in [4, 7, 9, 10, 11] {
+            assert_eq!(menu.activate(index), None, "{}", index);
+        }
+        assert_eq!(checked(&menu), [2, 5]);
+    }
+
+    #[test]
+    fn groups_adjacent_radio_items() {
+        let menu = menu();
+        assert_eq!(menu.radio_group(2), [2, 3]);
+        assert_eq!(menu.radio_group(3), [2, 3]);
+        assert_eq!(menu.radio_group(6), [5, 6]);
+        // Submenus are groups of their own.
+        assert_eq!(menu.radio_group(8), [8]);
+    }
+
+    #[test]
+    fn sizes_popups_in_whole_surface_pixels() {
+        let menu = menu();
+        for horizontal in [false, true] {
+            let (items, width, height) = layout(&menu, 0, horizontal, 2.0);
+            assert_eq!((width % 2, height % 2), (0, 0));
+            assert!(items.iter().all(|(_, rect)| rect.x + rect.width <= width && rect.y + rect.height <= height));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn hits_the_topmost_menu_drawn_over_the_window() {
+        let mut state = State::default();
+        let menu = menu();
+        let bar = strip(&menu, 1.0, 800);
+        let bounds = Rect { x: 10, y: 20, width: 100, height: 50 };
+        let dropped = Popup { entry: 7, items: Vec::new(), horizontal: false, bounds, hovered: None };
+        state.open = Some(OpenMenu {
+            owner: 1,
+            menu: menu.clone(),
+            kind: MenuKind::Bar,
+            surfaces: Surfaces::Wayland { key: 0, display: 0, surface: 0 },
+            scale: 1.0,
+            screen: (800, 600),
+            popups: vec![bar.clone(), dropped],
+        });
+        let at = |state: &State, x: f64, y: f64| overlay_target(state, 1, PhysicalPosition::new(x, y));
+        assert_eq!(at(&state, 15.0, 21.0), Some((Target::Level(1), PhysicalPosition::new(5.0, 1.0))));
+        assert_eq!(at(&state, 300.0, 5.0), Some((Target::Level(0), PhysicalPosition::new(300.0, 5.0))));
+        assert_eq!(at(&state, 300.0, 300.0), None);
+
+        state.open = None;
+        let surfaces = Surfaces::Wayland { key: 0, display: 0, surface: 0 };
+        let size = PhysicalSize::new(800, 600);
+        state.window_menus.insert(1, WindowMenu { menu, surfaces, scale: 1.0, size, bar: Some(bar) });
+        assert_eq!(at(&state, 300.0, 5.0), Some((Target::Bar(1), PhysicalPosition::new(300.0, 5.0))));
+        assert_eq!(at(&state, 15.0, 21.0), None);
+        // Windows without menus keep their input.
+        assert_eq!(overlay_target(&state, 2, PhysicalPosition::new(300.0, 5.0)), None);
+    }
+
+    #[test]
+    fn places_popups_at_their_anchor() {
+        assert_eq!(place(Anchor::Point(10, 20), 100, 50, SCREEN), Rect { x: 10, y: 20, width: 100, height: 50 });
+        let item = Rect { x: 10, y: 0, width: 40, height: 22 };
+        assert_eq!(place(Anchor::Below(item), 100, 50, SCREEN), Rect { x: 10, y: 22, width: 100, height: 50 });
+        let item = Rect { x: 10, y: 100, width: 80, height: 22 };
+        assert_eq!(place(Anchor::Beside(item, 4), 100, 50, SCREEN), Rect { x: 90, y: 96, width: 100, height: 50 });
+    }
+
+    #[test]
+    fn flips_popups_that_would_go_past_the_screen() {
+        assert_eq!(place(Anchor::Point(750, 580), 100, 50, SCREEN), Rect { x: 650, y: 530, width: 100, height: 50 });
+        let item = Rect { x: 10, y: 500, width: 40, height: 22 };
+        assert_eq!(place(Anchor::Below(item), 100, 200, SCREEN), Rect { x: 10, y: 300, width: 100, height: 200 });
+        let item = Rect { x: 700, y: 100, width: 80, height: 22 };
+        assert_eq!(place(Anchor::Beside(item, 4), 120, 50, SCREEN), Rect { x: 580, y: 96, width: 120, height: 50 });
+    }
+
+    #[test]
+    fn keeps_popups_on_the_screen() {
+        let item = Rect { x: 750, y: 0, width: 40, height: 22 };
+        assert_eq!(place(Anchor::Below(item), 100, 50, SCREEN), Rect { x: 700, y: 22, width: 100, height: 50 });
+        // Too tall to fit either way, so it starts at the top.
+        assert_eq!(place(Anchor::Point(0, 300), 100, 700, SCREEN), Rect { x: 0, y: 0, width: 100, height: 700 });
+    }
+}
@@ -0,0 +1,95 @@
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use winit_main::reexports::dpi::PhysicalPosition;
+use winit_main::reexports::dpi::PhysicalSize;
+use winit_main::reexports::monitor::MonitorHandle;
+use winit_main::reexports::monitor::VideoMode;
+
+use crate::EVENT_LOOP;
+use crate::event_loop::serialize_physical_position;
+use crate::event_loop::serialize_physical_size;
+use crate::flags::Flags;
+
+/// The single monitor headless windows are on, a 1080p display at 60 Hz.
+pub const VIRTUAL_MONITOR_NAME: &str = "Virtual monitor";
+pub const VIRTUAL_MONITOR_SIZE: PhysicalSize<u32> = PhysicalSize { width: 1920, height: 1080 };
+pub const VIRTUAL_MONITOR_BIT_DEPTH: u16 = 32;
+pub const VIRTUAL_MONITOR_REFRESH_RATE: u16 = 60;
+
+pub fn serialize_video_mode(mode: &VideoMode) -> Value {
+    json!({
+        "size": serialize_physical_size(mode.size()),
+        "bitDepth": mode.bit_depth(),
+        "refreshRate": mode.refresh_rate(),
+    })
+}
+
+pub fn serialize_monitor(monitor: &MonitorHandle, primary: Option<&MonitorHandle>) -> Value {
+    json!({
+        "name": monitor.name(),
+        "position": serialize_physical_position(monitor.position()),
+        "size": serialize_physical_size(monitor.size()),
+        "scaleFactor": monitor.scale_factor(),
+        "primary": primary == Some(monitor),
+        "videoModes": monitor.video_modes().map(|mode| serialize_video_mode(&mode)).collect::<Vec<_>>(),
+    })
+}
+
+pub fn virtual_video_mode() -> Value {
+    json!({
+        "size": serialize_physical_size(VIRTUAL_MONITOR_SIZE),
+        "bitDepth": VIRTUAL_MONITOR_BIT_DEPTH,
+        "refreshRate": VIRTUAL_MONITOR_REFRESH_RATE,
+    })
+}
+
+/// The monitor reported in headless mode.
+pub fn virtual_monitor() -> Value {
+    json!({
+        "name": VIRTUAL_MONITOR_NAME,
+        "position": serialize_physical_position(PhysicalPosition::new(0, 0)),
+        "size": serialize_physical_size(VIRTUAL_MONITOR_SIZE),
+        "scaleFactor": 1.0,
+        "primary": true,
+        "videoModes": [virtual_video_mode()],
+    })
+}
+
+pub fn op_monitors(state: &mut OpState, _: (), _: ()) -> Result<Vec<Value>, AnyError> {
+    if state.borrow::<Flags>().headless {
+        return Ok(vec![virtual_monitor()]);
+    }
+
+    let ev = EVENT_LOOP.lock().unwrap();
+    let ev = ev.as_ref().unwrap().lock().unwrap();
+    let primary = ev.primary_monitor();
+    Ok(ev
+        .available_monitors()
+        .iter()
+        .map(|monitor| serialize_monitor(monitor, primary.as_ref()))
+        .collect())
+}
+
+pub fn op_primary_monitor(state: &mut OpState, _: (), _: ()) -> Result<Option<Value>, AnyError> {
+    if state.borrow::<Flags>().headless {
+        return Ok(Some(virtual_monitor()));
+    }
+
+    let ev = EVENT_LOOP.lock().unwrap();
+    let ev = ev.as_ref().unwrap().lock().unwrap();
+    let primary = ev.primary_monitor();
+    Ok(primary.as_ref().map(|monitor| serialize_monitor(monitor, Some(monitor))))
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_monitors", op_sync(op_monitors)),
+            ("op_primary_monitor", op_sync(op_primary_monitor)),
+        ])
+        .build()
+}
@@ -0,0 +1,356 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_async;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::clock;
+use crate::event_loop::push_event;
+use crate::flags::Flags;
+
+#[derive(Deserialize, Serialize)]
+pub struct NotificationAction {
+    action: String,
+    title: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationArgs {
+    title: String,
+    #[serde(default)]
+    body: String,
+    /// A path, a `file://` URI or an icon theme name.
+    icon: Option<String>,
+    #[serde(default)]
+    actions: Vec<NotificationAction>,
+    #[serde(default)]
+    silent: bool,
+    #[serde(default)]
+    require_interaction: bool,
+    /// ID of the notification with the same tag, which this one replaces.
+    replaces: Option<u32>,
+}
+
+impl NotificationArgs {
+    fn serialize(&self) -> Value {
+        json!({
+            "title": self.title,
+            "body": self.body,
+            "icon": self.icon,
+            "actions": self.actions,
+            "silent": self.silent,
+            "requireInteraction": self.require_interaction,
+        })
+    }
+}
+
+lazy_static! {
+    /// Our notifications that are still showing. The server's signals are
+    /// broadcast to every client, this tells ours apart. Headless runs have
+    /// no server and this is all there is to them.
+    static ref SHOWING: Mutex<HashMap<u32, Value>> = Mutex::new(HashMap::new());
+    /// Held from asking the server to show a notification until it's in
+    /// `SHOWING`, so that signals about it that come before the reply wait
+    /// instead of being taken for another client's.
+    static ref NOTIFYING: Mutex<()> = Mutex::new(());
+    static ref NEXT_HEADLESS_ID: Mutex<u32> = Mutex::new(1);
+    /// Clicks and closes for the JS `Notification` objects, which get them
+    /// whether or not the app takes events from the event loop.
+    static ref DISPATCH_QUEUE: Mutex<VecDeque<Value>> = Mutex::new(VecDeque::new());
+    static ref DISPATCH_NOTIFY: Notify = Notify::new();
+}
+
+/// Queues `event` for the event loop and for the `Notification` it's about.
+fn emit(event: Value) {
+    push_event(event.clone());
+    DISPATCH_QUEUE.lock().unwrap().push_back(event);
+    DISPATCH_NOTIFY.notify_one();
+}
+
+/// Queues a `notificationclick` event if `id` is one of ours.
+fn clicked(id: u32, action: String) {
+    if !SHOWING.lock().unwrap().contains_key(&id) {
+        return;
+    }
+    // Clicking the notification itself invokes the "default" action.
+    let action = if action == "default" { String::new() } else { action };
+    emit(json!({ "type": "notificationclick", "notificationID": id, "action": action, "timestamp": clock::now() }));
+}
+
+/// Queues a `notificationclose` event if `id` is one of ours, with the
+/// server's reason code.
+fn closed(id: u32, reason: u32) {
+    if SHOWING.lock().unwrap().remove(&id).is_none() {
+        return;
+    }
+    let reason = match reason {
+        1 => "expired",
+        2 => "dismissed",
+        3 => "closed",
+        _ => "undefined",
+    };
+    emit(json!({ "type": "notificationclose", "notificationID": id, "reason": reason, "timestamp": clock::now() }));
+}
+
+/// The notifications showing in a headless run, for tests to inspect.
+pub fn headless_notifications() -> Vec<Value> {
+    let mut notifications: Vec<_> = SHOWING.lock().unwrap().values().cloned().collect();
+    notifications.sort_by_key(|notification| notification["id"].as_u64());
+    notifications
+}
+
+/// Clicks a headless notification like a user would, which also dismisses
+/// it. An empty `action` clicks the notification itself. Returns false if
+/// there is no such notification.
+pub fn headless_click(id: u32, action: String) -> bool {
+    if !SHOWING.lock().unwrap().contains_key(&id) {
+        return false;
+    }
+    clicked(id, action);
+    closed(id, 2);
+    true
+}
+
+pub fn headless_dismiss(id: u32) -> bool {
+    if !SHOWING.lock().unwrap().contains_key(&id) {
+        return false;
+    }
+    closed(id, 2);
+    true
+}
+
+/// Resolves to `"granted"` if there is a notification server to show
+/// notifications, `"denied"` if not. Desktops don't ask the user.
+pub async fn op_notification_request_permission(
+    state: Rc<RefCell<OpState>>,
+    _: (),
+    _: (),
+) -> Result<String, AnyError> {
+    let headless = state.borrow().borrow::<Flags>().headless;
+    let available = headless || tokio::task::spawn_blocking(dbus::server_available).await?;
+    Ok(if available { "granted" } else { "denied" }.to_string())
+}
+
+/// Resolves to the notification's ID.
+pub async fn op_notification_show(
+    state: Rc<RefCell<OpState>>,
+    args: NotificationArgs,
+    _: (),
+) -> Result<u32, AnyError> {
+    let mut notification = args.serialize();
+    if state.borrow().borrow::<Flags>().headless {
+        let id = args.replaces.unwrap_or_else(|| {
+            let mut next_id = NEXT_HEADLESS_ID.lock().unwrap();
+            *next_id += 1;
+            *next_id - 1
+        });
+        notification["id"] = json!(id);
+        SHOWING.lock().unwrap().insert(id, notification);
+        return Ok(id);
+    }
+
+    dbus::listen()?;
+    tokio::task::spawn_blocking(move || {
+        let _notifying = NOTIFYING.lock().unwrap();
+        let id = dbus::notify(&args)?;
+        notification["id"] = json!(id);
+        SHOWING.lock().unwrap().insert(id, notification);
+        Ok(id)
+    })
+    .await?
+}
+
+/// Resolves to the next click or close for the JS `Notification` objects,
+/// which core.js waits for while any are showing.
+pub async fn op_notification_next_event(_: Rc<RefCell<OpState>>, _: (), _: ()) -> Result<Value, AnyError> {
+    loop {
+        let event = DISPATCH_QUEUE.lock().unwrap().pop_front();
+        if let Some(event) = event {
+            return Ok(event);
+        }
+        DISPATCH_NOTIFY.notified().await;
+    }
+}
+
+pub async fn op_notification_close(
+    state: Rc<RefCell<OpState>>,
+    id: u32,
+    _: (),
+) -> Result<(), AnyError> {
+    if !SHOWING.lock().unwrap().contains_key(&id) {
+        return Ok(());
+    }
+    if state.borrow().borrow::<Flags>().headless {
+        closed(id, 3);
+        return Ok(());
+    }
+    // The server answers with a `NotificationClosed` signal like for any
+    // other close.
+    tokio::task::spawn_blocking(move || dbus::close(id)).await?
+}
+
+/// The notification server, through `org.freedesktop.Notifications`.
+/// https://specifications.freedesktop.org/notification-spec/latest/
+#[cfg(target_os = "linux")]
+mod dbus {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use zbus::Connection;
+    use zbus::MessageType;
+    use zbus::fdo::DBusProxy;
+    use zvariant::Value;
+
+    use super::NotificationArgs;
+
+    const DESTINATION: &str = "org.freedesktop.Notifications";
+    const PATH: &str = "/org/freedesktop/Notifications";
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+    const APP_NAME: &str = "Deno";
+
+    lazy_static! {
+        static ref LISTENING: Mutex<bool> = Mutex::new(false);
+    }
+
+    pub fn server_available() -> bool {
+        Connection::new_session()
+            .and_then(|conn| conn.call_method(Some(DESTINATION), PATH, Some(INTERFACE), "GetServerInformation", &()))
+            .is_ok()
+    }
+
+    /// Starts turning the server's signals into events. The match is added
+    /// before returning, so no signal about a notification shown after this
+    /// can be missed.
+    pub fn listen() -> Result<(), AnyError> {
+        let mut listening = LISTENING.lock().unwrap();
+        if *listening {
+            return Ok(());
+        }
+        let conn = Connection::new_session()?;
+        DBusProxy::new(&conn)?.add_match(&format!("type='signal',interface='{}'", INTERFACE))?;
+        std::thread::spawn(move || {
+            if let Err(err) = receive(conn) {
+                eprintln!("Stopped receiving notification events: {}", err);
+            }
+        });
+        *listening = true;
+        Ok(())
+    }
+
+    fn receive(conn: Connection) -> Result<(), AnyError> {
+        loop {
+            let message = conn.receive_message()?;
+            let header = message.header()?;
+            if header.message_type()? != MessageType::Signal || header.interface()? != Some(INTERFACE) {
+                continue;
+            }
+            // Waits for a notification being shown to be known.
+            let _notifying = super::NOTIFYING.lock().unwrap();
+            match header.member()? {
+                Some("ActionInvoked") => {
+                    let (id, action): (u32, String) = message.body()?;
+                    super::clicked(id, action);
+                }
+                Some("NotificationClosed") => {
+                    let (id, reason): (u32, u32) = message.body()?;
+                    super::closed(id, reason);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Servers take icon names and `file://` URIs, but not bare paths.
+    fn icon_uri(icon: &str) -> String {
+        if icon.starts_with('/') {
+            format!("file://{}", icon)
+        } else {
+            icon.to_string()
+        }
+    }
+
+    pub fn notify(args: &NotificationArgs) -> Result<u32, AnyError> {
+        // Pairs of action keys and labels, "default" being the notification
+        // itself.
+        let mut actions = vec!["default", ""];
+        for action in &args.actions {
+            actions.push(&action.action);
+            actions.push(&action.title);
+        }
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        if args.silent {
+            hints.insert("suppress-sound", true.into());
+        }
+        if args.require_interaction {
+            hints.insert("resident", true.into());
+        }
+        // 0 never expires, -1 leaves it to the server.
+        let timeout: i32 = if args.require_interaction { 0 } else { -1 };
+        let icon = args.icon.as_deref().map(icon_uri).unwrap_or_default();
+
+        let conn = Connection::new_session()?;
+        let id: u32 = conn
+            .call_method(
+                Some(DESTINATION),
+                PATH,
+                Some(INTERFACE),
+                "Notify",
+                &(APP_NAME, args.replaces.unwrap_or(0), icon.as_str(), args.title.as_str(), args.body.as_str(), actions, hints, timeout),
+            )?
+            .body()?;
+        Ok(id)
+    }
+
+    pub fn close(id: u32) -> Result<(), AnyError> {
+        let conn = Connection::new_session()?;
+        conn.call_method(Some(DESTINATION), PATH, Some(INTERFACE), "CloseNotification", &id)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod dbus {
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+
+    use super::NotificationArgs;
+
+    pub fn server_available() -> bool {
+        false
+    }
+
+    pub fn listen() -> Result<(), AnyError> {
+        Ok(())
+    }
+
+    pub fn notify(_args: &NotificationArgs) -> Result<u32, AnyError> {
+        Err(custom_error("NotSupported", "Notifications are only supported on Linux"))
+    }
+
+    pub fn close(_id: u32) -> Result<(), AnyError> {
+        Ok(())
+    }
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_notification_close", op_async(op_notification_close)),
+            ("op_notification_next_event", op_async(op_notification_next_event)),
+            ("op_notification_request_permission", op_async(op_notification_request_permission)),
+            ("op_notification_show", op_async(op_notification_show)),
+        ])
+        .build()
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Deserialize;
+use winit_main::Blocker;
+use winit_main::reexports::event::ElementState;
+use winit_main::reexports::event::Event;
+use winit_main::reexports::event::WindowEvent;
+use winit_main::reexports::window::Window;
+#[cfg(target_os = "linux")]
+use winit_main::reexports::platform::unix::WindowExtUnix;
+
+use crate::clock;
+use crate::event_loop::push_event;
+use crate::ids;
+#[cfg(target_os = "linux")]
+use crate::x11;
+
+lazy_static! {
+    /// Windows created with a parent, by window ID.
+    static ref OWNED: Mutex<HashMap<u32, Owned>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowKind {
+    Normal,
+    /// A palette or toolbar, kept above its parent.
+    Tool,
+    /// A tooltip or dropdown, without decorations or window manager, that is
+    /// hidden when its parent loses focus.
+    Popup,
+    Dialog,
+}
+
+impl Default for WindowKind {
+    fn default() -> Self {
+        WindowKind::Normal
+    }
+}
+
+impl WindowKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            WindowKind::Normal => "normal",
+            WindowKind::Tool => "tool",
+            WindowKind::Popup => "popup",
+            WindowKind::Dialog => "dialog",
+        }
+    }
+}
+
+struct Owned {
+    parent: u32,
+    kind: WindowKind,
+    modal: bool,
+    visible: bool,
+}
+
+/// Records that window `id` belongs to `parent`, once it's created.
+pub fn register(id: u32, parent: u32, kind: WindowKind, modal: bool, visible: bool) {
+    OWNED.lock().unwrap().insert(id, Owned { parent, kind, modal, visible });
+}
+
+/// Keeps track of which owned windows are up, because only visible modal
+/// windows block their parent, and only visible popups are dismissed.
+pub fn set_visible(id: u32, visible: bool) {
+    if let Some(owned) = OWNED.lock().unwrap().get_mut(&id) {
+        owned.visible = visible;
+    }
+}
+
+/// The windows `id` owns, and theirs, each after its parent.
+pub fn descendants(id: u32) -> Vec<u32> {
+    let owned = OWNED.lock().unwrap();
+    let mut descendants = vec![];
+    let mut parents = vec![id];
+    while let Some(parent) = parents.pop() {
+        for (&window, child) in owned.iter() {
+            if child.parent == parent {
+                parents.push(window);
+                descendants.push(window);
+            }
+        }
+    }
+    descendants
+}
+
+/// Forgets window `id`. `op_window_close` closes the windows it owns first,
+/// but if its resource was closed some other way they are hidden instead.
+pub fn remove_window(id: u32) {
+    let windows = descendants(id);
+    let mut owned = OWNED.lock().unwrap();
+    owned.remove(&id);
+    for window in windows {
+        if let Some(child) = owned.get_mut(&window) {
+            if child.visible {
+                child.visible = false;
+                hide(window);
+            }
+        }
+    }
+}
+
+/// Whether window `id` has a visible modal child, which keeps input from it.
+pub fn has_modal_child(id: u32) -> bool {
+    OWNED.lock().unwrap().values().any(|child| child.parent == id && child.modal && child.visible)
+}
+
+/// Hides native window `id` from wherever, as neither the event pump nor
+/// closing windows can reach the others.
+#[cfg(target_os = "linux")]
+fn hide(id: u32) {
+    let window = match x11::window(id) {
+        Some(window) => window,
+        // Wayland windows can't be hidden in winit 0.24 either.
+        None => return,
+    };
+    if let Err(err) = x11::with_connection(|conn| x11::unmap(conn, window)) {
+        eprintln!("Failed to hide window: {}", err);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hide(_id: u32) {}
+
+/// Adds the `parent`, `kind` and `modal` options window `id` was created with
+/// to its serialized state, which also shows it hidden if its parent was
+/// closed.
+pub fn add_state(id: u32, state: &mut Value) {
+    let owned = OWNED.lock().unwrap();
+    let owned = owned.get(&id);
+    state["parent"] = json!(owned.map(|owned| owned.parent));
+    state["kind"] = json!(owned.map_or("normal", |owned| owned.kind.as_str()));
+    state["modal"] = json!(owned.map_or(false, |owned| owned.modal));
+    if owned.map_or(false, |owned| !owned.visible) {
+        state["visible"] = json!(false);
+    }
+}
+
+/// Sets the X11 hints that make `window` belong to `parent`. Popups also skip
+/// the window manager, so they have to be created hidden.
+#[cfg(target_os = "linux")]
+pub fn set_native_hints(window: &Window, parent: Option<&Window>, kind: WindowKind, modal: bool) -> Result<(), AnyError> {
+    let xlib_window = match window.xlib_window() {
+        Some(xlib_window) => xlib_window as u32,
+        // Wayland has no equivalent in winit 0.24.
+        None => return Ok(()),
+    };
+    let parent_xlib_window = parent.and_then(|parent| parent.xlib_window());
+    let window_type = match kind {
+        WindowKind::Normal => "_NET_WM_WINDOW_TYPE_NORMAL",
+        WindowKind::Tool => "_NET_WM_WINDOW_TYPE_UTILITY",
+        WindowKind::Popup => "_NET_WM_WINDOW_TYPE_POPUP_MENU",
+        WindowKind::Dialog => "_NET_WM_WINDOW_TYPE_DIALOG",
+    };
+    x11::with_connection(|conn| {
+        if kind == WindowKind::Popup {
+            x11::set_override_redirect(conn, xlib_window)?;
+        }
+        x11::set_atoms_property(conn, xlib_window, "_NET_WM_WINDOW_TYPE", &[window_type])?;
+        if let Some(parent) = parent_xlib_window {
+            x11::set_transient_for(conn, xlib_window, parent as u32)?;
+        }
+        if modal {
+            x11::set_atoms_property(conn, xlib_window, "_NET_WM_STATE", &["_NET_WM_STATE_MODAL"])?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_native_hints(_window: &Window, _parent: Option<&Window>, _kind: WindowKind, _modal: bool) -> Result<(), AnyError> {
+    Ok(())
+}
+
+/// Input a visible modal window or message box keeps from its parent.
+/// Closing counts, so that the parent can't ask again while it's up. Dropped
+/// files don't: the event pump turns a drop on a blocked window into the end
+/// of the drag, for `DragTracker` to report as a `dragleave`.
+pub fn is_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::TouchpadPressure { .. }
+            | WindowEvent::CloseRequested
+    )
+}
+
+/// Whether `event` to a parent dismisses its popups, as it does for menus.
+fn dismisses_popups(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::Focused(false)
+            | WindowEvent::MouseInput { state: ElementState::Pressed, .. }
+            | WindowEvent::Moved(_)
+            | WindowEvent::Resized(_)
+            | WindowEvent::Destroyed
+    )
+}
+
+/// Dismisses the popups of the window `event` is for, if it calls for it,
+/// and returns whether `event` is input a modal child keeps from it, in which
+/// case it must not be delivered.
+pub fn handle_event(event: &Event<Blocker>) -> bool {
+    let (id, event) = match event {
+        Event::WindowEvent { window_id, event } => (ids::window_id(*window_id), event),
+        _ => return false,
+    };
+    let mut owned = OWNED.lock().unwrap();
+
+    if dismisses_popups(event) {
+        for (&popup, child) in owned.iter_mut() {
+            if child.parent == id && child.kind == WindowKind::Popup && child.visible {
+                child.visible = false;
+                hide(popup);
+                push_event(json!({
+                    "type": "windowEvent",
+                    "windowID": popup,
+                    "event": { "type": "popupDismissed" },
+                    "timestamp": clock::now(),
+                }));
+            }
+        }
+    }
+
+    drop(owned);
+    is_input(event) && has_modal_child(id)
+}
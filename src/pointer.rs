@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use winit_main::Blocker;
+use winit_main::reexports::dpi::PhysicalPosition;
+use winit_main::reexports::event::DeviceEvent;
+use winit_main::reexports::event::ElementState;
+use winit_main::reexports::event::Event;
+use winit_main::reexports::event::Force;
+use winit_main::reexports::event::MouseButton;
+use winit_main::reexports::event::TouchPhase;
+use winit_main::reexports::event::WindowEvent;
+
+use crate::clock;
+use crate::event_loop::push_event;
+use crate::event_loop::remove_window_scale_factor;
+use crate::event_loop::serialize_physical_position;
+use crate::event_loop::window_scale_factor;
+use crate::ids;
+
+/// The mouse always gets pointer ID 1, same as in browsers.
+const MOUSE_POINTER_ID: u32 = 1;
+
+lazy_static! {
+    /// Window that has pointer lock. Set from the JS thread, released by the
+    /// event pump when that window loses focus.
+    static ref POINTER_LOCK: Mutex<Option<u32>> = Mutex::new(None);
+}
+
+fn pointer_lock_change(window_id: u32, locked: bool) -> Value {
+    json!({
+        "type": "windowEvent",
+        "windowID": window_id,
+        "event": { "type": "pointerlockchange", "locked": locked },
+    })
+}
+
+/// Gives `window_id` pointer lock, taking it from any other window.
+pub fn lock_pointer(window_id: u32) {
+    let previous = POINTER_LOCK.lock().unwrap().replace(window_id);
+    if previous == Some(window_id) {
+        return;
+    }
+    if let Some(previous) = previous {
+        push_pointer_lock_change(previous, false);
+    }
+    push_pointer_lock_change(window_id, true);
+}
+
+/// Releases pointer lock if `window_id` has it.
+pub fn unlock_pointer(window_id: u32) {
+    let mut lock = POINTER_LOCK.lock().unwrap();
+    if *lock == Some(window_id) {
+        *lock = None;
+        drop(lock);
+        push_pointer_lock_change(window_id, false);
+    }
+}
+
+fn push_pointer_lock_change(window_id: u32, locked: bool) {
+    let mut event = pointer_lock_change(window_id, locked);
+    event["timestamp"] = json!(clock::now());
+    push_event(event);
+}
+
+#[derive(Default)]
+struct MouseState {
+    position: PhysicalPosition<f64>,
+    buttons: u32,
+    /// Last reading from a force sensing touchpad, if there is one.
+    pressure: Option<f64>,
+}
+
+/// Turns winit's mouse, touch and touchpad pressure events into web-style
+/// pointer events. It sees every window event, so it also keeps track of the
+/// cursor position and scale factor of each window.
+#[derive(Default)]
+pub struct PointerTracker {
+    mice: HashMap<u32, MouseState>,
+    touches: HashMap<u64, u32>,
+    primary_touch: Option<u64>,
+    next_touch_pointer_id: u32,
+}
+
+/// Maps a winit button to its web `button` index and `buttons` bit.
+fn button_index(button: MouseButton) -> (i32, u32) {
+    match button {
+        MouseButton::Left => (0, 1),
+        MouseButton::Right => (2, 2),
+        MouseButton::Middle => (1, 4),
+        #[cfg(windows)]
+        MouseButton::Other(1) => (3, 8),
+        #[cfg(windows)]
+        MouseButton::Other(2) => (4, 16),
+        #[cfg(not(windows))]
+        MouseButton::Other(8) => (3, 8),
+        #[cfg(not(windows))]
+        MouseButton::Other(9) => (4, 16),
+        MouseButton::Other(n) => (n as i32, 1u32.checked_shl(n as u32).unwrap_or(0)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pointer_event(
+    window_id: u32,
+    kind: &str,
+    pointer_id: u32,
+    pointer_type: &str,
+    is_primary: bool,
+    position: PhysicalPosition<f64>,
+    pressure: f64,
+    button: i32,
+    buttons: u32,
+) -> Value {
+    let logical = position.to_logical::<f64>(window_scale_factor(window_id));
+    json!({
+        "type": "windowEvent",
+        "windowID": window_id,
+        "event": {
+            "type": kind,
+            "pointerId": pointer_id,
+            "pointerType": pointer_type,
+            "isPrimary": is_primary,
+            "position": serialize_physical_position(position),
+            "logicalPosition": { "x": logical.x, "y": logical.y },
+            "pressure": pressure,
+            "button": button,
+            "buttons": buttons,
+        },
+    })
+}
+
+impl PointerTracker {
+    /// Last known cursor position in `window_id`.
+    pub fn cursor_position(&self, window_id: u32) -> Option<PhysicalPosition<f64>> {
+        self.mice.get(&window_id).map(|mouse| mouse.position)
+    }
+
+    /// Updates the tracked state and returns the pointer event `event`
+    /// translates to, if any.
+    pub fn handle(&mut self, event: &Event<Blocker>) -> Option<Value> {
+        let (window_id, event) = match event {
+            Event::WindowEvent { window_id, event } => (ids::window_id(*window_id), event),
+            _ => return None,
+        };
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mice.entry(window_id).or_default().position = *position;
+                Some(self.mouse_event(window_id, "pointermove", -1))
+            }
+            WindowEvent::CursorEntered { .. } => Some(self.mouse_event(window_id, "pointerenter", -1)),
+            WindowEvent::CursorLeft { .. } => Some(self.mouse_event(window_id, "pointerleave", -1)),
+            WindowEvent::MouseInput { state, button, .. } => {
+                let (index, bit) = button_index(*button);
+                let mouse = self.mice.entry(window_id).or_default();
+                let before = mouse.buttons;
+                match state {
+                    ElementState::Pressed => mouse.buttons |= bit,
+                    ElementState::Released => mouse.buttons &= !bit,
+                }
+                // Like browsers, only the first press and the last release
+                // are downs and ups, other button changes are moves.
+                let kind = match (before, mouse.buttons) {
+                    (0, after) if after != 0 => "pointerdown",
+                    (before, 0) if before != 0 => "pointerup",
+                    _ => "pointermove",
+                };
+                Some(self.mouse_event(window_id, kind, index))
+            }
+            WindowEvent::TouchpadPressure { pressure, .. } => {
+                self.mice.entry(window_id).or_default().pressure = Some(*pressure as f64);
+                Some(self.mouse_event(window_id, "pointermove", -1))
+            }
+            WindowEvent::Touch(touch) => {
+                let pointer_id = match self.touches.get(&touch.id) {
+                    Some(pointer_id) => *pointer_id,
+                    None => {
+                        // Touch pointer IDs start after the mouse's.
+                        let pointer_id = MOUSE_POINTER_ID + 1 + self.next_touch_pointer_id;
+                        self.next_touch_pointer_id += 1;
+                        // Like in browsers, only a touch that starts while no
+                        // other is down is primary, and none takes over from
+                        // it when it ends.
+                        if self.touches.is_empty() {
+                            self.primary_touch = Some(touch.id);
+                        }
+                        self.touches.insert(touch.id, pointer_id);
+                        pointer_id
+                    }
+                };
+                let is_primary = self.primary_touch == Some(touch.id);
+
+                let (kind, button, buttons) = match touch.phase {
+                    TouchPhase::Started => ("pointerdown", 0, 1),
+                    TouchPhase::Moved => ("pointermove", -1, 1),
+                    TouchPhase::Ended => ("pointerup", 0, 0),
+                    TouchPhase::Cancelled => ("pointercancel", -1, 0),
+                };
+                if buttons == 0 {
+                    self.touches.remove(&touch.id);
+                    if is_primary {
+                        self.primary_touch = None;
+                    }
+                }
+
+                // Only the Apple Pencil reports an altitude angle.
+                let pointer_type = match touch.force {
+                    Some(Force::Calibrated { altitude_angle: Some(_), .. }) => "pen",
+                    _ => "touch",
+                };
+                let pressure = match touch.force {
+                    Some(force) => force.normalized(),
+                    None if buttons != 0 => 0.5,
+                    None => 0.0,
+                };
+
+                Some(pointer_event(
+                    window_id,
+                    kind,
+                    pointer_id,
+                    pointer_type,
+                    is_primary,
+                    touch.location,
+                    pressure,
+                    button,
+                    buttons,
+                ))
+            }
+            WindowEvent::Destroyed => {
+                self.mice.remove(&window_id);
+                remove_window_scale_factor(window_id);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Turns raw mouse motion into `pointermove` events with `movementX/Y`
+    /// for the window with pointer lock, and releases the lock when that
+    /// window loses focus.
+    pub fn handle_locked(&mut self, event: &Event<Blocker>) -> Option<Value> {
+        let mut lock = POINTER_LOCK.lock().unwrap();
+        let window_id = (*lock)?;
+
+        match event {
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+                drop(lock);
+                let mut value = self.mouse_event(window_id, "pointermove", -1);
+                value["event"]["movementX"] = json!(delta.0);
+                value["event"]["movementY"] = json!(delta.1);
+                Some(value)
+            }
+            Event::WindowEvent { window_id: id, event: WindowEvent::Focused(false) | WindowEvent::Destroyed }
+                if ids::window_id(*id) == window_id =>
+            {
+                *lock = None;
+                Some(pointer_lock_change(window_id, false))
+            }
+            _ => None,
+        }
+    }
+
+    fn mouse_event(&mut self, window_id: u32, kind: &str, button: i32) -> Value {
+        let mouse = self.mice.entry(window_id).or_default();
+        let pressure = match mouse.buttons {
+            0 => 0.0,
+            _ => mouse.pressure.unwrap_or(0.5),
+        };
+        pointer_event(
+            window_id,
+            kind,
+            MOUSE_POINTER_ID,
+            "mouse",
+            true,
+            mouse.position,
+            pressure,
+            button,
+            mouse.buttons,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit_main::reexports::event::DeviceId;
+    use winit_main::reexports::event::ModifiersState;
+    use winit_main::reexports::event::Touch;
+    use winit_main::reexports::window::WindowId;
+
+    use super::*;
+
+    fn window_event(event: WindowEvent<'static>) -> Event<'static, Blocker> {
+        Event::WindowEvent { window_id: unsafe { WindowId::dummy() }, event }
+    }
+
+    #[allow(deprecated)]
+    fn mouse_input(state: ElementState, button: MouseButton) -> Event<'static, Blocker> {
+        window_event(WindowEvent::MouseInput {
+            device_id: unsafe { DeviceId::dummy() },
+            state,
+            button,
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    #[allow(deprecated)]
+    fn cursor_moved(x: f64, y: f64) -> Event<'static, Blocker> {
+        window_event(WindowEvent::CursorMoved {
+            device_id: unsafe { DeviceId::dummy() },
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    fn touch(id: u64, phase: TouchPhase, force: Option<Force>) -> Event<'static, Blocker> {
+        window_event(WindowEvent::Touch(Touch {
+            device_id: unsafe { DeviceId::dummy() },
+            phase,
+            location: PhysicalPosition::new(0.0, 0.0),
+            force,
+            id,
+        }))
+    }
+
+    /// `(type, pointerId, isPrimary, button, buttons)` of each pointer event.
+    fn summarize(pointers: &mut PointerTracker, events: Vec<Event<'static, Blocker>>) -> Vec<(String, u64, bool, i64, u64)> {
+        events
+            .iter()
+            .map(|event| {
+                let value = pointers.handle(event).unwrap();
+                let event = &value["event"];
+                (
+                    event["type"].as_str().unwrap().to_string(),
+                    event["pointerId"].as_u64().unwrap(),
+                    event["isPrimary"].as_bool().unwrap(),
+                    event["button"].as_i64().unwrap(),
+                    event["buttons"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn pressure(pointers: &mut PointerTracker, event: Event<'static, Blocker>) -> f64 {
+        pointers.handle(&event).unwrap()["event"]["pressure"].as_f64().unwrap()
+    }
+
+    #[test]
+    fn chorded_buttons_are_moves() {
+        let mut pointers = PointerTracker::default();
+        let summary = summarize(&mut pointers, vec![
+            mouse_input(ElementState::Pressed, MouseButton::Left),
+            mouse_input(ElementState::Pressed, MouseButton::Right),
+            cursor_moved(1.0, 2.0),
+            mouse_input(ElementState::Released, MouseButton::Left),
+            mouse_input(ElementState::Released, MouseButton::Right),
+        ]);
+        assert_eq!(summary, [
+            ("pointerdown".to_string(), 1, true, 0, 1),
+            ("pointermove".to_string(), 1, true, 2, 3),
+            ("pointermove".to_string(), 1, true, -1, 3),
+            ("pointermove".to_string(), 1, true, 0, 2),
+            ("pointerup".to_string(), 1, true, 2, 0),
+        ]);
+    }
+
+    #[test]
+    fn releasing_an_unpressed_button_is_a_move() {
+        let mut pointers = PointerTracker::default();
+        let summary = summarize(&mut pointers, vec![mouse_input(ElementState::Released, MouseButton::Middle)]);
+        assert_eq!(summary, [("pointermove".to_string(), 1, true, 1, 0)]);
+    }
+
+    #[test]
+    fn maps_the_back_and_forward_buttons() {
+        #[cfg(not(windows))]
+        let (back, forward) = (MouseButton::Other(8), MouseButton::Other(9));
+        #[cfg(windows)]
+        let (back, forward) = (MouseButton::Other(1), MouseButton::Other(2));
+        assert_eq!(button_index(back), (3, 8));
+        assert_eq!(button_index(forward), (4, 16));
+        assert_eq!(button_index(MouseButton::Other(5)), (5, 32));
+        // No bit is left for buttons past the 32nd.
+        assert_eq!(button_index(MouseButton::Other(40)), (40, 0));
+
+        let mut pointers = PointerTracker::default();
+        let summary = summarize(&mut pointers, vec![
+            mouse_input(ElementState::Pressed, back),
+            mouse_input(ElementState::Pressed, forward),
+            mouse_input(ElementState::Released, back),
+        ]);
+        assert_eq!(summary, [
+            ("pointerdown".to_string(), 1, true, 3, 8),
+            ("pointermove".to_string(), 1, true, 4, 24),
+            ("pointermove".to_string(), 1, true, 3, 16),
+        ]);
+    }
+
+    #[test]
+    fn gives_each_touch_its_own_pointer_id() {
+        let mut pointers = PointerTracker::default();
+        let summary = summarize(&mut pointers, vec![
+            touch(7, TouchPhase::Started, None),
+            touch(8, TouchPhase::Started, None),
+            touch(7, TouchPhase::Moved, None),
+            touch(8, TouchPhase::Moved, None),
+            touch(7, TouchPhase::Ended, None),
+            touch(8, TouchPhase::Cancelled, None),
+            // A new contact gets a new ID, even with a finger ID seen before.
+            touch(7, TouchPhase::Started, None),
+        ]);
+        assert_eq!(summary, [
+            ("pointerdown".to_string(), 2, true, 0, 1),
+            ("pointerdown".to_string(), 3, false, 0, 1),
+            ("pointermove".to_string(), 2, true, -1, 1),
+            ("pointermove".to_string(), 3, false, -1, 1),
+            ("pointerup".to_string(), 2, true, 0, 0),
+            ("pointercancel".to_string(), 3, false, -1, 0),
+            ("pointerdown".to_string(), 4, true, 0, 1),
+        ]);
+    }
+
+    #[test]
+    fn keeps_the_primary_touch_until_all_are_up() {
+        let mut pointers = PointerTracker::default();
+        let summary = summarize(&mut pointers, vec![
+            touch(1, TouchPhase::Started, None),
+            touch(2, TouchPhase::Started, None),
+            touch(1, TouchPhase::Ended, None),
+            // Neither the touch still down nor one joining it becomes primary.
+            touch(2, TouchPhase::Moved, None),
+            touch(3, TouchPhase::Started, None),
+            touch(2, TouchPhase::Ended, None),
+            touch(3, TouchPhase::Ended, None),
+            touch(4, TouchPhase::Started, None),
+        ]);
+        let primary: Vec<_> = summary.iter().map(|(_, id, primary, ..)| (*id, *primary)).collect();
+        assert_eq!(primary, [(2, true), (3, false), (2, true), (3, false), (4, false), (3, false), (4, false), (5, true)]);
+        assert_eq!(summary[7].0, "pointerdown");
+    }
+
+    #[test]
+    fn defaults_the_pressure_of_devices_without_it() {
+        let mut pointers = PointerTracker::default();
+        assert_eq!(pressure(&mut pointers, cursor_moved(0.0, 0.0)), 0.0);
+        assert_eq!(pressure(&mut pointers, mouse_input(ElementState::Pressed, MouseButton::Left)), 0.5);
+        assert_eq!(pressure(&mut pointers, mouse_input(ElementState::Released, MouseButton::Left)), 0.0);
+
+        assert_eq!(pressure(&mut pointers, touch(1, TouchPhase::Started, None)), 0.5);
+        assert_eq!(pressure(&mut pointers, touch(1, TouchPhase::Moved, None)), 0.5);
+        assert_eq!(pressure(&mut pointers, touch(1, TouchPhase::Ended, None)), 0.0);
+    }
+
+    #[test]
+    fn reports_measured_pressure() {
+        let mut pointers = PointerTracker::default();
+        let touchpad = window_event(WindowEvent::TouchpadPressure {
+            device_id: unsafe { DeviceId::dummy() },
+            pressure: 0.75,
+            stage: 1,
+        });
+        // Without a button down the pointer is not pressing anything.
+        assert_eq!(pressure(&mut pointers, touchpad), 0.0);
+        assert_eq!(pressure(&mut pointers, mouse_input(ElementState::Pressed, MouseButton::Left)), 0.75);
+
+        let force = Some(Force::Normalized(0.25));
+        assert_eq!(pressure(&mut pointers, touch(1, TouchPhase::Started, force)), 0.25);
+        assert_eq!(pressure(&mut pointers, touch(1, TouchPhase::Ended, force)), 0.25);
+    }
+
+    #[test]
+    fn only_takes_touches_with_an_altitude_angle_for_a_pen() {
+        let mut pointers = PointerTracker::default();
+        let pointer_type = |pointers: &mut PointerTracker, force| {
+            let value = pointers.handle(&touch(1, TouchPhase::Started, force)).unwrap();
+            pointers.handle(&touch(1, TouchPhase::Ended, force));
+            value["event"]["pointerType"].as_str().unwrap().to_string()
+        };
+        let pencil = Force::Calibrated { force: 1.0, max_possible_force: 2.0, altitude_angle: Some(1.0) };
+        let finger = Force::Calibrated { force: 1.0, max_possible_force: 2.0, altitude_angle: None };
+        assert_eq!(pointer_type(&mut pointers, Some(pencil)), "pen");
+        assert_eq!(pointer_type(&mut pointers, Some(finger)), "touch");
+        assert_eq!(pointer_type(&mut pointers, None), "touch");
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::serde_json;
+use deno_runtime::deno_core::serde_json::json;
+use deno_runtime::deno_core::serde_json::Value;
+
+use crate::clock;
+use crate::event_loop::add_producer;
+use crate::event_loop::push_filtered_event;
+use crate::event_loop::remove_producer;
+
+lazy_static! {
+    /// Events loaded by `load_replay`, waiting for the script to be loaded.
+    static ref REPLAY: Mutex<Option<Vec<Value>>> = Mutex::new(None);
+}
+
+/// Appends serialized events to an NDJSON log, one event per line.
+pub struct Recorder(BufWriter<File>);
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, AnyError> {
+        Ok(Recorder(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn write(&mut self, event: &Value) -> Result<(), AnyError> {
+        serde_json::to_writer(&mut self.0, event)?;
+        self.0.write_all(b"\n")?;
+        // Flush every line so the log survives the app being killed.
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads a log written by `Recorder`, to be replayed by `start_replay`. The
+/// replay counts as a producer from now on, so the event loop can't end
+/// before it has started.
+pub fn load_replay(path: &Path) -> Result<(), AnyError> {
+    let mut events = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(serde_json::from_str::<Value>(&line)?);
+        }
+    }
+
+    add_producer();
+    *REPLAY.lock().unwrap() = Some(events);
+    Ok(())
+}
+
+/// Pushes the loaded events into the event queue on a separate thread,
+/// keeping the recorded spacing between them. Called once the script is
+/// loaded, so that the timing doesn't depend on how long startup took. The
+/// events go through the event filter like live ones.
+pub fn start_replay() {
+    let events = match REPLAY.lock().unwrap().take() {
+        Some(events) => events,
+        None => return,
+    };
+
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        let first = events.first().and_then(|event| event["timestamp"].as_f64()).unwrap_or(0.0);
+
+        for mut event in events {
+            let offset = event["timestamp"].as_f64().unwrap_or(first) - first;
+            let due = start + Duration::from_secs_f64(offset.max(0.0) / 1000.0);
+            let now = Instant::now();
+            if due > now {
+                std::thread::sleep(due - now);
+            }
+
+            event["timestamp"] = json!(clock::now());
+            push_filtered_event(event);
+        }
+
+        remove_producer();
+    });
+}
@@ -11,8 +11,19 @@ use std::path::Path;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use crate::clipboard;
+use crate::dialog;
 use crate::event_loop;
+use crate::flags::Flags;
+use crate::gamepad;
+use crate::headless;
+use crate::menu;
+use crate::monitor;
+use crate::notification;
+use crate::record;
+use crate::shortcuts;
 use crate::surface;
+use crate::tray;
 use crate::window;
 use crate::extra;
 
@@ -20,7 +31,7 @@ fn get_error_class_name(e: &AnyError) -> &'static str {
     deno_runtime::errors::get_error_class_name(e).unwrap_or("Error")
 }
 
-pub async fn start() -> Result<(), AnyError> {
+pub async fn start(flags: Flags) -> Result<(), AnyError> {
     let module_loader = Rc::new(FsModuleLoader);
     let create_web_worker_cb = Arc::new(|_| {
         todo!("Web workers are not supported");
@@ -49,6 +60,15 @@ pub async fn start() -> Result<(), AnyError> {
             // But aren't there (implemented in CLI)
             // https://github.com/denoland/deno/issues/12918
             extra::init(),
+            headless::init(),
+            gamepad::init(),
+            shortcuts::init(),
+            monitor::init(),
+            clipboard::init(),
+            dialog::init(),
+            notification::init(),
+            tray::init(),
+            menu::init(),
         ],
         unsafely_ignore_certificate_errors: None,
         root_cert_store: None,
@@ -67,18 +87,21 @@ pub async fn start() -> Result<(), AnyError> {
         compiled_wasm_module_store: None,
     };
 
-    let path = std::env::args().nth(1).unwrap_or(String::from("test.js"));
-    let js_path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+    let js_path = Path::new(env!("CARGO_MANIFEST_DIR")).join(&flags.script);
     let main_module = deno_runtime::deno_core::resolve_path(&js_path.to_string_lossy())?;
     let permissions = Permissions::allow_all();
 
     let mut worker = MainWorker::bootstrap_from_options(main_module.clone(), permissions, options);
+    worker.js_runtime.op_state().borrow_mut().put(flags);
 
     worker
         .js_runtime
         .execute_script("deno_desktop:core.js", include_str!("core.js"))?;
 
-    worker.execute_main_module(&main_module).await?;
+    let id = worker.preload_module(&main_module, true).await?;
+    // Replays start with the script rather than with the process.
+    record::start_replay();
+    worker.evaluate_module(id).await?;
     worker.run_event_loop(false).await?;
 
     Ok(())
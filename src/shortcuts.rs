@@ -0,0 +1,381 @@
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::custom_error;
+use deno_runtime::deno_core::error::type_error;
+use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Deserialize;
+use winit_main::Blocker;
+use winit_main::reexports::event::ElementState;
+use winit_main::reexports::event::Event;
+use winit_main::reexports::event::KeyboardInput;
+use winit_main::reexports::event::ModifiersState;
+use winit_main::reexports::event::VirtualKeyCode;
+use winit_main::reexports::event::WindowEvent;
+
+use crate::ids;
+use crate::menu;
+use crate::window::WindowResource;
+use crate::window::WindowRef;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Accelerator {
+    modifiers: ModifiersState,
+    key: VirtualKeyCode,
+}
+
+impl Accelerator {
+    /// winit reports the plus key as `Plus` on Linux, but as `Equals` with
+    /// Shift on Windows, where it is the shifted `=` key of US layouts. Both
+    /// are kept as the latter so that `"Ctrl+Plus"` works everywhere.
+    fn new(modifiers: ModifiersState, key: VirtualKeyCode) -> Self {
+        match key {
+            VirtualKeyCode::Plus => Accelerator { modifiers: modifiers | ModifiersState::SHIFT, key: VirtualKeyCode::Equals },
+            key => Accelerator { modifiers, key },
+        }
+    }
+}
+
+struct Shortcut {
+    id: String,
+    accelerator: Accelerator,
+    /// Window the shortcut is limited to, `None` for all windows.
+    window: Option<u32>,
+}
+
+lazy_static! {
+    static ref SHORTCUTS: Mutex<Vec<Shortcut>> = Mutex::new(Vec::new());
+}
+
+fn parse_modifier(name: &str) -> Option<ModifiersState> {
+    Some(match name {
+        "ctrl" | "control" => ModifiersState::CTRL,
+        "shift" => ModifiersState::SHIFT,
+        "alt" | "option" => ModifiersState::ALT,
+        "super" | "meta" | "cmd" | "command" => ModifiersState::LOGO,
+        #[cfg(target_os = "macos")]
+        "cmdorctrl" | "commandorcontrol" => ModifiersState::LOGO,
+        #[cfg(not(target_os = "macos"))]
+        "cmdorctrl" | "commandorcontrol" => ModifiersState::CTRL,
+        _ => return None,
+    })
+}
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const FUNCTION_KEYS: [VirtualKeyCode; 24] = [
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        F13, F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24,
+    ];
+
+    let mut chars = name.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        if ch.is_ascii_alphabetic() {
+            return Some(LETTERS[(ch.to_ascii_lowercase() as u8 - b'a') as usize]);
+        }
+        if ch.is_ascii_digit() {
+            return Some(DIGITS[(ch as u8 - b'0') as usize]);
+        }
+    }
+
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return FUNCTION_KEYS.get(n.wrapping_sub(1)).copied();
+    }
+
+    Some(match name {
+        "enter" | "return" => Return,
+        "escape" | "esc" => Escape,
+        "space" => Space,
+        "tab" => Tab,
+        "backspace" => Back,
+        "delete" | "del" => Delete,
+        "insert" => Insert,
+        "home" => Home,
+        "end" => End,
+        "pageup" => PageUp,
+        "pagedown" => PageDown,
+        "up" | "arrowup" => Up,
+        "down" | "arrowdown" => Down,
+        "left" | "arrowleft" => Left,
+        "right" | "arrowright" => Right,
+        "plus" | "+" => Plus,
+        "minus" | "-" => Minus,
+        "equal" | "=" => Equals,
+        "comma" | "," => Comma,
+        "period" | "." => Period,
+        "slash" | "/" => Slash,
+        "backslash" | "\\" => Backslash,
+        "semicolon" | ";" => Semicolon,
+        "quote" | "'" => Apostrophe,
+        "bracketleft" | "[" => LBracket,
+        "bracketright" | "]" => RBracket,
+        "backquote" | "`" => Grave,
+        "printscreen" => Snapshot,
+        _ => return None,
+    })
+}
+
+/// Splits an accelerator into its modifiers and its key.
+fn split_accelerator(accelerator: &str) -> (&str, &str) {
+    // Allow "Ctrl++" for the plus key.
+    match accelerator.strip_suffix("++") {
+        Some(modifiers) => (modifiers, "+"),
+        None => match accelerator.rsplit_once('+') {
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", accelerator),
+        },
+    }
+}
+
+fn parse_modifiers(modifiers: &str) -> Option<ModifiersState> {
+    let mut state = ModifiersState::empty();
+    for name in modifiers.split('+').filter(|name| !name.is_empty()) {
+        state |= parse_modifier(name.trim())?;
+    }
+    Some(state)
+}
+
+/// Parses accelerators such as `"Ctrl+S"`, `"CmdOrCtrl+Shift+P"` or
+/// `"Alt+F4"`. Names are case insensitive and the key comes last.
+pub fn parse_accelerator(accelerator: &str) -> Result<Accelerator, AnyError> {
+    let invalid = || type_error(format!("Invalid accelerator: {:?}", accelerator));
+
+    let lower = accelerator.to_ascii_lowercase();
+    let (modifiers, key) = split_accelerator(&lower);
+    let modifiers = parse_modifiers(modifiers).ok_or_else(invalid)?;
+    let key = parse_key(key.trim()).ok_or_else(invalid)?;
+    Ok(Accelerator::new(modifiers, key))
+}
+
+/// The parts of a valid accelerator the way menus spell them, e.g.
+/// `["Control", "Shift", "P"]` for `"CmdOrCtrl+shift+p"`.
+pub fn accelerator_parts(accelerator: &str) -> Vec<String> {
+    let lower = accelerator.to_ascii_lowercase();
+    let modifiers = parse_modifiers(split_accelerator(&lower).0).unwrap_or_default();
+    let mut parts: Vec<String> = [
+        (ModifiersState::CTRL, "Control"),
+        (ModifiersState::ALT, "Alt"),
+        (ModifiersState::SHIFT, "Shift"),
+        (ModifiersState::LOGO, "Super"),
+    ]
+    .iter()
+    .filter(|(modifier, _)| modifiers.contains(*modifier))
+    .map(|(_, name)| name.to_string())
+    .collect();
+
+    let key = split_accelerator(accelerator).1.trim();
+    let mut chars = key.chars();
+    parts.push(match (chars.next(), chars.next()) {
+        (Some(ch), None) => ch.to_uppercase().collect(),
+        _ => key.to_string(),
+    });
+    parts
+}
+
+/// What the event pump should do with an event after shortcut matching.
+pub enum Matched {
+    /// Not part of a shortcut, deliver as usual.
+    No,
+    /// Belongs to a shortcut that already fired, drop it.
+    Swallow,
+    /// Fires a shortcut, deliver this instead.
+    Shortcut(Value),
+}
+
+/// Matches key presses against the registered shortcuts before they are
+/// delivered, so that a shortcut's key events don't also reach JS.
+#[derive(Default)]
+pub struct ShortcutMatcher {
+    modifiers: ModifiersState,
+    /// Keys that fired a shortcut and haven't been released yet.
+    held: Vec<VirtualKeyCode>,
+    /// Drop the character the last shortcut key press produced.
+    swallow_char: bool,
+}
+
+impl ShortcutMatcher {
+    pub fn handle(&mut self, event: &Event<Blocker>) -> Matched {
+        let (window_id, event) = match event {
+            Event::WindowEvent { window_id, event } => (ids::window_id(*window_id), event),
+            _ => return Matched::No,
+        };
+
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                Matched::No
+            }
+            WindowEvent::ReceivedCharacter(_) if self.swallow_char => {
+                self.swallow_char = false;
+                Matched::Swallow
+            }
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { virtual_keycode: Some(key), state, .. },
+                is_synthetic: false,
+                ..
+            } => {
+                self.swallow_char = false;
+                match state {
+                    ElementState::Pressed => {
+                        let accelerator = Accelerator::new(self.modifiers, *key);
+                        let shortcuts = SHORTCUTS.lock().unwrap();
+                        let shortcut = shortcuts.iter().find(|shortcut| {
+                            shortcut.accelerator == accelerator
+                                && shortcut.window.map_or(true, |id| id == window_id)
+                        });
+                        // Registered shortcuts win over menu accelerators.
+                        let value = match shortcut {
+                            Some(shortcut) => json!({
+                                "type": "shortcut",
+                                "id": shortcut.id,
+                                "windowID": window_id,
+                            }),
+                            None => match menu::activate_accelerator(window_id, accelerator) {
+                                Some(value) => value,
+                                None => return Matched::No,
+                            },
+                        };
+                        if !self.held.contains(key) {
+                            self.held.push(*key);
+                        }
+                        self.swallow_char = true;
+                        Matched::Shortcut(value)
+                    }
+                    ElementState::Released => {
+                        let held = self.held.len();
+                        self.held.retain(|held| held != key);
+                        if self.held.len() != held {
+                            Matched::Swallow
+                        } else {
+                            Matched::No
+                        }
+                    }
+                }
+            }
+            _ => Matched::No,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RegisterShortcutArgs {
+    id: String,
+    accelerator: String,
+    window: Option<WindowRef>,
+}
+
+pub fn op_register_shortcut(
+    state: &mut OpState,
+    args: RegisterShortcutArgs,
+    _: (),
+) -> Result<(), AnyError> {
+    let accelerator = parse_accelerator(&args.accelerator)?;
+    let window = match args.window {
+        Some(window) => Some(WindowResource::get(state, &window)?.id()),
+        None => None,
+    };
+
+    let mut shortcuts = SHORTCUTS.lock().unwrap();
+    if shortcuts.iter().any(|shortcut| shortcut.id == args.id) {
+        return Err(custom_error("AlreadyExists", format!("Shortcut {:?} is already registered", args.id)));
+    }
+    // App-wide shortcuts overlap with those of every window.
+    if let Some(existing) = shortcuts.iter().find(|shortcut| {
+        shortcut.accelerator == accelerator
+            && (shortcut.window == window || shortcut.window.is_none() || window.is_none())
+    }) {
+        return Err(custom_error(
+            "AlreadyExists",
+            format!("{} conflicts with shortcut {:?}", args.accelerator, existing.id),
+        ));
+    }
+
+    shortcuts.push(Shortcut { id: args.id, accelerator, window });
+    Ok(())
+}
+
+pub fn op_unregister_shortcut(_: &mut OpState, id: String, _: ()) -> Result<bool, AnyError> {
+    let mut shortcuts = SHORTCUTS.lock().unwrap();
+    let len = shortcuts.len();
+    shortcuts.retain(|shortcut| shortcut.id != id);
+    Ok(shortcuts.len() != len)
+}
+
+/// Drops the shortcuts of a closed window.
+pub fn remove_window(window_id: u32) {
+    SHORTCUTS.lock().unwrap().retain(|shortcut| shortcut.window != Some(window_id));
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_register_shortcut", op_sync(op_register_shortcut)),
+            ("op_unregister_shortcut", op_sync(op_unregister_shortcut)),
+        ])
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accelerator(modifiers: ModifiersState, key: VirtualKeyCode) -> Accelerator {
+        Accelerator { modifiers, key }
+    }
+
+    #[test]
+    fn parses_modifiers_and_keys() {
+        assert_eq!(parse_accelerator("Ctrl+S").unwrap(), accelerator(ModifiersState::CTRL, VirtualKeyCode::S));
+        assert_eq!(
+            parse_accelerator("alt + shift + F4").unwrap(),
+            accelerator(ModifiersState::ALT | ModifiersState::SHIFT, VirtualKeyCode::F4),
+        );
+        assert_eq!(parse_accelerator("Super+7").unwrap(), accelerator(ModifiersState::LOGO, VirtualKeyCode::Key7));
+        assert_eq!(parse_accelerator("Escape").unwrap(), accelerator(ModifiersState::empty(), VirtualKeyCode::Escape));
+        assert_eq!(parse_accelerator("Ctrl+-").unwrap(), accelerator(ModifiersState::CTRL, VirtualKeyCode::Minus));
+    }
+
+    #[test]
+    fn parses_cmd_or_ctrl_per_platform() {
+        let modifiers = parse_accelerator("CmdOrCtrl+P").unwrap().modifiers;
+        if cfg!(target_os = "macos") {
+            assert_eq!(modifiers, ModifiersState::LOGO);
+        } else {
+            assert_eq!(modifiers, ModifiersState::CTRL);
+        }
+    }
+
+    #[test]
+    fn parses_plus_as_shifted_equals() {
+        let ctrl_shift = ModifiersState::CTRL | ModifiersState::SHIFT;
+        assert_eq!(parse_accelerator("Ctrl+Plus").unwrap(), accelerator(ctrl_shift, VirtualKeyCode::Equals));
+        assert_eq!(parse_accelerator("Ctrl++").unwrap(), accelerator(ctrl_shift, VirtualKeyCode::Equals));
+        // What winit reports for the key on Linux and on Windows.
+        assert_eq!(Accelerator::new(ctrl_shift, VirtualKeyCode::Plus), accelerator(ctrl_shift, VirtualKeyCode::Equals));
+        assert_eq!(Accelerator::new(ctrl_shift, VirtualKeyCode::Equals), accelerator(ctrl_shift, VirtualKeyCode::Equals));
+    }
+
+    #[test]
+    fn rejects_invalid_accelerators() {
+        for invalid in ["", "Ctrl+", "Hyper+A", "Ctrl+F25", "Ctrl+F0", "Ctrl+AB"] {
+            assert!(parse_accelerator(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn spells_accelerators_for_menus() {
+        let ctrl = if cfg!(target_os = "macos") { "Super" } else { "Control" };
+        assert_eq!(accelerator_parts("CmdOrCtrl+shift+p"), [ctrl, "Shift", "P"]);
+        assert_eq!(accelerator_parts("Ctrl++"), ["Control", "+"]);
+        assert_eq!(accelerator_parts("Alt+F4"), ["Alt", "F4"]);
+    }
+}
@@ -1,12 +1,9 @@
 #![allow(unused_macros)]
 
 use std::borrow::Cow;
-use std::rc::Rc;
 
 use deno_runtime::deno_core::Extension;
 use deno_runtime::deno_core::op_sync;
-use deno_runtime::deno_core::ResourceTable;
-use deno_runtime::deno_core::error::bad_resource_id;
 use deno_runtime::deno_core::error::AnyError;
 use deno_runtime::deno_core::OpState;
 use deno_runtime::deno_core::anyhow::anyhow;
@@ -22,16 +19,7 @@ use deno_runtime::deno_webgpu::WebGpuAdapter;
 use deno_runtime::deno_webgpu::texture::WebGpuTexture;
 
 use crate::window::WindowResource;
-
-fn get_resource<R: Resource>(state: &mut ResourceTable, rid: ResourceId) -> Result<Rc<R>, AnyError> {
-    let res = state.get::<R>(rid);
-
-    if res.is_err() {
-        Err(bad_resource_id())
-    } else {
-        Ok(res.unwrap())
-    }
-}
+use crate::window::WindowRef;
 
 macro_rules! gfx_select {
     ($id:expr => $global:ident.$method:ident( $($param:expr),* )) => {
@@ -67,9 +55,13 @@ pub fn op_webgpu_create_surface(
     args: CreateSurfaceArgs,
     _: (),
 ) -> Result<ResourceId, AnyError> {
-    let winres = get_resource::<WindowResource>(&mut state.resource_table, args.window_rid)?;
+    let winres = WindowResource::get(state, &WindowRef::Rid(args.window_rid))?;
+    let window = match &*winres {
+        WindowResource::Native(window) => window,
+        WindowResource::Headless(_) => return Err(anyhow!("Cannot create a surface for a headless window")),
+    };
     let instance = state.borrow::<Instance>();
-    let surface_id = instance.instance_create_surface(&winres.0, std::marker::PhantomData);
+    let surface_id = instance.instance_create_surface(window, std::marker::PhantomData);
     Ok(state.resource_table.add(WebGpuSurface(surface_id)))
 }
 
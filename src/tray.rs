@@ -0,0 +1,636 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+
+use deno_runtime::deno_core::Extension;
+use deno_runtime::deno_core::op_async;
+use deno_runtime::deno_core::op_sync;
+use deno_runtime::deno_core::OpState;
+use deno_runtime::deno_core::Resource;
+use deno_runtime::deno_core::ResourceId;
+use deno_runtime::deno_core::ZeroCopyBuf;
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::custom_error;
+use deno_runtime::deno_core::error::type_error;
+use deno_runtime::deno_core::serde_json::json;
+use serde::Deserialize;
+use winit_main::reexports::window::Icon;
+
+use crate::clock;
+use crate::event_loop::push_event;
+use crate::flags::Flags;
+use crate::menu::Menu;
+use crate::menu::MenuItem;
+
+static NEXT_TRAY_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Signals for the thread serving a tray icon to emit.
+#[derive(Clone, Copy)]
+pub enum Signal {
+    NewIcon,
+    NewTitle,
+    NewToolTip,
+    LayoutUpdated,
+}
+
+/// What a tray icon shows, shared with the thread serving it.
+pub struct Tray {
+    /// `(width, height, ARGB32 pixels)`, the `IconPixmap` format.
+    icon: Option<(i32, i32, Vec<u8>)>,
+    title: String,
+    tooltip: String,
+    menu: Menu,
+    /// Bumped on every menu change, for DBusMenu clients to notice.
+    revision: u32,
+    pending: Vec<Signal>,
+    closed: bool,
+}
+
+pub struct TrayResource {
+    id: u32,
+    tray: Arc<Mutex<Tray>>,
+    /// Wakes the thread serving the icon, `None` for headless tray icons.
+    waker: Option<sni::Waker>,
+}
+
+impl TrayResource {
+    pub fn get(state: &OpState, rid: ResourceId) -> Result<Rc<TrayResource>, AnyError> {
+        state
+            .resource_table
+            .get::<TrayResource>(rid)
+            .map_err(|_| custom_error("BadResource", format!("Tray icon {} is closed or doesn't exist", rid)))
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Applies `change` and has the tray host told about it with `signal`.
+    fn update(&self, signal: Signal, change: impl FnOnce(&mut Tray)) -> Result<(), AnyError> {
+        let mut tray = self.tray.lock().unwrap();
+        change(&mut tray);
+        if let Some(waker) = &self.waker {
+            tray.pending.push(signal);
+            drop(tray);
+            waker.wake()?;
+        }
+        Ok(())
+    }
+
+    /// Activates the item with ID `item_id` as if clicked. Returns false if
+    /// there is no such item or it's disabled.
+    pub fn activate_menu_item(&self, item_id: &str) -> Result<bool, AnyError> {
+        let mut activated = false;
+        self.update(Signal::LayoutUpdated, |tray| {
+            if let Some(index) = tray.menu.find(item_id) {
+                activated = menu_item_activated(self.id, tray, index);
+            }
+        })?;
+        Ok(activated)
+    }
+}
+
+impl Resource for TrayResource {
+    fn name(&self) -> std::borrow::Cow<str> {
+        "TrayIcon".into()
+    }
+
+    fn close(self: Rc<Self>) {
+        self.tray.lock().unwrap().closed = true;
+        if let Some(waker) = &self.waker {
+            // The thread serving the icon stops once woken up, and the host
+            // removes the icon when our bus name goes away with it.
+            if let Err(err) = waker.wake() {
+                eprintln!("Failed to remove tray icon: {}", err);
+            }
+        }
+    }
+}
+
+pub fn clicked(tray_id: u32, button: &str, x: i32, y: i32) {
+    push_event(json!({
+        "type": "trayIconClicked",
+        "trayID": tray_id,
+        "button": button,
+        "position": { "x": x, "y": y },
+        "timestamp": clock::now(),
+    }));
+}
+
+fn scrolled(tray_id: u32, delta: i32, orientation: &str) {
+    push_event(json!({
+        "type": "trayIconScrolled",
+        "trayID": tray_id,
+        "delta": delta,
+        "orientation": orientation.to_lowercase(),
+        "timestamp": clock::now(),
+    }));
+}
+
+/// Queues a `menuItemActivated` event if the item at `index` can be
+/// activated, which toggles checkboxes and radio items.
+fn menu_item_activated(tray_id: u32, tray: &mut Tray, index: usize) -> bool {
+    let (item_id, checked) = match tray.menu.activate(index) {
+        Some(activated) => activated,
+        None => return false,
+    };
+    tray.revision += 1;
+    push_event(json!({
+        "type": "menuItemActivated",
+        "trayID": tray_id,
+        "itemID": item_id,
+        "checked": checked,
+        "timestamp": clock::now(),
+    }));
+    true
+}
+
+#[derive(Deserialize)]
+pub struct IconSize {
+    width: u32,
+    height: u32,
+}
+
+/// Turns an RGBA icon, the same as window icons take, into an `IconPixmap`.
+fn icon_pixmap(size: Option<IconSize>, data: Option<ZeroCopyBuf>) -> Result<Option<(i32, i32, Vec<u8>)>, AnyError> {
+    let (size, data) = match (size, data) {
+        (Some(size), Some(data)) => (size, data),
+        (None, None) => return Ok(None),
+        _ => return Err(type_error("Icons need both a size and pixel data")),
+    };
+    // Checked by winit, so that bad icons fail the same as for windows.
+    Icon::from_rgba(data.to_vec(), size.width, size.height)?;
+    // Pixmaps are ARGB32 in network byte order.
+    let argb = data.chunks_exact(4).flat_map(|pixel| [pixel[3], pixel[0], pixel[1], pixel[2]]).collect();
+    Ok(Some((size.width as i32, size.height as i32, argb)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTrayIconArgs {
+    icon: Option<IconSize>,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    tooltip: String,
+    #[serde(default)]
+    menu: Vec<MenuItem>,
+}
+
+/// Resolves to `(id, rid)` once the tray host knows about the icon.
+pub async fn op_create_tray_icon(
+    state: Rc<RefCell<OpState>>,
+    args: CreateTrayIconArgs,
+    icon_data: Option<ZeroCopyBuf>,
+) -> Result<(u32, ResourceId), AnyError> {
+    let id = NEXT_TRAY_ID.fetch_add(1, Ordering::SeqCst);
+    let tray = Arc::new(Mutex::new(Tray {
+        icon: icon_pixmap(args.icon, icon_data)?,
+        title: args.title,
+        tooltip: args.tooltip,
+        menu: Menu::new(args.menu)?,
+        revision: 1,
+        pending: Vec::new(),
+        closed: false,
+    }));
+
+    let headless = state.borrow().borrow::<Flags>().headless;
+    let waker = if headless {
+        None
+    } else {
+        Some(sni::start(id, tray.clone()).await?)
+    };
+    let rid = state.borrow_mut().resource_table.add(TrayResource { id, tray, waker });
+    Ok((id, rid))
+}
+
+pub fn op_tray_icon_set_icon(
+    state: &mut OpState,
+    args: (ResourceId, Option<IconSize>),
+    icon_data: Option<ZeroCopyBuf>,
+) -> Result<(), AnyError> {
+    let icon = icon_pixmap(args.1, icon_data)?;
+    TrayResource::get(state, args.0)?.update(Signal::NewIcon, |tray| tray.icon = icon)
+}
+
+pub fn op_tray_icon_set_title(
+    state: &mut OpState,
+    args: (ResourceId, String),
+    _: (),
+) -> Result<(), AnyError> {
+    TrayResource::get(state, args.0)?.update(Signal::NewTitle, |tray| tray.title = args.1)
+}
+
+pub fn op_tray_icon_set_tooltip(
+    state: &mut OpState,
+    args: (ResourceId, String),
+    _: (),
+) -> Result<(), AnyError> {
+    TrayResource::get(state, args.0)?.update(Signal::NewToolTip, |tray| tray.tooltip = args.1)
+}
+
+pub fn op_tray_icon_set_menu(
+    state: &mut OpState,
+    args: (ResourceId, Vec<MenuItem>),
+    _: (),
+) -> Result<(), AnyError> {
+    let menu = Menu::new(args.1)?;
+    TrayResource::get(state, args.0)?.update(Signal::LayoutUpdated, |tray| {
+        tray.menu = menu;
+        tray.revision += 1;
+    })
+}
+
+/// Tray icons through the StatusNotifierItem protocol, with the menu served
+/// over DBusMenu. Each icon has its own connection and bus name, served by
+/// its own thread.
+/// https://www.freedesktop.org/wiki/Specifications/StatusNotifierItem/
+#[cfg(target_os = "linux")]
+mod sni {
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+    use tokio::sync::oneshot;
+    use zbus::Connection;
+    use zbus::ObjectServer;
+    use zbus::dbus_interface;
+    use zbus::fdo;
+    use zbus::fdo::DBusProxy;
+    use zbus::fdo::RequestNameFlags;
+    use zvariant::ObjectPath;
+    use zvariant::OwnedObjectPath;
+    use zvariant::OwnedValue;
+    use zvariant::Value;
+
+    use super::Signal;
+    use super::Tray;
+    use crate::bus;
+    use crate::menu::Menu;
+    use crate::menu::MenuEntry;
+    use crate::menu::MenuItemType;
+
+    const ITEM_PATH: &str = "/StatusNotifierItem";
+    const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+    const MENU_PATH: &str = "/MenuBar";
+    const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+    const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+    const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+
+    /// `(width, height, ARGB32 pixels)`
+    type Pixmap = (i32, i32, Vec<u8>);
+    /// `(id, properties, children)`, with each child a variant holding
+    /// another layout.
+    type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+    pub use crate::bus::Waker;
+
+    struct StatusNotifierItem {
+        id: u32,
+        tray: Arc<Mutex<Tray>>,
+    }
+
+    #[dbus_interface(name = "org.kde.StatusNotifierItem")]
+    impl StatusNotifierItem {
+        fn activate(&self, x: i32, y: i32) {
+            super::clicked(self.id, "primary", x, y);
+        }
+
+        fn secondary_activate(&self, x: i32, y: i32) {
+            super::clicked(self.id, "middle", x, y);
+        }
+
+        /// Only called by hosts that don't show the menu themselves.
+        fn context_menu(&self, x: i32, y: i32) {
+            super::clicked(self.id, "secondary", x, y);
+        }
+
+        fn scroll(&self, delta: i32, orientation: String) {
+            super::scrolled(self.id, delta, &orientation);
+        }
+
+        #[dbus_interface(property)]
+        fn category(&self) -> String {
+            "ApplicationStatus".to_string()
+        }
+
+        #[dbus_interface(property)]
+        fn id(&self) -> String {
+            format!("deno-{}-{}", std::process::id(), self.id)
+        }
+
+        #[dbus_interface(property)]
+        fn title(&self) -> String {
+            self.tray.lock().unwrap().title.clone()
+        }
+
+        #[dbus_interface(property)]
+        fn status(&self) -> String {
+            "Active".to_string()
+        }
+
+        #[dbus_interface(property)]
+        fn window_id(&self) -> i32 {
+            0
+        }
+
+        #[dbus_interface(property)]
+        fn icon_name(&self) -> String {
+            String::new()
+        }
+
+        #[dbus_interface(property)]
+        fn icon_pixmap(&self) -> Vec<Pixmap> {
+            self.tray.lock().unwrap().icon.iter().cloned().collect()
+        }
+
+        #[dbus_interface(property)]
+        fn tool_tip(&self) -> (String, Vec<Pixmap>, String, String) {
+            (String::new(), Vec::new(), self.tray.lock().unwrap().tooltip.clone(), String::new())
+        }
+
+        #[dbus_interface(property)]
+        fn item_is_menu(&self) -> bool {
+            false
+        }
+
+        #[dbus_interface(property)]
+        fn menu(&self) -> OwnedObjectPath {
+            OwnedObjectPath::try_from(MENU_PATH).unwrap()
+        }
+    }
+
+    struct DBusMenu {
+        id: u32,
+        tray: Arc<Mutex<Tray>>,
+    }
+
+    fn entry(menu: &Menu, id: i32) -> fdo::Result<&MenuEntry> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|index| menu.get(index))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("No menu item has ID {}", id)))
+    }
+
+    /// The DBusMenu properties of `entry`, limited to `names` unless empty.
+    fn properties(entry: &MenuEntry, names: &[String]) -> HashMap<String, OwnedValue> {
+        let mut properties = HashMap::new();
+        let mut set = |name: &str, value: Value| {
+            properties.insert(name.to_string(), OwnedValue::from(value));
+        };
+        match entry.kind {
+            MenuItemType::Separator => set("type", Value::from("separator")),
+            MenuItemType::Normal | MenuItemType::Checkbox | MenuItemType::Radio => {
+                // Underscores mark access keys.
+                set("label", Value::from(entry.label.replace('_', "__")));
+                if !entry.enabled {
+                    set("enabled", Value::from(false));
+                }
+            }
+        }
+        match entry.kind {
+            MenuItemType::Checkbox => set("toggle-type", Value::from("checkmark")),
+            MenuItemType::Radio => set("toggle-type", Value::from("radio")),
+            MenuItemType::Normal | MenuItemType::Separator => {}
+        }
+        if matches!(entry.kind, MenuItemType::Checkbox | MenuItemType::Radio) {
+            set("toggle-state", Value::from(entry.checked as i32));
+        }
+        if !entry.accelerator_parts.is_empty() {
+            set("shortcut", Value::from(vec![entry.accelerator_parts.clone()]));
+        }
+        if !entry.children.is_empty() {
+            set("children-display", Value::from("submenu"));
+        }
+        if !names.is_empty() {
+            properties.retain(|name, _| names.contains(name));
+        }
+        properties
+    }
+
+    /// The layout of `id` and, if `depth` isn't 0, its children. A negative
+    /// `depth` means all the way down.
+    fn layout(menu: &Menu, id: i32, depth: i32, names: &[String]) -> Layout {
+        let entry = &menu[id as usize];
+        let children = match depth {
+            0 => Vec::new(),
+            _ => entry
+                .children
+                .iter()
+                .map(|&child| OwnedValue::from(Value::from(layout(menu, child as i32, depth - 1, names))))
+                .collect(),
+        };
+        (id, properties(entry, names), children)
+    }
+
+    /// Activates menu item `id`, and has the host show checkboxes and radio
+    /// items in their new state.
+    fn menu_item_clicked(tray_id: u32, tray: &mut Tray, id: i32) {
+        if super::menu_item_activated(tray_id, tray, id as usize) {
+            tray.pending.push(Signal::LayoutUpdated);
+        }
+    }
+
+    #[dbus_interface(name = "com.canonical.dbusmenu")]
+    impl DBusMenu {
+        fn get_layout(&self, parent_id: i32, recursion_depth: i32, property_names: Vec<String>) -> fdo::Result<(u32, Layout)> {
+            let tray = self.tray.lock().unwrap();
+            entry(&tray.menu, parent_id)?;
+            Ok((tray.revision, layout(&tray.menu, parent_id, recursion_depth, &property_names)))
+        }
+
+        fn get_group_properties(&self, ids: Vec<i32>, property_names: Vec<String>) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+            let tray = self.tray.lock().unwrap();
+            let ids = if ids.is_empty() { (0..tray.menu.len() as i32).collect() } else { ids };
+            ids.into_iter()
+                .filter_map(|id| entry(&tray.menu, id).ok().map(|entry| (id, properties(entry, &property_names))))
+                .collect()
+        }
+
+        fn get_property(&self, id: i32, name: String) -> fdo::Result<OwnedValue> {
+            let tray = self.tray.lock().unwrap();
+            properties(entry(&tray.menu, id)?, &[name.clone()])
+                .remove(&name)
+                .ok_or_else(|| fdo::Error::InvalidArgs(format!("Menu item {} has no property {}", id, name)))
+        }
+
+        fn event(&self, id: i32, event_id: String, _data: OwnedValue, _timestamp: u32) -> fdo::Result<()> {
+            let mut tray = self.tray.lock().unwrap();
+            entry(&tray.menu, id)?;
+            if event_id == "clicked" {
+                menu_item_clicked(self.id, &mut tray, id);
+            }
+            Ok(())
+        }
+
+        /// Returns the IDs that don't exist.
+        fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+            let mut tray = self.tray.lock().unwrap();
+            let mut errors = Vec::new();
+            for (id, event_id, _, _) in events {
+                if entry(&tray.menu, id).is_err() {
+                    errors.push(id);
+                } else if event_id == "clicked" {
+                    menu_item_clicked(self.id, &mut tray, id);
+                }
+            }
+            errors
+        }
+
+        /// Menus are always up to date, nothing needs updating.
+        fn about_to_show(&self, _id: i32) -> bool {
+            false
+        }
+
+        fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+            (Vec::new(), Vec::new())
+        }
+
+        #[dbus_interface(property)]
+        fn version(&self) -> u32 {
+            3
+        }
+
+        #[dbus_interface(property)]
+        fn text_direction(&self) -> String {
+            "ltr".to_string()
+        }
+
+        #[dbus_interface(property)]
+        fn status(&self) -> String {
+            "normal".to_string()
+        }
+
+        #[dbus_interface(property)]
+        fn icon_theme_path(&self) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    fn emit(conn: &Connection, signal: Signal, revision: u32) -> zbus::Result<()> {
+        match signal {
+            Signal::NewIcon => conn.emit_signal(None, ITEM_PATH, ITEM_INTERFACE, "NewIcon", &()),
+            Signal::NewTitle => conn.emit_signal(None, ITEM_PATH, ITEM_INTERFACE, "NewTitle", &()),
+            Signal::NewToolTip => conn.emit_signal(None, ITEM_PATH, ITEM_INTERFACE, "NewToolTip", &()),
+            Signal::LayoutUpdated => conn.emit_signal(None, MENU_PATH, MENU_INTERFACE, "LayoutUpdated", &(revision, 0i32)),
+        }
+    }
+
+    fn register(conn: &Connection, server: &mut ObjectServer, id: u32, tray: &Arc<Mutex<Tray>>, service: &str) -> Result<(), AnyError> {
+        DBusProxy::new(conn)?.request_name(service, RequestNameFlags::DoNotQueue.into())?;
+        server.at(&ObjectPath::try_from(ITEM_PATH)?, StatusNotifierItem { id, tray: tray.clone() })?;
+        server.at(&ObjectPath::try_from(MENU_PATH)?, DBusMenu { id, tray: tray.clone() })?;
+        // The watcher may ask for our properties before replying, which get
+        // queued until we start serving.
+        match conn.call_method(Some(WATCHER), WATCHER_PATH, Some(WATCHER), "RegisterStatusNotifierItem", &service) {
+            Err(zbus::Error::MethodError(name, _, _)) if name == "org.freedesktop.DBus.Error.ServiceUnknown" => Err(
+                custom_error("NotFound", "No StatusNotifierWatcher is running, so there is no tray to add the icon to"),
+            ),
+            result => result.map(|_| ()).map_err(AnyError::from),
+        }
+    }
+
+    fn serve(id: u32, tray: Arc<Mutex<Tray>>, waker: Waker, service: String, started: oneshot::Sender<Result<(), AnyError>>) {
+        let conn = match Connection::new_session() {
+            Ok(conn) => conn,
+            Err(err) => {
+                let _ = started.send(Err(err.into()));
+                return;
+            }
+        };
+        let mut server = ObjectServer::new(&conn);
+        if let Err(err) = register(&conn, &mut server, id, &tray, &service) {
+            let _ = started.send(Err(err));
+            return;
+        }
+        // Registering takes blocking calls, serving doesn't.
+        if let Err(err) = bus::set_nonblocking(&conn) {
+            let _ = started.send(Err(err));
+            return;
+        }
+        let _ = started.send(Ok(()));
+
+        loop {
+            match server.try_handle_next() {
+                Ok(_) => {}
+                Err(err) if bus::would_block(&err) => {
+                    if let Err(err) = waker.wait(&conn) {
+                        eprintln!("Stopped serving tray icon: {}", err);
+                        return;
+                    }
+                }
+                Err(zbus::Error::Io(err)) => {
+                    eprintln!("Stopped serving tray icon: {}", err);
+                    return;
+                }
+                Err(err) => eprintln!("Failed to handle tray icon request: {}", err),
+            }
+
+            let (signals, revision) = {
+                let mut tray = tray.lock().unwrap();
+                if tray.closed {
+                    return;
+                }
+                (std::mem::take(&mut tray.pending), tray.revision)
+            };
+            for signal in signals {
+                if let Err(err) = emit(&conn, signal, revision) {
+                    eprintln!("Failed to update tray icon: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Starts serving tray icon `id` and resolves to a waker for the serving
+    /// thread once it's registered with the watcher.
+    pub async fn start(id: u32, tray: Arc<Mutex<Tray>>) -> Result<Waker, AnyError> {
+        let service = format!("org.kde.StatusNotifierItem-{}-{}", std::process::id(), id);
+        let waker = Waker::new()?;
+        let (started, result) = oneshot::channel();
+        let serving = waker.clone();
+        std::thread::spawn(move || serve(id, tray, serving, service, started));
+        result.await??;
+        Ok(waker)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sni {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use deno_runtime::deno_core::error::AnyError;
+    use deno_runtime::deno_core::error::custom_error;
+
+    use super::Tray;
+
+    pub struct Waker;
+
+    impl Waker {
+        pub fn wake(&self) -> Result<(), AnyError> {
+            Ok(())
+        }
+    }
+
+    pub async fn start(_id: u32, _tray: Arc<Mutex<Tray>>) -> Result<Waker, AnyError> {
+        Err(custom_error("NotSupported", "Tray icons are only supported on Linux"))
+    }
+}
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .ops(vec![
+            ("op_create_tray_icon", op_async(op_create_tray_icon)),
+            ("op_tray_icon_set_icon", op_sync(op_tray_icon_set_icon)),
+            ("op_tray_icon_set_menu", op_sync(op_tray_icon_set_menu)),
+            ("op_tray_icon_set_title", op_sync(op_tray_icon_set_title)),
+            ("op_tray_icon_set_tooltip", op_sync(op_tray_icon_set_tooltip)),
+        ])
+        .build()
+}
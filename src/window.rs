@@ -6,39 +6,191 @@ use deno_runtime::deno_core::ResourceId;
 use deno_runtime::deno_core::Resource;
 use deno_runtime::deno_core::ZeroCopyBuf;
 use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::custom_error;
+use deno_runtime::deno_core::error::type_error;
 use winit_main::reexports::dpi::PhysicalSize;
 use winit_main::reexports::dpi::Size;
 use winit_main::reexports::window::Window;
 use winit_main::reexports::window::WindowAttributes;
 use winit_main::reexports::window::UserAttentionType;
-use winit_main::reexports::window::CursorIcon;
 use winit_main::reexports::window::Fullscreen;
 use winit_main::reexports::window::Icon;
 use winit_main::reexports::dpi::Position;
 use winit_main::reexports::dpi::PhysicalPosition;
-use raw_window_handle::RawWindowHandle;
-use raw_window_handle::HasRawWindowHandle;
+use winit_main::reexports::dpi::LogicalPosition;
+use winit_main::reexports::dpi::LogicalSize;
+use winit_main::reexports::monitor::MonitorHandle;
+#[cfg(target_os = "linux")]
+use winit_main::reexports::platform::unix::WindowExtUnix;
+use serde::Serialize;
 use deno_runtime::deno_core::serde_json::Value;
+use deno_runtime::deno_core::serde_json::json;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Mutex;
 
 use crate::EVENT_LOOP;
-use crate::util::hash;
+use crate::clock;
+use crate::cursor;
+use crate::cursor::parse_cursor_icon;
+use crate::cursor::validate_cursor_image;
+use crate::flags::Flags;
+use crate::headless::HeadlessWindow;
+use crate::ids;
+#[cfg(target_os = "linux")]
+use crate::ime;
+use crate::monitor::VIRTUAL_MONITOR_BIT_DEPTH;
+use crate::monitor::VIRTUAL_MONITOR_NAME;
+use crate::monitor::VIRTUAL_MONITOR_REFRESH_RATE;
+use crate::monitor::VIRTUAL_MONITOR_SIZE;
+use crate::monitor::serialize_monitor;
+use crate::monitor::serialize_video_mode;
+use crate::monitor::virtual_monitor;
+use crate::monitor::virtual_video_mode;
+use crate::menu;
+use crate::owned;
+use crate::owned::WindowKind;
+use crate::pointer;
+use crate::shortcuts;
+#[cfg(target_os = "linux")]
+use crate::x11;
+use crate::event_loop::add_logical_values;
+use crate::event_loop::push_event;
 use crate::event_loop::serialize_physical_position;
 use crate::event_loop::serialize_physical_size;
+use crate::event_loop::set_window_scale_factor;
+use crate::event_loop::window_scale_factor;
+use crate::event_loop::subscribe_window;
+use crate::event_loop::unsubscribe_window;
 
-pub struct DynHasRawWindowHandle(pub Box<dyn HasRawWindowHandle>);
+lazy_static! {
+    /// Units the size and position ops of each window work in, by window ID.
+    static ref WINDOW_UNITS: Mutex<HashMap<u32, Units>> = Mutex::new(HashMap::new());
+    /// The fullscreen state last reported for each native window, and whether
+    /// its `_NET_WM_STATE` said fullscreen then, on X11.
+    static ref NATIVE_FULLSCREEN: Mutex<HashMap<u32, (Value, Option<bool>)>> = Mutex::new(HashMap::new());
+}
 
-unsafe impl HasRawWindowHandle for DynHasRawWindowHandle {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        self.0.raw_window_handle()
-    }
+/// A window created with `op_create_window`. winit's `Window` implements
+/// `HasRawWindowHandle`, so native windows can back WebGPU surfaces as is.
+pub enum WindowResource {
+    Native(Window),
+    Headless(HeadlessWindow),
 }
 
-pub struct WindowResource(pub DynHasRawWindowHandle);
+impl WindowResource {
+    /// Looks up the window `target` refers to. Unlike a plain resource table
+    /// lookup, the error says whether the window was closed or `target` is
+    /// some other kind of resource.
+    pub fn get(state: &OpState, target: &WindowRef) -> Result<Rc<WindowResource>, AnyError> {
+        let rid = target.rid()?;
+        state.resource_table.get::<WindowResource>(rid).map_err(|_| {
+            match state.resource_table.names().find(|(other, _)| *other == rid) {
+                Some((_, name)) => custom_error("BadResource", format!("Resource {} is a {}, not a window", rid, name)),
+                None => custom_error("BadResource", format!("Window {} is closed or doesn't exist", rid)),
+            }
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            WindowResource::Native(window) => ids::window_id(window.id()),
+            WindowResource::Headless(window) => window.id(),
+        }
+    }
+}
 
 impl Resource for WindowResource {
     fn name(&self) -> std::borrow::Cow<str> {
         "Window".into()
     }
+
+    fn close(self: Rc<Self>) {
+        let id = self.id();
+        ids::remove_window_rid(id);
+        unsubscribe_window(id);
+        shortcuts::remove_window(id);
+        menu::remove_window(id);
+        owned::remove_window(id);
+        #[cfg(target_os = "linux")]
+        ime::remove_window(id);
+        #[cfg(target_os = "linux")]
+        x11::remove_window(id);
+        WINDOW_UNITS.lock().unwrap().remove(&id);
+        NATIVE_FULLSCREEN.lock().unwrap().remove(&id);
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    Logical,
+    Physical,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Units::Physical
+    }
+}
+
+impl Units {
+    fn size(self, width: f64, height: f64) -> Size {
+        match self {
+            Units::Logical => Size::Logical(LogicalSize::new(width, height)),
+            Units::Physical => Size::Physical(PhysicalSize::new(width.round() as u32, height.round() as u32)),
+        }
+    }
+
+    pub fn position(self, x: f64, y: f64) -> Position {
+        match self {
+            Units::Logical => Position::Logical(LogicalPosition::new(x, y)),
+            Units::Physical => Position::Physical(PhysicalPosition::new(x.round() as i32, y.round() as i32)),
+        }
+    }
+
+    fn serialize_size(self, size: PhysicalSize<u32>, scale_factor: f64) -> Value {
+        match self {
+            Units::Logical => {
+                let size = size.to_logical::<f64>(scale_factor);
+                json!({ "width": size.width, "height": size.height })
+            }
+            Units::Physical => serialize_physical_size(size),
+        }
+    }
+
+    fn serialize_position(self, position: PhysicalPosition<i32>, scale_factor: f64) -> Value {
+        match self {
+            Units::Logical => {
+                let position = position.to_logical::<f64>(scale_factor);
+                json!({ "x": position.x, "y": position.y })
+            }
+            Units::Physical => serialize_physical_position(position),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowTheme {
+    Light,
+    Dark,
+}
+
+impl WindowTheme {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WindowTheme::Light => "light",
+            WindowTheme::Dark => "dark",
+        }
+    }
+}
+
+/// Size of the RGBA icon passed as the op's buffer.
+#[derive(Deserialize)]
+pub struct IconArgs {
+    width: u32,
+    height: u32,
 }
 
 #[derive(Deserialize)]
@@ -51,23 +203,71 @@ pub struct CreateWindowArgs {
     visible: Option<bool>,
     transparent: Option<bool>,
     always_on_top: Option<bool>,
-    width: Option<u32>,
-    height: Option<u32>,
-    min_width: Option<u32>,
-    min_height: Option<u32>,
-    max_width: Option<u32>,
-    max_height: Option<u32>,
+    width: Option<f64>,
+    height: Option<f64>,
+    min_width: Option<f64>,
+    min_height: Option<f64>,
+    max_width: Option<f64>,
+    max_height: Option<f64>,
+    x: Option<f64>,
+    y: Option<f64>,
+    #[serde(default)]
+    centered: bool,
+    fullscreen: Option<FullscreenArgs>,
+    icon: Option<IconArgs>,
+    theme: Option<WindowTheme>,
+    /// Units of the sizes and position above.
+    #[serde(default)]
+    units: Units,
+    /// The window this one belongs to and stays above.
+    parent: Option<WindowRef>,
+    #[serde(default)]
+    kind: WindowKind,
+    /// Whether the parent takes no input while this window is visible.
+    #[serde(default)]
+    modal: bool,
+}
+
+enum InitialPosition {
+    At(Position),
+    Centered,
+}
+
+/// Position that centers a window of `size` on a monitor.
+pub fn centered_position(
+    monitor_position: PhysicalPosition<i32>,
+    monitor_size: PhysicalSize<u32>,
+    size: PhysicalSize<u32>,
+) -> PhysicalPosition<i32> {
+    PhysicalPosition::new(
+        monitor_position.x + (monitor_size.width as i32 - size.width as i32) / 2,
+        monitor_position.y + (monitor_size.height as i32 - size.height as i32) / 2,
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn set_native_theme(window: &Window, theme: WindowTheme) -> Result<(), AnyError> {
+    // GTK based window managers draw dark decorations based on this.
+    if let Some(xlib_window) = window.xlib_window() {
+        x11::with_connection(|conn| {
+            x11::set_utf8_property(conn, xlib_window as u32, "_GTK_THEME_VARIANT", theme.as_str())
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_native_theme(_window: &Window, _theme: WindowTheme) -> Result<(), AnyError> {
+    Ok(())
 }
 
 pub fn op_create_window(
     state: &mut OpState,
     args: CreateWindowArgs,
-    _: ()
+    icon_data: Option<ZeroCopyBuf>,
 ) -> Result<(u32, ResourceId), AnyError> {
-    let ev = EVENT_LOOP.lock().unwrap();
-    let ev = ev.as_ref().unwrap().lock().unwrap();
-
     let mut attribs = WindowAttributes::default();
+    let units = args.units;
 
     if let Some(title) = args.title {
         attribs.title = title;
@@ -98,133 +298,440 @@ pub fn op_create_window(
     }
 
     if args.width.is_some() || args.height.is_some() {
-        let width = args.width.unwrap_or(800);
-        let height = args.height.unwrap_or(600);
-        let size = PhysicalSize::new(width, height);
-        attribs.inner_size = Some(Size::Physical(size));
+        let width = args.width.unwrap_or(800.0);
+        let height = args.height.unwrap_or(600.0);
+        attribs.inner_size = Some(units.size(width, height));
     }
 
     if args.min_width.is_some() || args.min_height.is_some() {
-        let min_width = args.min_width.unwrap_or(0);
-        let min_height = args.min_height.unwrap_or(0);
-        let size = PhysicalSize::new(min_width, min_height);
-        attribs.min_inner_size = Some(Size::Physical(size));
+        let min_width = args.min_width.unwrap_or(0.0);
+        let min_height = args.min_height.unwrap_or(0.0);
+        attribs.min_inner_size = Some(units.size(min_width, min_height));
     }
 
     if args.max_width.is_some() || args.max_height.is_some() {
-        let max_width = args.max_width.unwrap_or(0);
-        let max_height = args.max_height.unwrap_or(0);
-        let size = PhysicalSize::new(max_width, max_height);
-        attribs.max_inner_size = Some(Size::Physical(size));
+        // The dimension that isn't given stays unbounded.
+        let unbounded = i32::MAX as f64;
+        let max_width = args.max_width.unwrap_or(unbounded);
+        let max_height = args.max_height.unwrap_or(unbounded);
+        attribs.max_inner_size = Some(units.size(max_width, max_height));
     }
 
-    let window = ev.create_window(attribs)?;
+    if let Some(icon) = args.icon {
+        let data = icon_data.ok_or_else(|| type_error("Missing icon data"))?;
+        attribs.window_icon = Some(Icon::from_rgba(data.to_vec(), icon.width, icon.height)?);
+    }
 
-    Ok((hash(window.id()), state.resource_table.add(WindowResource(DynHasRawWindowHandle(Box::new(window))))))
+    let position = match (args.x, args.y, args.centered) {
+        (None, None, false) => None,
+        (Some(x), Some(y), false) => Some(InitialPosition::At(units.position(x, y))),
+        (None, None, true) => Some(InitialPosition::Centered),
+        (_, _, true) => return Err(type_error("centered can't be combined with x and y")),
+        _ => return Err(type_error("x and y must be given together")),
+    };
+    let fullscreen = fullscreen_options(args.fullscreen);
+
+    let parent = match &args.parent {
+        Some(parent) => Some(WindowResource::get(state, parent)?),
+        None => None,
+    };
+    if parent.is_none() && args.modal {
+        return Err(type_error("Modal windows need a parent"));
+    }
+    if parent.is_none() && args.kind == WindowKind::Popup {
+        return Err(type_error("Popup windows need a parent"));
+    }
+    if args.kind == WindowKind::Popup {
+        attribs.decorations = false;
+    }
+    let visible = attribs.visible;
+
+    // winit can only position, theme and parent windows after creating them,
+    // so keep them hidden until then instead of flashing at the default
+    // position. Popups also have to be hidden to skip the window manager.
+    let hinted = parent.is_some() || args.kind != WindowKind::Normal;
+    let show = attribs.visible && (position.is_some() || args.theme.is_some() || hinted);
+    if show {
+        attribs.visible = false;
+    }
+
+    let (id, resource) = if state.borrow::<Flags>().headless {
+        let fullscreen = match &fullscreen {
+            Some(options) => Some(headless_fullscreen(options)?),
+            None => None,
+        };
+        let window = HeadlessWindow::new(attribs);
+        match position {
+            Some(InitialPosition::At(position)) => window.set_outer_position(position),
+            Some(InitialPosition::Centered) => window.set_outer_position(centered_position(
+                PhysicalPosition::new(0, 0),
+                VIRTUAL_MONITOR_SIZE,
+                window.outer_size(),
+            )),
+            None => {}
+        }
+        if let Some(fullscreen) = fullscreen {
+            window.set_fullscreen_state(fullscreen);
+        }
+        if let Some(theme) = args.theme {
+            window.set_theme(theme.as_str());
+        }
+        if show {
+            window.set_visible(true);
+        }
+        set_window_scale_factor(window.id(), window.scale_factor());
+        (window.id(), WindowResource::Headless(window))
+    } else {
+        let ev = EVENT_LOOP.lock().unwrap();
+        let ev = ev.as_ref().unwrap().lock().unwrap();
+        if let Some(options) = &fullscreen {
+            // New windows open on the primary monitor.
+            attribs.fullscreen = Some(native_fullscreen(ev.available_monitors().into_iter(), ev.primary_monitor(), options)?);
+        }
+        let window = ev.create_window(attribs)?;
+        match position {
+            Some(InitialPosition::At(position)) => window.set_outer_position(position),
+            Some(InitialPosition::Centered) => {
+                if let Some(monitor) = window.current_monitor().or_else(|| ev.primary_monitor()) {
+                    window.set_outer_position(centered_position(monitor.position(), monitor.size(), window.outer_size()));
+                }
+            }
+            None => {}
+        }
+        if let Some(theme) = args.theme {
+            set_native_theme(&window, theme)?;
+        }
+        if hinted {
+            let parent = match parent.as_deref() {
+                Some(WindowResource::Native(parent)) => Some(parent),
+                _ => None,
+            };
+            owned::set_native_hints(&window, parent, args.kind, args.modal)?;
+        }
+        if show {
+            window.set_visible(true);
+        }
+        let id = ids::window_id(window.id());
+        set_window_scale_factor(id, window.scale_factor());
+        #[cfg(target_os = "linux")]
+        if let Some(xlib_window) = window.xlib_window() {
+            x11::set_window(id, xlib_window as u32);
+        }
+        track_fullscreen(id, &window, winit_fullscreen(&window));
+        (id, WindowResource::Native(window))
+    };
+
+    if let Some(parent) = parent {
+        owned::register(id, parent.id(), args.kind, args.modal, visible);
+    }
+    let rid = state.resource_table.add(resource);
+    ids::set_window_rid(id, rid);
+    WINDOW_UNITS.lock().unwrap().insert(id, units);
+    Ok((id, rid))
 }
 
+/// A position in the window's units.
 #[derive(Deserialize)]
 pub struct SerdePosition {
-    x: i32,
-    y: i32,
+    x: f64,
+    y: f64,
 }
 
-impl Into<PhysicalPosition<i32>> for SerdePosition {
-    fn into(self) -> PhysicalPosition<i32> {
-        PhysicalPosition::new(self.x, self.y)
+impl SerdePosition {
+    fn to_position(&self, units: Units) -> Position {
+        units.position(self.x, self.y)
     }
 }
 
+/// A size in the window's units.
 #[derive(Deserialize)]
 pub struct SerdeSize {
-    width: u32,
-    height: u32,
+    width: f64,
+    height: f64,
 }
 
-impl Into<PhysicalSize<u32>> for SerdeSize {
-    fn into(self) -> PhysicalSize<u32> {
-        PhysicalSize::new(self.width, self.height)
+impl SerdeSize {
+    fn to_size(&self, units: Units) -> Size {
+        units.size(self.width, self.height)
     }
 }
 
-fn get_window(state: &mut OpState, rid: ResourceId) -> Result<&Window, AnyError> {
-    let window = state.resource_table.get::<WindowResource>(rid)?;
-    let window = window.0.0.as_ref() as *const _ as *const Window;
-    let window = unsafe { &*window };
-    Ok(window)
+/// Ops address a window either by its resource ID or, as `{ id }`, by the ID
+/// used in events.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum WindowRef {
+    Rid(ResourceId),
+    Id { id: u32 },
 }
 
-pub fn op_window_fullscreen(
+impl WindowRef {
+    pub fn rid(&self) -> Result<ResourceId, AnyError> {
+        match *self {
+            WindowRef::Rid(rid) => Ok(rid),
+            WindowRef::Id { id } => ids::window_rid(id)
+                .ok_or_else(|| custom_error("BadResource", format!("No open window has ID {}", id))),
+        }
+    }
+}
+
+/// The units the size and position ops of `window` work in.
+pub fn window_units(state: &OpState, window: &WindowRef) -> Result<Units, AnyError> {
+    let id = WindowResource::get(state, window)?.id();
+    Ok(WINDOW_UNITS.lock().unwrap().get(&id).copied().unwrap_or_default())
+}
+
+/// winit-main drops `ScaleFactorChanged`, because its `new_inner_size`
+/// borrows from the event loop and the event can't be made `'static`. Its
+/// `Blocker` only follows `RedrawRequested` and carries nothing back, so the
+/// new size can't be chosen as winit intends without forking winit-main.
+/// Instead native windows are checked for a new scale factor as
+/// their move and resize events are delivered. If it changed, `event`'s
+/// logical values are corrected and the `scaleFactorChanged` event to deliver
+/// before it is returned.
+pub fn check_scale_factor(state: &OpState, event: &mut Value) -> Option<Value> {
+    let kind = event["event"]["type"].as_str()?;
+    if event["type"] != "windowEvent" || (kind != "moved" && kind != "resized") {
+        return None;
+    }
+    let id = event["windowID"].as_u64()? as u32;
+    let resource = state.resource_table.get::<WindowResource>(ids::window_rid(id)?).ok()?;
+    let window = match &*resource {
+        WindowResource::Native(window) => window,
+        WindowResource::Headless(_) => return None,
+    };
+
+    let scale_factor = window.scale_factor();
+    if scale_factor == window_scale_factor(id) {
+        return None;
+    }
+    set_window_scale_factor(id, scale_factor);
+    add_logical_values(&mut event["event"], scale_factor);
+
+    let mut changed = json!({
+        "type": "scaleFactorChanged",
+        "scaleFactor": scale_factor,
+        "newInnerSize": serialize_physical_size(window.inner_size()),
+    });
+    add_logical_values(&mut changed, scale_factor);
+    Some(json!({
+        "type": "windowEvent",
+        "windowID": id,
+        "event": changed,
+        "timestamp": event["timestamp"].clone(),
+    }))
+}
+
+/// Windows also enter and leave fullscreen when the window manager or
+/// compositor decides to, which is noticed as their move and resize events are
+/// delivered. If the state changed, the `fullscreenChanged` event to deliver
+/// before `event` is returned.
+pub fn check_fullscreen(state: &OpState, event: &Value) -> Option<Value> {
+    let kind = event["event"]["type"].as_str()?;
+    if event["type"] != "windowEvent" || (kind != "moved" && kind != "resized") {
+        return None;
+    }
+    let id = event["windowID"].as_u64()? as u32;
+    let resource = state.resource_table.get::<WindowResource>(ids::window_rid(id)?).ok()?;
+    let window = match &*resource {
+        WindowResource::Native(window) => window,
+        WindowResource::Headless(_) => return None,
+    };
+
+    let mut tracked = NATIVE_FULLSCREEN.lock().unwrap();
+    let (previous, was_net_wm) = tracked.get(&id)?.clone();
+    let net_wm = net_wm_fullscreen(window);
+    // winit 0.24 on X11 only knows what was last set, so changes are read from
+    // `_NET_WM_STATE` instead. Without a window manager it never changes.
+    let current = match (was_net_wm, net_wm) {
+        (Some(false), Some(true)) if previous.is_null() => serialize_fullscreen(
+            Some(Fullscreen::Borderless(window.current_monitor())),
+            window.primary_monitor().as_ref(),
+        ),
+        (Some(true), Some(false)) => Value::Null,
+        (Some(_), Some(_)) => previous.clone(),
+        _ => winit_fullscreen(window),
+    };
+    tracked.insert(id, (current.clone(), net_wm));
+    if current == previous {
+        return None;
+    }
+    Some(json!({
+        "type": "windowEvent",
+        "windowID": id,
+        "event": { "type": "fullscreenChanged", "fullscreen": current },
+        "timestamp": event["timestamp"].clone(),
+    }))
+}
+
+/// Evaluates `$body` with `$window` bound to the window `$target` refers to.
+/// The headless window mirrors the winit methods used here, so the same body
+/// works for both backends.
+macro_rules! with_window {
+    ($state:expr, $target:expr, |$window:ident| $body:expr) => {{
+        let resource = WindowResource::get($state, &$target)?;
+        match &*resource {
+            WindowResource::Native($window) => $body,
+            WindowResource::Headless($window) => $body,
+        }
+    }};
+}
+
+/// Starts routing the window's events to its own queue, returning the window
+/// ID to poll it with.
+pub fn op_window_subscribe_events(
+    state: &mut OpState,
+    window: WindowRef,
+    _: ()
+) -> Result<u32, AnyError> {
+    let resource = WindowResource::get(state, &window)?;
+    let id = resource.id();
+    if !subscribe_window(id) {
+        return Err(custom_error("Busy", "Window events are already being read"));
+    }
+    Ok(id)
+}
+
+/// Closes the window, after the windows it owns and theirs.
+pub fn op_window_close(
+    state: &mut OpState,
+    window: WindowRef,
+    _: ()
+) -> Result<(), AnyError> {
+    let rid = window.rid()?;
+    let id = WindowResource::get(state, &window)?.id();
+    for owned in owned::descendants(id).into_iter().rev() {
+        if let Some(owned_rid) = ids::window_rid(owned) {
+            state.resource_table.close(owned_rid)?;
+        }
+    }
+    state.resource_table.close(rid)
+}
+
+pub fn op_window_current_monitor(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
-) -> Result<bool, AnyError> {
-    let window = get_window(state, rid)?;
-    let fs = window.fullscreen();
-    Ok(match fs {
-        Some(_) => true,
-        None => false,
+) -> Result<Option<Value>, AnyError> {
+    let resource = WindowResource::get(state, &window)?;
+    Ok(match &*resource {
+        WindowResource::Native(window) => {
+            let primary = window.primary_monitor();
+            window.current_monitor().map(|monitor| serialize_monitor(&monitor, primary.as_ref()))
+        }
+        WindowResource::Headless(_) => Some(virtual_monitor()),
     })
 }
 
+fn serialize_fullscreen(fullscreen: Option<Fullscreen>, primary: Option<&MonitorHandle>) -> Value {
+    match fullscreen {
+        Some(Fullscreen::Borderless(monitor)) => json!({
+            "mode": "borderless",
+            "monitor": monitor.map(|monitor| serialize_monitor(&monitor, primary)),
+        }),
+        Some(Fullscreen::Exclusive(mode)) => json!({
+            "mode": "exclusive",
+            "monitor": serialize_monitor(&mode.monitor(), primary),
+            "videoMode": serialize_video_mode(&mode),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn winit_fullscreen(window: &Window) -> Value {
+    serialize_fullscreen(window.fullscreen(), window.primary_monitor().as_ref())
+}
+
+#[cfg(target_os = "linux")]
+fn net_wm_fullscreen(window: &Window) -> Option<bool> {
+    let xlib_window = window.xlib_window()? as u32;
+    x11::with_connection(|conn| x11::has_atom(conn, xlib_window, "_NET_WM_STATE", "_NET_WM_STATE_FULLSCREEN")).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn net_wm_fullscreen(_: &Window) -> Option<bool> {
+    None
+}
+
+fn track_fullscreen(id: u32, window: &Window, state: Value) {
+    NATIVE_FULLSCREEN.lock().unwrap().insert(id, (state, net_wm_fullscreen(window)));
+}
+
+/// `null` when windowed, otherwise the `mode`, `monitor` and (when
+/// exclusive) `videoMode`.
+fn fullscreen_state(resource: &WindowResource) -> Value {
+    match resource {
+        WindowResource::Native(window) => match NATIVE_FULLSCREEN.lock().unwrap().get(&resource.id()) {
+            Some((state, _)) => state.clone(),
+            None => winit_fullscreen(window),
+        },
+        WindowResource::Headless(window) => window.fullscreen_state(),
+    }
+}
+
+pub fn op_window_fullscreen(
+    state: &mut OpState,
+    window: WindowRef,
+    _: ()
+) -> Result<Value, AnyError> {
+    let resource = WindowResource::get(state, &window)?;
+    Ok(fullscreen_state(&resource))
+}
+
 pub fn op_window_inner_position(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
 ) -> Result<Value, AnyError> {
-    let window = get_window(state, rid)?;
-    let position = window.inner_position()?;
-    Ok(serialize_physical_position(position))
+    let units = window_units(state, &window)?;
+    let (position, scale_factor) = with_window!(state, window, |window| (window.inner_position()?, window.scale_factor()));
+    Ok(units.serialize_position(position, scale_factor))
 }
 
 pub fn op_window_inner_size(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
 ) -> Result<Value, AnyError> {
-    let window = get_window(state, rid)?;
-    let size = window.inner_size();
-    Ok(serialize_physical_size(size))
+    let units = window_units(state, &window)?;
+    let (size, scale_factor) = with_window!(state, window, |window| (window.inner_size(), window.scale_factor()));
+    Ok(units.serialize_size(size, scale_factor))
 }
 
 pub fn op_window_outer_position(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
 ) -> Result<Value, AnyError> {
-    let window = get_window(state, rid)?;
-    let position = window.outer_position()?;
-    Ok(serialize_physical_position(position))
+    let units = window_units(state, &window)?;
+    let (position, scale_factor) = with_window!(state, window, |window| (window.outer_position()?, window.scale_factor()));
+    Ok(units.serialize_position(position, scale_factor))
 }
 
 pub fn op_window_outer_size(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
 ) -> Result<Value, AnyError> {
-    let window = get_window(state, rid)?;
-    let size = window.outer_size();
-    Ok(serialize_physical_size(size))
+    let units = window_units(state, &window)?;
+    let (size, scale_factor) = with_window!(state, window, |window| (window.outer_size(), window.scale_factor()));
+    Ok(units.serialize_size(size, scale_factor))
 }
 
 pub fn op_window_request_redraw(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
 ) -> Result<(), AnyError> {
-    let window = state.resource_table.get::<WindowResource>(rid)?;
-    let window = window.0.0.as_ref() as *const _ as *const Window;
-    let window = unsafe { &*window };
-    window.request_redraw();
+    with_window!(state, window, |window| window.request_redraw());
     Ok(())
 }
 
 pub fn op_window_request_user_attention(
     state: &mut OpState,
-    args: (ResourceId, Option<String>),
+    args: (WindowRef, Option<String>),
     _: (),
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
     let ty = match args.1 {
         Some(ty) => match ty.as_str() {
             "critical" => Some(UserAttentionType::Critical),
@@ -233,213 +740,474 @@ pub fn op_window_request_user_attention(
         },
         _ => None,
     };
-    window.request_user_attention(ty);
+    with_window!(state, args.0, |window| window.request_user_attention(ty));
     Ok(())
 }
 
 pub fn op_window_scale_factor(
     state: &mut OpState,
-    rid: ResourceId,
+    window: WindowRef,
     _: ()
 ) -> Result<f64, AnyError> {
-    let window = get_window(state, rid)?;
-    Ok(window.scale_factor())
+    Ok(with_window!(state, window, |window| window.scale_factor()))
+}
+
+/// Grabs and hides the cursor. Until the window loses focus or exits pointer
+/// lock, mouse motion is delivered to it as `pointermove` with `movementX/Y`.
+pub fn op_window_request_pointer_lock(
+    state: &mut OpState,
+    window: WindowRef,
+    _: ()
+) -> Result<(), AnyError> {
+    let id = WindowResource::get(state, &window)?.id();
+    with_window!(state, window, |window| {
+        window.set_cursor_grab(true)?;
+        window.set_cursor_visible(false);
+    });
+    pointer::lock_pointer(id);
+    Ok(())
+}
+
+/// Releases pointer lock and restores the cursor. Also used by core.js to
+/// restore it after the lock was lost to a focus change.
+pub fn op_window_exit_pointer_lock(
+    state: &mut OpState,
+    window: WindowRef,
+    _: ()
+) -> Result<(), AnyError> {
+    let id = WindowResource::get(state, &window)?.id();
+    with_window!(state, window, |window| {
+        window.set_cursor_grab(false)?;
+        window.set_cursor_visible(true);
+    });
+    pointer::unlock_pointer(id);
+    Ok(())
 }
 
 pub fn op_window_set_always_on_top(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_always_on_top(args.1);
+    with_window!(state, args.0, |window| window.set_always_on_top(args.1));
     Ok(())
 }
 
 pub fn op_window_set_cursor_grab(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_cursor_grab(args.1)?;
+    with_window!(state, args.0, |window| window.set_cursor_grab(args.1)?);
     Ok(())
 }
 
 pub fn op_window_set_cursor_icon(
     state: &mut OpState,
-    args: (ResourceId, String),
+    args: (WindowRef, String),
     _: (),
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    let cursor = match args.1.as_str() {
-        // TODO
-        _ => CursorIcon::Default,
-    };
-    window.set_cursor_icon(cursor);
+    let cursor = parse_cursor_icon(&args.1)?;
+    with_window!(state, args.0, |window| match cursor {
+        Some(cursor) => {
+            window.set_cursor_icon(cursor);
+            window.set_cursor_visible(true);
+        }
+        None => window.set_cursor_visible(false),
+    });
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+fn set_native_custom_cursor(
+    window: &Window,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    hotspot: (u32, u32),
+) -> Result<(), AnyError> {
+    match window.xlib_window() {
+        Some(xlib_window) => cursor::x11::set_cursor(xlib_window as u32, rgba, width, height, hotspot),
+        None => Err(custom_error("NotSupported", "Custom cursors are only supported on X11")),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_native_custom_cursor(
+    _window: &Window,
+    _rgba: &[u8],
+    _width: u32,
+    _height: u32,
+    _hotspot: (u32, u32),
+) -> Result<(), AnyError> {
+    Err(custom_error("NotSupported", "Custom cursors are only supported on X11"))
+}
+
+/// Sets an RGBA image as the cursor, until the next `setCursorIcon`.
+pub fn op_window_set_custom_cursor(
+    state: &mut OpState,
+    args: (WindowRef, u32, u32, u32, u32),
+    zc: ZeroCopyBuf,
+) -> Result<(), AnyError> {
+    let (window, width, height, hotspot_x, hotspot_y) = args;
+    let hotspot = (hotspot_x, hotspot_y);
+    validate_cursor_image(&zc, width, height, hotspot)?;
+
+    let resource = WindowResource::get(state, &window)?;
+    match &*resource {
+        WindowResource::Native(window) => set_native_custom_cursor(window, &zc, width, height, hotspot),
+        WindowResource::Headless(window) => {
+            window.set_custom_cursor(width, height, hotspot);
+            Ok(())
+        }
+    }
+}
+
 pub fn op_window_set_cursor_position(
     state: &mut OpState,
-    args: (ResourceId, SerdePosition),
+    args: (WindowRef, SerdePosition),
     _: (),
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_cursor_position(Position::Physical(args.1.into()))?;
+    let position = args.1.to_position(window_units(state, &args.0)?);
+    with_window!(state, args.0, |window| window.set_cursor_position(position)?);
     Ok(())
 }
 
 pub fn op_window_set_cursor_visible(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_cursor_visible(args.1);
+    with_window!(state, args.0, |window| window.set_cursor_visible(args.1));
     Ok(())
 }
 
 pub fn op_window_set_decorations(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_decorations(args.1);
+    with_window!(state, args.0, |window| window.set_decorations(args.1));
     Ok(())
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerdeVideoMode {
+    size: SerdeSize,
+    bit_depth: Option<u16>,
+    refresh_rate: Option<u16>,
+}
+
+impl SerdeVideoMode {
+    fn matches(&self, size: PhysicalSize<u32>, bit_depth: u16, refresh_rate: u16) -> bool {
+        // Video modes are always in physical pixels.
+        self.size.width == size.width as f64
+            && self.size.height == size.height as f64
+            && self.bit_depth.map_or(true, |b| b == bit_depth)
+            && self.refresh_rate.map_or(true, |r| r == refresh_rate)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FullscreenMode {
+    Borderless,
+    Exclusive,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FullscreenOptions {
+    mode: FullscreenMode,
+    /// Monitor name, defaults to the one the window is on.
+    monitor: Option<String>,
+    /// Exclusive fullscreen only, defaults to the monitor's largest mode.
+    video_mode: Option<SerdeVideoMode>,
+}
+
+/// `true` still means borderless fullscreen on the current monitor.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum FullscreenArgs {
+    Enabled(bool),
+    Options(FullscreenOptions),
+}
+
+/// `None` for windowed, `true` is borderless on the current monitor.
+fn fullscreen_options(args: Option<FullscreenArgs>) -> Option<FullscreenOptions> {
+    match args {
+        None | Some(FullscreenArgs::Enabled(false)) => None,
+        Some(FullscreenArgs::Enabled(true)) => Some(FullscreenOptions {
+            mode: FullscreenMode::Borderless,
+            monitor: None,
+            video_mode: None,
+        }),
+        Some(FullscreenArgs::Options(options)) => Some(options),
+    }
+}
+
+/// Picks the monitor named `name`, or `current` when no name is given.
+fn find_monitor(
+    mut monitors: impl Iterator<Item = MonitorHandle>,
+    current: Option<MonitorHandle>,
+    name: &Option<String>,
+) -> Result<Option<MonitorHandle>, AnyError> {
+    match name {
+        Some(name) => monitors
+            .find(|monitor| monitor.name().as_ref() == Some(name))
+            .map(Some)
+            .ok_or_else(|| type_error(format!("No monitor named {:?}", name))),
+        None => Ok(current),
+    }
+}
+
+fn native_fullscreen(
+    monitors: impl Iterator<Item = MonitorHandle>,
+    current: Option<MonitorHandle>,
+    options: &FullscreenOptions,
+) -> Result<Fullscreen, AnyError> {
+    let monitor = find_monitor(monitors, current, &options.monitor)?;
+    match options.mode {
+        FullscreenMode::Borderless => Ok(Fullscreen::Borderless(monitor)),
+        FullscreenMode::Exclusive => {
+            let monitor = monitor.ok_or_else(|| type_error("The window is not on any monitor"))?;
+            let mode = match &options.video_mode {
+                Some(requested) => monitor
+                    .video_modes()
+                    .find(|mode| requested.matches(mode.size(), mode.bit_depth(), mode.refresh_rate())),
+                None => monitor.video_modes().max_by_key(|mode| {
+                    let size = mode.size();
+                    (size.width * size.height, mode.refresh_rate(), mode.bit_depth())
+                }),
+            };
+            Ok(Fullscreen::Exclusive(mode.ok_or_else(|| type_error("No matching video mode"))?))
+        }
+    }
+}
+
+fn headless_fullscreen(options: &FullscreenOptions) -> Result<Value, AnyError> {
+    let monitor = virtual_monitor();
+    if let Some(name) = &options.monitor {
+        if name != VIRTUAL_MONITOR_NAME {
+            return Err(type_error(format!("No monitor named {:?}", name)));
+        }
+    }
+    Ok(match options.mode {
+        FullscreenMode::Borderless => json!({ "mode": "borderless", "monitor": monitor }),
+        FullscreenMode::Exclusive => {
+            if let Some(requested) = &options.video_mode {
+                if !requested.matches(VIRTUAL_MONITOR_SIZE, VIRTUAL_MONITOR_BIT_DEPTH, VIRTUAL_MONITOR_REFRESH_RATE) {
+                    return Err(type_error("No matching video mode"));
+                }
+            }
+            json!({ "mode": "exclusive", "monitor": monitor, "videoMode": virtual_video_mode() })
+        }
+    })
+}
+
+/// Switches fullscreen mode and returns the new state. Changes are also
+/// reported as a `fullscreenChanged` window event, as are the ones the window
+/// manager makes (see `check_fullscreen`).
 pub fn op_window_set_fullscreen(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, Option<FullscreenArgs>),
+    _: ()
+) -> Result<Value, AnyError> {
+    let options = fullscreen_options(args.1);
+    let resource = WindowResource::get(state, &args.0)?;
+    let previous = fullscreen_state(&resource);
+    match &*resource {
+        WindowResource::Native(window) => {
+            let fullscreen = match &options {
+                Some(options) => Some(native_fullscreen(window.available_monitors(), window.current_monitor(), options)?),
+                None => None,
+            };
+            window.set_fullscreen(fullscreen);
+            track_fullscreen(resource.id(), window, winit_fullscreen(window));
+        }
+        WindowResource::Headless(window) => {
+            let fullscreen = match &options {
+                Some(options) => headless_fullscreen(options)?,
+                None => Value::Null,
+            };
+            window.set_fullscreen_state(fullscreen);
+        }
+    }
+
+    let current = fullscreen_state(&resource);
+    if current != previous {
+        push_event(json!({
+            "type": "windowEvent",
+            "windowID": resource.id(),
+            "event": { "type": "fullscreenChanged", "fullscreen": current },
+            "timestamp": clock::now(),
+        }));
+    }
+    Ok(current)
+}
+
+pub fn op_window_set_ime_position(
+    state: &mut OpState,
+    args: (WindowRef, SerdePosition),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_fullscreen(match args.1 {
-        true => Some(Fullscreen::Borderless(None)),
-        false => None,
-    });
+    let position = args.1.to_position(window_units(state, &args.0)?);
+    with_window!(state, args.0, |window| window.set_ime_position(position));
+    #[cfg(target_os = "linux")]
+    {
+        let id = WindowResource::get(state, &args.0)?.id();
+        let position = position.to_physical::<i32>(window_scale_factor(id));
+        ime::set_position(id, position.x, position.y);
+    }
     Ok(())
 }
 
-pub fn op_window_set_ime_position(
+/// Lets the input method compose text for the window, which then gets `ime`
+/// events. Like in winit 0.27, windows don't allow it until asked to.
+pub fn op_window_set_ime_allowed(
     state: &mut OpState,
-    args: (ResourceId, SerdePosition),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_ime_position(Position::Physical(args.1.into()));
+    let resource = WindowResource::get(state, &args.0)?;
+    set_ime_allowed(&resource, args.1);
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+fn set_ime_allowed(resource: &WindowResource, allowed: bool) {
+    let id = resource.id();
+    let focused = match resource {
+        // Keys only reach the input method from X11 windows. winit 0.24
+        // speaks no Wayland text input protocol.
+        WindowResource::Native(window) if window.xlib_window().is_none() => return,
+        WindowResource::Native(_) => crate::event_loop::focused_window() == Some(id),
+        // Headless windows have nothing to lose focus to.
+        WindowResource::Headless(_) => true,
+    };
+    ime::set_allowed(id, allowed, focused);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_ime_allowed(_resource: &WindowResource, _allowed: bool) {}
+
 pub fn op_window_set_inner_size(
     state: &mut OpState,
-    args: (ResourceId, SerdeSize),
+    args: (WindowRef, SerdeSize),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_inner_size(Size::Physical(args.1.into()));
+    let size = args.1.to_size(window_units(state, &args.0)?);
+    with_window!(state, args.0, |window| window.set_inner_size(size));
     Ok(())
 }
 
 pub fn op_window_set_max_inner_size(
     state: &mut OpState,
-    args: (ResourceId, Option<SerdeSize>),
+    args: (WindowRef, Option<SerdeSize>),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_max_inner_size(match args.1 {
-        Some(size) => Some(Size::Physical(size.into())),
-        None => None,
-    });
+    let units = window_units(state, &args.0)?;
+    let size = args.1.map(|size| size.to_size(units));
+    with_window!(state, args.0, |window| window.set_max_inner_size(size));
     Ok(())
 }
 
 pub fn op_window_set_maximized(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_maximized(args.1);
+    with_window!(state, args.0, |window| window.set_maximized(args.1));
     Ok(())
 }
 
 pub fn op_window_set_min_inner_size(
     state: &mut OpState,
-    args: (ResourceId, Option<SerdeSize>),
+    args: (WindowRef, Option<SerdeSize>),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_min_inner_size(match args.1 {
-        Some(size) => Some(Size::Physical(size.into())),
-        None => None,
-    });
+    let units = window_units(state, &args.0)?;
+    let size = args.1.map(|size| size.to_size(units));
+    with_window!(state, args.0, |window| window.set_min_inner_size(size));
     Ok(())
 }
 
 pub fn op_window_set_minimized(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_minimized(args.1);
+    with_window!(state, args.0, |window| window.set_minimized(args.1));
     Ok(())
 }
 
 pub fn op_window_set_outer_position(
     state: &mut OpState,
-    args: (ResourceId, SerdePosition),
+    args: (WindowRef, SerdePosition),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_outer_position(Position::Physical(args.1.into()));
+    let position = args.1.to_position(window_units(state, &args.0)?);
+    with_window!(state, args.0, |window| window.set_outer_position(position));
     Ok(())
 }
 
 pub fn op_window_set_resizable(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_resizable(args.1);
+    with_window!(state, args.0, |window| window.set_resizable(args.1));
     Ok(())
 }
 
 pub fn op_window_set_title(
     state: &mut OpState,
-    args: (ResourceId, String),
+    args: (WindowRef, String),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_title(&args.1);
+    with_window!(state, args.0, |window| window.set_title(&args.1));
+    Ok(())
+}
+
+pub fn op_window_set_units(
+    state: &mut OpState,
+    args: (WindowRef, Units),
+    _: ()
+) -> Result<(), AnyError> {
+    let id = WindowResource::get(state, &args.0)?.id();
+    WINDOW_UNITS.lock().unwrap().insert(id, args.1);
     Ok(())
 }
 
 pub fn op_window_set_visible(
     state: &mut OpState,
-    args: (ResourceId, bool),
+    args: (WindowRef, bool),
     _: ()
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
-    window.set_visible(args.1);
+    with_window!(state, args.0, |window| window.set_visible(args.1));
+    owned::set_visible(WindowResource::get(state, &args.0)?.id(), args.1);
     Ok(())
 }
 
 pub fn op_window_set_window_icon(
     state: &mut OpState,
-    args: (ResourceId, u32, u32),
+    args: (WindowRef, u32, u32),
     zc: ZeroCopyBuf,
 ) -> Result<(), AnyError> {
-    let window = get_window(state, args.0)?;
     let icon = Icon::from_rgba(zc.to_vec(), args.1, args.2)?;
-    window.set_window_icon(Some(icon));
+    with_window!(state, args.0, |window| window.set_window_icon(Some(icon)));
     Ok(())
 }
 
+pub fn op_window_units(
+    state: &mut OpState,
+    window: WindowRef,
+    _: ()
+) -> Result<Units, AnyError> {
+    window_units(state, &window)
+}
+
 macro_rules! op {
     ($name:ident) => {
         (stringify!($name), op_sync($name))
@@ -450,6 +1218,8 @@ pub fn init() -> Extension {
     Extension::builder()
         .ops(vec![
             op!(op_create_window),
+            op!(op_window_close),
+            op!(op_window_current_monitor),
             op!(op_window_fullscreen),
             op!(op_window_inner_position),
             op!(op_window_inner_size),
@@ -457,14 +1227,18 @@ pub fn init() -> Extension {
             op!(op_window_outer_size),
             op!(op_window_request_redraw),
             op!(op_window_request_user_attention),
+            op!(op_window_request_pointer_lock),
+            op!(op_window_exit_pointer_lock),
             op!(op_window_scale_factor),
             op!(op_window_set_always_on_top),
             op!(op_window_set_cursor_grab),
             op!(op_window_set_cursor_icon),
             op!(op_window_set_cursor_position),
             op!(op_window_set_cursor_visible),
+            op!(op_window_set_custom_cursor),
             op!(op_window_set_decorations),
             op!(op_window_set_fullscreen),
+            op!(op_window_set_ime_allowed),
             op!(op_window_set_ime_position),
             op!(op_window_set_inner_size),
             op!(op_window_set_max_inner_size),
@@ -474,8 +1248,11 @@ pub fn init() -> Extension {
             op!(op_window_set_outer_position),
             op!(op_window_set_resizable),
             op!(op_window_set_title),
+            op!(op_window_set_units),
             op!(op_window_set_visible),
             op!(op_window_set_window_icon),
+            op!(op_window_subscribe_events),
+            op!(op_window_units),
         ])
         .build()
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use deno_runtime::deno_core::error::AnyError;
+use deno_runtime::deno_core::error::custom_error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::Atom;
+use x11rb::protocol::xproto::AtomEnum;
+use x11rb::protocol::xproto::ChangeWindowAttributesAux;
+use x11rb::protocol::xproto::ConfigureWindowAux;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use x11rb::protocol::xproto::CreateGCAux;
+use x11rb::protocol::xproto::ImageFormat;
+use x11rb::protocol::xproto::ImageOrder;
+use x11rb::protocol::xproto::PropMode;
+use x11rb::protocol::xproto::StackMode;
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+lazy_static! {
+    static ref CONNECTION: Mutex<Option<RustConnection>> = Mutex::new(None);
+    /// X11 IDs of the native windows, by window ID, for the event pump, which
+    /// can't reach the windows themselves.
+    static ref WINDOWS: Mutex<HashMap<u32, Window>> = Mutex::new(HashMap::new());
+}
+
+pub fn set_window(id: u32, window: Window) {
+    WINDOWS.lock().unwrap().insert(id, window);
+}
+
+pub fn window(id: u32) -> Option<Window> {
+    WINDOWS.lock().unwrap().get(&id).copied()
+}
+
+pub fn remove_window(id: u32) {
+    WINDOWS.lock().unwrap().remove(&id);
+}
+
+/// Runs `f` with our own connection to the X server winit is using, for the
+/// things winit 0.24 can't do. The connection is opened on first use and kept
+/// open, because the server frees a client's resources when it leaves.
+pub fn with_connection<R>(f: impl FnOnce(&RustConnection) -> Result<R, AnyError>) -> Result<R, AnyError> {
+    let mut connection = CONNECTION.lock().unwrap();
+    if connection.is_none() {
+        *connection = Some(x11rb::connect(None)?.0);
+    }
+    f(connection.as_ref().unwrap())
+}
+
+pub fn intern_atom(conn: &RustConnection, name: &str) -> Result<Atom, AnyError> {
+    Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+}
+
+pub fn set_utf8_property(conn: &RustConnection, window: Window, name: &str, value: &str) -> Result<(), AnyError> {
+    let property = intern_atom(conn, name)?;
+    let utf8_string = intern_atom(conn, "UTF8_STRING")?;
+    conn.change_property8(PropMode::REPLACE, window, property, utf8_string, value.as_bytes())?.check()?;
+    Ok(())
+}
+
+/// Sets `name` to a list of atoms, as used by `_NET_WM_WINDOW_TYPE` and
+/// `_NET_WM_STATE`.
+pub fn set_atoms_property(conn: &RustConnection, window: Window, name: &str, values: &[&str]) -> Result<(), AnyError> {
+    let property = intern_atom(conn, name)?;
+    let atoms = values.iter().map(|value| intern_atom(conn, value)).collect::<Result<Vec<_>, _>>()?;
+    conn.change_property32(PropMode::REPLACE, window, property, AtomEnum::ATOM, &atoms)?.check()?;
+    Ok(())
+}
+
+/// Whether the list of atoms `name` includes `value`, as the window manager
+/// keeps `_NET_WM_STATE` up to date.
+pub fn has_atom(conn: &RustConnection, window: Window, name: &str, value: &str) -> Result<bool, AnyError> {
+    let property = intern_atom(conn, name)?;
+    let value = intern_atom(conn, value)?;
+    let reply = conn.get_property(false, window, property, AtomEnum::ATOM, 0, u32::MAX)?.reply()?;
+    Ok(reply.value32().map_or(false, |mut atoms| atoms.any(|atom| atom == value)))
+}
+
+/// Tells the window manager `window` belongs to `parent`, to be kept above it
+/// and not get its own taskbar entry.
+pub fn set_transient_for(conn: &RustConnection, window: Window, parent: Window) -> Result<(), AnyError> {
+    conn.change_property32(PropMode::REPLACE, window, AtomEnum::WM_TRANSIENT_FOR, AtomEnum::WINDOW, &[parent])?.check()?;
+    Ok(())
+}
+
+/// Keeps the window manager away from `window`, for popups that place
+/// themselves and never take focus. Only has an effect before the window is
+/// first shown.
+pub fn set_override_redirect(conn: &RustConnection, window: Window) -> Result<(), AnyError> {
+    conn.change_window_attributes(window, &ChangeWindowAttributesAux::new().override_redirect(1))?.check()?;
+    Ok(())
+}
+
+/// Makes `window` a child of `parent` at its top left corner, to be clipped
+/// to it and move along with it.
+pub fn reparent(conn: &RustConnection, window: Window, parent: Window) -> Result<(), AnyError> {
+    conn.reparent_window(window, parent, 0, 0)?.check()?;
+    Ok(())
+}
+
+/// Hides `window`, the way winit does.
+pub fn unmap(conn: &RustConnection, window: Window) -> Result<(), AnyError> {
+    conn.unmap_window(window)?.check()?;
+    Ok(())
+}
+
+/// Moves and resizes `window`, in its parent's coordinates, and raises it.
+pub fn configure(conn: &RustConnection, window: Window, x: i32, y: i32, width: u32, height: u32) -> Result<(), AnyError> {
+    let aux = ConfigureWindowAux::new().x(x).y(y).width(width).height(height).stack_mode(StackMode::ABOVE);
+    conn.configure_window(window, &aux)?.check()?;
+    Ok(())
+}
+
+/// Where `(x, y)` in `window` is in root window coordinates.
+pub fn to_root(conn: &RustConnection, window: Window, x: i16, y: i16) -> Result<(i32, i32), AnyError> {
+    let root = conn.setup().roots[0].root;
+    let reply = conn.translate_coordinates(window, root, x, y)?.reply()?;
+    Ok((reply.dst_x as i32, reply.dst_y as i32))
+}
+
+/// Where the pointer is in `window`. Unlike motion events, this works while
+/// another client has the pointer grabbed, as during a drag.
+pub fn query_pointer(conn: &RustConnection, window: Window) -> Result<(i16, i16), AnyError> {
+    let reply = conn.query_pointer(window)?.reply()?;
+    Ok((reply.win_x, reply.win_y))
+}
+
+pub fn screen_size(conn: &RustConnection) -> (i32, i32) {
+    let screen = &conn.setup().roots[0];
+    (screen.width_in_pixels as i32, screen.height_in_pixels as i32)
+}
+
+/// Puts `0xRRGGBB` pixels on `window`, in as many requests as it takes.
+/// Works on windows whose depth takes 32 bits per pixel, as true color ones do.
+pub fn put_image(window: Window, width: u16, height: u16, pixels: &[u32]) -> Result<(), AnyError> {
+    with_connection(|conn| {
+        let depth = conn.get_geometry(window)?.reply()?.depth;
+        let setup = conn.setup();
+        let format = setup.pixmap_formats.iter().find(|format| format.depth == depth);
+        if format.map(|format| format.bits_per_pixel) != Some(32) {
+            return Err(custom_error("NotSupported", format!("Can't draw on windows of depth {}", depth)));
+        }
+        let big_endian = setup.image_byte_order == ImageOrder::MSB_FIRST;
+        // Windows with an alpha channel would be see-through otherwise.
+        let alpha = if depth == 32 { 0xff000000 } else { 0 };
+
+        let gc = conn.generate_id()?;
+        conn.create_gc(gc, window, &CreateGCAux::new())?;
+        let row_bytes = width as usize * 4;
+        let rows_per_request = ((conn.maximum_request_bytes() - 24) / row_bytes).max(1);
+        let mut data = Vec::with_capacity(rows_per_request * row_bytes);
+        let pixels = &pixels[..width as usize * height as usize];
+        for (i, rows) in pixels.chunks(rows_per_request * width as usize).enumerate() {
+            data.clear();
+            for pixel in rows {
+                let pixel = pixel | alpha;
+                data.extend_from_slice(&if big_endian { pixel.to_be_bytes() } else { pixel.to_le_bytes() });
+            }
+            let y = (i * rows_per_request) as i16;
+            let rows = (rows.len() / width as usize) as u16;
+            conn.put_image(ImageFormat::Z_PIXMAP, window, gc, width, rows, 0, y, 0, depth, &data)?;
+        }
+        conn.free_gc(gc)?;
+        conn.flush()?;
+        Ok(())
+    })
+}
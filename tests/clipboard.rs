@@ -0,0 +1,257 @@
+//! The X11 clipboard, checked against a second client that reads and owns
+//! the selection the way other apps do. CI runs these under Xvfb.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use common::PRELUDE;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+use x11rb::CURRENT_TIME;
+use x11rb::NONE;
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::Atom;
+use x11rb::protocol::xproto::AtomEnum;
+use x11rb::protocol::xproto::ConnectionExt as _;
+use x11rb::protocol::xproto::CreateWindowAux;
+use x11rb::protocol::xproto::EventMask;
+use x11rb::protocol::xproto::PropMode;
+use x11rb::protocol::xproto::SELECTION_NOTIFY_EVENT;
+use x11rb::protocol::xproto::SelectionNotifyEvent;
+use x11rb::protocol::xproto::Window;
+use x11rb::protocol::xproto::WindowClass;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// There is one clipboard per X server, so the tests take turns.
+static CLIPBOARD_TESTS: Mutex<()> = Mutex::new(());
+
+fn take_turn() -> MutexGuard<'static, ()> {
+    CLIPBOARD_TESTS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        CLIPBOARD,
+        TARGETS,
+        UTF8_STRING,
+        IMAGE_PNG: b"image/png",
+        TEST_SELECTION,
+    }
+}
+
+/// Another X client, with a window of its own to convert selections to or to
+/// own the clipboard with.
+struct Client {
+    conn: RustConnection,
+    atoms: Atoms,
+    window: Window,
+}
+
+impl Client {
+    fn connect() -> Client {
+        let (conn, screen_num) = x11rb::connect(None).unwrap();
+        let atoms = Atoms::new(&conn).unwrap().reply().unwrap();
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        conn.flush().unwrap();
+        Client { conn, atoms, window }
+    }
+
+    /// Asks the clipboard owner for `target`, `None` if it refuses.
+    fn convert(&self, target: Atom) -> Option<Vec<u8>> {
+        let property = self.atoms.TEST_SELECTION;
+        self.conn.convert_selection(self.window, self.atoms.CLIPBOARD, target, property, CURRENT_TIME).unwrap();
+        self.conn.flush().unwrap();
+
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            match self.conn.poll_for_event().unwrap() {
+                Some(Event::SelectionNotify(notify)) if notify.property == NONE => return None,
+                Some(Event::SelectionNotify(_)) => {
+                    let reply = self.conn.get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX).unwrap();
+                    return Some(reply.reply().unwrap().value);
+                }
+                Some(_) => {}
+                None => {
+                    assert!(Instant::now() < deadline, "the clipboard owner didn't respond");
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+    }
+
+    fn targets(&self) -> Vec<Atom> {
+        let data = self.convert(self.atoms.TARGETS).expect("no TARGETS");
+        data.chunks_exact(4).map(|atom| u32::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]])).collect()
+    }
+
+    /// Takes the clipboard and hands out `text` until `stop` is set.
+    fn serve_text(self, text: &str, stop: Arc<AtomicBool>) -> std::thread::JoinHandle<()> {
+        self.conn.set_selection_owner(self.window, self.atoms.CLIPBOARD, CURRENT_TIME).unwrap();
+        assert_eq!(self.conn.get_selection_owner(self.atoms.CLIPBOARD).unwrap().reply().unwrap().owner, self.window);
+        let text = text.to_string();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                let request = match self.conn.poll_for_event().unwrap() {
+                    Some(Event::SelectionRequest(request)) => request,
+                    Some(_) => continue,
+                    None => {
+                        std::thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                };
+                let property = if request.target == self.atoms.TARGETS {
+                    let targets = [self.atoms.TARGETS, self.atoms.UTF8_STRING];
+                    self.conn
+                        .change_property32(PropMode::REPLACE, request.requestor, request.property, AtomEnum::ATOM, &targets)
+                        .unwrap();
+                    request.property
+                } else if request.target == self.atoms.UTF8_STRING {
+                    self.conn
+                        .change_property8(PropMode::REPLACE, request.requestor, request.property, request.target, text.as_bytes())
+                        .unwrap();
+                    request.property
+                } else {
+                    NONE
+                };
+                let notify = SelectionNotifyEvent {
+                    response_type: SELECTION_NOTIFY_EVENT,
+                    sequence: 0,
+                    time: request.time,
+                    requestor: request.requestor,
+                    selection: request.selection,
+                    target: request.target,
+                    property,
+                };
+                self.conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify).unwrap();
+                self.conn.flush().unwrap();
+            }
+        })
+    }
+}
+
+/// Copies with `copy`, then keeps the window that owns the clipboard open.
+fn copy_script(copy: &str) -> String {
+    format!(
+        r#"
+const win = Deno.createWindow({{ title: "Clipboard" }});
+{}
+console.log("copied");
+for await (const _ of Deno.eventLoop()) {{}}
+"#,
+        copy,
+    )
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn serves_copied_text_to_other_clients() {
+    let _turn = take_turn();
+    let mut running = common::spawn(
+        &[],
+        &copy_script(r#"await navigator.clipboard.writeText("Hello, ünïcode", { window: win });"#),
+    );
+    running.wait_for_line("copied");
+
+    let client = Client::connect();
+    let targets = client.targets();
+    assert!(targets.contains(&client.atoms.UTF8_STRING), "{:?}", targets);
+    assert!(!targets.contains(&client.atoms.IMAGE_PNG), "{:?}", targets);
+    assert_eq!(client.convert(client.atoms.UTF8_STRING).unwrap(), "Hello, ünïcode".as_bytes());
+    assert_eq!(client.convert(client.atoms.IMAGE_PNG), None);
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn serves_copied_images_as_png() {
+    let _turn = take_turn();
+    let mut running = common::spawn(
+        &[],
+        &copy_script(
+            r#"const data = new Uint8Array([255, 0, 0, 255, 0, 0, 255, 128]);
+await navigator.clipboard.writeImage({ width: 2, height: 1, data }, { window: win });"#,
+        ),
+    );
+    running.wait_for_line("copied");
+
+    let client = Client::connect();
+    let png = client.convert(client.atoms.IMAGE_PNG).expect("no image/png");
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(reader.info().size(), (2, 1));
+    assert_eq!(pixels, [255, 0, 0, 255, 0, 0, 255, 128]);
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn reads_what_other_clients_copied() {
+    let _turn = take_turn();
+    let stop = Arc::new(AtomicBool::new(false));
+    let serving = Client::connect().serve_text("Copied elsewhere", stop.clone());
+
+    let output = common::run(
+        &[],
+        &format!(
+            r#"{}
+const win = Deno.createWindow({{ title: "Clipboard" }});
+assertEquals(await navigator.clipboard.readText({{ window: win }}), "Copied elsewhere");
+assertEquals(await navigator.clipboard.readImage({{ window: win }}), null);
+Deno.exit(0);
+"#,
+            PRELUDE,
+        ),
+    );
+    stop.store(true, Ordering::SeqCst);
+    serving.join().unwrap();
+    common::assert_success(&output);
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn writing_without_a_window_throws() {
+    let _turn = take_turn();
+    let output = common::run(
+        &[],
+        &format!(
+            r#"{}
+let error;
+try {{
+  await navigator.clipboard.writeText("Nobody owns this");
+}} catch (err) {{
+  error = err;
+}}
+assertEquals(error instanceof Deno.errors.NotFound, true);
+Deno.exit(0);
+"#,
+            PRELUDE,
+        ),
+    );
+    common::assert_success(&output);
+}
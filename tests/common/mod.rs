@@ -0,0 +1,135 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the temp directory, removed again when dropped.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(extension: &str, contents: &str) -> TempFile {
+        let name = format!(
+            "deno_desktop_test_{}_{}.{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::SeqCst),
+            extension,
+        );
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        TempFile(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn read(&self) -> String {
+        std::fs::read_to_string(&self.0).unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Runs `script` with the runtime and the given flags.
+pub fn run(flags: &[&str], script: &str) -> Output {
+    run_with_env(flags, script, &[])
+}
+
+/// Runs `script` like `run`, with extra environment variables.
+pub fn run_with_env(flags: &[&str], script: &str, env: &[(&str, &str)]) -> Output {
+    let script = TempFile::new("js", script);
+    Command::new(env!("CARGO_BIN_EXE_deno_desktop"))
+        .args(flags)
+        .arg(script.path())
+        .env_remove("DENO_DESKTOP_HEADLESS")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+/// A script running in the background, killed when dropped.
+#[allow(dead_code)]
+pub struct Running {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    _script: TempFile,
+}
+
+#[allow(dead_code)]
+impl Running {
+    /// Waits for the script to print `line`, for it to tell it got somewhere.
+    pub fn wait_for_line(&mut self, line: &str) {
+        let mut read = String::new();
+        loop {
+            read.clear();
+            if self.stdout.read_line(&mut read).unwrap() == 0 {
+                let status = self.child.wait().unwrap();
+                panic!("script exited with {} before printing {:?}", status, line);
+            }
+            if read.trim_end() == line {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Starts `script` with the runtime and the given flags, for tests that
+/// interact with it while it runs.
+#[allow(dead_code)]
+pub fn spawn(flags: &[&str], script: &str) -> Running {
+    let script = TempFile::new("js", script);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_deno_desktop"))
+        .args(flags)
+        .arg(script.path())
+        .env_remove("DENO_DESKTOP_HEADLESS")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    Running { child, stdout, _script: script }
+}
+
+/// Runs `script` headless and fails the test if it throws.
+#[allow(dead_code)]
+pub fn run_headless(script: &str) {
+    assert_success(&run(&["--headless"], script));
+}
+
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "script failed with {}\nstdout:\n{}\nstderr:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+}
+
+/// The assertion helper scripts start with.
+pub const PRELUDE: &str = r#"
+function assertEquals(actual, expected) {
+  if (JSON.stringify(actual) !== JSON.stringify(expected)) {
+    throw new Error(`Expected ${JSON.stringify(expected)}, got ${JSON.stringify(actual)}`);
+  }
+}
+"#;
@@ -0,0 +1,165 @@
+//! File dialogs against a mock `org.freedesktop.portal.Desktop`, served on a
+//! private session bus socket so no desktop or display is needed.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use common::PRELUDE;
+use zbus::Connection;
+use zbus::Guid;
+use zbus::MessageType;
+use zvariant::ObjectPath;
+use zvariant::Value;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const REQUEST_PATH: &str = "/org/freedesktop/portal/desktop/request/1_42/test";
+
+/// A mock portal, answering one file chooser request on its own socket.
+struct Portal {
+    socket: PathBuf,
+    stop: Arc<AtomicBool>,
+    serving: JoinHandle<Option<Request>>,
+}
+
+/// A `FileChooser` call the mock portal received.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    title: String,
+    multiple: Option<bool>,
+}
+
+impl Portal {
+    /// Serves one file chooser request, answering with `response` (0 for
+    /// success, 1 for cancelled) and `uris`.
+    fn serve(name: &str, response: u32, uris: &'static [&'static str]) -> Portal {
+        let socket = std::env::temp_dir().join(format!("deno_desktop_test_{}_{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let serving = std::thread::spawn(move || {
+            let stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        if stopped.load(Ordering::SeqCst) {
+                            return None;
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                    Err(err) => panic!("{}", err),
+                }
+            };
+            stream.set_nonblocking(false).unwrap();
+            let conn = Connection::new_unix_server(stream, &Guid::generate()).unwrap();
+            answer(&conn, response, uris)
+        });
+        Portal { socket, stop, serving }
+    }
+
+    fn address(&self) -> String {
+        format!("unix:path={}", self.socket.display())
+    }
+
+    /// The request the script made, if it made one.
+    fn finish(self) -> Option<Request> {
+        self.stop.store(true, Ordering::SeqCst);
+        let request = self.serving.join().unwrap();
+        let _ = std::fs::remove_file(&self.socket);
+        request
+    }
+}
+
+/// Plays the bus for the runtime's connection until the file chooser request
+/// is answered, returning it.
+fn answer(conn: &Connection, response: u32, uris: &[&str]) -> Option<Request> {
+    loop {
+        let message = match conn.receive_message() {
+            Ok(message) => message,
+            // The script hung up without asking.
+            Err(_) => return None,
+        };
+        let header = message.header().unwrap();
+        if header.message_type().unwrap() != MessageType::MethodCall {
+            continue;
+        }
+        match header.member().unwrap().unwrap() {
+            "Hello" => {
+                conn.reply(&message, &":1.42").unwrap();
+            }
+            "AddMatch" => {
+                conn.reply(&message, &()).unwrap();
+            }
+            method @ ("OpenFile" | "SaveFile") => {
+                let (_parent, title, options): (&str, &str, HashMap<&str, Value>) = message.body().unwrap();
+                let request = Request {
+                    method: method.to_string(),
+                    title: title.to_string(),
+                    multiple: match options.get("multiple") {
+                        Some(Value::Bool(multiple)) => Some(*multiple),
+                        _ => None,
+                    },
+                };
+                conn.reply(&message, &ObjectPath::try_from(REQUEST_PATH).unwrap()).unwrap();
+                let mut results = HashMap::new();
+                results.insert("uris", Value::from(uris.to_vec()));
+                conn.emit_signal(None, REQUEST_PATH, "org.freedesktop.portal.Request", "Response", &(response, results))
+                    .unwrap();
+                return Some(request);
+            }
+            other => panic!("unexpected call to {}", other),
+        }
+    }
+}
+
+fn run_with_portal(portal: &Portal, script: &str) {
+    let address = portal.address();
+    let output = common::run_with_env(
+        &["--headless"],
+        &format!("{}\n{}", PRELUDE, script),
+        &[("DBUS_SESSION_BUS_ADDRESS", &address)],
+    );
+    common::assert_success(&output);
+}
+
+#[test]
+fn opens_paths_that_are_not_unicode() {
+    let portal = Portal::serve("open", 0, &["file:///tmp/with%20space.txt", "file:///tmp/caf%E9.txt"]);
+    run_with_portal(
+        &portal,
+        r#"
+const paths = await Deno.desktop.showOpenDialog({ title: "Pick", multiple: true });
+assertEquals(paths, ["/tmp/with space.txt", { bytes: [47, 116, 109, 112, 47, 99, 97, 102, 233, 46, 116, 120, 116] }]);
+"#,
+    );
+    let request = portal.finish().expect("no file chooser request");
+    assert_eq!(request.method, "OpenFile");
+    assert_eq!(request.title, "Pick");
+    assert_eq!(request.multiple, Some(true));
+}
+
+#[test]
+fn resolves_to_null_when_cancelled() {
+    let portal = Portal::serve("cancel", 1, &[]);
+    run_with_portal(
+        &portal,
+        r#"
+assertEquals(await Deno.desktop.showSaveDialog({ defaultName: "untitled.txt" }), null);
+"#,
+    );
+    let request = portal.finish().expect("no file chooser request");
+    assert_eq!(request.method, "SaveFile");
+    assert_eq!(request.title, "Save As");
+}
@@ -0,0 +1,315 @@
+mod common;
+
+use common::PRELUDE;
+
+fn run(script: &str) {
+    common::run_headless(&format!("{}\n{}", PRELUDE, script));
+}
+
+#[test]
+fn runs_the_headless_example() {
+    common::run_headless(include_str!("../examples/headless.js"));
+}
+
+#[test]
+fn creates_windows_with_their_options() {
+    run(r#"
+const win = Deno.createWindow({
+  title: "Created",
+  width: 320,
+  height: 240,
+  x: 10,
+  y: 20,
+  resizable: false,
+  minWidth: 100,
+  minHeight: 50,
+});
+const state = Deno.headless.getWindowState(win);
+assertEquals(
+  [state.title, state.size, state.position, state.resizable, state.minSize, state.maxSize, state.visible],
+  ["Created", { width: 320, height: 240 }, { x: 10, y: 20 }, false, { width: 100, height: 50 }, null, true],
+);
+assertEquals(win.getSize(), { width: 320, height: 240 });
+assertEquals(win.getPosition(), { x: 10, y: 20 });
+
+const centered = Deno.createWindow({ width: 920, height: 480, centered: true });
+assertEquals(centered.getPosition(), { x: 500, y: 300 });
+
+const logical = Deno.createWindow({ width: 100, height: 100, units: "logical" });
+Deno.headless.setScaleFactor(logical, 2);
+assertEquals(logical.getSize(), { width: 100, height: 100 });
+logical.setUnits("physical");
+assertEquals(logical.getSize(), { width: 200, height: 200 });
+"#);
+}
+
+#[test]
+fn rejects_bad_options() {
+    run(r#"
+for (const options of [{ x: 1 }, { x: 1, y: 2, centered: true }, { modal: true }]) {
+  let error;
+  try {
+    Deno.createWindow(options);
+  } catch (err) {
+    error = err;
+  }
+  assertEquals(error instanceof TypeError, true);
+}
+"#);
+}
+
+#[test]
+fn delivers_events_in_order() {
+    run(r#"
+const win = Deno.createWindow();
+win.setSize({ width: 100, height: 100 });
+Deno.headless.pushEvent({ type: "windowEvent", windowID: win.id, event: { type: "focused", focused: true } });
+win.requestRedraw();
+Deno.headless.pushEvent({ type: "windowEvent", windowID: win.id, event: { type: "closeRequested" } });
+
+const events = [];
+for await (const event of Deno.eventLoop()) {
+  events.push(event);
+}
+assertEquals(events.map((event) => event.event?.type ?? event.type), ["resized", "focused", "redrawRequested", "closeRequested"]);
+assertEquals(events.every((event) => event.windowID === win.id), true);
+for (let i = 1; i < events.length; i++) {
+  if (events[i].timestamp < events[i - 1].timestamp) {
+    throw new Error("Timestamps went backwards");
+  }
+}
+"#);
+}
+
+#[test]
+fn routes_window_events_to_window_streams() {
+    run(r#"
+const first = Deno.createWindow();
+const second = Deno.createWindow();
+const stream = first.events();
+const next = stream.next();
+Deno.headless.pushEvent({ type: "windowEvent", windowID: second.id, event: { type: "focused", focused: true } });
+Deno.headless.pushEvent({ type: "windowEvent", windowID: first.id, event: { type: "focused", focused: false } });
+assertEquals((await next).value.event, { type: "focused", focused: false });
+await stream.return();
+
+const rest = [];
+for await (const event of Deno.eventLoop()) {
+  rest.push([event.windowID, event.event.focused]);
+}
+assertEquals(rest, [[second.id, true]]);
+"#);
+}
+
+#[test]
+fn assigns_sequential_window_ids() {
+    run(r#"
+const first = Deno.createWindow();
+const second = Deno.createWindow();
+assertEquals(second.id, first.id + 1);
+assertEquals(Deno.headless.getWindowState(second).id, second.id);
+// Ops take the ID as well as the rid.
+assertEquals(Deno.core.opSync("op_window_inner_size", { id: second.id }), second.getSize());
+first.close();
+// IDs aren't reused after a window is closed.
+const third = Deno.createWindow();
+assertEquals(third.id, second.id + 1);
+"#);
+}
+
+#[test]
+fn closed_windows_are_gone() {
+    run(r#"
+const win = Deno.createWindow();
+const stream = win.events();
+const next = stream.next();
+win.close();
+assertEquals((await next).done, true);
+
+let error;
+try {
+  win.getSize();
+} catch (err) {
+  error = err;
+}
+assertEquals(error instanceof Deno.errors.BadResource, true);
+assertEquals(error.message, `Window ${win.rid} is closed or doesn't exist`);
+
+// Events for it still reach the main loop, where nothing listens anymore.
+Deno.headless.pushEvent({ type: "windowEvent", windowID: win.id, event: { type: "closeRequested" } });
+const events = [];
+for await (const event of Deno.eventLoop()) {
+  events.push(event.event.type);
+}
+assertEquals(events, ["closeRequested"]);
+"#);
+}
+
+#[test]
+fn reports_scale_factor_changes_with_the_new_inner_size() {
+    run(r#"
+const win = Deno.createWindow({ width: 400, height: 300, units: "logical" });
+Deno.headless.setScaleFactor(win, 2);
+const events = [];
+for await (const { event } of Deno.eventLoop()) {
+  if (event?.type === "scaleFactorChanged") {
+    events.push([event.type, event.scaleFactor, event.newInnerSize, event.logicalInnerSize]);
+    // The README's way of picking another size.
+    win.setSize({ width: 200, height: 150 });
+  } else if (event?.type === "resized") {
+    events.push([event.type, event.size, event.logicalSize]);
+  }
+}
+assertEquals(events, [
+  ["scaleFactorChanged", 2, { width: 800, height: 600 }, { width: 400, height: 300 }],
+  ["resized", { width: 800, height: 600 }, { width: 400, height: 300 }],
+  ["resized", { width: 400, height: 300 }, { width: 200, height: 150 }],
+]);
+assertEquals(win.getSize(), { width: 200, height: 150 });
+"#);
+}
+
+#[test]
+fn hides_the_cursor_for_css_none() {
+    run(r#"
+const win = Deno.createWindow();
+const cursor = () => {
+  const { cursorIcon, cursorVisible } = Deno.headless.getWindowState(win);
+  return [cursorIcon, cursorVisible];
+};
+win.setCursorIcon("pointer");
+assertEquals(cursor(), ["Hand", true]);
+win.setCursorIcon("none");
+assertEquals(cursor(), ["Hand", false]);
+win.setCursorIcon("text");
+assertEquals(cursor(), ["Text", true]);
+"#);
+}
+
+#[test]
+fn sets_fullscreen_modes() {
+    run(r#"
+const monitor = Deno.primaryMonitor();
+const [videoMode] = monitor.videoModes;
+const win = Deno.createWindow();
+assertEquals(win.getFullscreen(), null);
+
+assertEquals(win.setFullscreen(true), { mode: "borderless", monitor });
+assertEquals(win.getFullscreen(), { mode: "borderless", monitor });
+// Nothing changes, so no event.
+win.setFullscreen({ mode: "borderless" });
+
+const exclusive = { mode: "exclusive", monitor, videoMode };
+assertEquals(win.setFullscreen({ mode: "exclusive", monitor: monitor.name }), exclusive);
+win.setFullscreen(false);
+const { bitDepth, ...withoutBitDepth } = videoMode;
+assertEquals(win.setFullscreen({ mode: "exclusive", videoMode: withoutBitDepth }), exclusive);
+assertEquals(win.getFullscreen(), exclusive);
+
+win.close();
+const changes = [];
+for await (const { event } of Deno.eventLoop()) {
+  if (event?.type === "fullscreenChanged") {
+    changes.push(event.fullscreen?.mode ?? null);
+  }
+}
+assertEquals(changes, ["borderless", "exclusive", null, "exclusive"]);
+
+const opened = Deno.createWindow({ fullscreen: { mode: "exclusive" } });
+assertEquals(opened.getFullscreen(), exclusive);
+"#);
+}
+
+#[test]
+fn rejects_unknown_fullscreen_monitors_and_video_modes() {
+    run(r#"
+const win = Deno.createWindow();
+win.setFullscreen(true);
+const { videoModes: [{ size }] } = Deno.primaryMonitor();
+for (const [options, message] of [
+  [{ mode: "borderless", monitor: "Nowhere" }, 'No monitor named "Nowhere"'],
+  [{ mode: "exclusive", videoMode: { size: { width: 1, height: 1 } } }, "No matching video mode"],
+  [{ mode: "exclusive", videoMode: { size, refreshRate: 1 } }, "No matching video mode"],
+]) {
+  let error;
+  try {
+    win.setFullscreen(options);
+  } catch (err) {
+    error = err;
+  }
+  assertEquals([error instanceof TypeError, error?.message], [true, message]);
+  // The window stays as it was.
+  assertEquals(win.getFullscreen()?.mode, "borderless");
+}
+
+let error;
+try {
+  Deno.createWindow({ fullscreen: { mode: "windowed" } });
+} catch (err) {
+  error = err;
+}
+assertEquals(error instanceof TypeError, true);
+"#);
+}
+
+#[test]
+fn closing_a_parent_closes_its_children() {
+    run(r#"
+const parent = Deno.createWindow();
+const child = Deno.createWindow({ parent, kind: "tool" });
+const grandchild = Deno.createWindow({ parent: child, kind: "dialog" });
+const other = Deno.createWindow();
+parent.close();
+for (const win of [parent, child, grandchild]) {
+  let error;
+  try {
+    win.getSize();
+  } catch (err) {
+    error = err;
+  }
+  assertEquals(error instanceof Deno.errors.BadResource, true);
+}
+assertEquals(Deno.headless.getWindowState(other).visible, true);
+"#);
+}
+
+#[test]
+fn drops_on_a_window_with_a_modal_child_end_the_drag() {
+    run(r#"
+const parent = Deno.createWindow();
+const child = Deno.createWindow({ parent, kind: "dialog", modal: true });
+Deno.headless.dropFiles(parent, ["/blocked"]);
+child.setVisible(false);
+Deno.headless.dropFiles(parent, ["/dropped"]);
+
+const events = [];
+for await (const { windowID, event } of Deno.eventLoop()) {
+  if (["dragenter", "dragleave", "drop"].includes(event?.type)) {
+    events.push([windowID, event.type, event.paths]);
+  }
+}
+assertEquals(events, [
+  [parent.id, "dragenter", ["/blocked"]],
+  [parent.id, "dragleave", ["/blocked"]],
+  [parent.id, "dragenter", ["/dropped"]],
+  [parent.id, "drop", ["/dropped"]],
+]);
+"#);
+}
+
+#[test]
+fn fires_notification_events_without_taking_events() {
+    run(r#"
+// Only the timer keeps the script running, nothing takes events.
+const timeout = setTimeout(() => {}, 5000);
+await Notification.requestPermission();
+const notification = new Notification("Done");
+await new Promise((resolve) => notification.onshow = resolve);
+const clicked = new Promise((resolve) => notification.onclick = (event) => resolve(event.action));
+const closed = new Promise((resolve) => notification.onclose = resolve);
+Deno.headless.clickNotification(Deno.headless.notifications()[0].id, "");
+assertEquals(await clicked, "");
+await closed;
+clearTimeout(timeout);
+"#);
+}
@@ -0,0 +1,239 @@
+//! IME composition against a mock IBus daemon, served on a socket of its own
+//! so no desktop or display is needed.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use common::PRELUDE;
+use zbus::Connection;
+use zbus::Guid;
+use zbus::Message;
+use zbus::MessageType;
+use zvariant::ObjectPath;
+use zvariant::Structure;
+use zvariant::Value;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const CONTEXT_PATH: &str = "/org/freedesktop/IBus/InputContext_1";
+const INPUT_CONTEXT: &str = "org.freedesktop.IBus.InputContext";
+
+/// A mock IBus daemon, serving one client on its own socket.
+struct InputMethod {
+    socket: PathBuf,
+    stop: Arc<AtomicBool>,
+    serving: JoinHandle<Vec<String>>,
+}
+
+impl InputMethod {
+    fn serve(name: &str) -> InputMethod {
+        let socket = std::env::temp_dir().join(format!("deno_desktop_test_{}_{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let serving = std::thread::spawn(move || {
+            let stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        if stopped.load(Ordering::SeqCst) {
+                            return Vec::new();
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                    Err(err) => panic!("{}", err),
+                }
+            };
+            stream.set_nonblocking(false).unwrap();
+            let conn = Connection::new_unix_server(stream, &Guid::generate()).unwrap();
+            answer(&conn)
+        });
+        InputMethod { socket, stop, serving }
+    }
+
+    fn address(&self) -> String {
+        format!("unix:path={}", self.socket.display())
+    }
+
+    /// The input context calls the script made, with their arguments.
+    fn finish(self) -> Vec<String> {
+        self.stop.store(true, Ordering::SeqCst);
+        let calls = self.serving.join().unwrap();
+        let _ = std::fs::remove_file(&self.socket);
+        calls
+    }
+}
+
+/// An `IBusText` with no attributes.
+fn ibus_text(text: &str) -> Value {
+    let attributes = Structure::from(("IBusAttrList", HashMap::<&str, Value>::new(), Vec::<Value>::new()));
+    Value::from(Structure::from(("IBusText", HashMap::<&str, Value>::new(), text, Value::new(attributes))))
+}
+
+fn emit(conn: &Connection, signal: &str, body: &(impl serde::Serialize + zvariant::Type)) {
+    conn.emit_signal(Some(":1.42"), CONTEXT_PATH, INPUT_CONTEXT, signal, body).unwrap();
+}
+
+/// Plays IBus for the runtime's connection until it hangs up, composing
+/// にほん into 日本 once the input context has focus.
+fn answer(conn: &Connection) -> Vec<String> {
+    let mut calls = Vec::new();
+    loop {
+        let message: Message = match conn.receive_message() {
+            Ok(message) => message,
+            Err(_) => return calls,
+        };
+        let header = message.header().unwrap();
+        if header.message_type().unwrap() != MessageType::MethodCall {
+            continue;
+        }
+        match header.member().unwrap().unwrap() {
+            "Hello" => {
+                conn.reply(&message, &":1.42").unwrap();
+            }
+            "AddMatch" => {
+                conn.reply(&message, &()).unwrap();
+            }
+            "CreateInputContext" => {
+                let name: &str = message.body().unwrap();
+                calls.push(format!("CreateInputContext {}", name));
+                conn.reply(&message, &ObjectPath::try_from(CONTEXT_PATH).unwrap()).unwrap();
+            }
+            "SetCapabilities" => {
+                let capabilities: u32 = message.body().unwrap();
+                calls.push(format!("SetCapabilities {}", capabilities));
+                conn.reply(&message, &()).unwrap();
+            }
+            "SetCursorLocation" => {
+                let (x, y, width, height): (i32, i32, i32, i32) = message.body().unwrap();
+                calls.push(format!("SetCursorLocation {} {} {} {}", x, y, width, height));
+                conn.reply(&message, &()).unwrap();
+            }
+            "FocusIn" => {
+                calls.push("FocusIn".to_string());
+                conn.reply(&message, &()).unwrap();
+                emit(conn, "UpdatePreeditText", &(ibus_text("にほん"), 2u32, true));
+                emit(conn, "HidePreeditText", &());
+                emit(conn, "ShowPreeditText", &());
+                emit(conn, "UpdatePreeditText", &(ibus_text(""), 0u32, false));
+                emit(conn, "CommitText", &ibus_text("日本"));
+            }
+            method @ ("FocusOut" | "Reset" | "Destroy") => {
+                calls.push(method.to_string());
+                conn.reply(&message, &()).unwrap();
+            }
+            other => panic!("unexpected call to {}", other),
+        }
+    }
+}
+
+fn run_with_input_method(input_method: &InputMethod, script: &str) {
+    let address = input_method.address();
+    let output = common::run_with_env(
+        &["--headless"],
+        &format!("{}\n{}", PRELUDE, script),
+        &[("IBUS_ADDRESS", &address)],
+    );
+    common::assert_success(&output);
+}
+
+#[test]
+fn turns_preedit_and_commit_into_composition_events() {
+    let input_method = InputMethod::serve("compose");
+    run_with_input_method(
+        &input_method,
+        r#"
+const win = Deno.createWindow();
+win.setImeAllowed(true);
+win.setImePosition({ x: 10, y: 20 });
+
+const events = [];
+for await (const { event } of Deno.eventLoop()) {
+  if (event?.type === "ime" || event?.type.startsWith("composition")) {
+    const { type, ...rest } = event;
+    events.push([type, rest]);
+  }
+  if (event?.type === "compositionend") {
+    win.setImeAllowed(false);
+  }
+  if (event?.kind === "disabled") {
+    break;
+  }
+}
+win.close();
+
+assertEquals(events, [
+  ["ime", { kind: "enabled" }],
+  ["ime", { kind: "preedit", text: "にほん", cursorRange: [6, 6] }],
+  ["compositionstart", { data: "", cursorRange: null }],
+  ["compositionupdate", { data: "にほん", cursorRange: [6, 6] }],
+  ["ime", { kind: "preedit", text: "", cursorRange: null }],
+  ["compositionupdate", { data: "", cursorRange: null }],
+  ["ime", { kind: "preedit", text: "にほん", cursorRange: [6, 6] }],
+  ["compositionupdate", { data: "にほん", cursorRange: [6, 6] }],
+  ["ime", { kind: "preedit", text: "", cursorRange: null }],
+  ["compositionupdate", { data: "", cursorRange: null }],
+  ["ime", { kind: "commit", text: "日本" }],
+  ["compositionend", { data: "日本", cursorRange: null }],
+  ["ime", { kind: "disabled" }],
+]);
+"#,
+    );
+    let calls = input_method.finish();
+    // Closing the window destroys its input context, unless the script exits
+    // first.
+    let calls: Vec<_> = calls.iter().map(String::as_str).filter(|call| *call != "Destroy").collect();
+    assert_eq!(
+        calls,
+        ["CreateInputContext deno_desktop", "SetCapabilities 9", "FocusIn", "SetCursorLocation 10 20 0 0", "Reset", "FocusOut"],
+    );
+}
+
+#[test]
+fn ends_the_headless_loop_once_no_window_allows_ime() {
+    let input_method = InputMethod::serve("disallow");
+    run_with_input_method(
+        &input_method,
+        r#"
+const win = Deno.createWindow();
+win.setImeAllowed(true);
+win.setImeAllowed(false);
+const kinds = [];
+for await (const { event } of Deno.eventLoop()) {
+  // Whatever IBus sends in between is for a window that no longer allows it.
+  if (event?.type === "ime" && ["enabled", "disabled"].includes(event.kind)) {
+    kinds.push(event.kind);
+  }
+}
+assertEquals(kinds, ["enabled", "disabled"]);
+"#,
+    );
+    input_method.finish();
+}
+
+#[test]
+fn ignores_ime_without_an_input_method() {
+    common::run_headless(
+        r#"
+const win = Deno.createWindow();
+win.setImeAllowed(true);
+win.setImePosition({ x: 10, y: 20 });
+for await (const { event } of Deno.eventLoop()) {
+  if (event?.type === "ime") {
+    throw new Error("got an ime event");
+  }
+}
+"#,
+    );
+}
@@ -0,0 +1,42 @@
+//! Window and context menus drawn on a display, which headless runs skip.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::PRELUDE;
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn draws_menus_with_any_text() {
+    let output = common::run(
+        &[],
+        &format!(
+            r#"{}
+const win = Deno.createWindow({{ title: "Menus" }});
+win.setMenu([
+  {{ label: "Fichier", submenu: [{{ id: "quit", label: "Quitter", accelerator: "Ctrl+Q" }}] }},
+  {{ label: "表示", submenu: [{{ id: "dark", label: "Dark", type: "checkbox" }}] }},
+]);
+const chosen = Deno.desktop.showContextMenu(
+  [{{ id: "copy", label: "Copier « ü »" }}, {{ label: "More", submenu: [{{ id: "more", label: "→ More" }}] }}],
+  {{ window: win, x: 20, y: 40 }},
+);
+// Menus are drawn by the time the window's redraw gets here.
+win.requestRedraw();
+for await (const event of Deno.eventLoop()) {{
+  if (event.type === "redrawRequested") {{
+    break;
+  }}
+}}
+// Closing the window dismisses its context menu.
+win.close();
+assertEquals(await chosen, null);
+Deno.exit(0);
+"#,
+            PRELUDE,
+        ),
+    );
+    common::assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Failed to"), "{}", stderr);
+}
@@ -0,0 +1,242 @@
+//! Notifications against a mock `org.freedesktop.Notifications` server,
+//! served on a private session bus socket. Headless runs have a notification
+//! server of their own, so these need a display.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use common::PRELUDE;
+use zbus::Connection;
+use zbus::Guid;
+use zbus::Message;
+use zbus::MessageType;
+use zvariant::Value;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+/// How long the server sits on its answer to `Notify` after signalling about
+/// the notification, for the signals to be read before the ID is known.
+const SIGNAL_HEAD_START: Duration = Duration::from_millis(100);
+/// A notification of another client's, which must not turn into events.
+const OTHER_ID: u32 = 99;
+
+#[derive(Default)]
+struct Shared {
+    calls: Vec<String>,
+    /// Connections that added a match, to send signals to.
+    listeners: Vec<Connection>,
+    next_id: u32,
+}
+
+/// A mock notification server, serving every connection made to its socket.
+struct NotificationServer {
+    socket: PathBuf,
+    stop: Arc<AtomicBool>,
+    serving: JoinHandle<Vec<String>>,
+}
+
+impl NotificationServer {
+    fn serve(name: &str) -> NotificationServer {
+        let socket = std::env::temp_dir().join(format!("deno_desktop_test_{}_{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let serving = std::thread::spawn(move || {
+            let shared = Arc::new(Mutex::new(Shared { next_id: 1, ..Shared::default() }));
+            let mut clients = vec![];
+            while !stopped.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let shared = shared.clone();
+                        let name = format!(":1.{}", 42 + clients.len());
+                        clients.push(std::thread::spawn(move || {
+                            stream.set_nonblocking(false).unwrap();
+                            let conn = Connection::new_unix_server(stream, &Guid::generate()).unwrap();
+                            answer(conn, &name, &shared);
+                        }));
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                    Err(err) => panic!("{}", err),
+                }
+            }
+            for client in clients {
+                client.join().unwrap();
+            }
+            let mut shared = shared.lock().unwrap();
+            std::mem::take(&mut shared.calls)
+        });
+        NotificationServer { socket, stop, serving }
+    }
+
+    fn address(&self) -> String {
+        format!("unix:path={}", self.socket.display())
+    }
+
+    /// The server methods the script called, with their arguments.
+    fn finish(self) -> Vec<String> {
+        self.stop.store(true, Ordering::SeqCst);
+        let calls = self.serving.join().unwrap();
+        let _ = std::fs::remove_file(&self.socket);
+        calls
+    }
+}
+
+/// Sends a signal to every listening client, which may have exited.
+fn signal(shared: &Shared, name: &str, body: &(impl serde::Serialize + zvariant::Type)) {
+    for listener in &shared.listeners {
+        let _ = listener.emit_signal(None, PATH, INTERFACE, name, body);
+    }
+}
+
+/// `Notify`'s arguments, hints sorted by name.
+fn notify_call(message: &Message) -> String {
+    #[allow(clippy::type_complexity)]
+    let (app, replaces, icon, summary, body, actions, hints, timeout): (
+        &str,
+        u32,
+        &str,
+        &str,
+        &str,
+        Vec<&str>,
+        HashMap<&str, Value>,
+        i32,
+    ) = message.body().unwrap();
+    let mut hints: Vec<String> = hints
+        .iter()
+        .map(|(name, value)| match value {
+            Value::Bool(value) => format!("{}={}", name, value),
+            other => panic!("unexpected hint {}: {:?}", name, other),
+        })
+        .collect();
+    hints.sort();
+    format!(
+        "Notify {} {} {:?} {:?} {:?} {:?} {:?} {}",
+        app, replaces, icon, summary, body, actions, hints, timeout
+    )
+}
+
+/// Plays the bus and the notification server for one of the runtime's
+/// connections. Notifications titled "Race" are clicked and closed before
+/// `Notify` even returns.
+fn answer(conn: Connection, name: &str, shared: &Mutex<Shared>) {
+    loop {
+        let message = match conn.receive_message() {
+            Ok(message) => message,
+            Err(_) => return,
+        };
+        let header = message.header().unwrap();
+        if header.message_type().unwrap() != MessageType::MethodCall {
+            continue;
+        }
+        match header.member().unwrap().unwrap() {
+            "Hello" => {
+                conn.reply(&message, &name).unwrap();
+            }
+            "AddMatch" => {
+                conn.reply(&message, &()).unwrap();
+                // Listeners only listen from here on. Reading on would hold
+                // the connection while signals are sent to it.
+                shared.lock().unwrap().listeners.push(conn);
+                return;
+            }
+            "GetServerInformation" => {
+                shared.lock().unwrap().calls.push("GetServerInformation".to_string());
+                conn.reply(&message, &("Mock", "deno_desktop", "1.0", "1.2")).unwrap();
+            }
+            "Notify" => {
+                let mut shared = shared.lock().unwrap();
+                let call = notify_call(&message);
+                let id = shared.next_id;
+                shared.next_id += 1;
+                if call.contains("\"Race\"") {
+                    signal(&shared, "ActionInvoked", &(OTHER_ID, "open"));
+                    signal(&shared, "ActionInvoked", &(id, "default"));
+                    signal(&shared, "ActionInvoked", &(id, "open"));
+                    signal(&shared, "NotificationClosed", &(OTHER_ID, 1u32));
+                    signal(&shared, "NotificationClosed", &(id, 2u32));
+                    std::thread::sleep(SIGNAL_HEAD_START);
+                }
+                shared.calls.push(call);
+                conn.reply(&message, &id).unwrap();
+            }
+            "CloseNotification" => {
+                let id: u32 = message.body().unwrap();
+                let mut shared = shared.lock().unwrap();
+                shared.calls.push(format!("CloseNotification {}", id));
+                conn.reply(&message, &()).unwrap();
+                signal(&shared, "NotificationClosed", &(id, 3u32));
+            }
+            other => panic!("unexpected call to {}", other),
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn shows_notifications_and_routes_their_signals() {
+    let server = NotificationServer::serve("notify");
+    let address = server.address();
+    let output = common::run_with_env(
+        &[],
+        &format!(
+            "{}\n{}",
+            PRELUDE,
+            r#"
+assertEquals(await Notification.requestPermission(), "granted");
+// Only the timer keeps the script running while it waits for the server.
+const timeout = setTimeout(() => {}, 10000);
+
+function record(notification) {
+  const events = [];
+  notification.onshow = () => events.push("show");
+  notification.onclick = (event) => events.push(`click ${event.action}`);
+  return new Promise((resolve) => {
+    notification.onclose = () => {
+      events.push("close");
+      resolve(events);
+    };
+  });
+}
+
+const race = new Notification("Race", {
+  body: "Signals first",
+  icon: "/tmp/icon.png",
+  actions: [{ action: "open", title: "Open" }],
+  silent: true,
+  requireInteraction: true,
+});
+assertEquals(await record(race), ["show", "click ", "click open", "close"]);
+
+const closing = new Notification("Closing");
+closing.addEventListener("show", () => closing.close());
+assertEquals(await record(closing), ["show", "close"]);
+clearTimeout(timeout);
+"#,
+        ),
+        &[("DBUS_SESSION_BUS_ADDRESS", &address)],
+    );
+    common::assert_success(&output);
+    assert_eq!(
+        server.finish(),
+        [
+            "GetServerInformation",
+            r#"Notify Deno 0 "file:///tmp/icon.png" "Race" "Signals first" ["default", "", "open", "Open"] ["resident=true", "suppress-sound=true"] 0"#,
+            r#"Notify Deno 0 "" "Closing" "" ["default", ""] [] -1"#,
+            "CloseNotification 2",
+        ],
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::TempFile;
+use common::PRELUDE;
+use deno_runtime::deno_core::serde_json;
+use deno_runtime::deno_core::serde_json::Value;
+
+#[test]
+fn replays_through_the_event_filter_at_the_recorded_pace() {
+    let log = TempFile::new(
+        "ndjson",
+        r#"{"type":"windowEvent","windowID":1,"event":{"type":"focused","focused":true},"timestamp":1000}
+{"type":"deviceEvent","deviceID":1,"event":{"type":"mouseMotion","delta":[1,2]},"timestamp":1200}
+{"type":"windowEvent","windowID":1,"event":{"type":"cursorMoved","deviceID":1,"position":{"x":5,"y":6}},"timestamp":1250}
+{"type":"windowEvent","windowID":1,"event":{"type":"pointermove","pointerId":1,"button":-1,"buttons":0},"timestamp":1250}
+"#,
+    );
+    let script = format!(
+        r#"{}
+Deno.setEventFilter({{ exclude: ["deviceEvent"] }});
+const events = [];
+for await (const event of Deno.eventLoop()) {{
+  events.push(event);
+}}
+assertEquals(events.map((event) => event.event.type), ["focused", "cursorMoved"]);
+if (events[1].timestamp - events[0].timestamp < 200) {{
+  throw new Error("Replayed events came too early");
+}}
+"#,
+        PRELUDE
+    );
+    let output = common::run(&["--headless", "--replay-events", log.path().to_str().unwrap()], &script);
+    common::assert_success(&output);
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn records_events_the_filter_excludes() {
+    let log = TempFile::new("ndjson", "");
+    let script = r#"
+Deno.setEventFilter({ exclude: ["newEvents", "mainEventsCleared", "redrawEventsCleared"] });
+const win = Deno.createWindow({ title: "Recording" });
+win.requestRedraw();
+for await (const event of Deno.eventLoop()) {
+  if (event.type === "newEvents") {
+    throw new Error("Excluded event delivered");
+  }
+  if (event.type === "redrawRequested") {
+    break;
+  }
+}
+Deno.exit(0);
+"#;
+    let output = common::run(&["--record-events", log.path().to_str().unwrap()], script);
+    common::assert_success(&output);
+
+    let types: Vec<String> = log
+        .read()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["type"].as_str().unwrap().to_string())
+        .collect();
+    assert!(types.iter().any(|ty| ty == "newEvents"), "{:?}", types);
+    assert!(types.iter().any(|ty| ty == "redrawRequested"), "{:?}", types);
+}
@@ -0,0 +1,264 @@
+//! Tray icons against a mock session bus, which plays both the bus and the
+//! tray host: it takes the StatusNotifierItem's registration, then reads its
+//! icon and drives its DBusMenu the way a desktop's tray would. Headless runs
+//! keep tray icons to themselves, so these need a display.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use common::PRELUDE;
+use zbus::Connection;
+use zbus::Guid;
+use zbus::Message;
+use zbus::MessageType;
+use zvariant::OwnedValue;
+use zvariant::Value;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+
+/// A DBusMenu layout: `(id, properties, child layouts)`.
+type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+/// A mock session bus with a tray host, serving one client on its own socket.
+struct TrayHost {
+    socket: PathBuf,
+    stop: Arc<AtomicBool>,
+    serving: JoinHandle<Vec<String>>,
+}
+
+impl TrayHost {
+    fn serve(name: &str) -> TrayHost {
+        let socket = std::env::temp_dir().join(format!("deno_desktop_test_{}_{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let serving = std::thread::spawn(move || {
+            let stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        if stopped.load(Ordering::SeqCst) {
+                            return Vec::new();
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                    Err(err) => panic!("{}", err),
+                }
+            };
+            stream.set_nonblocking(false).unwrap();
+            let conn = Connection::new_unix_server(stream, &Guid::generate()).unwrap();
+            let service = register(&conn);
+            host(&conn, &service)
+        });
+        TrayHost { socket, stop, serving }
+    }
+
+    fn address(&self) -> String {
+        format!("unix:path={}", self.socket.display())
+    }
+
+    /// What the host saw of the tray icon, in order.
+    fn finish(self) -> Vec<String> {
+        self.stop.store(true, Ordering::SeqCst);
+        let seen = self.serving.join().unwrap();
+        let _ = std::fs::remove_file(&self.socket);
+        seen
+    }
+}
+
+/// Plays the bus and the StatusNotifierWatcher until the icon registers, and
+/// returns the bus name it registered with.
+fn register(conn: &Connection) -> String {
+    let mut requested = None;
+    loop {
+        let message = conn.receive_message().unwrap();
+        let header = message.header().unwrap();
+        if header.message_type().unwrap() != MessageType::MethodCall {
+            continue;
+        }
+        match header.member().unwrap().unwrap() {
+            "Hello" => {
+                conn.reply(&message, &":1.42").unwrap();
+            }
+            "RequestName" => {
+                let (name, _flags): (String, u32) = message.body().unwrap();
+                requested = Some(name);
+                // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+                conn.reply(&message, &1u32).unwrap();
+            }
+            "RegisterStatusNotifierItem" => {
+                let service: String = message.body().unwrap();
+                assert_eq!(requested.as_ref(), Some(&service), "registered a name it doesn't own");
+                conn.reply(&message, &()).unwrap();
+                return service;
+            }
+            other => panic!("unexpected call to {}", other),
+        }
+    }
+}
+
+fn get_property(conn: &Connection, service: &str, name: &str) -> OwnedValue {
+    conn.call_method(Some(service), ITEM_PATH, Some("org.freedesktop.DBus.Properties"), "Get", &(ITEM_INTERFACE, name))
+        .unwrap()
+        .body()
+        .unwrap()
+}
+
+/// `layout` on one line, properties sorted by name and children in brackets.
+fn describe((id, properties, children): Layout) -> String {
+    let mut described: Vec<String> = properties
+        .iter()
+        .map(|(name, value)| match &**value {
+            Value::Str(value) => format!("{}={}", name, value.as_str()),
+            Value::I32(value) => format!("{}={}", name, value),
+            Value::Bool(value) => format!("{}={}", name, value),
+            other => panic!("unexpected property {}: {:?}", name, other),
+        })
+        .collect();
+    described.sort();
+    described.insert(0, id.to_string());
+    if !children.is_empty() {
+        let children: Vec<String> = children.into_iter().map(|child| describe(Layout::try_from(child).unwrap())).collect();
+        described.push(format!("[{}]", children.join(", ")));
+    }
+    described.join(" ")
+}
+
+fn get_layout(conn: &Connection, service: &str) -> String {
+    let reply = conn.call_method(Some(service), MENU_PATH, Some(MENU_INTERFACE), "GetLayout", &(0i32, -1i32, Vec::<&str>::new())).unwrap();
+    let (revision, layout): (u32, Layout) = reply.body().unwrap();
+    format!("GetLayout {} {}", revision, describe(layout))
+}
+
+/// The next signal the icon emits, with its arguments.
+fn next_signal(conn: &Connection) -> String {
+    let message: Message = conn
+        .receive_specific(|message| Ok(message.header()?.message_type()? == MessageType::Signal))
+        .unwrap();
+    let header = message.header().unwrap();
+    match header.member().unwrap().unwrap() {
+        "LayoutUpdated" => {
+            let (revision, parent): (u32, i32) = message.body().unwrap();
+            format!("LayoutUpdated {} {}", revision, parent)
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Reads the registered icon and its menu, clicks a menu item, then clicks
+/// and scrolls the icon, for the script to change the icon in between.
+fn host(conn: &Connection, service: &str) -> Vec<String> {
+    let mut seen = Vec::new();
+    let pixmaps: Vec<(i32, i32, Vec<u8>)> = Vec::try_from(get_property(conn, service, "IconPixmap")).unwrap();
+    seen.push(format!("IconPixmap {:?}", pixmaps));
+    seen.push(get_layout(conn, service));
+
+    conn.call_method(Some(service), MENU_PATH, Some(MENU_INTERFACE), "Event", &(1i32, "clicked", Value::from(0i32), 0u32))
+        .unwrap();
+    // Emitted right after answering the click.
+    seen.push(next_signal(conn));
+    seen.push(get_layout(conn, service));
+
+    // The script changes the icon when clicked, which only reaches the thread
+    // serving it through its waker, as that thread is waiting on the bus.
+    conn.call_method(Some(service), ITEM_PATH, Some(ITEM_INTERFACE), "Activate", &(5i32, 6i32)).unwrap();
+    seen.push(next_signal(conn));
+    seen.push(next_signal(conn));
+    let title = String::try_from(get_property(conn, service, "Title")).unwrap();
+    seen.push(format!("Title {}", title));
+    seen.push(get_layout(conn, service));
+
+    // The script closes the icon when scrolled, and may be gone before the
+    // answer gets here.
+    let _ = conn.call_method(Some(service), ITEM_PATH, Some(ITEM_INTERFACE), "Scroll", &(3i32, "Vertical"));
+    seen
+}
+
+#[test]
+#[ignore = "needs an X server, run with `xvfb-run cargo test -- --include-ignored`"]
+fn serves_the_icon_and_its_menu_to_the_tray() {
+    let host = TrayHost::serve("tray");
+    let address = host.address();
+    let output = common::run_with_env(
+        &[],
+        &format!(
+            "{}\n{}",
+            PRELUDE,
+            r#"
+// Ends the script should the host give up on it.
+const timeout = setTimeout(() => {
+  throw new Error("timed out");
+}, 10000);
+
+const tray = await Deno.desktop.createTrayIcon({
+  icon: { width: 2, height: 1, data: new Uint8Array([255, 0, 0, 255, 0, 0, 255, 128]) },
+  title: "Syncing",
+  menu: [
+    { id: "pause", label: "Pause_syncing", type: "checkbox", checked: false },
+    { type: "separator" },
+    { label: "Open", submenu: [{ id: "folder", label: "Folder", enabled: false }] },
+  ],
+});
+
+const events = [];
+for await (const event of Deno.eventLoop()) {
+  if (event.trayID !== tray.id) {
+    continue;
+  }
+  const { type, trayID: _, timestamp: __, ...rest } = event;
+  events.push([type, rest]);
+  if (type === "trayIconClicked") {
+    tray.setTitle("Paused");
+    tray.setMenu([{ id: "resume", label: "Resume" }]);
+  }
+  if (type === "trayIconScrolled") {
+    tray.close();
+    break;
+  }
+}
+clearTimeout(timeout);
+
+assertEquals(events, [
+  ["menuItemActivated", { itemID: "pause", checked: true }],
+  ["trayIconClicked", { button: "primary", position: { x: 5, y: 6 } }],
+  ["trayIconScrolled", { delta: 3, orientation: "vertical" }],
+]);
+"#,
+        ),
+        &[("DBUS_SESSION_BUS_ADDRESS", &address)],
+    );
+    common::assert_success(&output);
+    assert_eq!(
+        host.finish(),
+        [
+            // RGBA red and half transparent blue, as ARGB in network order.
+            "IconPixmap [(2, 1, [255, 255, 0, 0, 128, 0, 0, 255])]",
+            "GetLayout 1 0 children-display=submenu label= [1 label=Pause__syncing toggle-state=0 toggle-type=checkmark, \
+             2 type=separator, 3 children-display=submenu label=Open [4 enabled=false label=Folder]]",
+            "LayoutUpdated 2 0",
+            "GetLayout 2 0 children-display=submenu label= [1 label=Pause__syncing toggle-state=1 toggle-type=checkmark, \
+             2 type=separator, 3 children-display=submenu label=Open [4 enabled=false label=Folder]]",
+            "NewTitle",
+            "LayoutUpdated 3 0",
+            "Title Paused",
+            "GetLayout 3 0 children-display=submenu label= [1 label=Resume]",
+        ],
+    );
+}